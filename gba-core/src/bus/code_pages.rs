@@ -0,0 +1,70 @@
+/// Pages are 256 bytes, which keeps the number of blocks thrown away by a single write small.
+pub const PAGE_SHIFT: usize = 8;
+const EW_RAM_PAGES: usize = 0x40000 >> PAGE_SHIFT;
+const IW_RAM_PAGES: usize = 0x8000 >> PAGE_SHIFT;
+/// The number of pages of writable RAM, which code can be cached from.
+pub const NUM_PAGES: usize = EW_RAM_PAGES + IW_RAM_PAGES;
+
+/// Tracks which pages of RAM hold code that the CPU has cached, so that writes to those pages
+/// can invalidate the cached code.
+pub struct CodePages {
+    marked: Vec<bool>,
+    invalidated: Vec<usize>,
+    flush_all: bool,
+}
+
+impl Default for CodePages {
+    fn default() -> Self {
+        Self {
+            marked: vec![false; NUM_PAGES],
+            invalidated: vec![],
+            flush_all: false,
+        }
+    }
+}
+
+impl CodePages {
+    /// Returns the page containing `address`, or `None` if the address isn't in writable RAM.
+    pub fn page(address: u32) -> Option<usize> {
+        let index: usize = address.try_into().unwrap();
+        match index {
+            0x2000000..=0x2ffffff => Some((index & 0x3ffff) >> PAGE_SHIFT),
+            0x3000000..=0x3ffffff => Some(EW_RAM_PAGES + ((index & 0x7fff) >> PAGE_SHIFT)),
+            _ => None,
+        }
+    }
+
+    /// Marks `page` as holding cached code.
+    pub fn mark(&mut self, page: usize) {
+        self.marked[page] = true;
+    }
+
+    /// Called on every write to RAM.
+    pub fn write(&mut self, address: u32) {
+        let Some(page) = Self::page(address) else {
+            return;
+        };
+        if self.marked[page] {
+            self.marked[page] = false;
+            self.invalidated.push(page);
+        }
+    }
+
+    /// Invalidates all cached code, e.g. after loading a new ROM.
+    pub fn flush(&mut self) {
+        self.flush_all = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.flush_all || !self.invalidated.is_empty()
+    }
+
+    /// Returns whether everything should be flushed and the pages written since the last call.
+    pub fn take_invalidated(&mut self) -> (bool, Vec<usize>) {
+        let flush_all = std::mem::take(&mut self.flush_all);
+        if flush_all {
+            self.marked.fill(false);
+        }
+        (flush_all, std::mem::take(&mut self.invalidated))
+    }
+}
@@ -1,4 +1,5 @@
 use num_traits::{FromBytes, ToBytes};
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::utils::AddressableBits;
@@ -9,8 +10,9 @@ pub enum Interrupt {
     VCount,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct IoMap {
-    mock: [u8; 0x400],
+    mock: Vec<u8>,
     keyinput: u8,
     ime: [u8; 4],
     ie: [u8; 2],
@@ -19,6 +21,7 @@ pub struct IoMap {
 }
 
 #[cfg_attr(feature="debugger", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Key {
     A,
     B,
@@ -54,7 +57,7 @@ const BASE_ADDR: usize = 0x4000000;
 impl IoMap {
     pub fn new() -> Self {
         Self {
-            mock: [0; 0x400],
+            mock: vec![0; 0x400],
             keyinput: 0xff,
             ime: [0; 4],
             ie: [0; 2],
@@ -76,6 +79,14 @@ impl IoMap {
         }
     }
 
+    /// The interrupts that are both enabled and requested, or none if IME is off.
+    pub fn pending_interrupts(&self) -> u16 {
+        if self.ime[0].bit(0) == 0 {
+            return 0;
+        }
+        u16::from_le_bytes(self.ie) & u16::from_le_bytes(self.irq_flags)
+    }
+
     pub fn set_key(&mut self, key: Key, pressed: bool) {
         self.keyinput.mut_bit(key.bit(), !pressed);
     }
@@ -122,6 +133,15 @@ impl IoMap {
         }
     }
 
+    /// Writes a byte for the debugger, so writing to IF sets its bits rather than acknowledging
+    /// the interrupts.
+    pub fn poke_byte(&mut self, index: usize, value: u8) {
+        match index {
+            0x4000202..=0x4000203 => self.irq_flags[index - 0x4000202] = value,
+            _ => self.write_byte(index, value),
+        }
+    }
+
     fn write_byte(&mut self, index: usize, value: u8) {
         assert!(index >= 0x4000000);
         assert!(index < 0x4000400);
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cpu::Cpu;
+
+use super::{Bus, CodePages};
+
+/// Whether an access fetches an instruction or is made by one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccessKind {
+    Code,
+    Data,
+}
+
+/// How the CPU drives the bus for an access. A sequential access is at the address straight after
+/// the previous access of the same kind, which some memory can answer faster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub sequential: bool,
+}
+
+/// The memory that the CPU executes against. `Bus` is the GBA's memory map, but anything that
+/// can answer reads and take writes can stand in for it, such as a bus that records every access
+/// an instruction makes.
+///
+/// Reads and writes take the address as the CPU issued it, so misaligned accesses are rotated or
+/// aligned by the implementation the same way the GBA's bus does. Each one returns the number of
+/// cycles it took, alongside the value for reads.
+pub trait MemoryInterface {
+    fn read(&mut self, index: u32, access: Access, cpu: &Cpu) -> (u32, u32);
+    fn read_half(&mut self, index: u32, access: Access, cpu: &Cpu) -> (u32, u32);
+    fn read_signed_half(&mut self, index: u32, access: Access, cpu: &Cpu) -> (u32, u32);
+    fn read_byte(&mut self, index: u32, access: Access, cpu: &Cpu) -> (u8, u32);
+
+    fn write(&mut self, index: u32, value: u32, access: Access, cpu: &Cpu) -> u32;
+    fn write_half(&mut self, index: u32, value: u16, access: Access, cpu: &Cpu) -> u32;
+    fn write_byte(&mut self, index: u32, value: u8, access: Access, cpu: &Cpu) -> u32;
+
+    /// The cycles an access of `size` bytes at `index` takes, without making it. Fetches served
+    /// from the block cache still take time, and this is how they find out how much.
+    fn access_cycles(&self, _index: u32, _size: u32, _access: Access) -> u32 {
+        1
+    }
+
+    /// The interrupts that are both enabled and requested, as a mask of IE bits. This is checked
+    /// before every instruction, so it shouldn't count as an access.
+    fn pending_interrupts(&self) -> u16 {
+        0
+    }
+
+    /// Reads a word of code without side effects, or returns `None` if `index` isn't in a region
+    /// that code can be cached from.
+    fn read_code(&self, _index: u32) -> Option<u32> {
+        None
+    }
+
+    /// The pages that cached code was decoded from. Code is only cached if this is tracked, as
+    /// it's how the cache finds out about writes to code.
+    fn code_pages(&mut self) -> Option<&mut CodePages> {
+        None
+    }
+}
+
+impl MemoryInterface for Bus {
+    fn read(&mut self, index: u32, access: Access, cpu: &Cpu) -> (u32, u32) {
+        let value = Bus::read(self, index, cpu);
+        self.watch_read(index & !3, 4, value, access);
+        (value, self.access_cycles(index, 4, access))
+    }
+
+    fn read_half(&mut self, index: u32, access: Access, cpu: &Cpu) -> (u32, u32) {
+        let value = Bus::read_half(self, index, cpu);
+        self.watch_read(index & !1, 2, value, access);
+        (value, self.access_cycles(index, 2, access))
+    }
+
+    fn read_signed_half(&mut self, index: u32, access: Access, cpu: &Cpu) -> (u32, u32) {
+        let value = Bus::read_signed_half(self, index, cpu);
+        self.watch_read(index & !1, 2, value, access);
+        (value, self.access_cycles(index, 2, access))
+    }
+
+    fn read_byte(&mut self, index: u32, access: Access, cpu: &Cpu) -> (u8, u32) {
+        let value = Bus::read_byte(self, index, cpu);
+        self.watch_read(index, 1, value.into(), access);
+        (value, self.access_cycles(index, 1, access))
+    }
+
+    fn write(&mut self, index: u32, value: u32, access: Access, cpu: &Cpu) -> u32 {
+        if !self.watchpoints.is_empty() {
+            let old_value = Bus::read(self, index & !3, cpu);
+            self.watchpoints
+                .check_write(index & !3, 4, value, old_value);
+        }
+        Bus::write(self, index, value);
+        self.access_cycles(index, 4, access)
+    }
+
+    fn write_half(&mut self, index: u32, value: u16, access: Access, cpu: &Cpu) -> u32 {
+        if !self.watchpoints.is_empty() {
+            let old_value = Bus::read_half(self, index & !1, cpu);
+            self.watchpoints
+                .check_write(index & !1, 2, value.into(), old_value);
+        }
+        Bus::write_half(self, index, value);
+        self.access_cycles(index, 2, access)
+    }
+
+    fn write_byte(&mut self, index: u32, value: u8, access: Access, cpu: &Cpu) -> u32 {
+        if !self.watchpoints.is_empty() {
+            let old_value = Bus::read_byte(self, index, cpu);
+            self.watchpoints
+                .check_write(index, 1, value.into(), old_value.into());
+        }
+        Bus::write_byte(self, index, value);
+        self.access_cycles(index, 1, access)
+    }
+
+    /// Timings are for WAITCNT at its reset value, which is all there is until it's emulated.
+    fn access_cycles(&self, index: u32, size: u32, access: Access) -> u32 {
+        // Regions with a 16 bit bus take two accesses for a word
+        let halves = if size == 4 { 2 } else { 1 };
+        match index {
+            0x2000000..=0x2ffffff => 3 * halves,
+            0x5000000..=0x6ffffff => halves,
+            // Only the first access to the game pak can be non-sequential
+            0x8000000..=0xdffffff => {
+                let first = if access.sequential { 3 } else { 5 };
+                first + 3 * (halves - 1)
+            }
+            0xe000000..=0xfffffff => 5,
+            _ => 1,
+        }
+    }
+
+    fn pending_interrupts(&self) -> u16 {
+        self.io_map.pending_interrupts()
+    }
+
+    fn read_code(&self, index: u32) -> Option<u32> {
+        Bus::read_code(self, index)
+    }
+
+    fn code_pages(&mut self) -> Option<&mut CodePages> {
+        Some(&mut self.code_pages)
+    }
+}
+
+impl Bus {
+    /// Checks a read against the watchpoints. Fetches aren't watched, only the accesses that
+    /// instructions make.
+    fn watch_read(&mut self, address: u32, size: u32, value: u32, access: Access) {
+        if !self.watchpoints.is_empty() && access.kind == AccessKind::Data {
+            self.watchpoints.check_read(address, size, value);
+        }
+    }
+}
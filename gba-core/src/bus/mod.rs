@@ -1,13 +1,23 @@
+mod code_pages;
 mod io_map;
+mod memory_interface;
+mod multiboot;
+mod region;
 
+pub use code_pages::{CodePages, NUM_PAGES, PAGE_SHIFT};
 pub use io_map::Key;
 pub use io_map::{Interrupt, IoMap};
+pub use memory_interface::{Access, AccessKind, MemoryInterface};
+pub use multiboot::{boot_rom, is_multiboot, MAX_MULTIBOOT_SIZE, MULTIBOOT_ENTRY};
+pub use region::MemoryRegion;
 use num_traits::{AsPrimitive, FromBytes, ToBytes, Zero};
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
 use js_sys;
 
 use crate::{
     cpu::Cpu,
+    debugger::Watchpoints,
     ppu::Ppu,
     utils::{get, set, AddressableBits},
 };
@@ -25,16 +35,28 @@ impl MemoryDetails {
     }
 }
 
+/// The memory map. Save states leave out the BIOS and the ROM, which are loaded separately.
+#[derive(Serialize, Deserialize)]
 pub struct Bus {
+    #[serde(skip)]
     bios: Vec<u8>,//[u8; 0x4000],
     ew_ram: Vec<u8>,//[u8; 0x40000],
     iw_ram: Vec<u8>,//[u8; 0x8000],
 
+    #[serde(skip)]
     game_pak_rom: Vec<u8>,
+    /// Cartridge SRAM, which is the only kind of save memory so far.
+    sram: Vec<u8>,
 
     pub(crate) io_map: IoMap,
 
     pub(crate) ppu: Ppu,
+
+    #[serde(skip)]
+    pub(crate) code_pages: CodePages,
+
+    #[serde(skip)]
+    pub(crate) watchpoints: Watchpoints,
 }
 
 impl Default for Bus {
@@ -45,9 +67,14 @@ impl Default for Bus {
             iw_ram: vec![0; 0x8000],
 
             game_pak_rom: vec![0; 0x2000000],
+            sram: vec![0; 0x10000],
 
             ppu: Ppu::default(),
             io_map: IoMap::new(),
+
+            code_pages: CodePages::default(),
+
+            watchpoints: Watchpoints::default(),
         }
     }
 }
@@ -55,6 +82,25 @@ impl Default for Bus {
 impl Bus {
     pub fn set_bios(&mut self, bios: &[u8]) {
         self.bios.clone_from_slice(bios);
+        self.code_pages.flush();
+    }
+
+    /// Takes the memory and the hardware's state from `saved`, which came from a save state.
+    pub(crate) fn restore(&mut self, saved: Bus) {
+        let Bus {
+            ew_ram,
+            iw_ram,
+            sram,
+            io_map,
+            ppu,
+            ..
+        } = saved;
+        self.ew_ram = ew_ram;
+        self.iw_ram = iw_ram;
+        self.sram = sram;
+        self.io_map = io_map;
+        self.ppu = ppu;
+        self.code_pages.flush();
     }
 
     pub fn set_key(&mut self, key: Key, pressed: bool) {
@@ -69,6 +115,81 @@ impl Bus {
 
     pub fn load_rom(&mut self, bytes: &[u8]) {
         self.game_pak_rom[..bytes.len()].clone_from_slice(bytes);
+        self.code_pages.flush();
+    }
+
+    /// Loads a multiboot image into EWRAM, along with a cartridge ROM that the BIOS can boot it
+    /// from.
+    pub fn load_multiboot(&mut self, bytes: &[u8]) {
+        self.ew_ram[..bytes.len()].clone_from_slice(bytes);
+        self.load_rom(&boot_rom(bytes, MULTIBOOT_ENTRY, MULTIBOOT_ENTRY));
+    }
+
+    /// Reads a byte for the debugger, without the side effects a read by the CPU can have. Memory
+    /// that isn't mapped reads as 0, rather than as whatever was last on the bus.
+    pub fn peek_byte(&self, address: u32) -> u8 {
+        let index = address as usize;
+        match index {
+            0x0000000..=0x1ffffff => self.bios[index & 0x3fff],
+            0x2000000..=0x2ffffff => self.ew_ram[index & 0x3ffff],
+            0x3000000..=0x3ffffff => self.iw_ram[index & 0x7fff],
+            0x4000000..=0x4ffffff => match index & 0x3ff {
+                0..=0x5f => self.ppu.read_lcd_io_regs::<u8, 1>(index & 0x40003ff),
+                _ => self.io_map.read::<u8, 1>(index & 0x40003ff),
+            },
+            0x5000000..=0x7ffffff => self.ppu.read_simple::<u8, 1>(index),
+            0x8000000..=0xdffffff => self.game_pak_rom[index & 0x1ffffff],
+            0xe000000..=0xfffffff => self.sram[index & 0xffff],
+            _ => 0,
+        }
+    }
+
+    /// Reads `length` bytes from `address` with `peek_byte`.
+    pub fn peek(&self, address: u32, length: u32) -> Vec<u8> {
+        (0..length)
+            .map(|offset| self.peek_byte(address.wrapping_add(offset)))
+            .collect()
+    }
+
+    /// Writes `bytes` to memory for the debugger, without the side effects a write by the CPU can
+    /// have. Unlike normal writes, this can change the ROM and the BIOS, and I/O registers are
+    /// written as they are, read-only bits included.
+    pub fn poke(&mut self, address: u32, bytes: &[u8]) {
+        for (offset, &byte) in (0..).zip(bytes) {
+            let address = address.wrapping_add(offset);
+            let index = address as usize;
+            match index {
+                0x0000000..=0x1ffffff => {
+                    self.bios[index & 0x3fff] = byte;
+                    self.code_pages.flush();
+                }
+                0x2000000..=0x2ffffff => {
+                    self.ew_ram[index & 0x3ffff] = byte;
+                    self.code_pages.write(address);
+                }
+                0x3000000..=0x3ffffff => {
+                    self.iw_ram[index & 0x7fff] = byte;
+                    self.code_pages.write(address);
+                }
+                0x4000000..=0x4ffffff => match index & 0x3ff {
+                    0..=0x5f => self.ppu.poke_lcd_io_reg(index & 0x40003ff, byte),
+                    _ => self.io_map.poke_byte(index & 0x40003ff, byte),
+                },
+                0x5000000..=0x7ffffff => self.ppu.write_simple::<u8, 1>(index, byte),
+                0x8000000..=0xdffffff => {
+                    self.game_pak_rom[index & 0x1ffffff] = byte;
+                    // Code in ROM isn't tracked by page, so it all has to go
+                    self.code_pages.flush();
+                }
+                0xe000000..=0xfffffff => self.sram[index & 0xffff] = byte,
+                _ => {}
+            }
+        }
+    }
+
+    /// A copy of everything in `region`.
+    pub fn dump(&self, region: MemoryRegion) -> Vec<u8> {
+        self.peek(region.start(), region.size())
     }
 
     fn read_internal<T, const N: usize>(&self, address: u32, cpu: &Cpu) -> T
@@ -80,7 +201,7 @@ impl Bus {
         assert_eq!(address % u32::try_from(N).unwrap(), 0);
         let index: usize = address.try_into().unwrap();
         match index {
-            0x0000000..=0x1ffffff => get(&self.bios, index & 0x3ffc),
+            0x0000000..=0x1ffffff => get(&self.bios, index & 0x3fff),
             0x2000000..=0x2ffffff => get(&self.ew_ram, index & 0x3ffff),
             0x3000000..=0x3ffffff => get(&self.iw_ram, index & 0x7fff),
             0x4000000..=0x4ffffff => match index & 0x3ff {
@@ -95,8 +216,9 @@ impl Bus {
             // that for now.
             0xa000000..=0xbffffff => get(&self.game_pak_rom, index - 0xa000000),
             0xc000000..=0xdffffff => get(&self.game_pak_rom, index - 0xc000000),
-            // TODO: Cartridge SRAM
-            0xe000000..=0xe00ffff => T::zero(),
+            // SRAM is on an 8-bit bus, so wider reads repeat the byte. It's mirrored every 64 KiB
+            // up to the end of the region.
+            0xe000000..=0xfffffff => T::from_le_bytes(&[self.sram[index & 0xffff]; N]),
             0x1000_0000..=0xffff_ffff => cpu.prefetched_instruction().as_(),
             _ => T::zero(),
         }
@@ -125,6 +247,20 @@ impl Bus {
         self.read_internal(index, cpu)
     }
 
+    /// Reads a word of code without side effects, or returns `None` if `index` isn't in a region
+    /// that code can be cached from.
+    pub(crate) fn read_code(&self, index: u32) -> Option<u32> {
+        let aligned_index: usize = (index & 0xfffffffc).try_into().unwrap();
+        let value: u32 = match aligned_index {
+            0x0000000..=0x0003fff => get(&self.bios, aligned_index),
+            0x2000000..=0x2ffffff => get(&self.ew_ram, aligned_index & 0x3ffff),
+            0x3000000..=0x3ffffff => get(&self.iw_ram, aligned_index & 0x7fff),
+            0x8000000..=0xdffffff => get(&self.game_pak_rom, aligned_index & 0x1ffffff),
+            _ => return None,
+        };
+        Some(value.rotate_right(8 * index.bits(0, 1)))
+    }
+
     fn write_internal<T, const N: usize>(&mut self, index: u32, value: T)
     where
         T: ToBytes<Bytes = [u8; N]>,
@@ -134,8 +270,14 @@ impl Bus {
         match index {
             // Don't write to bios.
             0x0000000..=0x1ffffff => {}
-            0x2000000..=0x2ffffff => set(&mut self.ew_ram, index & 0x3ffff, value),
-            0x3000000..=0x3ffffff => set(&mut self.iw_ram, index & 0x7fff, value),
+            0x2000000..=0x2ffffff => {
+                set(&mut self.ew_ram, index & 0x3ffff, value);
+                self.code_pages.write(index as u32);
+            }
+            0x3000000..=0x3ffffff => {
+                set(&mut self.iw_ram, index & 0x7fff, value);
+                self.code_pages.write(index as u32);
+            }
             0x4000000..=0x4ffffff => match index & 0x3ff {
                 0..=0x5f => self.ppu.write_lcd_io_regs(index & 0x40003ff, value),
                 0x60..=0x3fe => self.io_map.write(index & 0x40003ff, value),
@@ -145,8 +287,7 @@ impl Bus {
             0x5000000..=0x7ffffff => self.ppu.write_simple(index, value),
             // Cartridge ROM - read only?
             0x8000000..=0xdffffff => {}
-            // TODO: Cartridge SRAM
-            0xe000000..=0xe00ffff => {}
+            0xe000000..=0xfffffff => self.sram[index & 0xffff] = value.to_le_bytes()[0],
             0x1000_0000..=0xffff_ffff => {}
             _ => todo!("index {:#x} not implemented", index),
         }
@@ -164,3 +305,37 @@ impl Bus {
         self.write_internal(index, value);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pokes_without_side_effects() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+
+        // Writing to IF acknowledges interrupts, but poking it sets them
+        bus.poke(0x4000202, &[0x05, 0x00]);
+        assert_eq!(bus.peek(0x4000202, 2), [0x05, 0x00]);
+        bus.write_half(0x4000202, 0x01);
+        assert_eq!(bus.peek_byte(0x4000202), 0x04);
+
+        bus.poke(0x100, &[0x12]);
+        bus.poke(0x8000000, &[0x01, 0x02, 0x03, 0x04]);
+        bus.write(0x8000000, 0);
+        assert_eq!(bus.read_byte(0x100, &cpu), 0x12);
+        assert_eq!(bus.read(0x8000000, &cpu), 0x04030201);
+
+        bus.write_byte(0xe000010, 0x5a);
+        assert_eq!(bus.read_half(0xe000010, &cpu), 0x5a5a);
+        assert_eq!(bus.dump(MemoryRegion::Save)[0x10], 0x5a);
+        bus.write_byte(0xfff0010, 0xa5);
+        assert_eq!(bus.read_byte(0xe000010, &cpu), 0xa5);
+        assert_eq!(bus.dump(MemoryRegion::Vram).len(), 0x18000);
+
+        assert_eq!(bus.peek_byte(0x10000000), 0);
+        assert_eq!(bus.peek_byte(0x40003ff), 0);
+        assert_eq!(bus.peek(0xffffffff, 2), [0, bus.peek_byte(0)]);
+    }
+}
@@ -0,0 +1,120 @@
+//! Multiboot images, which a GBA downloads over the link cable into EWRAM and runs from there
+//! rather than from a cartridge.
+
+/// Where multiboot images are loaded and run from.
+pub const MULTIBOOT_ENTRY: u32 = 0x2000000;
+/// The most a multiboot image can be, which is all of EWRAM.
+pub const MAX_MULTIBOOT_SIZE: usize = 0x40000;
+/// How many instructions after the header to look through for an address in EWRAM.
+const SCAN_INSTRUCTIONS: usize = 80;
+
+fn word(bytes: &[u8], offset: usize) -> Option<u32> {
+    let word = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(word.try_into().unwrap()))
+}
+
+/// Guesses whether `bytes` is a multiboot image rather than a cartridge ROM. Both have the same
+/// header, so this goes by the code after it: an image that fits in EWRAM is taken to be one if
+/// the RAM entry point at 0xc0 branches somewhere other than straight past the header, or if the
+/// startup code loads an address at the start of EWRAM from a literal pool.
+pub fn is_multiboot(bytes: &[u8]) -> bool {
+    // The fixed value in the header that every ROM has
+    if bytes.len() > MAX_MULTIBOOT_SIZE || bytes.get(0xb2) != Some(&0x96) {
+        return false;
+    }
+    let Some(entry) = word(bytes, 0xc0) else {
+        return false;
+    };
+    if entry & 0x0f000000 == 0x0a000000 {
+        let offset = (entry << 8) as i32 >> 6;
+        // A branch to 0xe0 is what a cartridge's startup code has too, and some old toolchains
+        // branch to 0xe4 even in cartridges
+        if offset <= 0 || offset == 28 {
+            return false;
+        }
+        if offset != 24 {
+            return true;
+        }
+    }
+    (1..=SCAN_INSTRUCTIONS).any(|i| {
+        let pc = 0xc0 + 4 * i;
+        // LDR rd, [pc, #offset]
+        let Some(instruction) = word(bytes, pc).filter(|op| op & 0x0f7f0000 == 0x051f0000) else {
+            return false;
+        };
+        let offset = (instruction & 0xfff) as usize;
+        let literal = match instruction & (1 << 23) {
+            0 => (pc + 8).checked_sub(offset),
+            _ => Some(pc + 8 + offset),
+        };
+        literal
+            .and_then(|literal| word(bytes, literal))
+            .is_some_and(|address| address & !0x7ff == MULTIBOOT_ENTRY)
+    })
+}
+
+/// Where [`boot_rom`] puts the image it copies into RAM.
+const IMAGE_OFFSET: usize = 0x100;
+
+/// A stand-in cartridge ROM for booting an image through the BIOS, which always boots the
+/// cartridge and clears RAM first. It copies `image` to `address` and jumps to `entry`, as if the
+/// image had been downloaded as soon as the BIOS finished. It has the image's header, so that a
+/// BIOS that checks the header finds it valid.
+pub fn boot_rom(image: &[u8], address: u32, entry: u32) -> Vec<u8> {
+    let mut rom = image[..image.len().min(0xc0)].to_vec();
+    rom.resize(0xc0, 0);
+    let source = 0x8000000 + IMAGE_OFFSET as u32;
+    let end = source + image.len().next_multiple_of(4) as u32;
+    let code = [
+        0xea00002e, // B 0xc0, over the header
+        0xe59f0018, // LDR r0, [pc, #0x18], the image in ROM
+        0xe59f1018, // LDR r1, [pc, #0x18], where it goes
+        0xe59f2018, // LDR r2, [pc, #0x18], the end of the image
+        0xe1500002, // CMP r0, r2
+        0xb4903004, // LDRLT r3, [r0], #4
+        0xb4813004, // STRLT r3, [r1], #4
+        0xbafffffb, // BLT 0xcc
+        0xe59ff008, // LDR pc, [pc, #8], the entry point
+        source, address, end, entry,
+    ];
+    rom[..4].copy_from_slice(&code[0].to_le_bytes());
+    for word in &code[1..] {
+        rom.extend(word.to_le_bytes());
+    }
+    rom.resize(IMAGE_OFFSET, 0);
+    rom.extend(image);
+    rom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_multiboot_images() {
+        let mut image = vec![0; 0x100];
+        let mut set = |offset: usize, word: u32| {
+            image[offset..offset + 4].copy_from_slice(&word.to_le_bytes());
+        };
+        // B 0xc0, B 0xe0, LDR r0, [pc] and the address it loads
+        set(0, 0xea00002e);
+        set(0xc0, 0xea000006);
+        set(0xe0, 0xe59f0000);
+        set(0xe8, 0x2000100);
+        image[0xb2] = 0x96;
+        assert!(is_multiboot(&image));
+        image[0xeb] = 0x03;
+        assert!(!is_multiboot(&image));
+        // B 0x100
+        image[0xc0] = 0x0e;
+        assert!(is_multiboot(&image));
+        assert!(!is_multiboot(&image[..0xc0]));
+
+        // Despite the name, this is linked to run from ROM
+        let mb = include_bytes!("../../tests/roms/tonc_bins/m7_demo_mb.gba");
+        assert!(!is_multiboot(mb));
+        assert!(!is_multiboot(include_bytes!(
+            "../../tests/roms/armwrestler-gba-fixed.gba"
+        )));
+    }
+}
@@ -0,0 +1,59 @@
+/// A part of memory that the debugger can dump whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegion {
+    Bios,
+    Ewram,
+    Iwram,
+    Io,
+    Palette,
+    Vram,
+    Oam,
+    /// The cartridge's save memory.
+    Save,
+}
+
+impl MemoryRegion {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "bios" => Ok(Self::Bios),
+            "ewram" => Ok(Self::Ewram),
+            "iwram" => Ok(Self::Iwram),
+            "io" => Ok(Self::Io),
+            "palette" => Ok(Self::Palette),
+            "vram" => Ok(Self::Vram),
+            "oam" => Ok(Self::Oam),
+            "save" => Ok(Self::Save),
+            _ => Err(format!(
+                "expected bios, ewram, iwram, io, palette, vram, oam or save, found `{}`",
+                text
+            )),
+        }
+    }
+
+    /// Where the region starts, ignoring its mirrors.
+    pub fn start(self) -> u32 {
+        match self {
+            Self::Bios => 0x0000000,
+            Self::Ewram => 0x2000000,
+            Self::Iwram => 0x3000000,
+            Self::Io => 0x4000000,
+            Self::Palette => 0x5000000,
+            Self::Vram => 0x6000000,
+            Self::Oam => 0x7000000,
+            Self::Save => 0xe000000,
+        }
+    }
+
+    pub fn size(self) -> u32 {
+        match self {
+            Self::Bios => 0x4000,
+            Self::Ewram => 0x40000,
+            Self::Iwram => 0x8000,
+            Self::Io => 0x400,
+            Self::Palette => 0x400,
+            Self::Vram => 0x18000,
+            Self::Oam => 0x400,
+            Self::Save => 0x10000,
+        }
+    }
+}
@@ -0,0 +1,960 @@
+//! Assembles ARM and THUMB code written in the same syntax the disassembler emits. Text is
+//! parsed into an [`Instr`] and then encoded, so anything the disassembler prints assembles back
+//! to an instruction that disassembles the same way.
+
+use std::fmt::{self, Display};
+
+use crate::utils::AddressableBits;
+
+use super::ir::{
+    AddressingMode, Condition, Instr, MemoryOffset, Mnemonic, Operand, Psr, Shift, ShiftAmount,
+};
+use super::State;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblerError(pub String);
+
+impl Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+type Result<T> = std::result::Result<T, AssemblerError>;
+
+fn error<T>(message: impl Into<String>) -> Result<T> {
+    Err(AssemblerError(message.into()))
+}
+
+const MNEMONICS: &[Mnemonic] = &[
+    Mnemonic::AND,
+    Mnemonic::EOR,
+    Mnemonic::SUB,
+    Mnemonic::RSB,
+    Mnemonic::ADD,
+    Mnemonic::ADC,
+    Mnemonic::SBC,
+    Mnemonic::RSC,
+    Mnemonic::TST,
+    Mnemonic::TEQ,
+    Mnemonic::CMP,
+    Mnemonic::CMN,
+    Mnemonic::ORR,
+    Mnemonic::MOV,
+    Mnemonic::BIC,
+    Mnemonic::MVN,
+    Mnemonic::LSL,
+    Mnemonic::LSR,
+    Mnemonic::ASR,
+    Mnemonic::ROR,
+    Mnemonic::NEG,
+    Mnemonic::MRS,
+    Mnemonic::MSR,
+    Mnemonic::MUL,
+    Mnemonic::MLA,
+    Mnemonic::UMULL,
+    Mnemonic::UMLAL,
+    Mnemonic::SMULL,
+    Mnemonic::SMLAL,
+    Mnemonic::SWP,
+    Mnemonic::SWPB,
+    Mnemonic::B,
+    Mnemonic::BL,
+    Mnemonic::BL1,
+    Mnemonic::BL2,
+    Mnemonic::BX,
+    Mnemonic::LDR,
+    Mnemonic::LDRB,
+    Mnemonic::LDRT,
+    Mnemonic::LDRBT,
+    Mnemonic::STR,
+    Mnemonic::STRB,
+    Mnemonic::STRT,
+    Mnemonic::STRBT,
+    Mnemonic::LDRH,
+    Mnemonic::STRH,
+    Mnemonic::LDRSB,
+    Mnemonic::LDRSH,
+    Mnemonic::LDM,
+    Mnemonic::STM,
+    Mnemonic::PUSH,
+    Mnemonic::POP,
+    Mnemonic::SWI,
+    Mnemonic::CDP,
+    Mnemonic::LDC,
+    Mnemonic::STC,
+    Mnemonic::MCR,
+    Mnemonic::MRC,
+    Mnemonic::UND,
+];
+
+/// Assembles a single ARM instruction at `address`.
+pub fn assemble_arm(text: &str, address: u32) -> Result<u32> {
+    encode_arm(&parse(text, address)?)
+}
+
+/// Assembles a single THUMB instruction at `address`. BL assembles to both of its halves.
+pub fn assemble_thumb(text: &str, address: u32) -> Result<Vec<u16>> {
+    encode_thumb(&parse(text, address)?)
+}
+
+/// Assembles one instruction per line, returning the bytes to place at `address`. Blank lines
+/// and anything after a `;` are ignored.
+pub fn assemble(source: &str, address: u32, state: State) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    for (number, line) in source.lines().enumerate() {
+        let line = strip_comment(line);
+        if line.is_empty() {
+            continue;
+        }
+
+        let address = address.wrapping_add(bytes.len() as u32);
+        let with_line =
+            |err: AssemblerError| AssemblerError(format!("line {}: {}", number + 1, err));
+        match state {
+            State::ARM => {
+                let opcode = assemble_arm(line, address).map_err(with_line)?;
+                bytes.extend(opcode.to_le_bytes());
+            }
+            State::Thumb => {
+                for opcode in assemble_thumb(line, address).map_err(with_line)? {
+                    bytes.extend(opcode.to_le_bytes());
+                }
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split(';').next().unwrap_or_default().trim()
+}
+
+/// Parses an instruction at `address`, which absolute branch targets are made relative to.
+pub fn parse(text: &str, address: u32) -> Result<Instr> {
+    let text = strip_comment(text);
+    let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let mut instr = parse_mnemonic(&word.to_uppercase())?;
+
+    let mut tokens = split_operands(rest.trim())?.into_iter();
+    while let Some(token) = tokens.next() {
+        let operand = if let Some(list) = token.strip_suffix('^') {
+            // Only block transfers take a ^
+            instr.set_flags = true;
+            parse_reg_list(list)?
+        } else if token.starts_with('{') {
+            parse_reg_list(&token)?
+        } else if token.starts_with('[') {
+            let rest: Vec<String> = tokens.by_ref().collect();
+            parse_memory(&mut instr, &token, &rest)?
+        } else if let Some(shift) = parse_shift(&token)? {
+            let Some(Operand::Reg(rm)) = instr.operands.pop() else {
+                return error(format!("nothing to shift in `{}`", text));
+            };
+            let (shift, amount) = shift;
+            Operand::ShiftedReg { rm, shift, amount }
+        } else if let Some(imm) = token.strip_prefix('#') {
+            Operand::Imm(parse_unsigned(imm)?)
+        } else if let Some(offset) = token.strip_prefix('$') {
+            Operand::Relative(parse_signed(offset)?)
+        } else if token.starts_with(|c: char| c.is_ascii_digit()) {
+            let target = parse_unsigned(&token)?;
+            Operand::Relative(target.wrapping_sub(address) as i32)
+        } else if let Some(psr) = parse_psr(&token)? {
+            Operand::Psr(psr)
+        } else if let Some(reg) = token.strip_suffix('!') {
+            instr.writeback = true;
+            Operand::Reg(parse_reg(reg)?)
+        } else {
+            Operand::Reg(parse_reg(&token)?)
+        };
+        instr.operands.push(operand);
+    }
+
+    Ok(instr)
+}
+
+/// Splits a mnemonic into the instruction, S suffix, block addressing mode and condition.
+fn parse_mnemonic(word: &str) -> Result<Instr> {
+    let mut candidates: Vec<Mnemonic> = MNEMONICS
+        .iter()
+        .copied()
+        .filter(|mnemonic| word.starts_with(&mnemonic.to_string()))
+        .collect();
+    // Prefer the longest match, so that e.g. BLS is B with LS before BL with S
+    candidates.sort_by_key(|mnemonic| std::cmp::Reverse(mnemonic.to_string().len()));
+
+    for mnemonic in candidates {
+        let suffix = &word[mnemonic.to_string().len()..];
+        if let Some(instr) = parse_suffix(mnemonic, suffix) {
+            return Ok(instr);
+        }
+    }
+    error(format!("unknown instruction `{}`", word))
+}
+
+fn parse_suffix(mnemonic: Mnemonic, suffix: &str) -> Option<Instr> {
+    let mut instr = Instr::new(mnemonic, Condition::AL, vec![]);
+    let mut suffix = suffix;
+
+    if mnemonic.only_sets_flags() {
+        instr.set_flags = true;
+    } else if takes_s_suffix(mnemonic) {
+        if let Some(rest) = suffix.strip_prefix('S') {
+            // A condition never starts with S, so this can't be ambiguous
+            instr.set_flags = true;
+            suffix = rest;
+        }
+    }
+
+    if matches!(mnemonic, Mnemonic::LDM | Mnemonic::STM) {
+        let addressing = match suffix.get(..2)? {
+            "IA" => AddressingMode::IncrementAfter,
+            "IB" => AddressingMode::IncrementBefore,
+            "DA" => AddressingMode::DecrementAfter,
+            "DB" => AddressingMode::DecrementBefore,
+            _ => return None,
+        };
+        instr.addressing = Some(addressing);
+        suffix = &suffix[2..];
+    }
+
+    if !suffix.is_empty() {
+        instr.condition = (0..16)
+            .map(Condition::from_bits)
+            .find(|condition| format!("{:?}", condition) == suffix)?;
+    }
+    Some(instr)
+}
+
+fn takes_s_suffix(mnemonic: Mnemonic) -> bool {
+    use Mnemonic::*;
+    matches!(
+        mnemonic,
+        AND | EOR
+            | SUB
+            | RSB
+            | ADD
+            | ADC
+            | SBC
+            | RSC
+            | ORR
+            | MOV
+            | BIC
+            | MVN
+            | LSL
+            | LSR
+            | ASR
+            | ROR
+            | NEG
+            | MUL
+            | MLA
+            | UMULL
+            | UMLAL
+            | SMULL
+            | SMLAL
+    )
+}
+
+/// Splits operands on commas, except for those inside brackets or braces.
+fn split_operands(text: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut depth = 0;
+    let mut token = String::new();
+    for c in text.chars() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' if depth == 0 => return error(format!("unbalanced `{}`", c)),
+            ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                tokens.push(token.trim().to_string());
+                token.clear();
+                continue;
+            }
+            _ => {}
+        }
+        token.push(c);
+    }
+    if depth != 0 {
+        return error(format!("unbalanced brackets in `{}`", text));
+    }
+    if !token.trim().is_empty() || !tokens.is_empty() {
+        tokens.push(token.trim().to_string());
+    }
+    Ok(tokens)
+}
+
+fn parse_reg(text: &str) -> Result<u32> {
+    let reg = match text.to_lowercase().as_str() {
+        "sp" => 13,
+        "lr" => 14,
+        "pc" => 15,
+        name => match name.strip_prefix('r').map(str::parse) {
+            Some(Ok(reg)) if reg < 16 => reg,
+            _ => return error(format!("expected a register, found `{}`", text)),
+        },
+    };
+    Ok(reg)
+}
+
+fn parse_unsigned(text: &str) -> Result<u32> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.or_else(|_| error(format!("expected a number, found `{}`", text)))
+}
+
+fn parse_signed(text: &str) -> Result<i32> {
+    match text.strip_prefix('-') {
+        Some(magnitude) => Ok(parse_unsigned(magnitude)?.wrapping_neg() as i32),
+        None => Ok(parse_unsigned(text.strip_prefix('+').unwrap_or(text))? as i32),
+    }
+}
+
+/// Parses shifts like `LSL #4`, `ASR r2` and `RRX`.
+fn parse_shift(text: &str) -> Result<Option<(Shift, ShiftAmount)>> {
+    let (name, amount) = text.split_once(' ').unwrap_or((text, ""));
+    let shift = match name.to_uppercase().as_str() {
+        "LSL" => Shift::LSL,
+        "LSR" => Shift::LSR,
+        "ASR" => Shift::ASR,
+        "ROR" => Shift::ROR,
+        "RRX" => return Ok(Some((Shift::RRX, ShiftAmount::Imm(0)))),
+        _ => return Ok(None),
+    };
+
+    let amount = amount.trim();
+    let amount = match amount.strip_prefix('#') {
+        Some(imm) => ShiftAmount::Imm(parse_unsigned(imm)?),
+        None => ShiftAmount::Reg(parse_reg(amount)?),
+    };
+    Ok(Some((shift, amount)))
+}
+
+fn parse_reg_list(text: &str) -> Result<Operand> {
+    let Some(inner) = text.strip_prefix('{').and_then(|t| t.strip_suffix('}')) else {
+        return error(format!("expected a register list, found `{}`", text));
+    };
+
+    let mut list = 0u16;
+    for item in inner
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let (start, end) = match item.split_once('-') {
+            Some((start, end)) => (parse_reg(start.trim())?, parse_reg(end.trim())?),
+            None => (parse_reg(item)?, parse_reg(item)?),
+        };
+        if start > end {
+            return error(format!("backwards register range `{}`", item));
+        }
+        for reg in start..=end {
+            list |= 1 << reg;
+        }
+    }
+    Ok(Operand::RegList(list))
+}
+
+fn parse_psr(text: &str) -> Result<Option<Psr>> {
+    let upper = text.to_uppercase();
+    let (name, fields) = upper.split_once('_').unwrap_or((&upper, ""));
+    let spsr = match name {
+        "CPSR" => false,
+        "SPSR" => true,
+        _ => return Ok(None),
+    };
+
+    let mut mask = 0;
+    for field in fields.chars() {
+        mask |= match field {
+            'F' => 0b1000,
+            'S' => 0b0100,
+            'X' => 0b0010,
+            'C' => 0b0001,
+            _ => return error(format!("unknown PSR field in `{}`", text)),
+        };
+    }
+    Ok(Some(Psr { spsr, fields: mask }))
+}
+
+/// Parses `[rn, offset]`, `[rn, offset]!` or `[rn], offset`, where the post-indexed offset is
+/// in `rest`.
+fn parse_memory(instr: &mut Instr, token: &str, rest: &[String]) -> Result<Operand> {
+    let (inner, pre_indexed) = match token.strip_suffix('!') {
+        Some(token) => (token, true),
+        None => (token, false),
+    };
+    let Some(inner) = inner.strip_prefix('[').and_then(|t| t.strip_suffix(']')) else {
+        return error(format!("expected a memory operand, found `{}`", token));
+    };
+
+    let parts = split_operands(inner)?;
+    let Some((rn, offset)) = parts.split_first() else {
+        return error("empty memory operand");
+    };
+    let rn = parse_reg(rn)?;
+
+    // User mode transfers are always post-indexed
+    let post_indexed = !rest.is_empty()
+        || matches!(
+            instr.mnemonic,
+            Mnemonic::LDRT | Mnemonic::LDRBT | Mnemonic::STRT | Mnemonic::STRBT
+        );
+    if !rest.is_empty() && (pre_indexed || !offset.is_empty()) {
+        return error(format!("`{}` can't be followed by an offset", token));
+    }
+    let (offset, subtract) = parse_memory_offset(if rest.is_empty() { offset } else { rest })?;
+
+    instr.addressing = Some(if post_indexed {
+        AddressingMode::PostIndexed
+    } else if pre_indexed {
+        AddressingMode::PreIndexed
+    } else {
+        AddressingMode::Offset
+    });
+    instr.writeback = pre_indexed || post_indexed;
+
+    Ok(Operand::Memory {
+        rn,
+        offset,
+        subtract,
+    })
+}
+
+fn parse_memory_offset(parts: &[String]) -> Result<(MemoryOffset, bool)> {
+    let Some((offset, shift)) = parts.split_first() else {
+        return Ok((MemoryOffset::Imm(0), false));
+    };
+
+    if let Some(imm) = offset.strip_prefix('#') {
+        if !shift.is_empty() {
+            return error("immediate offsets can't be shifted");
+        }
+        return Ok(match imm.strip_prefix('-') {
+            Some(imm) => (MemoryOffset::Imm(parse_unsigned(imm)?), true),
+            None => (MemoryOffset::Imm(parse_unsigned(imm)?), false),
+        });
+    }
+
+    let (rm, subtract) = match offset.strip_prefix('-') {
+        Some(rm) => (parse_reg(rm)?, true),
+        None => (
+            parse_reg(offset.strip_prefix('+').unwrap_or(offset))?,
+            false,
+        ),
+    };
+    let offset = match shift {
+        [] => MemoryOffset::Reg(rm),
+        [shift] => match parse_shift(shift)? {
+            Some((shift, ShiftAmount::Imm(amount))) => {
+                MemoryOffset::ShiftedReg { rm, shift, amount }
+            }
+            _ => return error(format!("can't shift a memory offset by `{}`", shift)),
+        },
+        _ => return error("too many parts in memory offset"),
+    };
+    Ok((offset, subtract))
+}
+
+fn reg(operand: &Operand) -> Result<u32> {
+    match *operand {
+        Operand::Reg(reg) => Ok(reg),
+        _ => error(format!("expected a register, found `{}`", operand)),
+    }
+}
+
+fn fits(value: u32, bits: u32, what: &str) -> Result<u32> {
+    if value >> bits != 0 {
+        return error(format!(
+            "{} {:#x} doesn't fit in {} bits",
+            what, value, bits
+        ));
+    }
+    Ok(value)
+}
+
+/// Divides `value` by `scale`, checking that it's a multiple of it and fits in `bits` bits.
+fn scaled(value: u32, scale: u32, bits: u32) -> Result<u32> {
+    let scaled = value / scale;
+    if scaled * scale != value {
+        return error(format!("{:#x} isn't a multiple of {}", value, scale));
+    }
+    fits(scaled, bits, "offset")
+}
+
+/// Checks that a branch offset is aligned and in range, and returns its encoding.
+fn branch_offset(offset: i32, pc_offset: i32, shift: u32, bits: u32) -> Result<u32> {
+    let offset = offset.wrapping_sub(pc_offset);
+    let limit = 1i64 << (bits + shift - 1);
+    if offset & ((1 << shift) - 1) != 0 || i64::from(offset) < -limit || i64::from(offset) >= limit
+    {
+        return error(format!("branch offset {} is out of range", offset));
+    }
+    Ok((offset >> shift) as u32 & ((1 << bits) - 1))
+}
+
+/// Finds a rotation of an 8-bit value that produces `value`.
+fn rotated_imm(value: u32) -> Result<u32> {
+    (0..16)
+        .find(|rot| value.rotate_left(2 * rot) < 0x100)
+        .map(|rot| rot << 8 | value.rotate_left(2 * rot))
+        .map_or_else(
+            || {
+                error(format!(
+                    "{:#x} can't be encoded as a rotated immediate",
+                    value
+                ))
+            },
+            Ok,
+        )
+}
+
+/// Encodes an ARM shift by immediate, where shifts by 32 are written as 0.
+fn arm_shift(shift: Shift, amount: u32) -> Result<u32> {
+    let (bits, amount) = match (shift, amount) {
+        (Shift::LSL, 0..=31) => (0b00, amount),
+        (Shift::LSR, 1..=32) => (0b01, amount % 32),
+        (Shift::ASR, 1..=32) => (0b10, amount % 32),
+        (Shift::ROR, 1..=31) => (0b11, amount),
+        (Shift::RRX, _) => (0b11, 0),
+        _ => return error(format!("can't shift by {:?} #{}", shift, amount)),
+    };
+    Ok(amount << 7 | bits << 5)
+}
+
+/// Encodes the second operand of a data processing instruction, including the I bit.
+fn shifter_operand(operand: &Operand) -> Result<u32> {
+    match *operand {
+        Operand::Imm(imm) => Ok(1 << 25 | rotated_imm(imm)?),
+        Operand::Reg(rm) => Ok(rm),
+        Operand::ShiftedReg {
+            rm,
+            shift,
+            amount: ShiftAmount::Imm(amount),
+        } => Ok(arm_shift(shift, amount)? | rm),
+        Operand::ShiftedReg {
+            rm,
+            shift,
+            amount: ShiftAmount::Reg(rs),
+        } => {
+            let bits = match shift {
+                Shift::LSL => 0b00,
+                Shift::LSR => 0b01,
+                Shift::ASR => 0b10,
+                Shift::ROR => 0b11,
+                Shift::RRX => return error("RRX can't shift by a register"),
+            };
+            Ok(rs << 8 | bits << 5 | 1 << 4 | rm)
+        }
+        _ => error(format!("invalid second operand `{}`", operand)),
+    }
+}
+
+fn memory(operand: &Operand) -> Result<(u32, MemoryOffset, bool)> {
+    match *operand {
+        Operand::Memory {
+            rn,
+            offset,
+            subtract,
+        } => Ok((rn, offset, subtract)),
+        _ => error(format!("expected a memory operand, found `{}`", operand)),
+    }
+}
+
+/// Returns the P and W bits for a single load or store.
+fn indexing_bits(instr: &Instr) -> u32 {
+    match instr.addressing {
+        Some(AddressingMode::PreIndexed) => 1 << 24 | 1 << 21,
+        Some(AddressingMode::PostIndexed) => 0,
+        _ => 1 << 24,
+    }
+}
+
+pub fn encode_arm(instr: &Instr) -> Result<u32> {
+    use Mnemonic::*;
+
+    let cond = instr.condition.bits() << 28;
+    let s = u32::from(instr.set_flags) << 20;
+    let operands = instr.operands.as_slice();
+
+    let encoding = match (instr.mnemonic, operands) {
+        (AND | EOR | SUB | RSB | ADD | ADC | SBC | RSC | ORR | BIC, [rd, rn, op2]) => {
+            data_processing(instr.mnemonic)
+                | s
+                | reg(rn)? << 16
+                | reg(rd)? << 12
+                | shifter_operand(op2)?
+        }
+        (MOV | MVN, [rd, op2]) => {
+            data_processing(instr.mnemonic) | s | reg(rd)? << 12 | shifter_operand(op2)?
+        }
+        (TST | TEQ | CMP | CMN, [rn, op2]) => {
+            data_processing(instr.mnemonic) | 1 << 20 | reg(rn)? << 16 | shifter_operand(op2)?
+        }
+        (MRS, [rd, Operand::Psr(psr)]) => 0x010f0000 | u32::from(psr.spsr) << 22 | reg(rd)? << 12,
+        (MSR, [Operand::Psr(psr), Operand::Imm(imm)]) => {
+            0x0320f000 | u32::from(psr.spsr) << 22 | psr.fields << 16 | rotated_imm(*imm)?
+        }
+        (MSR, [Operand::Psr(psr), rm]) => {
+            0x0120f000 | u32::from(psr.spsr) << 22 | psr.fields << 16 | reg(rm)?
+        }
+        (MUL, [rd, rm, rs]) => 0x00000090 | s | reg(rd)? << 16 | reg(rs)? << 8 | reg(rm)?,
+        (MLA, [rd, rm, rs, rn]) => {
+            0x00200090 | s | reg(rd)? << 16 | reg(rn)? << 12 | reg(rs)? << 8 | reg(rm)?
+        }
+        (UMULL | UMLAL | SMULL | SMLAL, [rdlo, rdhi, rm, rs]) => {
+            let base = match instr.mnemonic {
+                UMULL => 0x00800090,
+                UMLAL => 0x00a00090,
+                SMULL => 0x00c00090,
+                _ => 0x00e00090,
+            };
+            base | s | reg(rdhi)? << 16 | reg(rdlo)? << 12 | reg(rs)? << 8 | reg(rm)?
+        }
+        (SWP | SWPB, [rd, rm, address]) => {
+            let (rn, offset, subtract) = memory(address)?;
+            if offset != MemoryOffset::Imm(0) || subtract {
+                return error("swaps can't have an offset");
+            }
+            let b = u32::from(instr.mnemonic == SWPB) << 22;
+            0x01000090 | b | rn << 16 | reg(rd)? << 12 | reg(rm)?
+        }
+        (B | BL, [Operand::Relative(offset)]) => {
+            let l = u32::from(instr.mnemonic == BL) << 24;
+            0x0a000000 | l | branch_offset(*offset, 8, 2, 24)?
+        }
+        (BX, [rm]) => 0x012fff10 | reg(rm)?,
+        (LDR | LDRB | LDRT | LDRBT | STR | STRB | STRT | STRBT, [rd, address]) => {
+            let (rn, offset, subtract) = memory(address)?;
+            let l = u32::from(matches!(instr.mnemonic, LDR | LDRB | LDRT | LDRBT)) << 20;
+            let b = u32::from(matches!(instr.mnemonic, LDRB | LDRBT | STRB | STRBT)) << 22;
+            let t = u32::from(matches!(instr.mnemonic, LDRT | LDRBT | STRT | STRBT)) << 21;
+            let u = u32::from(!subtract) << 23;
+            let offset = match offset {
+                MemoryOffset::Imm(imm) => fits(imm, 12, "offset")?,
+                MemoryOffset::Reg(rm) => 1 << 25 | rm,
+                MemoryOffset::ShiftedReg { rm, shift, amount } => {
+                    1 << 25 | arm_shift(shift, amount)? | rm
+                }
+            };
+            let indexing = if t != 0 { t } else { indexing_bits(instr) };
+            0x04000000 | indexing | u | b | l | rn << 16 | reg(rd)? << 12 | offset
+        }
+        (LDRH | STRH | LDRSB | LDRSH, [rd, address]) => {
+            let (rn, offset, subtract) = memory(address)?;
+            let base = match instr.mnemonic {
+                STRH => 0x000000b0,
+                LDRH => 0x001000b0,
+                LDRSB => 0x001000d0,
+                _ => 0x001000f0,
+            };
+            let u = u32::from(!subtract) << 23;
+            let offset = match offset {
+                MemoryOffset::Imm(imm) => {
+                    let imm = fits(imm, 8, "offset")?;
+                    1 << 22 | imm.bits(4, 7) << 8 | imm.bits(0, 3)
+                }
+                MemoryOffset::Reg(rm) => rm,
+                MemoryOffset::ShiftedReg { .. } => {
+                    return error("halfword transfers can't shift their offset")
+                }
+            };
+            base | indexing_bits(instr) | u | rn << 16 | reg(rd)? << 12 | offset
+        }
+        (LDM | STM, [rn, Operand::RegList(list)]) => {
+            let l = u32::from(instr.mnemonic == LDM) << 20;
+            let pu = match instr.addressing {
+                Some(AddressingMode::IncrementBefore) => 0b11,
+                Some(AddressingMode::DecrementAfter) => 0b00,
+                Some(AddressingMode::DecrementBefore) => 0b10,
+                _ => 0b01,
+            };
+            let s = u32::from(instr.set_flags) << 22;
+            let w = u32::from(instr.writeback) << 21;
+            0x08000000 | pu << 23 | s | w | l | reg(rn)? << 16 | u32::from(*list)
+        }
+        (SWI, [Operand::Imm(imm)]) => 0x0f000000 | fits(*imm, 24, "comment")?,
+        _ => return error(format!("can't encode `{}` as ARM", instr)),
+    };
+    Ok(cond | encoding)
+}
+
+fn data_processing(mnemonic: Mnemonic) -> u32 {
+    use Mnemonic::*;
+    let opcode = match mnemonic {
+        AND => 0x0,
+        EOR => 0x1,
+        SUB => 0x2,
+        RSB => 0x3,
+        ADD => 0x4,
+        ADC => 0x5,
+        SBC => 0x6,
+        RSC => 0x7,
+        TST => 0x8,
+        TEQ => 0x9,
+        CMP => 0xa,
+        CMN => 0xb,
+        ORR => 0xc,
+        MOV => 0xd,
+        BIC => 0xe,
+        _ => 0xf,
+    };
+    opcode << 21
+}
+
+/// Returns the register if it's one of r0-r7, which most THUMB instructions are limited to.
+fn low(operand: &Operand) -> Result<u16> {
+    match *operand {
+        Operand::Reg(reg) if reg < 8 => Ok(reg as u16),
+        _ => error(format!("expected one of r0-r7, found `{}`", operand)),
+    }
+}
+
+fn imm(operand: &Operand) -> Result<u32> {
+    match *operand {
+        Operand::Imm(imm) => Ok(imm),
+        _ => error(format!("expected an immediate, found `{}`", operand)),
+    }
+}
+
+pub fn encode_thumb(instr: &Instr) -> Result<Vec<u16>> {
+    use Mnemonic::*;
+
+    let operands = instr.operands.as_slice();
+    if instr.condition != Condition::AL && instr.mnemonic != B {
+        return error(format!("THUMB can't execute `{}` conditionally", instr));
+    }
+
+    let encoding: u32 = match (instr.mnemonic, operands) {
+        (LSL | LSR | ASR, [rd, rm, Operand::Imm(amount)]) => {
+            let (op, amount) = match (instr.mnemonic, amount) {
+                (LSL, 0..=31) => (0b00, *amount),
+                (LSR, 1..=32) => (0b01, amount % 32),
+                (ASR, 1..=32) => (0b10, amount % 32),
+                _ => return error(format!("can't shift by {}", amount)),
+            };
+            op << 11 | amount << 6 | u32::from(low(rm)? << 3 | low(rd)?)
+        }
+        (ADD, [rd, Operand::Reg(15 | 13), Operand::Imm(offset)]) => {
+            let sp = u32::from(operands[1] == Operand::Reg(13)) << 11;
+            0xa000 | sp | u32::from(low(rd)?) << 8 | scaled(*offset, 4, 8)?
+        }
+        (ADD | SUB, [rd, rs, rn]) => {
+            let op = u32::from(instr.mnemonic == SUB) << 9;
+            let (i, rn) = match *rn {
+                Operand::Imm(imm) => (1 << 10, fits(imm, 3, "immediate")?),
+                _ => (0, u32::from(low(rn)?)),
+            };
+            0x1800 | i | op | rn << 6 | u32::from(low(rs)? << 3 | low(rd)?)
+        }
+        (ADD | SUB, [Operand::Reg(13), Operand::Imm(offset)]) => {
+            let s = u32::from(instr.mnemonic == SUB) << 7;
+            0xb000 | s | scaled(*offset, 4, 7)?
+        }
+        (MOV | CMP | ADD | SUB, [rd, Operand::Imm(imm)]) => {
+            let op = match instr.mnemonic {
+                MOV => 0,
+                CMP => 1,
+                ADD => 2,
+                _ => 3,
+            };
+            0x2000 | op << 11 | u32::from(low(rd)?) << 8 | fits(*imm, 8, "immediate")?
+        }
+        // Two low registers use the ALU operations, apart from ADD and MOV which don't have one
+        (
+            AND | EOR | LSL | LSR | ASR | ADC | SBC | ROR | TST | NEG | CMP | CMN | ORR | MUL | BIC
+            | MVN,
+            [rd @ Operand::Reg(0..=7), rs @ Operand::Reg(0..=7)],
+        ) => {
+            let op = match instr.mnemonic {
+                AND => 0x0,
+                EOR => 0x1,
+                LSL => 0x2,
+                LSR => 0x3,
+                ASR => 0x4,
+                ADC => 0x5,
+                SBC => 0x6,
+                ROR => 0x7,
+                TST => 0x8,
+                NEG => 0x9,
+                CMP => 0xa,
+                CMN => 0xb,
+                ORR => 0xc,
+                MUL => 0xd,
+                BIC => 0xe,
+                _ => 0xf,
+            };
+            0x4000 | op << 6 | u32::from(low(rs)? << 3 | low(rd)?)
+        }
+        (ADD | CMP | MOV, [rd, rs]) => {
+            let op = match instr.mnemonic {
+                ADD => 0,
+                CMP => 1,
+                _ => 2,
+            };
+            let (rd, rs) = (reg(rd)?, reg(rs)?);
+            0x4400 | op << 8 | rd.bit(3) << 7 | rs << 3 | rd.bits(0, 2)
+        }
+        (BX, [rm]) => 0x4700 | reg(rm)? << 3,
+        (LDR | STR | LDRB | STRB | LDRH | STRH | LDRSB | LDRSH, [rd, address]) => {
+            return thumb_transfer(instr.mnemonic, low(rd)?, address).map(|opcode| vec![opcode]);
+        }
+        (PUSH | POP, [Operand::RegList(list)]) => {
+            let (base, extra) = match instr.mnemonic {
+                PUSH => (0xb400, 14),
+                _ => (0xbc00, 15),
+            };
+            if list & !(0xff | 1 << extra) != 0 {
+                return error(format!("can't {} {}", instr.mnemonic, operands[0]));
+            }
+            base | u32::from(list.bit(extra)) << 8 | u32::from(list & 0xff)
+        }
+        (LDM | STM, [rn, Operand::RegList(list)]) => {
+            if !instr.writeback || instr.addressing != Some(AddressingMode::IncrementAfter) {
+                return error("THUMB block transfers are always LDMIA or STMIA with writeback");
+            }
+            let l = u32::from(instr.mnemonic == LDM) << 11;
+            0xc000 | l | u32::from(low(rn)?) << 8 | fits(u32::from(*list), 8, "register list")?
+        }
+        (B, [Operand::Relative(offset)]) if instr.condition != Condition::AL => {
+            0xd000 | instr.condition.bits() << 8 | branch_offset(*offset, 4, 1, 8)?
+        }
+        (B, [Operand::Relative(offset)]) => 0xe000 | branch_offset(*offset, 4, 1, 11)?,
+        (BL, [Operand::Relative(offset)]) => {
+            let offset = branch_offset(*offset, 4, 1, 22)?;
+            let high = 0xf000 | offset >> 11;
+            let low = 0xf800 | offset & 0x7ff;
+            return Ok(vec![high as u16, low as u16]);
+        }
+        (BL1, [Operand::Relative(offset)]) => 0xf000 | branch_offset(*offset, 4, 12, 11)?,
+        (BL2, [Operand::Imm(offset)]) => 0xf800 | scaled(*offset, 2, 11)?,
+        (SWI, [comment]) => 0xdf00 | fits(imm(comment)?, 8, "comment")?,
+        _ => return error(format!("can't encode `{}` as THUMB", instr)),
+    };
+    Ok(vec![encoding as u16])
+}
+
+fn thumb_transfer(mnemonic: Mnemonic, rd: u16, address: &Operand) -> Result<u16> {
+    use Mnemonic::*;
+
+    let (rn, offset, subtract) = memory(address)?;
+    if subtract {
+        return error("THUMB can't subtract offsets");
+    }
+    let rd = u32::from(rd);
+
+    let encoding = match (mnemonic, rn, offset) {
+        (LDR, 15, MemoryOffset::Imm(imm)) => 0x4800 | rd << 8 | scaled(imm, 4, 8)?,
+        (LDR | STR, 13, MemoryOffset::Imm(imm)) => {
+            let l = u32::from(mnemonic == LDR) << 11;
+            0x9000 | l | rd << 8 | scaled(imm, 4, 8)?
+        }
+        (_, 0..=7, MemoryOffset::Reg(rm @ 0..=7)) => {
+            let op = match mnemonic {
+                STR => 0x5000,
+                STRH => 0x5200,
+                STRB => 0x5400,
+                LDRSB => 0x5600,
+                LDR => 0x5800,
+                LDRH => 0x5a00,
+                LDRB => 0x5c00,
+                _ => 0x5e00,
+            };
+            op | rm << 6 | rn << 3 | rd
+        }
+        (LDR | STR | LDRB | STRB | LDRH | STRH, 0..=7, MemoryOffset::Imm(imm)) => {
+            let (op, scale) = match mnemonic {
+                STR => (0x6000, 4),
+                LDR => (0x6800, 4),
+                STRB => (0x7000, 1),
+                LDRB => (0x7800, 1),
+                STRH => (0x8000, 2),
+                _ => (0x8800, 2),
+            };
+            op | scaled(imm, scale, 5)? << 6 | rn << 3 | rd
+        }
+        _ => {
+            return error(format!(
+                "can't encode `{} r{}, {}` as THUMB",
+                mnemonic, rd, address
+            ))
+        }
+    };
+    Ok(encoding as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ir::{decode_arm, decode_thumb};
+    use super::*;
+
+    #[test]
+    fn assembles_arm() {
+        assert_eq!(assemble_arm("MOV r0, #1", 0), Ok(0xe3a00001));
+        assert_eq!(assemble_arm("addsne r0, r1, r2", 0), Ok(0x10910002));
+        assert_eq!(assemble_arm("LDMIA sp!, {r0, r4, lr}", 0), Ok(0xe8bd4011));
+        assert_eq!(assemble_arm("B 0x8000000", 0x8000008), Ok(0xeafffffc));
+        assert!(assemble_arm("MOV r0, #0x101", 0).is_err());
+    }
+
+    #[test]
+    fn assembles_thumb() {
+        assert_eq!(assemble_thumb("PUSH {r4, lr}", 0), Ok(vec![0xb510]));
+        assert_eq!(assemble_thumb("BL $+0x1004", 0), Ok(vec![0xf001, 0xf800]));
+        assert!(assemble_thumb("MOVNE r0, #1", 0).is_err());
+    }
+
+    #[test]
+    fn thumb_round_trips() {
+        for opcode in 0..=0xffff {
+            let instr = decode_thumb(opcode);
+            if instr.mnemonic == Mnemonic::UND {
+                continue;
+            }
+            let text = instr.to_string();
+            let assembled =
+                assemble_thumb(&text, 0).unwrap_or_else(|err| panic!("{}: {}", text, err));
+            assert_eq!(
+                decode_thumb(assembled[0]).to_string(),
+                text,
+                "{:#06x}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn arm_round_trips() {
+        // Every decoding table entry, with the rest of the bits filled in pseudo-randomly
+        let mut bits: u32 = 0x2545f491;
+        for index in 0..0x1000u32 {
+            for _ in 0..16 {
+                bits ^= bits << 13;
+                bits ^= bits >> 17;
+                bits ^= bits << 5;
+                let opcode = (bits & 0xf00ff00f) | index.bits(4, 11) << 20 | index.bits(0, 3) << 4;
+
+                let instr = decode_arm(opcode);
+                if matches!(
+                    instr.mnemonic,
+                    Mnemonic::UND
+                        | Mnemonic::CDP
+                        | Mnemonic::LDC
+                        | Mnemonic::STC
+                        | Mnemonic::MCR
+                        | Mnemonic::MRC
+                ) {
+                    continue;
+                }
+                let text = instr.to_string();
+                let assembled =
+                    assemble_arm(&text, 0).unwrap_or_else(|err| panic!("{}: {}", text, err));
+                assert_eq!(decode_arm(assembled).to_string(), text, "{:#010x}", opcode);
+            }
+        }
+    }
+}
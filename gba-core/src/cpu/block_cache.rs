@@ -0,0 +1,330 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::bus::{CodePages, MemoryInterface, NUM_PAGES, PAGE_SHIFT};
+use crate::utils::AddressableBits;
+
+use super::{luts, ArmInstr, Luts, State, ThumbInstr};
+
+/// The most instructions a single block holds.
+const MAX_BLOCK_LEN: usize = 64;
+/// The number of slots in the cache. A block replaces whatever was in its slot before.
+const NUM_SLOTS: usize = 0x4000;
+
+#[derive(Clone, Copy)]
+pub(super) enum Decoded {
+    Arm(ArmInstr),
+    Thumb(ThumbInstr),
+}
+
+/// An opcode sitting in the pipeline, along with the instruction parsed from it.
+#[derive(Clone, Copy)]
+pub(super) struct Fetched {
+    pub opcode: u32,
+    pub decoded: Decoded,
+}
+
+impl Fetched {
+    pub fn decode(opcode: u32, state: State, luts: &'static Luts) -> Self {
+        let decoded = match state {
+            State::ARM => {
+                let index = opcode.bits(20, 27) << 4 | opcode.bits(4, 7);
+                Decoded::Arm(luts.arm[index as usize](opcode))
+            }
+            State::Thumb => Decoded::Thumb(luts.thumb[opcode.bits(4, 15) as usize](opcode as u16)),
+        };
+        Self { opcode, decoded }
+    }
+
+    fn state(&self) -> State {
+        match self.decoded {
+            Decoded::Arm(_) => State::ARM,
+            Decoded::Thumb(_) => State::Thumb,
+        }
+    }
+
+    /// Whether executing this might write to the PC or change state, which ends a block.
+    fn ends_block(&self) -> bool {
+        let opcode = self.opcode;
+        match &self.decoded {
+            Decoded::Arm(instr) => match instr {
+                ArmInstr::Branch(_)
+                | ArmInstr::Bx(_)
+                | ArmInstr::Swi(_)
+                | ArmInstr::Msr(_)
+                | ArmInstr::Todo(_)
+                | ArmInstr::Unimplemented(_) => true,
+                ArmInstr::Ldm(_) => opcode.bit(15) == 1,
+                ArmInstr::Mul(_)
+                | ArmInstr::Mla(_)
+                | ArmInstr::Umull(_)
+                | ArmInstr::Umlal(_)
+                | ArmInstr::Smull(_)
+                | ArmInstr::Smlal(_)
+                | ArmInstr::Stm(_)
+                | ArmInstr::Str(_)
+                | ArmInstr::Strh(_) => false,
+                // Everything else writes to Rd
+                _ => opcode.bits(12, 15) == 15,
+            },
+            Decoded::Thumb(instr) => match instr {
+                ThumbInstr::ConditionalBranch(_)
+                | ThumbInstr::Branch(_)
+                | ThumbInstr::LongBranchWithLinkSecond(_)
+                | ThumbInstr::Bx(_)
+                | ThumbInstr::Swi(_)
+                | ThumbInstr::Invalid(_) => true,
+                ThumbInstr::Pop(_) => opcode.bit(8) == 1,
+                ThumbInstr::AddHi(_) | ThumbInstr::MovHi(_) => {
+                    opcode.bit(7) == 1 && opcode.bits(0, 2) == 7
+                }
+                // An empty register list loads the PC
+                ThumbInstr::Ldmia(_) => opcode.bits(0, 7) == 0,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Only the opcode and the state it was fetched in are saved, and the instruction is parsed again
+/// on load.
+impl Serialize for Fetched {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.opcode, self.state()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fetched {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (opcode, state) = <(u32, State)>::deserialize(deserializer)?;
+        Ok(Self::decode(opcode, state, luts()))
+    }
+}
+
+/// A run of straight-line code, decoded and parsed ahead of time.
+struct Block {
+    start: u32,
+    state: State,
+    page: Option<usize>,
+    instrs: Vec<Fetched>,
+}
+
+impl Block {
+    fn build(
+        start: u32,
+        state: State,
+        luts: &'static Luts,
+        bus: &impl MemoryInterface,
+    ) -> Option<Self> {
+        let size = match state {
+            State::ARM => 4,
+            State::Thumb => 2,
+        };
+
+        let mut instrs = vec![];
+        let mut address = start;
+        while instrs.len() < MAX_BLOCK_LEN {
+            let Some(word) = bus.read_code(address) else {
+                break;
+            };
+            // Keep only the halfword in THUMB, like an uncached fetch does
+            let opcode = match state {
+                State::ARM => word,
+                State::Thumb => word & 0xffff,
+            };
+            let fetched = Fetched::decode(opcode, state, luts);
+            instrs.push(fetched);
+
+            address = address.wrapping_add(size);
+            // Blocks don't cross pages, so each one only needs to watch a single page for writes
+            if fetched.ends_block() || address >> PAGE_SHIFT != start >> PAGE_SHIFT {
+                break;
+            }
+        }
+
+        if instrs.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            start,
+            state,
+            page: CodePages::page(start),
+            instrs,
+        })
+    }
+}
+
+/// Caches decoded blocks of code in the BIOS, ROM and RAM, keyed by start address and state.
+/// This is direct mapped rather than a `HashMap`, since hashing on every branch costs more than
+/// the decoding it saves.
+///
+/// Blocks in RAM are thrown away when the bus reports a write to their page.
+pub(super) struct BlockCache {
+    pub enabled: bool,
+    slots: Vec<Option<Block>>,
+    /// The slots holding a block from each page of RAM, so that a write only has to look at the
+    /// blocks on its page.
+    page_slots: Vec<Vec<usize>>,
+    /// Where the next fetch is expected to be, if it's from the block being fetched from.
+    cursor: Option<Cursor>,
+}
+
+/// The next instruction in the block being fetched from, which the fetch after a cached one
+/// usually wants, so that it can be found without looking the block up again.
+struct Cursor {
+    pc: u32,
+    state: State,
+    slot: usize,
+    index: usize,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            slots: std::iter::repeat_with(|| None).take(NUM_SLOTS).collect(),
+            page_slots: vec![vec![]; NUM_PAGES],
+            cursor: None,
+        }
+    }
+}
+
+impl BlockCache {
+    /// Returns the instruction at `pc`, or `None` if it can't be cached.
+    #[inline]
+    pub fn fetch(
+        &mut self,
+        pc: u32,
+        state: State,
+        luts: &'static Luts,
+        bus: &mut impl MemoryInterface,
+    ) -> Option<&Fetched> {
+        if !self.enabled {
+            return None;
+        }
+
+        let code_pages = bus.code_pages()?;
+        if code_pages.is_dirty() {
+            self.invalidate(code_pages);
+        }
+
+        if let Some(cursor) = &self.cursor {
+            if cursor.pc == pc && cursor.state == state {
+                let (slot, index) = (cursor.slot, cursor.index);
+                return Some(self.fetch_from(slot, index));
+            }
+        }
+        self.fetch_block(pc, state, luts, bus)
+    }
+
+    /// Returns the first instruction of the block at `pc`, building it if it isn't cached.
+    #[inline(never)]
+    fn fetch_block(
+        &mut self,
+        pc: u32,
+        state: State,
+        luts: &'static Luts,
+        bus: &mut impl MemoryInterface,
+    ) -> Option<&Fetched> {
+        let slot = (pc as usize >> 1) % NUM_SLOTS;
+        let hit = self.slots[slot]
+            .as_ref()
+            .is_some_and(|block| block.start == pc && block.state == state);
+        if !hit {
+            let block = Block::build(pc, state, luts, bus)?;
+            self.evict(slot);
+            if let Some(page) = block.page {
+                self.page_slots[page].push(slot);
+                if let Some(code_pages) = bus.code_pages() {
+                    code_pages.mark(page);
+                }
+            }
+            self.slots[slot] = Some(block);
+        }
+
+        Some(self.fetch_from(slot, 0))
+    }
+
+    /// Returns instruction `index` of the block in `slot`, and moves the cursor on past it.
+    #[inline]
+    fn fetch_from(&mut self, slot: usize, index: usize) -> &Fetched {
+        // The cursor is dropped or moved whenever a block is evicted, so its block is still there
+        let block = self.slots[slot].as_ref().unwrap();
+        self.cursor = (index + 1 < block.instrs.len()).then(|| {
+            let size = match block.state {
+                State::ARM => 4,
+                State::Thumb => 2,
+            };
+            Cursor {
+                pc: block.start.wrapping_add((index as u32 + 1) * size),
+                state: block.state,
+                slot,
+                index: index + 1,
+            }
+        });
+        &block.instrs[index]
+    }
+
+    /// Empties `slot`, taking it out of its block's page.
+    fn evict(&mut self, slot: usize) {
+        if let Some(page) = self.slots[slot].take().and_then(|block| block.page) {
+            let slots = &mut self.page_slots[page];
+            if let Some(index) = slots.iter().position(|&s| s == slot) {
+                slots.swap_remove(index);
+            }
+        }
+    }
+
+    fn invalidate(&mut self, code_pages: &mut CodePages) {
+        let (flush_all, pages) = code_pages.take_invalidated();
+        if flush_all {
+            self.clear();
+            return;
+        }
+        for page in pages {
+            for slot in std::mem::take(&mut self.page_slots[page]) {
+                self.slots[slot] = None;
+            }
+        }
+        self.cursor = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.fill_with(|| None);
+        self.page_slots.iter_mut().for_each(Vec::clear);
+        self.cursor = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu::Cpu;
+
+    #[test]
+    fn writes_to_cached_code_invalidate_it() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+
+        // mov r0, #1; b .
+        bus.write(0x3000000, 0xe3a00001);
+        bus.write(0x3000004, 0xeafffffe);
+
+        cpu.set_reg(15, 0x3000000);
+        cpu.flush_pipeline();
+        for _ in 0..3 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!(cpu.get_reg(0), 1);
+
+        // mov r0, #2
+        bus.write(0x3000000, 0xe3a00002);
+
+        cpu.set_reg(15, 0x3000000);
+        cpu.flush_pipeline();
+        for _ in 0..3 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!(cpu.get_reg(0), 2);
+    }
+}
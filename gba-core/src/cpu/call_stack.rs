@@ -0,0 +1,104 @@
+//! A shadow call stack, kept by watching the branches the CPU takes, so the debugger can show how
+//! it got to where it is.
+//!
+//! A branch that leaves LR pointing just past itself is a call. That covers BL, both halves of a
+//! THUMB BL, and `mov lr, pc` followed by `bx` or a load into PC. A branch to the return address
+//! of a frame returns from it, however it gets there, whether that's `bx lr`, `pop {pc}`, an
+//! `ldm` into PC or `subs pc, lr, #4` at the end of an IRQ handler. Exceptions push frames of
+//! their own when they're taken.
+
+use serde::{Deserialize, Serialize};
+
+use super::Exception;
+
+/// How deep the stack can get before the outermost frames are forgotten, for code that never
+/// returns the way it was called, like a scheduler switching between threads.
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Frame {
+    /// Where the frame starts: the function that was called, or the exception vector.
+    pub function: u32,
+    /// The call, or the instruction that was interrupted or took the exception.
+    pub call_site: u32,
+    /// Where the frame returns to.
+    pub return_address: u32,
+    /// The exception that entered the frame, or `None` for a call.
+    pub exception: Option<Exception>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    /// The frames, outermost first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    /// Pops every frame down to the innermost one that returns to `target`, if there is one.
+    /// Returning from more than one frame at once happens when a function is left without
+    /// returning, like with `longjmp`.
+    pub fn branch(&mut self, target: u32) {
+        if let Some(index) = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_address == target)
+        {
+            self.frames.truncate(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(function: u32, call_site: u32) -> Frame {
+        Frame {
+            function,
+            call_site,
+            return_address: call_site + 4,
+            exception: None,
+        }
+    }
+
+    #[test]
+    fn returns_to_matching_frames() {
+        let mut stack = CallStack::default();
+        stack.push(call(0x8000100, 0x8000000));
+        stack.push(call(0x8000200, 0x8000110));
+        stack.push(Frame {
+            function: 0x18,
+            call_site: 0x8000204,
+            return_address: 0x8000204,
+            exception: Some(Exception::Irq(1)),
+        });
+        // Branches within a function don't return
+        stack.branch(0x8000200);
+        assert_eq!(stack.frames().len(), 3);
+        stack.branch(0x8000204);
+        assert_eq!(
+            stack.frames(),
+            [call(0x8000100, 0x8000000), call(0x8000200, 0x8000110)]
+        );
+        // Leaving both functions at once
+        stack.branch(0x8000004);
+        assert!(stack.frames().is_empty());
+
+        for i in 0..MAX_DEPTH as u32 + 1 {
+            stack.push(call(0x8000100, 0x8000000 + 4 * i));
+        }
+        assert_eq!(stack.frames().len(), MAX_DEPTH);
+        assert_eq!(stack.frames()[0], call(0x8000100, 0x8000004));
+    }
+}
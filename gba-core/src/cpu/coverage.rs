@@ -0,0 +1,167 @@
+//! Code coverage, as a bit for every instruction saying whether it's been executed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use super::State;
+use crate::debug_info::LineTable;
+
+const PAGE_SHIFT: u32 = 12;
+
+/// A bit for each halfword in a page. ARM instructions only use the bits for the first halfword
+/// of each word.
+type Page = [u64; 1 << (PAGE_SHIFT - 1 - 6)];
+
+#[derive(Debug, Clone, Default)]
+struct Bitmap {
+    pages: HashMap<u32, Box<Page>>,
+}
+
+impl Bitmap {
+    fn mark(&mut self, address: u32) {
+        let page = self.pages.entry(address >> PAGE_SHIFT).or_default();
+        let bit = (address & ((1 << PAGE_SHIFT) - 1)) >> 1;
+        page[bit as usize / 64] |= 1 << (bit % 64);
+    }
+
+    fn get(&self, address: u32) -> bool {
+        self.pages
+            .get(&(address >> PAGE_SHIFT))
+            .is_some_and(|page| {
+                let bit = (address & ((1 << PAGE_SHIFT) - 1)) >> 1;
+                page[bit as usize / 64] & (1 << (bit % 64)) != 0
+            })
+    }
+
+    /// The addresses that are marked, in order.
+    fn addresses(&self) -> Vec<u32> {
+        let mut pages: Vec<_> = self.pages.iter().collect();
+        pages.sort_unstable_by_key(|&(&page, _)| page);
+        pages
+            .into_iter()
+            .flat_map(|(&page, bits)| {
+                bits.iter().enumerate().flat_map(move |(i, &word)| {
+                    (0..64)
+                        .filter(move |bit| word & (1 << bit) != 0)
+                        .map(move |bit| (page << PAGE_SHIFT) | ((i as u32 * 64 + bit) << 1))
+                })
+            })
+            .collect()
+    }
+}
+
+/// A run of instructions in the same state that were all executed, from `start` up to `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CoveredRange {
+    pub start: u32,
+    pub end: u32,
+    pub thumb: bool,
+}
+
+/// The instructions that have been executed, in each state.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    arm: Bitmap,
+    thumb: Bitmap,
+}
+
+impl Coverage {
+    pub fn mark(&mut self, address: u32, state: State) {
+        match state {
+            State::ARM => self.arm.mark(address),
+            State::Thumb => self.thumb.mark(address),
+        }
+    }
+
+    pub fn is_executed(&self, address: u32, state: State) -> bool {
+        match state {
+            State::ARM => self.arm.get(address),
+            State::Thumb => self.thumb.get(address),
+        }
+    }
+
+    /// The executed instructions as runs of consecutive ones, ordered by address with ARM first.
+    pub fn ranges(&self) -> Vec<CoveredRange> {
+        let mut ranges: Vec<CoveredRange> = vec![];
+        for (bitmap, thumb) in [(&self.arm, false), (&self.thumb, true)] {
+            let size = if thumb { 2 } else { 4 };
+            let first = ranges.len();
+            for address in bitmap.addresses() {
+                match ranges[first..].last_mut() {
+                    Some(range) if range.end == address => range.end += size,
+                    _ => ranges.push(CoveredRange {
+                        start: address,
+                        end: address + size,
+                        thumb,
+                    }),
+                }
+            }
+        }
+        ranges
+    }
+
+    /// Line coverage in the lcov tracefile format, for each line that `lines` has code for. A
+    /// line is hit once if any of its instructions was executed, as there are no counts.
+    pub fn lcov(&self, lines: &LineTable) -> String {
+        let mut files: BTreeMap<&str, BTreeMap<u32, bool>> = BTreeMap::new();
+        for (file, line, range) in lines.ranges() {
+            let hit = range
+                .step_by(2)
+                .any(|address| self.arm.get(address) || self.thumb.get(address));
+            *files.entry(file).or_default().entry(line).or_default() |= hit;
+        }
+        let mut text = String::new();
+        for (file, lines) in files {
+            let _ = writeln!(text, "TN:\nSF:{}", file);
+            for (line, hit) in &lines {
+                let _ = writeln!(text, "DA:{},{}", line, u32::from(*hit));
+            }
+            let hit = lines.values().filter(|&&hit| hit).count();
+            let _ = writeln!(text, "LF:{}\nLH:{}\nend_of_record", lines.len(), hit);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_info::test_data::test_line_table;
+    use crate::debug_info::Strings;
+
+    #[test]
+    fn merges_ranges_and_maps_lines() {
+        let mut coverage = Coverage::default();
+        for address in [0x8000000, 0x8000004, 0x8000008, 0x8000ffc, 0x8001000] {
+            coverage.mark(address, State::ARM);
+        }
+        for address in [0x8000100, 0x8000102, 0x8000108] {
+            coverage.mark(address, State::Thumb);
+        }
+        assert!(coverage.is_executed(0x8000004, State::ARM));
+        assert!(!coverage.is_executed(0x8000004, State::Thumb));
+        let range = |start, end, thumb| CoveredRange { start, end, thumb };
+        assert_eq!(
+            coverage.ranges(),
+            [
+                range(0x8000000, 0x800000c, false),
+                range(0x8000ffc, 0x8001004, false),
+                range(0x8000100, 0x8000104, true),
+                range(0x8000108, 0x800010a, true),
+            ]
+        );
+
+        let (debug_line, line_str) = test_line_table();
+        let strings = Strings {
+            line_str: &line_str,
+            ..Strings::default()
+        };
+        let lines = LineTable::parse(&debug_line, strings).unwrap();
+        assert_eq!(
+            coverage.lcov(&lines),
+            "TN:\nSF:/src/main.c\nDA:3,1\nDA:4,0\nDA:7,1\nLF:3\nLH:2\nend_of_record\n"
+        );
+    }
+}
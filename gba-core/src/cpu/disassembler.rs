@@ -0,0 +1,209 @@
+//! Disassembles ranges of memory. Unlike decoding a single opcode, this knows where each
+//! instruction lives, so branch targets and literal pool loads can be resolved.
+
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+
+use serde::{Serialize, Serializer};
+
+use crate::bus::Bus;
+
+use super::ir::{self, AddressingMode, Instr, MemoryOffset, Mnemonic, Operand};
+use super::State;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DisassembledInstr {
+    pub address: u32,
+    /// The opcode as it's laid out in memory. A merged THUMB BL pair has the first half in the
+    /// bottom 16 bits.
+    pub opcode: u32,
+    /// The number of bytes this covers.
+    pub size: u32,
+    #[serde(serialize_with = "serialize_instr")]
+    pub instr: Instr,
+    /// Set when another instruction in the disassembled range branches here.
+    pub label: Option<String>,
+    /// The absolute address this branches to.
+    pub target: Option<u32>,
+    /// The symbol the target is in, like `main+0x1c`, when the core has symbols.
+    pub target_symbol: Option<String>,
+    /// The address and value of the literal pool entry this loads.
+    pub literal: Option<(u32, u32)>,
+}
+
+impl Display for DisassembledInstr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.instr)?;
+        if let Some((_, value)) = self.literal {
+            write!(f, " ; ={:#010x}", value)?;
+        }
+        Ok(())
+    }
+}
+
+fn serialize_instr<S: Serializer>(instr: &Instr, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(instr)
+}
+
+/// Disassembles `count` instructions starting at `address`, reading them from the bus without any
+/// side effects. Stops early on reaching memory that can't hold code.
+pub fn disassemble(bus: &Bus, address: u32, count: usize, state: State) -> Vec<DisassembledInstr> {
+    let size = match state {
+        State::ARM => 4,
+        State::Thumb => 2,
+    };
+
+    let mut instrs = vec![];
+    let mut address = address & !(size - 1);
+    while instrs.len() < count {
+        let Some(instr) = disassemble_one(bus, address, state) else {
+            break;
+        };
+        address = address.wrapping_add(instr.size);
+        instrs.push(instr);
+    }
+
+    let targets: HashSet<u32> = instrs.iter().filter_map(|instr| instr.target).collect();
+    for instr in instrs.iter_mut() {
+        if targets.contains(&instr.address) {
+            instr.label = Some(format!("loc_{:08x}", instr.address));
+        }
+    }
+    instrs
+}
+
+fn disassemble_one(bus: &Bus, address: u32, state: State) -> Option<DisassembledInstr> {
+    let (opcode, size, mut instr) = match state {
+        State::ARM => {
+            let opcode = bus.read_code(address)?;
+            (opcode, 4, ir::decode_arm(opcode))
+        }
+        State::Thumb => {
+            let opcode = bus.read_code(address)? & 0xffff;
+            let instr = ir::decode_thumb(opcode as u16);
+            match merge_long_branch(bus, address, &instr) {
+                Some((second, merged)) => (opcode | second << 16, 4, merged),
+                None => (opcode, 2, instr),
+            }
+        }
+    };
+
+    let target = instr.branch_target(address);
+    if let Some(target) = target {
+        instr.operands = vec![Operand::Address(target)];
+    }
+
+    Some(DisassembledInstr {
+        address,
+        opcode,
+        size,
+        literal: literal(bus, address, state, &instr),
+        instr,
+        label: None,
+        target,
+        target_symbol: None,
+    })
+}
+
+/// THUMB splits BL into two halves, which only make sense together. Returns the second half and
+/// the merged instruction if `instr` is the first half of a pair.
+fn merge_long_branch(bus: &Bus, address: u32, instr: &Instr) -> Option<(u32, Instr)> {
+    let Instr {
+        mnemonic: Mnemonic::BL1,
+        operands,
+        ..
+    } = instr
+    else {
+        return None;
+    };
+    let [Operand::Relative(high)] = operands.as_slice() else {
+        return None;
+    };
+
+    let second = bus.read_code(address.wrapping_add(2))? & 0xffff;
+    match ir::decode_thumb(second as u16) {
+        Instr {
+            mnemonic: Mnemonic::BL2,
+            operands,
+            ..
+        } => match operands.as_slice() {
+            [Operand::Imm(low)] => Some((
+                second,
+                Instr::new(
+                    Mnemonic::BL,
+                    instr.condition,
+                    vec![Operand::Relative(high.wrapping_add(*low as i32))],
+                ),
+            )),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the address and value loaded by a PC relative LDR.
+fn literal(bus: &Bus, address: u32, state: State, instr: &Instr) -> Option<(u32, u32)> {
+    if instr.mnemonic != Mnemonic::LDR
+        || !matches!(instr.addressing, None | Some(AddressingMode::Offset))
+    {
+        return None;
+    }
+    let Some(&Operand::Memory {
+        rn: 15,
+        offset: MemoryOffset::Imm(offset),
+        subtract,
+    }) = instr.operands.get(1)
+    else {
+        return None;
+    };
+
+    // THUMB word aligns the PC before adding the offset
+    let pc = match state {
+        State::ARM => address.wrapping_add(8),
+        State::Thumb => address.wrapping_add(4) & !3,
+    };
+    let literal_address = if subtract {
+        pc.wrapping_sub(offset)
+    } else {
+        pc.wrapping_add(offset)
+    };
+    Some((literal_address, bus.read_code(literal_address)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_branches_and_literals() {
+        let mut bus = Bus::default();
+        // ldr r0, [pc, #0]; b .-4; .word 0x4000000
+        bus.write(0x3000000, 0xe59f0000);
+        bus.write(0x3000004, 0xeafffffd);
+        bus.write(0x3000008, 0x4000000);
+
+        let instrs = disassemble(&bus, 0x3000000, 2, State::ARM);
+        assert_eq!(instrs[0].to_string(), "LDR r0, [pc] ; =0x04000000");
+        assert_eq!(instrs[0].literal, Some((0x3000008, 0x4000000)));
+        assert_eq!(instrs[0].label.as_deref(), Some("loc_03000000"));
+        assert_eq!(instrs[1].to_string(), "B 0x03000000");
+        assert_eq!(instrs[1].target, Some(0x3000000));
+    }
+
+    #[test]
+    fn merges_thumb_long_branches() {
+        let mut bus = Bus::default();
+        // bl .+0x1004; ldr r1, [pc, #4]
+        bus.write_half(0x3000000, 0xf001);
+        bus.write_half(0x3000002, 0xf800);
+        bus.write_half(0x3000004, 0x4901);
+        bus.write(0x300000c, 0x1234);
+
+        let instrs = disassemble(&bus, 0x3000000, 2, State::Thumb);
+        assert_eq!(instrs[0].size, 4);
+        assert_eq!(instrs[0].opcode, 0xf800f001);
+        assert_eq!(instrs[0].to_string(), "BL 0x03001004");
+        assert_eq!(instrs[1].address, 0x3000004);
+        assert_eq!(instrs[1].literal, Some((0x300000c, 0x1234)));
+    }
+}
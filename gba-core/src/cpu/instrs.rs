@@ -1,34 +1,33 @@
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{bus::Bus, utils::AddressableBits};
+use crate::{bus::MemoryInterface, utils::AddressableBits};
 
-use super::{ArmLut, Cpu, State, ThumbLut};
+use super::block_cache::{Decoded, Fetched};
+use super::{Cpu, State};
 
 pub mod arm;
 pub mod thumb;
 
 impl Cpu {
-    pub fn execute(
-        &mut self,
-        bus: &mut Bus,
-        instruction: u32,
-        arm_lut: &ArmLut,
-        thumb_lut: &ThumbLut,
-    ) {
-        match self.get_state() {
-            State::ARM => {
+    pub fn execute(&mut self, bus: &mut impl MemoryInterface, instruction: u32) {
+        let fetched = Fetched::decode(instruction, self.get_state(), self.luts);
+        self.execute_fetched(bus, fetched);
+    }
+
+    pub(super) fn execute_fetched(&mut self, bus: &mut impl MemoryInterface, fetched: Fetched) {
+        let instruction = fetched.opcode;
+        match (self.get_state(), fetched.decoded) {
+            (State::ARM, Decoded::Arm(instr)) => {
                 if !self.check_cond(instruction.bits(28, 31)) {
                     return;
                 }
-                let index = instruction.bits(20, 27) << 4 | instruction.bits(4, 7);
-                let arm_instruction = &arm_lut[usize::try_from(index).unwrap()];
-                arm_instruction.execute(self, bus, instruction);
+                instr.execute(self, bus);
             }
-            State::Thumb => {
-                let index = instruction.bits(4, 15);
-                let thumb_instruction = &thumb_lut[usize::try_from(index).unwrap()];
-                thumb_instruction.execute(self, bus, instruction as u16);
+            (State::Thumb, Decoded::Thumb(instr)) => {
+                instr.execute(self, bus);
             }
+            // The state changed after this was fetched, so it needs decoding again.
+            _ => self.execute(bus, instruction),
         }
     }
 }
@@ -40,5 +39,5 @@ pub fn disassemble_arm(instruction: u32) -> String {
 
 #[cfg_attr(feature="debugger", wasm_bindgen)]
 pub fn disassemble_thumb(instruction: u16) -> String {
-    Cpu::decode_thumb(instruction).disassembly(instruction)
+    Cpu::decode_thumb(instruction).disassembly()
 }
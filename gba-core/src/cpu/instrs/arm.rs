@@ -9,22 +9,119 @@ mod single_data_swap;
 mod single_data_transfer;
 mod swi;
 
-use crate::bus::Bus;
+use crate::bus::MemoryInterface;
+use crate::cpu::ir::{Condition, Instr, Mnemonic};
 use crate::cpu::{Cpu, CPSR};
 use crate::utils::AddressableBits;
 
 pub trait ArmInstruction {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32);
-    fn disassembly(&self, instruction: u32) -> String;
+    /// Pulls the instruction's fields out of its opcode. This happens once, when the opcode is
+    /// decoded, so executing it again from the block cache doesn't parse it again.
+    fn parse(instruction: u32) -> Self;
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface);
+    /// Builds the IR for the instruction from its parsed fields.
+    fn decode(&self, cond: Condition) -> Instr;
 }
 
-impl std::fmt::Debug for dyn ArmInstruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "ArmInstruction")
-    }
+/// Generates the `ArmInstr` enum, which dispatches statically to each instruction's
+/// `ArmInstruction` implementation.
+macro_rules! arm_instr_enum {
+    ($($Variant:ident($InstrT:ty)),* $(,)?) => {
+        /// An ARM instruction, picked by bits 20-27 and 4-7 of the opcode, with its fields parsed.
+        #[derive(Clone, Copy)]
+        pub enum ArmInstr {
+            $($Variant($InstrT),)*
+        }
+
+        $(
+            impl From<$InstrT> for ArmInstr {
+                fn from(instr: $InstrT) -> Self {
+                    Self::$Variant(instr)
+                }
+            }
+        )*
+
+        impl ArmInstr {
+            /// Returns the parser for opcodes of the same kind as this one.
+            pub fn parser(&self) -> fn(u32) -> ArmInstr {
+                match self {
+                    $(Self::$Variant(_) => |instruction| Self::$Variant(<$InstrT>::parse(instruction)),)*
+                }
+            }
+
+            #[inline]
+            pub fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+                match self {
+                    $(Self::$Variant(instr) => instr.execute(cpu, bus),)*
+                }
+            }
+
+            /// Builds the IR for the instruction, taking only its condition from `opcode`.
+            pub fn decode(&self, opcode: u32) -> Instr {
+                let cond = Condition::from_bits(opcode.bits(28, 31));
+                match self {
+                    $(Self::$Variant(instr) => instr.decode(cond),)*
+                }
+            }
+
+            pub fn disassembly(&self, instruction: u32) -> String {
+                self.decode(instruction).to_string()
+            }
+        }
+
+        impl std::fmt::Debug for ArmInstr {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Self::$Variant(_) => write!(f, stringify!($Variant)),)*
+                }
+            }
+        }
+    };
 }
 
-#[derive(Debug, PartialEq, Eq)]
+arm_instr_enum! {
+    And(data_processing::And),
+    Eor(data_processing::Eor),
+    Sub(data_processing::Sub),
+    Rsb(data_processing::Rsb),
+    Add(data_processing::Add),
+    Adc(data_processing::Adc),
+    Sbc(data_processing::Sbc),
+    Rsc(data_processing::Rsc),
+    Tst(data_processing::Tst),
+    Teq(data_processing::Teq),
+    Cmp(data_processing::Cmp),
+    Cmn(data_processing::Cmn),
+    Orr(data_processing::Orr),
+    Mov(data_processing::Mov),
+    Bic(data_processing::Bic),
+    Mvn(data_processing::Mvn),
+    Mrs(psr_transfer::MRS),
+    Msr(psr_transfer::MSR),
+    Mul(multiply::Mul),
+    Mla(multiply::Mla),
+    Umull(multiply::Umull),
+    Umlal(multiply::Umlal),
+    Smull(multiply::Smull),
+    Smlal(multiply::Smlal),
+    Swp(single_data_swap::Swp),
+    Swpb(single_data_swap::Swpb),
+    Bx(branch_and_exchange::BranchAndExchange),
+    Ldrh(halfword_transfer::LDRH),
+    Strh(halfword_transfer::STRH),
+    Ldrsb(halfword_transfer::LDRSB),
+    Ldrsh(halfword_transfer::LDRSH),
+    Ldr(single_data_transfer::LDR),
+    Str(single_data_transfer::STR),
+    Ldm(block_data_transfer::Ldm),
+    Stm(block_data_transfer::Stm),
+    Branch(branch::Branch),
+    Swi(swi::Swi),
+    Todo(TodoInstruction),
+    Unimplemented(UnimplementedInstruction),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MetaInstr {
     DataProcessing,
     PsrTransfer,
@@ -89,7 +186,15 @@ impl MetaInstr {
         }
     }
 
-    pub(super) fn decode_arm(instruction: u32) -> Box<dyn ArmInstruction> {
+    pub(super) fn decode_arm(instruction: u32) -> ArmInstr {
+        match Self::of(instruction) {
+            Some(meta_instr) => meta_instr.get_arm_instruction(instruction),
+            None => UnimplementedInstruction::parse(instruction).into(),
+        }
+    }
+
+    /// Returns the group `instruction` is in, if any.
+    fn of(instruction: u32) -> Option<Self> {
         let high_bits = (instruction >> 20) & 0b1111_1111;
         let low_bits = (instruction >> 4) & 0b1111;
 
@@ -118,7 +223,7 @@ impl MetaInstr {
                 let (high_fmt, low_fmt) = meta_instr.bit_format();
 
                 if high_bits & high_mask == high_fmt && low_bits & low_mask == low_fmt {
-                    return meta_instr.get_arm_instruction(instruction);
+                    return Some(meta_instr);
                 }
             } else {
                 // PSR-specific check
@@ -126,89 +231,111 @@ impl MetaInstr {
                 let s = instruction.bit(20);
                 let opcode_only_sets_flags = (0b1000..=0b1011).contains(&opcode);
                 if instruction.bits(26, 27) == 0 && opcode_only_sets_flags && s == 0 {
-                    return meta_instr.get_arm_instruction(instruction);
+                    return Some(meta_instr);
                 }
             }
         }
 
-        Box::new(UnimplementedInstruction)
+        None
     }
 
-    fn get_arm_instruction(&self, instruction: u32) -> Box<dyn ArmInstruction> {
+    fn get_arm_instruction(&self, instruction: u32) -> ArmInstr {
         match *self {
             Self::DataProcessing => match instruction.bits(21, 24) {
-                0b0000 => Box::new(data_processing::And),
-                0b0001 => Box::new(data_processing::Eor),
-                0b0010 => Box::new(data_processing::Sub),
-                0b0011 => Box::new(data_processing::Rsb),
-                0b0101 => Box::new(data_processing::Adc),
-                0b0100 => Box::new(data_processing::Add),
-                0b0110 => Box::new(data_processing::Sbc),
-                0b0111 => Box::new(data_processing::Rsc),
-                0b1000 => Box::new(data_processing::Tst),
-                0b1001 => Box::new(data_processing::Teq),
-                0b1010 => Box::new(data_processing::Cmp),
-                0b1011 => Box::new(data_processing::Cmn),
-                0b1100 => Box::new(data_processing::Orr),
-                0b1101 => Box::new(data_processing::Mov),
-                0b1110 => Box::new(data_processing::Bic),
-                0b1111 => Box::new(data_processing::Mvn),
+                0b0000 => data_processing::And::parse(instruction).into(),
+                0b0001 => data_processing::Eor::parse(instruction).into(),
+                0b0010 => data_processing::Sub::parse(instruction).into(),
+                0b0011 => data_processing::Rsb::parse(instruction).into(),
+                0b0101 => data_processing::Adc::parse(instruction).into(),
+                0b0100 => data_processing::Add::parse(instruction).into(),
+                0b0110 => data_processing::Sbc::parse(instruction).into(),
+                0b0111 => data_processing::Rsc::parse(instruction).into(),
+                0b1000 => data_processing::Tst::parse(instruction).into(),
+                0b1001 => data_processing::Teq::parse(instruction).into(),
+                0b1010 => data_processing::Cmp::parse(instruction).into(),
+                0b1011 => data_processing::Cmn::parse(instruction).into(),
+                0b1100 => data_processing::Orr::parse(instruction).into(),
+                0b1101 => data_processing::Mov::parse(instruction).into(),
+                0b1110 => data_processing::Bic::parse(instruction).into(),
+                0b1111 => data_processing::Mvn::parse(instruction).into(),
                 _ => unreachable!(),
             },
             Self::BlockDataTrans => Self::decode_block_data_transfer(instruction),
-            Self::Branch => Box::new(branch::Branch),
-            Self::BranchAndExchange => Box::new(branch_and_exchange::BranchAndExchange),
+            Self::Branch => branch::Branch::parse(instruction).into(),
+            Self::BranchAndExchange => {
+                branch_and_exchange::BranchAndExchange::parse(instruction).into()
+            }
             Self::HalfwordTransImm => Self::decode_halfword_transfer(instruction),
             Self::HalfwordTransReg => Self::decode_halfword_transfer(instruction),
             Self::PsrTransfer => Self::decode_psr_transfer(instruction),
             Self::SingleDataTrans => Self::decode_single_data_transfer(instruction),
-            Self::SoftwareInterrupt => Box::new(swi::Swi),
+            Self::SoftwareInterrupt => swi::Swi::parse(instruction).into(),
             Self::Multiply => multiply::decode_multiply(instruction),
             Self::MultiplyLong => multiply::decode_multiply_long(instruction),
             Self::SingleDataSwap => single_data_swap::decode_swap(instruction),
 
             // TODO
-            Self::CoprocDataOp => Box::new(TodoInstruction::new_message(format!("{:?}", self))),
-            Self::CoprocRegTrans => Box::new(TodoInstruction::new_message(format!("{:?}", self))),
-            Self::CoprocDataTrans => Box::new(TodoInstruction::new_message(format!("{:?}", self))),
-            Self::Undefined => Box::new(TodoInstruction::new_message(format!("{:?}", self))),
+            Self::CoprocDataOp | Self::CoprocRegTrans | Self::CoprocDataTrans | Self::Undefined => {
+                TodoInstruction(*self, instruction.bit(20) == 1).into()
+            }
         }
     }
 }
 
-pub struct TodoInstruction(pub String);
-impl TodoInstruction {
-    pub fn new_message(message: String) -> Self {
-        Self(message)
-    }
-}
+/// The group, and for coprocessor transfers whether it's a load.
+#[derive(Clone, Copy)]
+pub struct TodoInstruction(MetaInstr, bool);
 
 impl ArmInstruction for TodoInstruction {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, _: u32) {
+    fn parse(instruction: u32) -> Self {
+        Self(
+            MetaInstr::of(instruction).expect("only opcodes in a group are left to do"),
+            instruction.bit(20) == 1,
+        )
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        if self.0 == MetaInstr::Undefined {
+            return cpu.undefined_instruction();
+        }
         todo!(
-            "TODO: {} at PC: {:x}",
+            "TODO: {:?} at PC: {:x}",
             self.0,
             cpu.get_executing_instruction_pc()
         )
     }
 
-    fn disassembly(&self, _: u32) -> String {
-        format!("TODO: {}", self.0)
+    fn decode(&self, cond: Condition) -> Instr {
+        let l = self.1;
+        let mnemonic = match self.0 {
+            MetaInstr::CoprocDataOp => Mnemonic::CDP,
+            MetaInstr::CoprocRegTrans if l => Mnemonic::MRC,
+            MetaInstr::CoprocRegTrans => Mnemonic::MCR,
+            MetaInstr::CoprocDataTrans if l => Mnemonic::LDC,
+            MetaInstr::CoprocDataTrans => Mnemonic::STC,
+            _ => Mnemonic::UND,
+        };
+        Instr::new(mnemonic, cond, vec![])
     }
 }
 
-struct UnimplementedInstruction;
+#[derive(Clone, Copy)]
+pub struct UnimplementedInstruction(u32);
 impl ArmInstruction for UnimplementedInstruction {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
+    fn parse(instruction: u32) -> Self {
+        Self(instruction)
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
         panic!(
             "Unimplemented instruction {:x} at PC {:x}",
-            instruction,
+            self.0,
             cpu.get_reg(15) - 8
         );
     }
 
-    fn disassembly(&self, _instruction: u32) -> String {
-        "Unimplemented".to_string()
+    fn decode(&self, cond: Condition) -> Instr {
+        Instr::new(Mnemonic::UND, cond, vec![])
     }
 }
 
@@ -241,29 +368,7 @@ impl Cpu {
         }
     }
 
-    pub fn disassemble_cond(cond_bits: u32) -> &'static str {
-        match cond_bits {
-            0b0000 => "EQ",
-            0b0001 => "NE",
-            0b0010 => "CS/HS",
-            0b0011 => "CC/LO",
-            0b0100 => "MI",
-            0b0101 => "PL",
-            0b0110 => "VS",
-            0b0111 => "VC",
-            0b1000 => "HI",
-            0b1001 => "LS",
-            0b1010 => "GE",
-            0b1011 => "LT",
-            0b1100 => "GT",
-            0b1101 => "LE",
-            0b1110 => "",
-            0b1111 => "Unpredictable COND",
-            _ => unreachable!(),
-        }
-    }
-
-    pub fn decode_arm(instruction: u32) -> Box<dyn ArmInstruction> {
+    pub fn decode_arm(instruction: u32) -> ArmInstr {
         MetaInstr::decode_arm(instruction)
     }
 }
@@ -1,58 +1,135 @@
+use crate::cpu::ir::{self, Condition, Instr, Mnemonic, Operand};
 use crate::cpu::Mode;
-use crate::utils::reg_list;
 use crate::utils::AddressableBits;
-use crate::Bus;
+use crate::bus::MemoryInterface;
 use crate::Cpu;
 use tracing::error;
 
-use super::ArmInstruction;
 use super::MetaInstr;
+use super::{ArmInstr, ArmInstruction};
+
+#[derive(Clone, Copy)]
+pub struct BlockTransferFields {
+    addressing_mode: AddressingMode,
+    rn: u32,
+    s: bool,
+    w: bool,
+    reg_list: u32,
+}
 
-struct Ldm(AddressingMode);
-struct Stm(AddressingMode);
+impl BlockTransferFields {
+    fn parse(instruction: u32) -> Self {
+        let p = instruction.bit(24) == 1;
+        let u = instruction.bit(23) == 1;
+        let addressing_mode = match (p, u) {
+            (false, true) => AddressingMode::IncrementAfter,
+            (true, true) => AddressingMode::IncrementBefore,
+            (false, false) => AddressingMode::DecrementAfter,
+            (true, false) => AddressingMode::DecrementBefore,
+        };
+
+        Self {
+            addressing_mode,
+            rn: instruction.bits(16, 19),
+            s: instruction.bit(22) == 1,
+            w: instruction.bit(21) == 1,
+            reg_list: instruction.bits(0, 15),
+        }
+    }
+
+    /// Returns address and an optional write-back address
+    fn address(&self, cpu: &Cpu) -> (u32, Option<u32>) {
+        let rn = cpu.get_reg(self.rn);
+        let reg_count = self.reg_list.count_ones();
+        let (address, write_back) = match self.addressing_mode {
+            AddressingMode::IncrementAfter => (rn, rn + reg_count * 4),
+            AddressingMode::IncrementBefore => (rn + 4, rn + reg_count * 4),
+            AddressingMode::DecrementAfter => (rn - reg_count * 4 + 4, rn - reg_count * 4),
+            AddressingMode::DecrementBefore => (rn - reg_count * 4, rn - reg_count * 4),
+        };
+
+        if self.w {
+            (address, Some(write_back))
+        } else {
+            (address, None)
+        }
+    }
+
+    fn decode(&self, mnemonic: Mnemonic, cond: Condition) -> Instr {
+        let addressing = match self.addressing_mode {
+            AddressingMode::IncrementAfter => ir::AddressingMode::IncrementAfter,
+            AddressingMode::IncrementBefore => ir::AddressingMode::IncrementBefore,
+            AddressingMode::DecrementAfter => ir::AddressingMode::DecrementAfter,
+            AddressingMode::DecrementBefore => ir::AddressingMode::DecrementBefore,
+        };
+        let operands = vec![
+            Operand::Reg(self.rn),
+            Operand::RegList(self.reg_list as u16),
+        ];
+        Instr {
+            set_flags: self.s,
+            writeback: self.w,
+            addressing: Some(addressing),
+            ..Instr::new(mnemonic, cond, operands)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Ldm(BlockTransferFields);
+#[derive(Clone, Copy)]
+pub struct Stm(BlockTransferFields);
 
 impl ArmInstruction for Ldm {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
-        let (start_address, write_back) = self.0.address(instruction, cpu);
+    fn parse(instruction: u32) -> Self {
+        Self(BlockTransferFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let (start_address, write_back) = self.0.address(cpu);
 
-        let rn = instruction.bits(16, 19);
-        let s = instruction.bit(22);
-        let mode = if s == 1 { Mode::User } else { cpu.get_mode() };
+        let BlockTransferFields {
+            addressing_mode,
+            rn,
+            reg_list,
+            ..
+        } = self.0;
+        let mode = if self.0.s { Mode::User } else { cpu.get_mode() };
 
         if rn == 15 {
             error!("UNPREDICTABLE");
         }
 
-        if instruction.bits(0, 15) != 0 {
+        if reg_list != 0 {
             let mut address = start_address;
             // Normal case
             for i in 0..=14 {
-                if instruction.bit(i) == 1 {
-                    let value = bus.read(address & 0xfffffffc, cpu);
+                if reg_list.bit(i) == 1 {
+                    let value = cpu.read(bus, address & 0xfffffffc);
                     *cpu.regs.get_mut(i.try_into().unwrap(), &mode) = value;
                     address += 4;
                 }
             }
 
-            if instruction.bit(15) == 1 {
+            if reg_list.bit(15) == 1 {
                 // Write to PC
-                let value = bus.read(address, cpu);
+                let value = cpu.read(bus, address);
                 cpu.set_reg(15, value & 0xffff_fffc);
                 cpu.flush_pipeline();
             }
 
             if let Some(address) = write_back {
                 // Don't writeback if the base register is in the reg list
-                if instruction.bit(rn.try_into().unwrap()) == 0 {
+                if reg_list.bit(rn.try_into().unwrap()) == 0 {
                     cpu.set_reg(rn, address);
                 }
             }
         } else {
             // Empty register list loads value from memory into PC
-            let value = bus.read(start_address, cpu);
+            let value = cpu.read(bus, start_address);
             cpu.set_reg(15, value & 0xffff_fffc);
             cpu.flush_pipeline();
-            if self.0.is_increment() {
+            if addressing_mode.is_increment() {
                 cpu.set_reg(rn, cpu.get_reg(rn) + 0x40);
             } else {
                 cpu.set_reg(rn, cpu.get_reg(rn) - 0x40);
@@ -60,41 +137,39 @@ impl ArmInstruction for Ldm {
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let s = if instruction.bit(22) == 1 { "^" } else { "" };
-        let w = if instruction.bit(21) == 1 { "!" } else { "" };
-        let rn = instruction.bits(16, 19);
-        format!(
-            "LDM{} r{}{}, {{{}}}{}",
-            self.0,
-            rn,
-            w,
-            reg_list(instruction, 16),
-            s
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::LDM, cond)
     }
 }
 
 impl ArmInstruction for Stm {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
-        let (start_address, write_back) = self.0.address(instruction, cpu);
-        let rn = instruction.bits(16, 19);
+    fn parse(instruction: u32) -> Self {
+        Self(BlockTransferFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let (start_address, write_back) = self.0.address(cpu);
+        let BlockTransferFields {
+            addressing_mode,
+            rn,
+            reg_list,
+            ..
+        } = self.0;
 
-        let s = instruction.bit(22);
-        let mode = if s == 1 { Mode::User } else { cpu.get_mode() };
+        let mode = if self.0.s { Mode::User } else { cpu.get_mode() };
 
         if rn == 15 {
             error!("UNPREDICTABLE");
         }
 
-        if instruction.bits(0, 15) != 0 {
+        if reg_list != 0 {
             // Normal case
             let mut address = start_address;
             let mut first_reg = true;
 
             for i in 0..=14 {
-                if instruction.bit(i) == 1 {
-                    bus.write(address, cpu.regs.get(i.try_into().unwrap(), &mode));
+                if reg_list.bit(i) == 1 {
+                    cpu.write(bus, address, cpu.regs.get(i.try_into().unwrap(), &mode));
                     address += 4;
 
                     // Write back in STM happens after first register
@@ -107,48 +182,39 @@ impl ArmInstruction for Stm {
                 }
             }
 
-            if instruction.bit(15) == 1 {
-                bus.write(address, cpu.get_reg(15) + 4);
+            if reg_list.bit(15) == 1 {
+                cpu.write(bus, address, cpu.get_reg(15) + 4);
             }
         } else {
             // Empty register list stores PC
-            let write_back = if self.0.is_increment() {
+            let write_back = if addressing_mode.is_increment() {
                 cpu.get_reg(rn) + 0x40
             } else {
                 cpu.get_reg(rn) - 0x40
             };
-            match self.0 {
+            match addressing_mode {
                 AddressingMode::IncrementAfter | AddressingMode::IncrementBefore => {
-                    bus.write(start_address, cpu.get_reg(15) + 4);
+                    cpu.write(bus, start_address, cpu.get_reg(15) + 4);
                     cpu.set_reg(rn, write_back);
                 }
                 AddressingMode::DecrementAfter => {
-                    bus.write(write_back + 4, cpu.get_reg(15) + 4);
+                    cpu.write(bus, write_back + 4, cpu.get_reg(15) + 4);
                     cpu.set_reg(rn, write_back);
                 }
                 AddressingMode::DecrementBefore => {
-                    bus.write(write_back, cpu.get_reg(15) + 4);
+                    cpu.write(bus, write_back, cpu.get_reg(15) + 4);
                     cpu.set_reg(rn, write_back);
                 }
             }
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let s = if instruction.bit(22) == 1 { "^" } else { "" };
-        let w = if instruction.bit(21) == 1 { "!" } else { "" };
-        let rn = instruction.bits(16, 19);
-        format!(
-            "STM{} r{}{}, {{{}}}{}",
-            self.0,
-            rn,
-            w,
-            reg_list(instruction, 16),
-            s
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::STM, cond)
     }
 }
 
+#[derive(Clone, Copy)]
 enum AddressingMode {
     IncrementAfter,
     IncrementBefore,
@@ -157,25 +223,6 @@ enum AddressingMode {
 }
 
 impl AddressingMode {
-    /// Returns address and an optional write-back address
-    fn address(&self, instruction: u32, cpu: &Cpu) -> (u32, Option<u32>) {
-        let rn = cpu.get_reg(instruction.bits(16, 19));
-        let reg_count = instruction.bits(0, 15).count_ones();
-        let (address, write_back) = match *self {
-            Self::IncrementAfter => (rn, rn + reg_count * 4),
-            Self::IncrementBefore => (rn + 4, rn + reg_count * 4),
-            Self::DecrementAfter => (rn - reg_count * 4 + 4, rn - reg_count * 4),
-            Self::DecrementBefore => (rn - reg_count * 4, rn - reg_count * 4),
-        };
-
-        // Check w bit
-        if instruction.bit(21) == 0 {
-            (address, None)
-        } else {
-            (address, Some(write_back))
-        }
-    }
-
     fn is_increment(&self) -> bool {
         match *self {
             Self::IncrementAfter | Self::IncrementBefore => true,
@@ -184,35 +231,12 @@ impl AddressingMode {
     }
 }
 
-impl std::fmt::Display for AddressingMode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let str = match *self {
-            Self::IncrementAfter => "IA",
-            Self::IncrementBefore => "IB",
-            Self::DecrementAfter => "DA",
-            Self::DecrementBefore => "DB",
-        };
-        write!(f, "{}", str)
-    }
-}
-
 impl MetaInstr {
-    pub(super) fn decode_block_data_transfer(instruction: u32) -> Box<dyn ArmInstruction> {
-        let p = instruction.bit(24) == 1;
-        let u = instruction.bit(23) == 1;
-        let l = instruction.bit(20) == 1;
-
-        let addressing_mode = match (p, u) {
-            (false, true) => AddressingMode::IncrementAfter,
-            (true, true) => AddressingMode::IncrementBefore,
-            (false, false) => AddressingMode::DecrementAfter,
-            (true, false) => AddressingMode::DecrementBefore,
-        };
-
-        if l {
-            Box::new(Ldm(addressing_mode))
+    pub(super) fn decode_block_data_transfer(instruction: u32) -> ArmInstr {
+        if instruction.bit(20) == 1 {
+            Ldm::parse(instruction).into()
         } else {
-            Box::new(Stm(addressing_mode))
+            Stm::parse(instruction).into()
         }
     }
 }
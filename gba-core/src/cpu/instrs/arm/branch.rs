@@ -1,31 +1,41 @@
-use crate::Bus;
+use crate::cpu::ir::{Condition, Instr, Mnemonic, Operand};
+use crate::bus::MemoryInterface;
 use crate::Cpu;
 
 use super::ArmInstruction;
 
-pub struct Branch;
+#[derive(Clone, Copy)]
+pub struct Branch {
+    link: bool,
+    offset: i32,
+}
+
 impl ArmInstruction for Branch {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        let link = (instruction >> 24) & 1 != 0;
+    fn parse(instruction: u32) -> Self {
         let offset = instruction & 0xffffff;
-        let offset = ((offset << 8) as i32) >> 6;
+        Self {
+            link: (instruction >> 24) & 1 != 0,
+            offset: ((offset << 8) as i32) >> 6,
+        }
+    }
 
-        if link {
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        if self.link {
             cpu.set_reg(14, cpu.get_reg(15) - 4);
         }
 
-        let dest = cpu.get_reg(15).wrapping_add_signed(offset);
+        let dest = cpu.get_reg(15).wrapping_add_signed(self.offset);
 
         cpu.set_reg(15, dest);
         cpu.flush_pipeline();
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let link = (instruction >> 24) & 1 != 0;
-        let offset = instruction & 0xffffff;
-        let offset = ((offset << 8) as i32) >> 6;
-
-        let cond = Cpu::disassemble_cond(instruction >> 28);
-        format!("B{}{} PC+{:x}", if link { "L" } else { "" }, cond, offset)
+    fn decode(&self, cond: Condition) -> Instr {
+        Instr::new(
+            if self.link { Mnemonic::BL } else { Mnemonic::B },
+            cond,
+            // Branches are relative to the PC, which is 8 bytes ahead
+            vec![Operand::Relative(self.offset + 8)],
+        )
     }
 }
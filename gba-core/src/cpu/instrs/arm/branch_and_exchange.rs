@@ -1,20 +1,29 @@
+use crate::cpu::ir::{Condition, Instr, Mnemonic, Operand};
 use crate::cpu::State;
 use crate::utils::AddressableBits;
-use crate::Bus;
+use crate::bus::MemoryInterface;
 use crate::Cpu;
 
 use super::ArmInstruction;
 
-pub struct BranchAndExchange;
+#[derive(Clone, Copy)]
+pub struct BranchAndExchange {
+    rn: u32,
+}
+
 impl ArmInstruction for BranchAndExchange {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        let rn = instruction.bits(0, 3);
+    fn parse(instruction: u32) -> Self {
+        Self {
+            rn: instruction.bits(0, 3),
+        }
+    }
 
-        if rn == 15 {
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        if self.rn == 15 {
             todo!("undefined behaviour");
         }
 
-        let dest = cpu.get_reg(rn);
+        let dest = cpu.get_reg(self.rn);
 
         if dest.bit(0) == 1 {
             cpu.set_state(State::Thumb);
@@ -25,8 +34,7 @@ impl ArmInstruction for BranchAndExchange {
         cpu.flush_pipeline();
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let rn = instruction.bits(0, 3);
-        format!("BX r{}", rn)
+    fn decode(&self, cond: Condition) -> Instr {
+        Instr::new(Mnemonic::BX, cond, vec![Operand::Reg(self.rn)])
     }
 }
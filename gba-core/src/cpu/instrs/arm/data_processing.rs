@@ -1,10 +1,10 @@
+use crate::cpu::ir::{Condition, Instr, Mnemonic, Operand, Shift, ShiftAmount};
 use crate::cpu::CPSR;
 use crate::utils::add_overflows;
 use crate::utils::sub_overflows;
 use crate::utils::AddressableBits;
-use crate::Bus;
+use crate::bus::MemoryInterface;
 use crate::Cpu;
-use std::fmt::Debug;
 
 use super::ArmInstruction;
 
@@ -22,15 +22,6 @@ impl ShiftSource {
         }
     }
 }
-impl Debug for ShiftSource {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            ShiftSource::Immediate(x) => write!(f, "{:x}", x),
-            ShiftSource::Register(x) => write!(f, "r{}", x),
-        }
-    }
-}
-
 #[derive(Clone, Copy)]
 enum ShifterOperand {
     Imm { imm: u32, c: Option<bool> },
@@ -41,32 +32,6 @@ enum ShifterOperand {
     RRX { rm: u32 },
 }
 
-impl Debug for ShifterOperand {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            Self::Imm { imm, .. } => write!(f, "#{:x}", imm),
-            Self::LSL { rm, shift_source } => match shift_source {
-                ShiftSource::Immediate(0) => write!(f, "r{}", rm),
-                ShiftSource::Immediate(imm) => write!(f, "r{}, LSL #{:x}", rm, imm),
-                ShiftSource::Register(reg) => write!(f, "r{}, LSL r{}", rm, reg),
-            },
-            Self::LSR { rm, shift_source } => match shift_source {
-                ShiftSource::Immediate(imm) => write!(f, "r{}, LSR #{:x}", rm, imm),
-                ShiftSource::Register(reg) => write!(f, "r{}, LSR r{}", rm, reg),
-            },
-            Self::ASR { rm, shift_source } => match shift_source {
-                ShiftSource::Immediate(imm) => write!(f, "r{}, ASR #{:x}", rm, imm),
-                ShiftSource::Register(reg) => write!(f, "r{}, ASR r{}", rm, reg),
-            },
-            Self::ROR { rm, shift_source } => match shift_source {
-                ShiftSource::Immediate(imm) => write!(f, "r{}, ROR #{:x}", rm, imm),
-                ShiftSource::Register(reg) => write!(f, "r{}, ROR r{}", rm, reg),
-            },
-            Self::RRX { rm } => write!(f, "r{}, RRX", rm),
-        }
-    }
-}
-
 impl ShifterOperand {
     fn parse_immediate(instruction: u32) -> ShifterOperand {
         let imm = instruction.bits(0, 7);
@@ -94,14 +59,9 @@ impl ShifterOperand {
             let shift_amt = instruction.bits(7, 11);
             shift_source = ShiftSource::Immediate(shift_amt);
         } else {
-            // Read shift_amt from bottom byte of register
+            // Read shift_amt from bottom byte of register. Using r15 here is unpredictable, but
+            // still has to decode so that data can be disassembled.
             let shift_reg = instruction.bits(8, 11);
-
-            // TODO: Is this check necessary - maybe the format of instructions prevents this
-            if shift_reg == 15 {
-                panic!("shift reg cannot be r15")
-            }
-
             shift_source = ShiftSource::Register(shift_reg);
         };
 
@@ -269,6 +229,32 @@ impl ShifterOperand {
         }
     }
 
+    fn operand(&self) -> Operand {
+        let (rm, shift, shift_source) = match *self {
+            Self::Imm { imm, .. } => return Operand::Imm(imm),
+            Self::RRX { rm } => {
+                return Operand::ShiftedReg {
+                    rm,
+                    shift: Shift::RRX,
+                    amount: ShiftAmount::Imm(0),
+                }
+            }
+            Self::LSL { rm, shift_source } => (rm, Shift::LSL, shift_source),
+            Self::LSR { rm, shift_source } => (rm, Shift::LSR, shift_source),
+            Self::ASR { rm, shift_source } => (rm, Shift::ASR, shift_source),
+            Self::ROR { rm, shift_source } => (rm, Shift::ROR, shift_source),
+        };
+
+        let amount = match shift_source {
+            ShiftSource::Immediate(0) if shift == Shift::LSL => return Operand::Reg(rm),
+            // LSR #0 and ASR #0 encode shifts by 32
+            ShiftSource::Immediate(0) => ShiftAmount::Imm(32),
+            ShiftSource::Immediate(imm) => ShiftAmount::Imm(imm),
+            ShiftSource::Register(reg) => ShiftAmount::Reg(reg),
+        };
+        Operand::ShiftedReg { rm, shift, amount }
+    }
+
     fn takes_extra_cycle(&self) -> bool {
         let shift_source = match *self {
             Self::Imm { .. } | Self::RRX { .. } => return false,
@@ -284,6 +270,7 @@ impl ShifterOperand {
     }
 }
 
+#[derive(Clone, Copy)]
 pub(super) struct DataProcessingFields {
     set: bool,
     rn: u32,
@@ -304,24 +291,58 @@ impl DataProcessingFields {
             shifter: ShifterOperand::parse(instruction),
         }
     }
+
+    fn decode(&self, mnemonic: Mnemonic, cond: Condition) -> Instr {
+        let operands = match mnemonic {
+            Mnemonic::TST | Mnemonic::TEQ | Mnemonic::CMP | Mnemonic::CMN => {
+                vec![Operand::Reg(self.rn), self.shifter.operand()]
+            }
+            Mnemonic::MOV | Mnemonic::MVN => vec![Operand::Reg(self.rd), self.shifter.operand()],
+            _ => vec![
+                Operand::Reg(self.rd),
+                Operand::Reg(self.rn),
+                self.shifter.operand(),
+            ],
+        };
+        Instr {
+            set_flags: self.set,
+            ..Instr::new(mnemonic, cond, operands)
+        }
+    }
 }
 
-pub struct And;
-pub struct Eor;
-pub struct Sub;
-pub struct Rsb;
-pub struct Add;
-pub struct Sbc;
-pub struct Rsc;
-pub struct Adc;
-pub struct Tst;
-pub struct Teq;
-pub struct Cmp;
-pub struct Cmn;
-pub struct Orr;
-pub struct Mov;
-pub struct Bic;
-pub struct Mvn;
+#[derive(Clone, Copy)]
+pub struct And(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Eor(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Sub(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Rsb(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Add(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Sbc(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Rsc(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Adc(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Tst(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Teq(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Cmp(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Cmn(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Orr(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Mov(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Bic(DataProcessingFields);
+#[derive(Clone, Copy)]
+pub struct Mvn(DataProcessingFields);
 
 struct FlagUpdates {
     n: Option<bool>,
@@ -331,12 +352,11 @@ struct FlagUpdates {
 }
 
 #[inline]
-fn execute_op<F>(cpu: &mut Cpu, instruction: u32, flag_only: bool, op_closure: F)
+fn execute_op<F>(cpu: &mut Cpu, fields: &DataProcessingFields, flag_only: bool, op_closure: F)
 where
     // op1, op2, shifter carry
     F: Fn(u32, u32, bool) -> (u32, FlagUpdates),
 {
-    let fields = DataProcessingFields::parse(instruction);
     let (op2, c) = fields.shifter.op2(cpu);
 
     let op1 = if fields.shifter.takes_extra_cycle() && fields.rn == 15 {
@@ -378,8 +398,12 @@ where
 }
 
 impl ArmInstruction for And {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op1, op2, shift_carry| {
             let result = op1 & op2;
             (
                 result,
@@ -393,15 +417,18 @@ impl ArmInstruction for And {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("AND r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::AND, cond)
     }
 }
 
 impl ArmInstruction for Eor {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op1, op2, shift_carry| {
             let result = op1 ^ op2;
             (
                 result,
@@ -415,15 +442,18 @@ impl ArmInstruction for Eor {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("EOR r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::EOR, cond)
     }
 }
 
 impl ArmInstruction for Sub {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, _| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op1, op2, _| {
             let (result, borrow) = op1.overflowing_sub(op2);
             (
                 result,
@@ -437,15 +467,18 @@ impl ArmInstruction for Sub {
         })
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("SUB r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::SUB, cond)
     }
 }
 
 impl ArmInstruction for Rsb {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, _| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op1, op2, _| {
             let (result, borrow) = op2.overflowing_sub(op1);
             (
                 result,
@@ -459,15 +492,18 @@ impl ArmInstruction for Rsb {
         })
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("RSB r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::RSB, cond)
     }
 }
 
 impl ArmInstruction for Add {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, _| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op1, op2, _| {
             let (result, c) = op1.overflowing_add(op2);
             let n = result.bit(31) == 1;
             let z = result == 0;
@@ -484,15 +520,18 @@ impl ArmInstruction for Add {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("ADD r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::ADD, cond)
     }
 }
 
 impl ArmInstruction for Sbc {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op1, op2, shift_carry| {
             let (mut result, mut borrow) = op1.overflowing_sub(op2);
             let mut overflow = op1.bit(31) != op2.bit(31) && op1.bit(31) != result.bit(31);
             if !shift_carry {
@@ -515,15 +554,18 @@ impl ArmInstruction for Sbc {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("SBC r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::SBC, cond)
     }
 }
 
 impl ArmInstruction for Rsc {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op2, op1, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op2, op1, shift_carry| {
             let (mut result, mut borrow) = op1.overflowing_sub(op2);
             let mut overflow = sub_overflows(op1, op2, result);
             if !shift_carry {
@@ -546,16 +588,19 @@ impl ArmInstruction for Rsc {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("RSC r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::RSC, cond)
     }
 }
 
 impl ArmInstruction for Adc {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
         let c_flag = cpu.get_cpsr_bit(CPSR::C);
-        execute_op(cpu, instruction, false, |op1, op2, _| {
+        execute_op(cpu, &self.0, false, |op1, op2, _| {
             let (mut result, mut carry) = op1.overflowing_add(op2);
             let mut overflow = add_overflows(op1, op2, result);
             if c_flag == 1 {
@@ -578,15 +623,18 @@ impl ArmInstruction for Adc {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("ADC r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::ADC, cond)
     }
 }
 
 impl ArmInstruction for Tst {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, true, |op1, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, true, |op1, op2, shift_carry| {
             let result = op1 & op2;
             (
                 result,
@@ -600,15 +648,18 @@ impl ArmInstruction for Tst {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("TST r{}, {:?}", fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::TST, cond)
     }
 }
 
 impl ArmInstruction for Teq {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, true, |op1, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, true, |op1, op2, shift_carry| {
             let result = op1 ^ op2;
             (
                 result,
@@ -622,15 +673,18 @@ impl ArmInstruction for Teq {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("TEQ r{}, {:?}", fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::TEQ, cond)
     }
 }
 
 impl ArmInstruction for Cmp {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, true, |op1, op2, _| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, true, |op1, op2, _| {
             let (result, borrow) = op1.overflowing_sub(op2);
 
             (
@@ -645,15 +699,18 @@ impl ArmInstruction for Cmp {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("CMP r{}, {:?}", fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::CMP, cond)
     }
 }
 
 impl ArmInstruction for Cmn {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, true, |op1, op2, _| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, true, |op1, op2, _| {
             let (result, carry) = op1.overflowing_add(op2);
             (
                 result,
@@ -667,15 +724,18 @@ impl ArmInstruction for Cmn {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("CMN r{}, {:?}", fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::CMN, cond)
     }
 }
 
 impl ArmInstruction for Orr {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op1, op2, shift_carry| {
             let result = op1 | op2;
             let n = result.bit(31) == 1;
             let z = result == 0;
@@ -690,15 +750,18 @@ impl ArmInstruction for Orr {
             )
         })
     }
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("ORR r{}, r{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::ORR, cond)
     }
 }
 
 impl ArmInstruction for Mov {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |_, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |_, op2, shift_carry| {
             (
                 op2,
                 FlagUpdates {
@@ -711,15 +774,18 @@ impl ArmInstruction for Mov {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("MOV r{}, {:?}", fields.rd, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::MOV, cond)
     }
 }
 
 impl ArmInstruction for Bic {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |op1, op2, shift_carry| {
             let result = op1 & !op2;
             (
                 result,
@@ -733,15 +799,18 @@ impl ArmInstruction for Bic {
         });
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("BIC r{}, rd{}, {:?}", fields.rd, fields.rn, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::BIC, cond)
     }
 }
 
 impl ArmInstruction for Mvn {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |_, op2, shift_carry| {
+    fn parse(instruction: u32) -> Self {
+        Self(DataProcessingFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        execute_op(cpu, &self.0, false, |_, op2, shift_carry| {
             let result = !op2;
             (
                 result,
@@ -755,8 +824,7 @@ impl ArmInstruction for Mvn {
         })
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let fields = DataProcessingFields::parse(instruction);
-        format!("MVN r{}, {:?}", fields.rd, fields.shifter)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::MVN, cond)
     }
 }
@@ -1,25 +1,41 @@
-use crate::{bus::Bus, cpu::Cpu, utils::AddressableBits};
+use crate::{
+    bus::MemoryInterface,
+    cpu::ir::{Condition, Instr, Mnemonic},
+    cpu::Cpu,
+    utils::AddressableBits,
+};
 
 use super::{
-    single_data_transfer::{Address, AddressingMode},
-    ArmInstruction, MetaInstr, UnimplementedInstruction,
+    single_data_transfer::{Address, TransferFields},
+    ArmInstr, ArmInstruction, MetaInstr, UnimplementedInstruction,
 };
 
-struct LDRH;
-struct STRH;
-struct LDRSB;
-struct LDRSH;
+#[derive(Clone, Copy)]
+pub struct LDRH(TransferFields);
+#[derive(Clone, Copy)]
+pub struct STRH(TransferFields);
+#[derive(Clone, Copy)]
+pub struct LDRSB(TransferFields);
+#[derive(Clone, Copy)]
+pub struct LDRSH(TransferFields);
 
 impl ArmInstruction for LDRH {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
+    fn parse(instruction: u32) -> Self {
+        Self(TransferFields::parse_halfword(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let TransferFields {
+            rn,
+            rd,
+            addressing_mode,
+        } = self.0;
         let Address {
             address,
             write_back,
-        } = AddressingMode::decode_halfword(instruction).address(cpu);
+        } = addressing_mode.address(cpu);
 
-        let val = bus.read_half(address, cpu);
+        let val = cpu.read_half(bus, address);
         cpu.set_reg(rd, val);
 
         if let Some(address) = write_back {
@@ -29,74 +45,89 @@ impl ArmInstruction for LDRH {
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let rd = instruction.bits(12, 15);
-        let addressing_mode = AddressingMode::decode_halfword(instruction);
-        format!("LDRH r{}, {}", rd, addressing_mode)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::LDRH, cond)
     }
 }
 
 impl ArmInstruction for STRH {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
+    fn parse(instruction: u32) -> Self {
+        Self(TransferFields::parse_halfword(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let TransferFields {
+            rn,
+            rd,
+            addressing_mode,
+        } = self.0;
         let Address {
             address,
             write_back,
-        } = AddressingMode::decode_halfword(instruction).address(cpu);
+        } = addressing_mode.address(cpu);
 
-        bus.write_half(address, cpu.get_reg(rd) as u16);
+        cpu.write_half(bus, address, cpu.get_reg(rd) as u16);
 
         if let Some(address) = write_back {
             cpu.set_reg(rn, address);
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let rd = instruction.bits(12, 15);
-        let addressing_mode = AddressingMode::decode_halfword(instruction);
-        format!("STRH r{}, {}", rd, addressing_mode)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::STRH, cond)
     }
 }
 
 impl ArmInstruction for LDRSB {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
+    fn parse(instruction: u32) -> Self {
+        Self(TransferFields::parse_halfword(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let TransferFields {
+            rn,
+            rd,
+            addressing_mode,
+        } = self.0;
         let Address {
             address,
             write_back,
-        } = AddressingMode::decode_halfword(instruction).address(cpu);
+        } = addressing_mode.address(cpu);
 
-        let val = bus.read_byte(address, cpu);
+        let val = cpu.read_byte(bus, address);
         cpu.set_reg(rd, i32::from(val as i8) as u32);
 
         if let Some(address) = write_back {
             cpu.set_reg(rn, address);
         }
     }
-    fn disassembly(&self, instruction: u32) -> String {
-        let rd = instruction.bits(12, 15);
-        let addressing_mode = AddressingMode::decode_halfword(instruction);
-        format!("LDRSB r{}, {}", rd, addressing_mode)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::LDRSB, cond)
     }
 }
 
 impl ArmInstruction for LDRSH {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
+    fn parse(instruction: u32) -> Self {
+        Self(TransferFields::parse_halfword(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let TransferFields {
+            rn,
+            rd,
+            addressing_mode,
+        } = self.0;
         let Address {
             address,
             write_back,
-        } = AddressingMode::decode_halfword(instruction).address(cpu);
+        } = addressing_mode.address(cpu);
 
         // LDRSH has weird misaligned behaviour - it reads the value at the address as a byte and sign
         // extends to 32 bits
         let val = if address.bit(0) == 0 {
-            bus.read_signed_half(address, cpu)
+            cpu.read_signed_half(bus, address)
         } else {
-            i32::from(bus.read_byte(address, cpu) as i8) as u32
+            i32::from(cpu.read_byte(bus, address) as i8) as u32
         };
         cpu.set_reg(rd, val);
 
@@ -105,24 +136,22 @@ impl ArmInstruction for LDRSH {
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let rd = instruction.bits(12, 15);
-        let addressing_mode = AddressingMode::decode_halfword(instruction);
-        format!("LDRSH r{}, {}", rd, addressing_mode)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::LDRSH, cond)
     }
 }
 
 impl MetaInstr {
-    pub(super) fn decode_halfword_transfer(instruction: u32) -> Box<dyn ArmInstruction> {
+    pub(super) fn decode_halfword_transfer(instruction: u32) -> ArmInstr {
         let l = instruction.bit(20);
         let sh = instruction.bits(5, 6);
 
         match (l, sh) {
-            (0, 0b01) => Box::new(STRH),
-            (1, 0b01) => Box::new(LDRH),
-            (1, 0b10) => Box::new(LDRSB),
-            (1, 0b11) => Box::new(LDRSH),
-            _ => Box::new(UnimplementedInstruction),
+            (0, 0b01) => STRH::parse(instruction).into(),
+            (1, 0b01) => LDRH::parse(instruction).into(),
+            (1, 0b10) => LDRSB::parse(instruction).into(),
+            (1, 0b11) => LDRSH::parse(instruction).into(),
+            _ => UnimplementedInstruction::parse(instruction).into(),
         }
     }
 }
@@ -1,104 +1,165 @@
-use crate::{cpu::CPSR, utils::AddressableBits};
+use crate::{
+    cpu::ir::{Condition, Instr, Mnemonic, Operand},
+    cpu::CPSR,
+    utils::AddressableBits,
+};
+
+use super::{ArmInstr, ArmInstruction};
+
+/// The fields shared by all multiplies. Long multiplies use `rd` and `rn` as RdHi and RdLo.
+#[derive(Clone, Copy)]
+pub struct MultiplyFields {
+    set: bool,
+    rd: u32,
+    rn: u32,
+    rs: u32,
+    rm: u32,
+}
 
-use super::ArmInstruction;
+impl MultiplyFields {
+    fn parse(instruction: u32) -> Self {
+        Self {
+            set: instruction.bit(20) == 1,
+            rd: instruction.bits(16, 19),
+            rn: instruction.bits(12, 15),
+            rs: instruction.bits(8, 11),
+            rm: instruction.bits(0, 3),
+        }
+    }
 
-pub struct Mla;
-pub struct Mul;
-pub struct Umlal;
-pub struct Smlal;
-pub struct Umull;
-pub struct Smull;
+    /// MUL and MLA, which put the accumulator last.
+    fn decode_short(&self, mnemonic: Mnemonic, cond: Condition) -> Instr {
+        let mut operands = vec![
+            Operand::Reg(self.rd),
+            Operand::Reg(self.rm),
+            Operand::Reg(self.rs),
+        ];
+        if mnemonic == Mnemonic::MLA {
+            operands.push(Operand::Reg(self.rn));
+        }
+        Instr {
+            set_flags: self.set,
+            ..Instr::new(mnemonic, cond, operands)
+        }
+    }
 
-pub fn decode_multiply(instruction: u32) -> Box<dyn ArmInstruction> {
+    fn decode_long(&self, mnemonic: Mnemonic, cond: Condition) -> Instr {
+        let operands = vec![
+            Operand::Reg(self.rn),
+            Operand::Reg(self.rd),
+            Operand::Reg(self.rm),
+            Operand::Reg(self.rs),
+        ];
+        Instr {
+            set_flags: self.set,
+            ..Instr::new(mnemonic, cond, operands)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Mla(MultiplyFields);
+#[derive(Clone, Copy)]
+pub struct Mul(MultiplyFields);
+#[derive(Clone, Copy)]
+pub struct Umlal(MultiplyFields);
+#[derive(Clone, Copy)]
+pub struct Smlal(MultiplyFields);
+#[derive(Clone, Copy)]
+pub struct Umull(MultiplyFields);
+#[derive(Clone, Copy)]
+pub struct Smull(MultiplyFields);
+
+pub fn decode_multiply(instruction: u32) -> ArmInstr {
     if instruction.bit(21) == 1 {
-        Box::new(Mla)
+        Mla::parse(instruction).into()
     } else {
-        Box::new(Mul)
+        Mul::parse(instruction).into()
     }
 }
 
-pub fn decode_multiply_long(instruction: u32) -> Box<dyn ArmInstruction> {
+pub fn decode_multiply_long(instruction: u32) -> ArmInstr {
     // bits are UA
     match instruction.bits(21, 22) {
-        0b00 => Box::new(Umull),
-        0b01 => Box::new(Umlal),
-        0b10 => Box::new(Smull),
-        0b11 => Box::new(Smlal),
+        0b00 => Umull::parse(instruction).into(),
+        0b01 => Umlal::parse(instruction).into(),
+        0b10 => Smull::parse(instruction).into(),
+        0b11 => Smlal::parse(instruction).into(),
         _ => unreachable!(),
     }
 }
 
 impl ArmInstruction for Mla {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
-        let s = instruction.bit(20);
-        let rd = instruction.bits(16, 19);
-        let rn = cpu.get_reg(instruction.bits(12, 15));
-        let rs = cpu.get_reg(instruction.bits(8, 11));
-        let rm = cpu.get_reg(instruction.bits(0, 3));
-
-        let result = rm * rs + rn;
+    fn parse(instruction: u32) -> Self {
+        Self(MultiplyFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let MultiplyFields {
+            set,
+            rd,
+            rn,
+            rs,
+            rm,
+        } = self.0;
+        let rn = cpu.get_reg(rn);
+        let rs = cpu.get_reg(rs);
+        let rm = cpu.get_reg(rm);
+
+        let result = rm.wrapping_mul(rs).wrapping_add(rn);
         cpu.set_reg(rd, result);
-        if s == 1 {
+        if set {
             cpu.set_flag(CPSR::N, result.bit(31) == 1);
             cpu.set_flag(CPSR::Z, result == 0);
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let s = instruction.bit(20);
-        let rd = instruction.bits(16, 19);
-        let rn = instruction.bits(12, 15);
-        let rs = instruction.bits(8, 11);
-        let rm = instruction.bits(0, 3);
-        format!(
-            "MLA{} r{}, r{}, r{}, r{}",
-            if s == 1 { "S" } else { "" },
-            rd,
-            rm,
-            rs,
-            rn
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode_short(Mnemonic::MLA, cond)
     }
 }
 
 impl ArmInstruction for Mul {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
-        let s = instruction.bit(20);
-        let rd = instruction.bits(16, 19);
-        let rs = cpu.get_reg(instruction.bits(8, 11));
-        let rm = cpu.get_reg(instruction.bits(0, 3));
+    fn parse(instruction: u32) -> Self {
+        Self(MultiplyFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let MultiplyFields {
+            set, rd, rs, rm, ..
+        } = self.0;
+        let rs = cpu.get_reg(rs);
+        let rm = cpu.get_reg(rm);
 
         let result = rm.wrapping_mul(rs);
         cpu.set_reg(rd, result);
 
-        if s == 1 {
+        if set {
             cpu.set_flag(CPSR::N, result.bit(31) == 1);
             cpu.set_flag(CPSR::Z, result == 0);
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let s = instruction.bit(20);
-        let rd = instruction.bits(16, 19);
-        let rs = instruction.bits(8, 11);
-        let rm = instruction.bits(0, 3);
-        format!(
-            "MUL{} r{}, r{}, r{}",
-            if s == 1 { "S" } else { "" },
-            rd,
-            rm,
-            rs
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode_short(Mnemonic::MUL, cond)
     }
 }
 
 impl ArmInstruction for Umlal {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
-        let s = instruction.bit(20);
-        let rd_hi = instruction.bits(16, 19);
-        let rd_lo = instruction.bits(12, 15);
-        let rs = cpu.get_reg(instruction.bits(8, 11));
-        let rm = cpu.get_reg(instruction.bits(0, 3));
+    fn parse(instruction: u32) -> Self {
+        Self(MultiplyFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let MultiplyFields {
+            set,
+            rd: rd_hi,
+            rn: rd_lo,
+            rs,
+            rm,
+        } = self.0;
+        let rs = cpu.get_reg(rs);
+        let rm = cpu.get_reg(rm);
 
         let wide_result: u64 = (rs as u64) * (rm as u64);
         let wide_result_lo: u32 = wide_result.bits(0, 31).try_into().unwrap();
@@ -113,37 +174,32 @@ impl ArmInstruction for Umlal {
         cpu.set_reg(rd_lo, low);
         cpu.set_reg(rd_hi, high);
 
-        if s == 1 {
+        if set {
             cpu.set_flag(CPSR::N, high.bit(31) == 1);
             cpu.set_flag(CPSR::Z, low == 0 && high == 0);
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let s = instruction.bit(20);
-        let rd_hi = instruction.bits(16, 19);
-        let rd_lo = instruction.bits(12, 15);
-        let rs = instruction.bits(8, 11);
-        let rm = instruction.bits(0, 3);
-
-        format!(
-            "UMLAL{} r{}, r{}, r{}, r{}",
-            if s == 1 { "S" } else { "" },
-            rd_lo,
-            rd_hi,
-            rm,
-            rs
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode_long(Mnemonic::UMLAL, cond)
     }
 }
 
 impl ArmInstruction for Smlal {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
-        let s = instruction.bit(20);
-        let rd_hi = instruction.bits(16, 19);
-        let rd_lo = instruction.bits(12, 15);
-        let rs = cpu.get_reg(instruction.bits(8, 11)) as i32;
-        let rm = cpu.get_reg(instruction.bits(0, 3)) as i32;
+    fn parse(instruction: u32) -> Self {
+        Self(MultiplyFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let MultiplyFields {
+            set,
+            rd: rd_hi,
+            rn: rd_lo,
+            rs,
+            rm,
+        } = self.0;
+        let rs = cpu.get_reg(rs) as i32;
+        let rm = cpu.get_reg(rm) as i32;
 
         let wide_result: u64 = (i64::from(rs) * i64::from(rm)) as u64;
         let wide_result_lo: u32 = wide_result.bits(0, 31).try_into().unwrap();
@@ -158,37 +214,32 @@ impl ArmInstruction for Smlal {
         cpu.set_reg(rd_lo, low);
         cpu.set_reg(rd_hi, high);
 
-        if s == 1 {
+        if set {
             cpu.set_flag(CPSR::N, high.bit(31) == 1);
             cpu.set_flag(CPSR::Z, low == 0 && high == 0);
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let s = instruction.bit(20);
-        let rd_hi = instruction.bits(16, 19);
-        let rd_lo = instruction.bits(12, 15);
-        let rs = instruction.bits(8, 11);
-        let rm = instruction.bits(0, 3);
-
-        format!(
-            "SMLAL{} r{}, r{}, r{}, r{}",
-            if s == 1 { "S" } else { "" },
-            rd_lo,
-            rd_hi,
-            rm,
-            rs
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode_long(Mnemonic::SMLAL, cond)
     }
 }
 
 impl ArmInstruction for Umull {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
-        let s = instruction.bit(20);
-        let rd_hi = instruction.bits(16, 19);
-        let rd_lo = instruction.bits(12, 15);
-        let rs = cpu.get_reg(instruction.bits(8, 11));
-        let rm = cpu.get_reg(instruction.bits(0, 3));
+    fn parse(instruction: u32) -> Self {
+        Self(MultiplyFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let MultiplyFields {
+            set,
+            rd: rd_hi,
+            rn: rd_lo,
+            rs,
+            rm,
+        } = self.0;
+        let rs = cpu.get_reg(rs);
+        let rm = cpu.get_reg(rm);
 
         let wide_result: u64 = (rs as u64) * (rm as u64);
         let low: u32 = wide_result.bits(0, 31).try_into().unwrap();
@@ -197,37 +248,32 @@ impl ArmInstruction for Umull {
         cpu.set_reg(rd_lo, low);
         cpu.set_reg(rd_hi, high);
 
-        if s == 1 {
+        if set {
             cpu.set_flag(CPSR::N, high.bit(31) == 1);
             cpu.set_flag(CPSR::Z, low == 0 && high == 0);
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let s = instruction.bit(20);
-        let rd_hi = instruction.bits(16, 19);
-        let rd_lo = instruction.bits(12, 15);
-        let rs = instruction.bits(8, 11);
-        let rm = instruction.bits(0, 3);
-
-        format!(
-            "UMULL{} r{}, r{}, r{}, r{}",
-            if s == 1 { "S" } else { "" },
-            rd_lo,
-            rd_hi,
-            rm,
-            rs
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode_long(Mnemonic::UMULL, cond)
     }
 }
 
 impl ArmInstruction for Smull {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
-        let s = instruction.bit(20);
-        let rd_hi = instruction.bits(16, 19);
-        let rd_lo = instruction.bits(12, 15);
-        let rs = cpu.get_reg(instruction.bits(8, 11)) as i32;
-        let rm = cpu.get_reg(instruction.bits(0, 3)) as i32;
+    fn parse(instruction: u32) -> Self {
+        Self(MultiplyFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let MultiplyFields {
+            set,
+            rd: rd_hi,
+            rn: rd_lo,
+            rs,
+            rm,
+        } = self.0;
+        let rs = cpu.get_reg(rs) as i32;
+        let rm = cpu.get_reg(rm) as i32;
 
         let wide_result: u64 = (i64::from(rs) * i64::from(rm)) as u64;
         let low: u32 = wide_result.bits(0, 31).try_into().unwrap();
@@ -236,26 +282,13 @@ impl ArmInstruction for Smull {
         cpu.set_reg(rd_lo, low);
         cpu.set_reg(rd_hi, high);
 
-        if s == 1 {
+        if set {
             cpu.set_flag(CPSR::N, high.bit(31) == 1);
             cpu.set_flag(CPSR::Z, low == 0 && high == 0);
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let s = instruction.bit(20);
-        let rd_hi = instruction.bits(16, 19);
-        let rd_lo = instruction.bits(12, 15);
-        let rs = instruction.bits(8, 11);
-        let rm = instruction.bits(0, 3);
-
-        format!(
-            "SMULL{} r{}, r{}, r{}, r{}",
-            if s == 1 { "S" } else { "" },
-            rd_lo,
-            rd_hi,
-            rm,
-            rs
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode_long(Mnemonic::SMULL, cond)
     }
 }
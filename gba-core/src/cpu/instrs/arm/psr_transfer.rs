@@ -1,70 +1,96 @@
-use crate::{bus::Bus, cpu::Cpu, utils::AddressableBits};
+use crate::{
+    bus::MemoryInterface,
+    cpu::ir::{Condition, Instr, Mnemonic, Operand, Psr},
+    cpu::Cpu,
+    utils::AddressableBits,
+};
 
-use super::{ArmInstruction, MetaInstr};
+use super::{ArmInstr, ArmInstruction, MetaInstr};
 
-struct MsrFields {
-    r: bool,
-    field_mask: u32,
-    operand: u32,
+/// MSR's source, which is read when it executes.
+#[derive(Clone, Copy)]
+enum MsrOperand {
+    Imm(u32),
+    Reg(u32),
 }
 
-impl MsrFields {
-    fn parse(instruction: u32, cpu: &Cpu) -> Self {
-        let operand;
-
-        if instruction.bit(25) == 1 {
-            operand = if instruction.bit(25) == 1 {
-                let rotate_imm = instruction.bits(8, 11);
-                let imm = instruction.bits(0, 7);
-                imm.rotate_right(2 * rotate_imm)
-            } else {
-                let rm = instruction.bits(0, 3);
-                cpu.get_reg(rm)
-            };
-        } else {
-            let rm = instruction.bits(0, 3);
-            operand = cpu.get_reg(rm);
+impl MsrOperand {
+    fn value(&self, cpu: &Cpu) -> u32 {
+        match *self {
+            Self::Imm(imm) => imm,
+            Self::Reg(rm) => cpu.get_reg(rm),
         }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MRS {
+    r: bool,
+    rd: u32,
+}
 
+#[derive(Clone, Copy)]
+pub struct MSR {
+    r: bool,
+    field_mask: u32,
+    operand: MsrOperand,
+}
+
+impl ArmInstruction for MRS {
+    fn parse(instruction: u32) -> Self {
         Self {
             r: instruction.bit(22) == 1,
-            field_mask: instruction.bits(16, 19),
-            operand,
+            rd: instruction.bits(12, 15),
         }
     }
-}
 
-struct MRS;
-struct MSR;
-
-impl ArmInstruction for MRS {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        let r = instruction.bit(22);
-        let rd = instruction.bits(12, 15);
-        if r == 1 {
-            cpu.set_reg(rd, cpu.regs.spsr(&cpu.get_mode()));
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        if self.r {
+            cpu.set_reg(self.rd, cpu.regs.spsr(&cpu.get_mode()));
         } else {
-            cpu.set_reg(rd, cpu.regs.cpsr);
+            cpu.set_reg(self.rd, cpu.regs.cpsr);
         }
     }
-    fn disassembly(&self, instruction: u32) -> String {
-        let r = instruction.bit(22);
-        let rd = instruction.bits(12, 15);
-        format!("MRS r{}, {}", rd, if r == 1 { "SPSR" } else { "CPSR" })
+    fn decode(&self, cond: Condition) -> Instr {
+        let psr = Psr {
+            spsr: self.r,
+            fields: 0,
+        };
+        Instr::new(
+            Mnemonic::MRS,
+            cond,
+            vec![Operand::Reg(self.rd), Operand::Psr(psr)],
+        )
     }
 }
 
 impl ArmInstruction for MSR {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
+    fn parse(instruction: u32) -> Self {
+        let operand = if instruction.bit(25) == 1 {
+            let rotate_imm = instruction.bits(8, 11);
+            let imm = instruction.bits(0, 7);
+            MsrOperand::Imm(imm.rotate_right(2 * rotate_imm))
+        } else {
+            MsrOperand::Reg(instruction.bits(0, 3))
+        };
+
+        Self {
+            r: instruction.bit(22) == 1,
+            field_mask: instruction.bits(16, 19),
+            operand,
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
         let unalloc_mask = 0x0fffff00;
         let user_mask = 0xf0000000;
         let priv_mask = 0x0000000f;
         let state_mask = 0x00000020;
 
-        let fields = MsrFields::parse(instruction, cpu);
-        let field_mask = fields.field_mask;
+        let operand = self.operand.value(cpu);
+        let field_mask = self.field_mask;
 
-        if fields.operand & unalloc_mask != 0 {
+        if operand & unalloc_mask != 0 {
             //todo!("unpredictable")
         }
 
@@ -77,11 +103,11 @@ impl ArmInstruction for MSR {
                 0
             });
 
-        if !fields.r {
+        if !self.r {
             let mask;
             println!("{:?}", cpu.get_mode());
             if cpu.in_privileged_mode() {
-                if (fields.operand & state_mask) != 0 {
+                if (operand & state_mask) != 0 {
                     todo!("unpredictable");
                 } else {
                     mask = byte_mask & (user_mask | priv_mask);
@@ -89,57 +115,37 @@ impl ArmInstruction for MSR {
             } else {
                 mask = byte_mask & user_mask;
             }
-            cpu.regs.cpsr = (cpu.regs.cpsr & !mask) | (fields.operand & mask) | 0x10;
+            cpu.regs.cpsr = (cpu.regs.cpsr & !mask) | (operand & mask) | 0x10;
         } else {
             if cpu.mode_has_spsr() {
                 let mask = byte_mask & (user_mask | priv_mask | state_mask);
                 *cpu.regs.spsr_mut(&cpu.get_mode()) =
-                    (cpu.regs.spsr(&cpu.get_mode()) & !mask) | (fields.operand & mask) | 0x10;
+                    (cpu.regs.spsr(&cpu.get_mode()) & !mask) | (operand & mask) | 0x10;
             } else {
                 // Writes do nothing
             }
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let r = instruction.bit(22);
-        let field_mask = instruction.bits(16, 19);
-        let fields = ["c", "x", "s", "f"]
-            .into_iter()
-            .enumerate()
-            .filter_map(|(i, f)| {
-                if field_mask.bit(i) == 1 {
-                    Some(f.to_string())
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<String>>()
-            .join("");
-
-        let operand = if instruction.bit(25) == 1 {
-            let rotate_imm: u32 = instruction.bits(8, 11);
-            let imm: u32 = instruction.bits(0, 7);
-            format!("#{:x}", imm.rotate_right(2 * rotate_imm))
-        } else {
-            let rm = instruction.bits(0, 3);
-            format!("r{}", rm)
+    fn decode(&self, cond: Condition) -> Instr {
+        let psr = Psr {
+            spsr: self.r,
+            fields: self.field_mask,
         };
-        format!(
-            "MSR {}_{}, {}",
-            if r == 1 { "SPSR" } else { "CPSR" },
-            fields,
-            operand
-        )
+        let operand = match self.operand {
+            MsrOperand::Imm(imm) => Operand::Imm(imm),
+            MsrOperand::Reg(rm) => Operand::Reg(rm),
+        };
+        Instr::new(Mnemonic::MSR, cond, vec![Operand::Psr(psr), operand])
     }
 }
 
 impl MetaInstr {
-    pub(super) fn decode_psr_transfer(instruction: u32) -> Box<dyn ArmInstruction> {
+    pub(super) fn decode_psr_transfer(instruction: u32) -> ArmInstr {
         if instruction.bit(21) == 0 {
-            Box::new(MRS)
+            MRS::parse(instruction).into()
         } else {
-            Box::new(MSR)
+            MSR::parse(instruction).into()
         }
     }
 }
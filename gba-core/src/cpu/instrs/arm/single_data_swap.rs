@@ -1,56 +1,85 @@
+use crate::cpu::ir::{Condition, Instr, MemoryOffset, Mnemonic, Operand};
 use crate::utils::AddressableBits;
 
-use super::ArmInstruction;
+use super::{ArmInstr, ArmInstruction};
 
-pub struct Swp;
-pub struct Swpb;
+#[derive(Clone, Copy)]
+pub struct SwapFields {
+    rn: u32,
+    rd: u32,
+    rm: u32,
+}
+
+impl SwapFields {
+    fn parse(instruction: u32) -> Self {
+        Self {
+            rn: instruction.bits(16, 19),
+            rd: instruction.bits(12, 15),
+            rm: instruction.bits(0, 3),
+        }
+    }
+
+    fn decode(&self, mnemonic: Mnemonic, cond: Condition) -> Instr {
+        let operands = vec![
+            Operand::Reg(self.rd),
+            Operand::Reg(self.rm),
+            Operand::Memory {
+                rn: self.rn,
+                offset: MemoryOffset::Imm(0),
+                subtract: false,
+            },
+        ];
+        Instr::new(mnemonic, cond, operands)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Swp(SwapFields);
+#[derive(Clone, Copy)]
+pub struct Swpb(SwapFields);
 
 impl ArmInstruction for Swp {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u32) {
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
-        let rm = instruction.bits(0, 3);
+    fn parse(instruction: u32) -> Self {
+        Self(SwapFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let SwapFields { rn, rd, rm } = self.0;
 
         let address = cpu.get_reg(rn);
-        let temp = bus.read(address, cpu);
-        bus.write(address, cpu.get_reg(rm));
+        let temp = cpu.read(bus, address);
+        cpu.write(bus, address, cpu.get_reg(rm));
         cpu.set_reg(rd, temp);
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
-        let rm = instruction.bits(0, 3);
-
-        format!("SWP r{}, r{}, [r{}]", rd, rm, rn)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::SWP, cond)
     }
 }
 
 impl ArmInstruction for Swpb {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u32) {
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
-        let rm = instruction.bits(0, 3);
+    fn parse(instruction: u32) -> Self {
+        Self(SwapFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let SwapFields { rn, rd, rm } = self.0;
 
         let address = cpu.get_reg(rn);
-        let temp = bus.read_byte(address, cpu);
-        bus.write_byte(address, cpu.get_reg(rm) as u8);
+        let temp = cpu.read_byte(bus, address);
+        cpu.write_byte(bus, address, cpu.get_reg(rm) as u8);
         cpu.set_reg(rd, temp.into());
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
-        let rm = instruction.bits(0, 3);
-
-        format!("SWPB r{}, r{}, [r{}]", rd, rm, rn)
+    fn decode(&self, cond: Condition) -> Instr {
+        self.0.decode(Mnemonic::SWPB, cond)
     }
 }
 
-pub fn decode_swap(instruction: u32) -> Box<dyn ArmInstruction> {
+pub fn decode_swap(instruction: u32) -> ArmInstr {
     if instruction.bit(22) == 0 {
-        Box::new(Swp)
+        Swp::parse(instruction).into()
     } else {
-        Box::new(Swpb)
+        Swpb::parse(instruction).into()
     }
 }
@@ -1,15 +1,29 @@
+use crate::cpu::ir::{self, Condition, Instr, MemoryOffset, Mnemonic, Operand, Shift};
 use crate::cpu::CPSR;
 use crate::utils::AddressableBits;
-use crate::Bus;
+use crate::bus::MemoryInterface;
 use crate::Cpu;
 
-use super::ArmInstruction;
 use super::MetaInstr;
+use super::{ArmInstr, ArmInstruction};
+
+#[derive(Clone, Copy)]
+pub struct STR {
+    byte: bool,
+    /// Post-indexed with W set, which uses user mode translation.
+    translate: bool,
+    transfer: TransferFields,
+}
 
-struct STR;
-struct LDR;
+#[derive(Clone, Copy)]
+pub struct LDR {
+    byte: bool,
+    /// Post-indexed with W set, which uses user mode translation.
+    translate: bool,
+    transfer: TransferFields,
+}
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum AddressingModeSource {
     Immediate {
         rn: u32,
@@ -95,13 +109,14 @@ impl AddressingModeSource {
     }
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum AddressingModeIndexing {
     Offset,
     PreIndexed,
     PostIndexed,
 }
 
+#[derive(Clone, Copy)]
 pub struct AddressingMode {
     source: AddressingModeSource,
     indexing: AddressingModeIndexing,
@@ -241,83 +256,110 @@ impl AddressingMode {
     }
 }
 
-impl std::fmt::Display for AddressingMode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let sign = if self.source.u() { "+" } else { "-" };
-
-        let filler = match self.source {
-            AddressingModeSource::Immediate {
-                rn: _,
-                u: _,
-                offset,
-            } => {
-                format!("#{}{:x}", sign, offset)
-            }
-            AddressingModeSource::Register {
-                rn: _,
-                u: _,
-                rm,
-                shift,
-                shift_imm,
-            } if shift == 0 && shift_imm == 0 => {
-                format!("{}r{}", sign, rm)
-            }
-            AddressingModeSource::Register {
-                rn: _,
-                u: _,
-                rm,
-                shift,
-                shift_imm,
-            } if shift == 0b11 && shift_imm == 0 => {
-                format!("{}r{}, RRX", sign, rm)
-            }
+impl AddressingMode {
+    /// Returns the memory operand, and the addressing mode and writeback to decode it with.
+    pub fn decode_operand(&self) -> (Operand, ir::AddressingMode, bool) {
+        let offset = match self.source {
+            AddressingModeSource::Immediate { offset, .. } => MemoryOffset::Imm(offset),
             AddressingModeSource::Register {
-                rn: _,
-                u: _,
                 rm,
                 shift,
                 shift_imm,
-            } => {
-                let shift = match shift {
-                    0b00 => "LSL",
-                    0b01 => "LSR",
-                    0b10 => "ASR",
-                    0b11 => "ROR",
-                    _ => unreachable!(),
-                };
-                format!("{}r{}, {}, #{}", sign, rm, shift, shift_imm)
-            }
+                ..
+            } => match (Shift::from_bits(shift), shift_imm) {
+                (Shift::LSL, 0) => MemoryOffset::Reg(rm),
+                (Shift::ROR, 0) => MemoryOffset::ShiftedReg {
+                    rm,
+                    shift: Shift::RRX,
+                    amount: 0,
+                },
+                // LSR #0 and ASR #0 encode shifts by 32
+                (shift, 0) => MemoryOffset::ShiftedReg {
+                    rm,
+                    shift,
+                    amount: 32,
+                },
+                (shift, amount) => MemoryOffset::ShiftedReg { rm, shift, amount },
+            },
+        };
+        let operand = Operand::Memory {
+            rn: self.source.rn(),
+            offset,
+            subtract: !self.source.u(),
         };
 
         match self.indexing {
-            AddressingModeIndexing::Offset => write!(f, "[r{}, {}]", self.source.rn(), filler),
-            AddressingModeIndexing::PreIndexed => write!(f, "[r{}, {}]!", self.source.rn(), filler),
-            AddressingModeIndexing::PostIndexed => write!(f, "[r{}], {}", self.source.rn(), filler),
+            AddressingModeIndexing::Offset => (operand, ir::AddressingMode::Offset, false),
+            AddressingModeIndexing::PreIndexed => (operand, ir::AddressingMode::PreIndexed, true),
+            AddressingModeIndexing::PostIndexed => (operand, ir::AddressingMode::PostIndexed, true),
+        }
+    }
+}
+
+/// The fields of a load or store of `rd`, for both word/byte and halfword transfers.
+#[derive(Clone, Copy)]
+pub struct TransferFields {
+    pub rn: u32,
+    pub rd: u32,
+    pub addressing_mode: AddressingMode,
+}
+
+impl TransferFields {
+    pub fn parse(instruction: u32) -> Self {
+        Self {
+            rn: instruction.bits(16, 19),
+            rd: instruction.bits(12, 15),
+            addressing_mode: AddressingMode::decode(instruction),
+        }
+    }
+
+    pub fn parse_halfword(instruction: u32) -> Self {
+        Self {
+            addressing_mode: AddressingMode::decode_halfword(instruction),
+            ..Self::parse(instruction)
+        }
+    }
+
+    pub fn decode(&self, mnemonic: Mnemonic, cond: Condition) -> Instr {
+        let (operand, addressing, writeback) = self.addressing_mode.decode_operand();
+        Instr {
+            writeback,
+            addressing: Some(addressing),
+            ..Instr::new(mnemonic, cond, vec![Operand::Reg(self.rd), operand])
         }
     }
 }
 
 impl ArmInstruction for STR {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
-        let b = instruction.bit(22);
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
+    fn parse(instruction: u32) -> Self {
+        Self {
+            byte: instruction.bit(22) == 1,
+            translate: instruction.bit(24) == 0 && instruction.bit(21) == 1,
+            transfer: TransferFields::parse(instruction),
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let TransferFields {
+            rn,
+            rd,
+            addressing_mode,
+        } = self.transfer;
         let rd = if rd == 15 {
             cpu.get_reg(rd) + 4
         } else {
             cpu.get_reg(rd)
         };
-        let addressing_mode = AddressingMode::decode(instruction);
 
         let Address {
             address,
             write_back,
         } = addressing_mode.address(cpu);
 
-        if b == 0 {
-            bus.write(address, rd);
+        if !self.byte {
+            cpu.write(bus, address, rd);
         } else {
-            bus.write_byte(address, rd as u8);
+            cpu.write_byte(bus, address, rd as u8);
         }
 
         if let Some(address) = write_back {
@@ -325,35 +367,42 @@ impl ArmInstruction for STR {
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let b = instruction.bit(22);
-        let rd = instruction.bits(12, 15);
-        let addressing_mode = AddressingMode::decode(instruction);
-        format!(
-            "STR{} r{} {}",
-            if b == 1 { "B" } else { "" },
-            rd,
-            addressing_mode
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        let mnemonic = match (self.byte, self.translate) {
+            (false, true) => Mnemonic::STRT,
+            (true, true) => Mnemonic::STRBT,
+            (false, false) => Mnemonic::STR,
+            (true, false) => Mnemonic::STRB,
+        };
+        self.transfer.decode(mnemonic, cond)
     }
 }
 
 impl ArmInstruction for LDR {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
-        let b = instruction.bit(22);
-        let rn = instruction.bits(16, 19);
-        let rd = instruction.bits(12, 15);
-        let addressing_mode = AddressingMode::decode(instruction);
+    fn parse(instruction: u32) -> Self {
+        Self {
+            byte: instruction.bit(22) == 1,
+            translate: instruction.bit(24) == 0 && instruction.bit(21) == 1,
+            transfer: TransferFields::parse(instruction),
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let TransferFields {
+            rn,
+            rd,
+            addressing_mode,
+        } = self.transfer;
 
         let Address {
             address,
             write_back,
         } = addressing_mode.address(cpu);
 
-        let val = if b == 0 {
-            bus.read(address, cpu)
+        let val = if !self.byte {
+            cpu.read(bus, address)
         } else {
-            bus.read_byte(address, cpu) as u32
+            cpu.read_byte(bus, address) as u32
         };
 
         cpu.set_reg(rd, val);
@@ -369,27 +418,25 @@ impl ArmInstruction for LDR {
         }
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let b = instruction.bit(22);
-        let rd = instruction.bits(12, 15);
-        let addressing_mode = AddressingMode::decode(instruction);
-        format!(
-            "LDR{} r{} {}",
-            if b == 1 { "B" } else { "" },
-            rd,
-            addressing_mode
-        )
+    fn decode(&self, cond: Condition) -> Instr {
+        let mnemonic = match (self.byte, self.translate) {
+            (false, true) => Mnemonic::LDRT,
+            (true, true) => Mnemonic::LDRBT,
+            (false, false) => Mnemonic::LDR,
+            (true, false) => Mnemonic::LDRB,
+        };
+        self.transfer.decode(mnemonic, cond)
     }
 }
 
 impl MetaInstr {
-    pub(super) fn decode_single_data_transfer(instruction: u32) -> Box<dyn ArmInstruction> {
+    pub(super) fn decode_single_data_transfer(instruction: u32) -> ArmInstr {
         let l = instruction.bit(20);
 
         if l == 1 {
-            Box::new(LDR)
+            LDR::parse(instruction).into()
         } else {
-            Box::new(STR)
+            STR::parse(instruction).into()
         }
     }
 }
@@ -1,13 +1,32 @@
 use super::ArmInstruction;
-use crate::bus::Bus;
-use crate::cpu::{Cpu, Mode};
+use crate::bus::MemoryInterface;
+use crate::cpu::ir::{Condition, Instr, Mnemonic, Operand};
+use crate::cpu::{Cpu, Exception, Frame, Mode};
 use crate::utils::AddressableBits;
 
-pub struct Swi;
+#[derive(Clone, Copy)]
+pub struct Swi {
+    comment: u32,
+}
 
 impl ArmInstruction for Swi {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, _: u32) {
-        cpu.set_reg_with_mode(14, Mode::Supervisor, cpu.get_reg(15) - 4);
+    fn parse(instruction: u32) -> Self {
+        Self {
+            comment: instruction.bits(0, 23),
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        // The BIOS reads the function number from the top of the comment field
+        cpu.exception = Some(Exception::Swi(self.comment.bits(16, 23)));
+        let return_address = cpu.get_reg(15) - 4;
+        cpu.call_stack.push(Frame {
+            function: 0x8,
+            call_site: return_address - 4,
+            return_address,
+            exception: cpu.exception,
+        });
+        cpu.set_reg_with_mode(14, Mode::Supervisor, return_address);
         *cpu.regs.spsr_mut(&Mode::Supervisor) = cpu.regs.cpsr;
         cpu.regs.cpsr = cpu.regs.cpsr.bits(6, 31) | 0b010011;
         cpu.regs.cpsr.mut_bit(7, true);
@@ -15,8 +34,7 @@ impl ArmInstruction for Swi {
         cpu.flush_pipeline();
     }
 
-    fn disassembly(&self, instruction: u32) -> String {
-        let imm = instruction.bits(0, 23);
-        format!("SWI {:x}", imm)
+    fn decode(&self, cond: Condition) -> Instr {
+        Instr::new(Mnemonic::SWI, cond, vec![Operand::Imm(self.comment)])
     }
 }
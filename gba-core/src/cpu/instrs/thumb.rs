@@ -17,17 +17,137 @@ mod push_pop_regs;
 mod sp_relative_ls;
 mod swi;
 
-use crate::{bus::Bus, cpu::Cpu};
+use crate::{
+    bus::MemoryInterface,
+    cpu::ir::{Condition, Instr, MemoryOffset, Mnemonic, Operand},
+    cpu::Cpu,
+};
 
 pub trait ThumbInstruction {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16);
-    fn disassembly(&self, instruction: u16) -> String;
+    /// Pulls the instruction's fields out of its opcode. This happens once, when the opcode is
+    /// decoded, so executing it again from the block cache doesn't parse it again.
+    fn parse(instruction: u16) -> Self;
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface);
+    /// Builds the IR for the instruction from its parsed fields.
+    fn decode(&self) -> Instr;
 }
 
-impl std::fmt::Debug for dyn ThumbInstruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "ThumbInstruction")
-    }
+/// Generates the `ThumbInstr` enum, which dispatches statically to each instruction's
+/// `ThumbInstruction` implementation.
+macro_rules! thumb_instr_enum {
+    ($($Variant:ident($InstrT:ty)),* $(,)?) => {
+        /// A THUMB instruction, picked by bits 4-15 of the opcode, with its fields parsed.
+        #[derive(Clone, Copy)]
+        pub enum ThumbInstr {
+            $($Variant($InstrT),)*
+        }
+
+        $(
+            impl From<$InstrT> for ThumbInstr {
+                fn from(instr: $InstrT) -> Self {
+                    Self::$Variant(instr)
+                }
+            }
+        )*
+
+        impl ThumbInstr {
+            /// Returns the parser for opcodes of the same kind as this one.
+            pub fn parser(&self) -> fn(u16) -> ThumbInstr {
+                match self {
+                    $(Self::$Variant(_) => |instruction| Self::$Variant(<$InstrT>::parse(instruction)),)*
+                }
+            }
+
+            #[inline]
+            pub fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+                match self {
+                    $(Self::$Variant(instr) => instr.execute(cpu, bus),)*
+                }
+            }
+
+            pub fn decode(&self) -> Instr {
+                match self {
+                    $(Self::$Variant(instr) => instr.decode(),)*
+                }
+            }
+
+            pub fn disassembly(&self) -> String {
+                self.decode().to_string()
+            }
+        }
+
+        impl std::fmt::Debug for ThumbInstr {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Self::$Variant(_) => write!(f, stringify!($Variant)),)*
+                }
+            }
+        }
+    };
+}
+
+thumb_instr_enum! {
+    LslImm(move_shifted_register::LSL),
+    LsrImm(move_shifted_register::LSR),
+    AsrImm(move_shifted_register::ASR),
+    AddReg(add_subtract::AddReg),
+    SubReg(add_subtract::SubReg),
+    AddImm3(add_subtract::AddImm),
+    SubImm3(add_subtract::SubImm),
+    MovImm8(mov_cmp_add_sub_immediate::Mov),
+    CmpImm8(mov_cmp_add_sub_immediate::Cmp),
+    AddImm8(mov_cmp_add_sub_immediate::Add),
+    SubImm8(mov_cmp_add_sub_immediate::Sub),
+    And(alu_operations::AND),
+    Eor(alu_operations::EOR),
+    Lsl(alu_operations::LSL),
+    Lsr(alu_operations::LSR),
+    Asr(alu_operations::ASR),
+    Adc(alu_operations::ADC),
+    Sbc(alu_operations::SBC),
+    Ror(alu_operations::ROR),
+    Tst(alu_operations::TST),
+    Neg(alu_operations::NEG),
+    Cmp(alu_operations::CMP),
+    Cmn(alu_operations::CMN),
+    Orr(alu_operations::ORR),
+    Mul(alu_operations::MUL),
+    Bic(alu_operations::BIC),
+    Mvn(alu_operations::MVN),
+    AddHi(high_reg_ops_or_bx::ADD),
+    CmpHi(high_reg_ops_or_bx::CMP),
+    MovHi(high_reg_ops_or_bx::MOV),
+    Bx(high_reg_ops_or_bx::BX),
+    LdrPc(pc_relative_load::LdrPc),
+    StrReg(load_store_register_offset::Str),
+    StrbReg(load_store_register_offset::Strb),
+    LdrReg(load_store_register_offset::Ldr),
+    LdrbReg(load_store_register_offset::Ldrb),
+    StrhReg(load_store_sign_extended::Strh),
+    LdrhReg(load_store_sign_extended::Ldrh),
+    LdrsbReg(load_store_sign_extended::Ldrsb),
+    LdrshReg(load_store_sign_extended::Ldrsh),
+    StrImm(load_store_immediate_offset::Str),
+    LdrImm(load_store_immediate_offset::Ldr),
+    StrbImm(load_store_immediate_offset::Strb),
+    LdrbImm(load_store_immediate_offset::Ldrb),
+    StrhImm(load_store_halfword::Strh),
+    LdrhImm(load_store_halfword::Ldrh),
+    StrSp(sp_relative_ls::Str),
+    LdrSp(sp_relative_ls::Ldr),
+    AddPc(load_address::AddPc),
+    AddSp(load_address::AddSp),
+    AddOffsetSp(add_offset_to_stack_pointer::Add),
+    Push(push_pop_regs::Push),
+    Pop(push_pop_regs::Pop),
+    Stmia(multiple_load_store::Stmia),
+    Ldmia(multiple_load_store::Ldmia),
+    ConditionalBranch(branch::ConditionalBranch),
+    Swi(swi::Swi),
+    Branch(branch::Branch),
+    LongBranchWithLinkFirst(branch::LongBranchWithLinkFirst),
+    LongBranchWithLinkSecond(branch::LongBranchWithLinkSecond),
+    Invalid(invalid::Invalid),
 }
 
 fn format_mask(instruction: u16, format: u16, mask: u16) -> bool {
@@ -154,7 +274,7 @@ impl ThumbInstrGroup {
 }
 
 impl Cpu {
-    pub fn decode_thumb(instruction: u16) -> Box<dyn ThumbInstruction> {
+    pub fn decode_thumb(instruction: u16) -> ThumbInstr {
         let thumb_instr = ThumbInstrGroup::decode(instruction);
         match thumb_instr {
             ThumbInstrGroup::LoadStoreHalfword => load_store_halfword::decode(instruction),
@@ -163,18 +283,22 @@ impl Cpu {
             }
             ThumbInstrGroup::HiRegOpsOrBx => high_reg_ops_or_bx::decode(instruction),
             ThumbInstrGroup::LoadAddress => load_address::decode(instruction),
-            ThumbInstrGroup::PcRelativeLoad => Box::new(pc_relative_load::LdrPc),
+            ThumbInstrGroup::PcRelativeLoad => pc_relative_load::LdrPc::parse(instruction).into(),
             ThumbInstrGroup::MoveShiftedRegister => move_shifted_register::decode(instruction),
-            ThumbInstrGroup::ConditionalBranch => Box::new(branch::ConditionalBranch),
+            ThumbInstrGroup::ConditionalBranch => {
+                branch::ConditionalBranch::parse(instruction).into()
+            }
             ThumbInstrGroup::LongBranchWithLink => {
                 branch::decode_long_branch_with_link(instruction)
             }
             ThumbInstrGroup::AddSubtract => add_subtract::decode(instruction),
             ThumbInstrGroup::AluOperations => alu_operations::decode(instruction),
             ThumbInstrGroup::MultipleLoadStore => multiple_load_store::decode(instruction),
-            ThumbInstrGroup::Branch => Box::new(branch::Branch),
+            ThumbInstrGroup::Branch => branch::Branch::parse(instruction).into(),
             ThumbInstrGroup::LoadStoreSignExtended => load_store_sign_extended::decode(instruction),
-            ThumbInstrGroup::AddOffsetToStackPointer => add_offset_to_stack_pointer::decode(),
+            ThumbInstrGroup::AddOffsetToStackPointer => {
+                add_offset_to_stack_pointer::decode(instruction)
+            }
             ThumbInstrGroup::LoadStoreImmediateOffset => {
                 load_store_immediate_offset::decode(instruction)
             }
@@ -183,8 +307,33 @@ impl Cpu {
             }
             ThumbInstrGroup::SpRelativeLoadStore => sp_relative_ls::decode(instruction),
             ThumbInstrGroup::PushPopRegisters => push_pop_regs::decode(instruction),
-            ThumbInstrGroup::SoftwareInterrupt => Box::new(swi::Swi),
-            ThumbInstrGroup::Invalid => Box::new(invalid::Invalid),
+            ThumbInstrGroup::SoftwareInterrupt => swi::Swi::parse(instruction).into(),
+            ThumbInstrGroup::Invalid => invalid::Invalid::parse(instruction).into(),
         }
     }
 }
+
+/// THUMB instructions are unconditional, apart from conditional branches.
+fn instr(mnemonic: Mnemonic, operands: Vec<Operand>) -> Instr {
+    Instr::new(mnemonic, Condition::AL, operands)
+}
+
+/// Most THUMB data processing instructions always set the flags.
+fn flag_setting_instr(mnemonic: Mnemonic, operands: Vec<Operand>) -> Instr {
+    Instr {
+        set_flags: true,
+        ..instr(mnemonic, operands)
+    }
+}
+
+fn reg(reg: u16) -> Operand {
+    Operand::Reg(reg.into())
+}
+
+fn memory(rn: u16, offset: MemoryOffset) -> Operand {
+    Operand::Memory {
+        rn: rn.into(),
+        offset,
+        subtract: false,
+    }
+}
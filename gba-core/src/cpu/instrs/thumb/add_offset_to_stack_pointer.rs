@@ -1,31 +1,41 @@
+use crate::cpu::ir::{Instr, Mnemonic, Operand};
 use crate::utils::AddressableBits;
 
-use super::ThumbInstruction;
+use super::{instr, ThumbInstr, ThumbInstruction};
 
-struct Add;
+#[derive(Clone, Copy)]
+pub struct Add {
+    subtract: bool,
+    imm: u32,
+}
 
-pub fn decode() -> Box<dyn ThumbInstruction> {
-    Box::new(Add)
+pub fn decode(instruction: u16) -> ThumbInstr {
+    Add::parse(instruction).into()
 }
 
 impl ThumbInstruction for Add {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        let sign = instruction.bit(7);
-        let imm: u32 = instruction.bits(0, 6).into();
-        if sign == 0 {
+    fn parse(instruction: u16) -> Self {
+        Self {
+            subtract: instruction.bit(7) == 1,
+            imm: instruction.bits(0, 6).into(),
+        }
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let imm = self.imm;
+        if !self.subtract {
             cpu.set_reg(13, cpu.get_reg(13).wrapping_add(imm * 4));
         } else {
             cpu.set_reg(13, cpu.get_reg(13).wrapping_sub(imm * 4));
         }
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let sign = instruction.bit(7);
-        let imm = instruction.bits(0, 6);
-        if sign == 0 {
-            format!("ADD SP, {:x}", imm * 4)
+    fn decode(&self) -> Instr {
+        let mnemonic = if self.subtract {
+            Mnemonic::SUB
         } else {
-            format!("SUB SP, {:x}", imm * 4)
-        }
+            Mnemonic::ADD
+        };
+        instr(mnemonic, vec![Operand::Reg(13), Operand::Imm(self.imm * 4)])
     }
 }
@@ -1,106 +1,128 @@
+use crate::cpu::ir::{Instr, Mnemonic, Operand};
 use crate::{
     cpu::{Cpu, CPSR},
     utils::{add_overflows, sub_overflows, AddressableBits},
 };
 
-use super::ThumbInstruction;
+use super::{flag_setting_instr, reg, ThumbInstr, ThumbInstruction};
+
+#[derive(Clone, Copy)]
+pub struct AddImm(AddSubtractFields);
+#[derive(Clone, Copy)]
+pub struct AddReg(AddSubtractFields);
+#[derive(Clone, Copy)]
+pub struct SubImm(AddSubtractFields);
+#[derive(Clone, Copy)]
+pub struct SubReg(AddSubtractFields);
+
+/// `rn` is the 3-bit immediate in the immediate forms.
+#[derive(Clone, Copy)]
+pub struct AddSubtractFields {
+    rn: u16,
+    rs: u16,
+    rd: u16,
+}
 
-struct AddImm;
-struct AddReg;
-struct SubImm;
-struct SubReg;
+impl AddSubtractFields {
+    fn parse(instruction: u16) -> Self {
+        Self {
+            rn: instruction.bits(6, 8),
+            rs: instruction.bits(3, 5),
+            rd: instruction.bits(0, 2),
+        }
+    }
+}
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+pub fn decode(instruction: u16) -> ThumbInstr {
     let i = instruction.bit(10) == 1;
     let op = instruction.bit(9) == 1;
 
     match (i, op) {
-        (false, false) => Box::new(AddReg),
-        (false, true) => Box::new(SubReg),
-        (true, false) => Box::new(AddImm),
-        (true, true) => Box::new(SubImm),
+        (false, false) => AddReg::parse(instruction).into(),
+        (false, true) => SubReg::parse(instruction).into(),
+        (true, false) => AddImm::parse(instruction).into(),
+        (true, true) => SubImm::parse(instruction).into(),
     }
 }
 
 impl ThumbInstruction for AddImm {
-    fn execute(&self, cpu: &mut Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        let term = get_imm_term(instruction);
-        let rd = instruction.bits(0, 2);
-        let rs = instruction.bits(3, 5);
+    fn parse(instruction: u16) -> Self {
+        Self(AddSubtractFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let AddSubtractFields { rn: imm, rs, rd } = self.0;
+        let term = imm.into();
 
         execute_add(term, rd, rs, cpu);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd = instruction.bits(0, 2);
-        let rs = instruction.bits(3, 5);
-        let imm = instruction.bits(6, 8);
-        format!("ADD r{}, r{}, {:x}", rd, rs, imm)
+    fn decode(&self) -> Instr {
+        let AddSubtractFields { rn: imm, rs, rd } = self.0;
+        flag_setting_instr(
+            Mnemonic::ADD,
+            vec![reg(rd), reg(rs), Operand::Imm(imm.into())],
+        )
     }
 }
 
 impl ThumbInstruction for AddReg {
-    fn execute(&self, cpu: &mut Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        let term = get_reg_term(instruction, cpu);
-        let rd = instruction.bits(0, 2);
-        let rs = instruction.bits(3, 5);
+    fn parse(instruction: u16) -> Self {
+        Self(AddSubtractFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let AddSubtractFields { rn, rs, rd } = self.0;
+        let term = cpu.get_reg(rn.into());
 
         execute_add(term, rd, rs, cpu);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd = instruction.bits(0, 2);
-        let rs = instruction.bits(3, 5);
-        let rn = instruction.bits(6, 8);
-        format!("ADD r{}, r{}, r{}", rd, rs, rn)
+    fn decode(&self) -> Instr {
+        let AddSubtractFields { rn, rs, rd } = self.0;
+        flag_setting_instr(Mnemonic::ADD, vec![reg(rd), reg(rs), reg(rn)])
     }
 }
 
 impl ThumbInstruction for SubImm {
-    fn execute(&self, cpu: &mut Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        let term = get_imm_term(instruction);
-        let rd = instruction.bits(0, 2);
-        let rs = instruction.bits(3, 5);
+    fn parse(instruction: u16) -> Self {
+        Self(AddSubtractFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let AddSubtractFields { rn: imm, rs, rd } = self.0;
+        let term = imm.into();
 
         execute_sub(term, rd, rs, cpu);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd = instruction.bits(0, 2);
-        let rs = instruction.bits(3, 5);
-        let imm = instruction.bits(6, 8);
-        format!("SUB r{}, r{}, {:x}", rd, rs, imm)
+    fn decode(&self) -> Instr {
+        let AddSubtractFields { rn: imm, rs, rd } = self.0;
+        flag_setting_instr(
+            Mnemonic::SUB,
+            vec![reg(rd), reg(rs), Operand::Imm(imm.into())],
+        )
     }
 }
 
 impl ThumbInstruction for SubReg {
-    fn execute(&self, cpu: &mut Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        let term = get_reg_term(instruction, cpu);
-        let rd = instruction.bits(0, 2);
-        let rs = instruction.bits(3, 5);
+    fn parse(instruction: u16) -> Self {
+        Self(AddSubtractFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let AddSubtractFields { rn, rs, rd } = self.0;
+        let term = cpu.get_reg(rn.into());
 
         execute_sub(term, rd, rs, cpu);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd = instruction.bits(0, 2);
-        let rs = instruction.bits(3, 5);
-        let rn = instruction.bits(6, 8);
-        format!("SUB r{}, r{}, r{}", rd, rs, rn)
+    fn decode(&self) -> Instr {
+        let AddSubtractFields { rn, rs, rd } = self.0;
+        flag_setting_instr(Mnemonic::SUB, vec![reg(rd), reg(rs), reg(rn)])
     }
 }
 
-#[inline]
-fn get_imm_term(instruction: u16) -> u32 {
-    instruction.bits(6, 8).into()
-}
-
-#[inline]
-fn get_reg_term(instruction: u16, cpu: &Cpu) -> u32 {
-    let rn = instruction.bits(6, 8);
-    cpu.get_reg(rn.into())
-}
-
 #[inline]
 fn execute_add(term: u32, rd: u16, rs: u16, cpu: &mut Cpu) {
     let rs_val = cpu.get_reg(rs.into());
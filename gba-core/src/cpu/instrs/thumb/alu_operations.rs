@@ -1,27 +1,59 @@
+use crate::cpu::ir::{Instr, Mnemonic};
 use crate::{
-    bus::Bus,
+    bus::MemoryInterface,
     cpu::{Cpu, CPSR},
     utils::{add_overflows, sub_overflows, AddressableBits},
 };
 
-use super::ThumbInstruction;
-
-struct AND;
-struct EOR;
-struct LSL;
-struct LSR;
-struct ASR;
-struct ADC;
-struct SBC;
-struct ROR;
-struct TST;
-struct NEG;
-struct CMP;
-struct CMN;
-struct ORR;
-struct MUL;
-struct BIC;
-struct MVN;
+use super::{flag_setting_instr, reg, ThumbInstr, ThumbInstruction};
+
+#[derive(Clone, Copy)]
+pub struct AND(AluFields);
+#[derive(Clone, Copy)]
+pub struct EOR(AluFields);
+#[derive(Clone, Copy)]
+pub struct LSL(AluFields);
+#[derive(Clone, Copy)]
+pub struct LSR(AluFields);
+#[derive(Clone, Copy)]
+pub struct ASR(AluFields);
+#[derive(Clone, Copy)]
+pub struct ADC(AluFields);
+#[derive(Clone, Copy)]
+pub struct SBC(AluFields);
+#[derive(Clone, Copy)]
+pub struct ROR(AluFields);
+#[derive(Clone, Copy)]
+pub struct TST(AluFields);
+#[derive(Clone, Copy)]
+pub struct NEG(AluFields);
+#[derive(Clone, Copy)]
+pub struct CMP(AluFields);
+#[derive(Clone, Copy)]
+pub struct CMN(AluFields);
+#[derive(Clone, Copy)]
+pub struct ORR(AluFields);
+#[derive(Clone, Copy)]
+pub struct MUL(AluFields);
+#[derive(Clone, Copy)]
+pub struct BIC(AluFields);
+#[derive(Clone, Copy)]
+pub struct MVN(AluFields);
+
+#[derive(Clone, Copy)]
+pub struct AluFields {
+    rs: u16,
+    rd: u16,
+}
+
+impl AluFields {
+    fn parse(instruction: u16) -> Self {
+        Self {
+            rs: instruction.bits(3, 5),
+            rd: instruction.bits(0, 2),
+        }
+    }
+}
 
 struct FlagUpdates {
     n: Option<bool>,
@@ -41,55 +73,58 @@ impl FlagUpdates {
     }
 }
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+pub fn decode(instruction: u16) -> ThumbInstr {
     match instruction.bits(6, 9) {
-        0b0000 => Box::new(AND),
-        0b0001 => Box::new(EOR),
-        0b0010 => Box::new(LSL),
-        0b0011 => Box::new(LSR),
-        0b0100 => Box::new(ASR),
-        0b0101 => Box::new(ADC),
-        0b0110 => Box::new(SBC),
-        0b0111 => Box::new(ROR),
-        0b1000 => Box::new(TST),
-        0b1001 => Box::new(NEG),
-        0b1010 => Box::new(CMP),
-        0b1011 => Box::new(CMN),
-        0b1100 => Box::new(ORR),
-        0b1101 => Box::new(MUL),
-        0b1110 => Box::new(BIC),
-        0b1111 => Box::new(MVN),
+        0b0000 => AND::parse(instruction).into(),
+        0b0001 => EOR::parse(instruction).into(),
+        0b0010 => LSL::parse(instruction).into(),
+        0b0011 => LSR::parse(instruction).into(),
+        0b0100 => ASR::parse(instruction).into(),
+        0b0101 => ADC::parse(instruction).into(),
+        0b0110 => SBC::parse(instruction).into(),
+        0b0111 => ROR::parse(instruction).into(),
+        0b1000 => TST::parse(instruction).into(),
+        0b1001 => NEG::parse(instruction).into(),
+        0b1010 => CMP::parse(instruction).into(),
+        0b1011 => CMN::parse(instruction).into(),
+        0b1100 => ORR::parse(instruction).into(),
+        0b1101 => MUL::parse(instruction).into(),
+        0b1110 => BIC::parse(instruction).into(),
+        0b1111 => MVN::parse(instruction).into(),
         _ => unreachable!(),
     }
 }
 
 macro_rules! alu_thumb_instr_impl {
-    ($SelfT:ty, $Op:literal, $Closure:expr) => {
+    ($SelfT:ty, $Op:expr, $Closure:expr) => {
         impl ThumbInstruction for $SelfT {
-            fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-                execute_op(cpu, instruction, $Closure);
+            fn parse(instruction: u16) -> Self {
+                Self(AluFields::parse(instruction))
+            }
+
+            fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+                execute_op(cpu, &self.0, $Closure);
             }
 
-            fn disassembly(&self, instruction: u16) -> String {
-                let rs = instruction.bits(3, 5);
-                let rd = instruction.bits(0, 2);
-                format!("{} r{}, r{}", $Op, rd, rs)
+            fn decode(&self) -> Instr {
+                let AluFields { rs, rd } = self.0;
+                flag_setting_instr($Op, vec![reg(rd), reg(rs)])
             }
         }
     };
 }
 
-alu_thumb_instr_impl!(AND, "AND", |op1, op2, _| {
+alu_thumb_instr_impl!(AND, Mnemonic::AND, |op1, op2, _| {
     let result = op1 & op2;
     (Some(result), FlagUpdates::default_nz(result))
 });
 
-alu_thumb_instr_impl!(EOR, "EOR", |op1, op2, _| {
+alu_thumb_instr_impl!(EOR, Mnemonic::EOR, |op1, op2, _| {
     let result = op1 ^ op2;
     (Some(result), FlagUpdates::default_nz(result))
 });
 
-alu_thumb_instr_impl!(LSL, "LSL", |op1, op2, _| {
+alu_thumb_instr_impl!(LSL, Mnemonic::LSL, |op1, op2, _| {
     let rs_low = op2.bits(0, 7);
     let result;
     let carry;
@@ -116,7 +151,7 @@ alu_thumb_instr_impl!(LSL, "LSL", |op1, op2, _| {
     )
 });
 
-alu_thumb_instr_impl!(LSR, "LSR", |op1, op2, _| {
+alu_thumb_instr_impl!(LSR, Mnemonic::LSR, |op1, op2, _| {
     let rs_low = op2.bits(0, 7);
     let result;
     let carry;
@@ -142,7 +177,7 @@ alu_thumb_instr_impl!(LSR, "LSR", |op1, op2, _| {
     )
 });
 
-alu_thumb_instr_impl!(ASR, "ASR", |op1, op2, _| {
+alu_thumb_instr_impl!(ASR, Mnemonic::ASR, |op1, op2, _| {
     let rs_low = op2.bits(0, 7);
     let result;
     let carry;
@@ -165,7 +200,7 @@ alu_thumb_instr_impl!(ASR, "ASR", |op1, op2, _| {
     )
 });
 
-alu_thumb_instr_impl!(ADC, "ADC", |op1, op2, c| {
+alu_thumb_instr_impl!(ADC, Mnemonic::ADC, |op1, op2, c| {
     let (result1, carry1) = op1.overflowing_add(op2);
     let (result2, carry2) = result1.overflowing_add(c);
 
@@ -179,7 +214,7 @@ alu_thumb_instr_impl!(ADC, "ADC", |op1, op2, c| {
     )
 });
 
-alu_thumb_instr_impl!(SBC, "SBC", |op1, op2, c| {
+alu_thumb_instr_impl!(SBC, Mnemonic::SBC, |op1, op2, c| {
     let not_c = 1 - c;
     let (result1, borrow1) = op1.overflowing_sub(op2);
     let (result2, borrow2) = result1.overflowing_sub(not_c);
@@ -194,7 +229,7 @@ alu_thumb_instr_impl!(SBC, "SBC", |op1, op2, c| {
     )
 });
 
-alu_thumb_instr_impl!(ROR, "ROR", |op1, op2, _| {
+alu_thumb_instr_impl!(ROR, Mnemonic::ROR, |op1, op2, _| {
     let rs_low = op2.bits(0, 7);
     let rs_lower = op2.bits(0, 4);
     let result;
@@ -220,12 +255,12 @@ alu_thumb_instr_impl!(ROR, "ROR", |op1, op2, _| {
     )
 });
 
-alu_thumb_instr_impl!(TST, "TST", |op1, op2, _| {
+alu_thumb_instr_impl!(TST, Mnemonic::TST, |op1, op2, _| {
     let result = op1 & op2;
     (None, FlagUpdates::default_nz(result))
 });
 
-alu_thumb_instr_impl!(NEG, "NEG", |_, op2, _| {
+alu_thumb_instr_impl!(NEG, Mnemonic::NEG, |_, op2, _| {
     let (result, borrow) = 0u32.overflowing_sub(op2);
     (
         Some(result),
@@ -237,7 +272,7 @@ alu_thumb_instr_impl!(NEG, "NEG", |_, op2, _| {
     )
 });
 
-alu_thumb_instr_impl!(CMP, "CMP", |op1, op2, _| {
+alu_thumb_instr_impl!(CMP, Mnemonic::CMP, |op1, op2, _| {
     let (result, borrow) = op1.overflowing_sub(op2);
     (
         None,
@@ -249,7 +284,7 @@ alu_thumb_instr_impl!(CMP, "CMP", |op1, op2, _| {
     )
 });
 
-alu_thumb_instr_impl!(CMN, "CMN", |op1, op2, _| {
+alu_thumb_instr_impl!(CMN, Mnemonic::CMN, |op1, op2, _| {
     let (result, carry) = op1.overflowing_add(op2);
     (
         None,
@@ -261,35 +296,34 @@ alu_thumb_instr_impl!(CMN, "CMN", |op1, op2, _| {
     )
 });
 
-alu_thumb_instr_impl!(ORR, "ORR", |op1, op2, _| {
+alu_thumb_instr_impl!(ORR, Mnemonic::ORR, |op1, op2, _| {
     let result = op1 | op2;
     (Some(result), FlagUpdates::default_nz(result))
 });
 
-alu_thumb_instr_impl!(MUL, "MUL", |op1, op2, _| {
+alu_thumb_instr_impl!(MUL, Mnemonic::MUL, |op1, op2, _| {
     let result = op1.wrapping_mul(op2);
     // TODO: MUL's C flag is unpredictable in v4
     (Some(result), FlagUpdates::default_nz(result))
 });
 
-alu_thumb_instr_impl!(BIC, "BIC", |op1, op2, _| {
+alu_thumb_instr_impl!(BIC, Mnemonic::BIC, |op1, op2, _| {
     let result = op1 & !op2;
     (Some(result), FlagUpdates::default_nz(result))
 });
 
-alu_thumb_instr_impl!(MVN, "MVN", |_, op2, _| {
+alu_thumb_instr_impl!(MVN, Mnemonic::MVN, |_, op2, _| {
     let result = !op2;
     (Some(result), FlagUpdates::default_nz(result))
 });
 
 #[inline]
-fn execute_op<F>(cpu: &mut Cpu, instruction: u16, op_closure: F)
+fn execute_op<F>(cpu: &mut Cpu, fields: &AluFields, op_closure: F)
 where
     // op1, op2, c_flag -> result, flag_updates
     F: Fn(u32, u32, u32) -> (Option<u32>, FlagUpdates),
 {
-    let rs = instruction.bits(3, 5);
-    let rd = instruction.bits(0, 2);
+    let AluFields { rs, rd } = *fields;
 
     let op1 = cpu.get_reg(rd.into());
     let op2 = cpu.get_reg(rs.into());
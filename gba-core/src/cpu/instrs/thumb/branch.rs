@@ -1,84 +1,115 @@
-use super::ThumbInstruction;
+use super::{instr, ThumbInstr, ThumbInstruction};
+use crate::cpu::ir::{Condition, Instr, Mnemonic, Operand};
 use crate::utils::AddressableBits;
-use crate::Bus;
+use crate::bus::MemoryInterface;
 use crate::Cpu;
 
-pub struct ConditionalBranch;
-pub struct Branch;
-pub struct LongBranchWithLinkFirst;
-pub struct LongBranchWithLinkSecond;
+#[derive(Clone, Copy)]
+pub struct ConditionalBranch {
+    cond: u32,
+    offset: i32,
+}
+
+#[derive(Clone, Copy)]
+pub struct Branch {
+    offset: i32,
+}
+
+#[derive(Clone, Copy)]
+pub struct LongBranchWithLinkFirst {
+    /// The high part of the offset, already shifted into place.
+    offset: i32,
+}
+
+#[derive(Clone, Copy)]
+pub struct LongBranchWithLinkSecond {
+    /// The low part of the offset, already shifted into place.
+    offset: u32,
+}
 
 impl ThumbInstruction for ConditionalBranch {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let cond = instruction.bits(8, 11);
+    fn parse(instruction: u16) -> Self {
         let signed_imm = instruction.bits(0, 7) as i8;
+        Self {
+            cond: instruction.bits(8, 11).into(),
+            offset: i32::from(signed_imm) << 1,
+        }
+    }
 
-        if cpu.check_cond(cond as u32) {
-            let offset = i32::from(signed_imm) << 1;
-            cpu.set_reg(15, cpu.get_reg(15).wrapping_add_signed(offset));
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        if cpu.check_cond(self.cond) {
+            cpu.set_reg(15, cpu.get_reg(15).wrapping_add_signed(self.offset));
             cpu.flush_pipeline();
         }
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let cond = Cpu::disassemble_cond(instruction.bits(8, 11).into());
-        let signed_imm = instruction.bits(0, 7) as i8;
-        let offset = i32::from(signed_imm) << 1;
-        let sign = if offset >= 0 { "+" } else { "-" };
-        format!("B{}, PC{}{:x}", cond, sign, offset.abs())
+    fn decode(&self) -> Instr {
+        let cond = Condition::from_bits(self.cond);
+        // Branches are relative to the PC, which is 4 bytes ahead
+        Instr::new(Mnemonic::B, cond, vec![Operand::Relative(self.offset + 4)])
     }
 }
 
 impl ThumbInstruction for Branch {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let signed_imm = sign_extend_i11(instruction.bits(0, 10));
-        cpu.set_reg(15, cpu.get_reg(15).wrapping_add_signed(signed_imm << 1));
+    fn parse(instruction: u16) -> Self {
+        Self {
+            offset: sign_extend_i11(instruction.bits(0, 10)) << 1,
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        cpu.set_reg(15, cpu.get_reg(15).wrapping_add_signed(self.offset));
         cpu.flush_pipeline();
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let signed_imm = sign_extend_i11(instruction.bits(0, 10));
-        let offset = signed_imm << 1;
-        let sign = if offset >= 0 { "+" } else { "-" };
-        format!("B, PC{}{:x}", sign, offset.abs())
+    fn decode(&self) -> Instr {
+        instr(Mnemonic::B, vec![Operand::Relative(self.offset + 4)])
     }
 }
 
-pub fn decode_long_branch_with_link(instruction: u16) -> Box<dyn ThumbInstruction> {
+pub fn decode_long_branch_with_link(instruction: u16) -> ThumbInstr {
     let h = instruction.bits(11, 12);
     match h {
-        0b10 => Box::new(LongBranchWithLinkFirst),
-        0b11 => Box::new(LongBranchWithLinkSecond),
+        0b10 => LongBranchWithLinkFirst::parse(instruction).into(),
+        0b11 => LongBranchWithLinkSecond::parse(instruction).into(),
         // H == 01 form only exists from ARMv5
         _ => unreachable!(),
     }
 }
 
 impl ThumbInstruction for LongBranchWithLinkFirst {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let offset = sign_extend_i11(instruction.bits(0, 10));
-        cpu.set_reg(14, cpu.get_reg(15).wrapping_add_signed(offset << 12));
+    fn parse(instruction: u16) -> Self {
+        Self {
+            offset: sign_extend_i11(instruction.bits(0, 10)) << 12,
+        }
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let offset = sign_extend_i11(instruction.bits(0, 10));
-        format!("BL(1) {:x}", offset << 12)
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        cpu.set_reg(14, cpu.get_reg(15).wrapping_add_signed(self.offset));
+    }
+
+    fn decode(&self) -> Instr {
+        instr(Mnemonic::BL1, vec![Operand::Relative(self.offset + 4)])
     }
 }
 
 impl ThumbInstruction for LongBranchWithLinkSecond {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let offset = instruction.bits(0, 10);
+    fn parse(instruction: u16) -> Self {
+        Self {
+            offset: u32::from(instruction.bits(0, 10) << 1),
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
         let lr = cpu.get_reg(14);
         let next_instr = cpu.get_reg(15) - 2;
-        cpu.set_reg(15, lr + u32::from(offset << 1));
+        cpu.set_reg(15, lr + self.offset);
         cpu.set_reg(14, next_instr | 1);
         cpu.flush_pipeline();
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let offset = instruction.bits(0, 10);
-        format!("BL(2) {:x}", offset << 1)
+    fn decode(&self) -> Instr {
+        instr(Mnemonic::BL2, vec![Operand::Imm(self.offset)])
     }
 }
 
@@ -1,27 +1,50 @@
-use crate::bus::Bus;
+use crate::bus::MemoryInterface;
+use crate::cpu::ir::{Instr, Mnemonic, Operand};
 use crate::cpu::{Cpu, CPSR};
 use crate::utils::{sub_overflows, AddressableBits};
 
-use super::ThumbInstruction;
+use super::{instr, ThumbInstr, ThumbInstruction};
 
-struct ADD;
-struct CMP;
-struct MOV;
-struct BX;
+#[derive(Clone, Copy)]
+pub struct ADD(HiRegFields);
+#[derive(Clone, Copy)]
+pub struct CMP(HiRegFields);
+#[derive(Clone, Copy)]
+pub struct MOV(HiRegFields);
+#[derive(Clone, Copy)]
+pub struct BX(HiRegFields);
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+/// `rd` and `rs` with their high bits, so either can be r8-r15.
+#[derive(Clone, Copy)]
+pub struct HiRegFields {
+    rd: u32,
+    rs: u32,
+}
+
+impl HiRegFields {
+    fn parse(instruction: u16) -> Self {
+        let (rd, rs) = get_fields(instruction);
+        Self { rd, rs }
+    }
+}
+
+pub fn decode(instruction: u16) -> ThumbInstr {
     match instruction.bits(8, 9) {
-        0b00 => Box::new(ADD),
-        0b01 => Box::new(CMP),
-        0b10 => Box::new(MOV),
-        0b11 => Box::new(BX),
+        0b00 => ADD::parse(instruction).into(),
+        0b01 => CMP::parse(instruction).into(),
+        0b10 => MOV::parse(instruction).into(),
+        0b11 => BX::parse(instruction).into(),
         _ => unreachable!(),
     }
 }
 
 impl ThumbInstruction for ADD {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let (rd, rs) = get_fields(instruction);
+    fn parse(instruction: u16) -> Self {
+        Self(HiRegFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let HiRegFields { rd, rs } = self.0;
         let result = cpu.get_reg(rd).wrapping_add(cpu.get_reg(rs));
         if rd == 15 {
             cpu.set_reg(15, result & 0xfffffffe);
@@ -31,15 +54,19 @@ impl ThumbInstruction for ADD {
         }
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let (rd, rs) = get_fields(instruction);
-        format!("ADD r{}, r{}", rd, rs)
+    fn decode(&self) -> Instr {
+        let HiRegFields { rd, rs } = self.0;
+        instr(Mnemonic::ADD, vec![Operand::Reg(rd), Operand::Reg(rs)])
     }
 }
 
 impl ThumbInstruction for CMP {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let (rd, rs) = get_fields(instruction);
+    fn parse(instruction: u16) -> Self {
+        Self(HiRegFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let HiRegFields { rd, rs } = self.0;
         let rd_val = cpu.get_reg(rd);
         let rs_val = cpu.get_reg(rs);
         let (result, borrow) = rd_val.overflowing_sub(rs_val);
@@ -49,15 +76,19 @@ impl ThumbInstruction for CMP {
         cpu.set_flag(CPSR::V, sub_overflows(rd_val, rs_val, result));
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let (rd, rs) = get_fields(instruction);
-        format!("CMP r{}, r{}", rd, rs)
+    fn decode(&self) -> Instr {
+        let HiRegFields { rd, rs } = self.0;
+        instr(Mnemonic::CMP, vec![Operand::Reg(rd), Operand::Reg(rs)])
     }
 }
 
 impl ThumbInstruction for MOV {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let (rd, rs) = get_fields(instruction);
+    fn parse(instruction: u16) -> Self {
+        Self(HiRegFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let HiRegFields { rd, rs } = self.0;
         if rd == 15 {
             cpu.set_reg(15, cpu.get_reg(rs) & 0xfffffffe);
             cpu.flush_pipeline();
@@ -66,25 +97,27 @@ impl ThumbInstruction for MOV {
         }
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let (rd, rs) = get_fields(instruction);
-        format!("MOV r{}, r{}", rd, rs)
+    fn decode(&self) -> Instr {
+        let HiRegFields { rd, rs } = self.0;
+        instr(Mnemonic::MOV, vec![Operand::Reg(rd), Operand::Reg(rs)])
     }
 }
 
 impl ThumbInstruction for BX {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(3, 6);
-        let rm_val = cpu.get_reg(rm.into());
+    fn parse(instruction: u16) -> Self {
+        Self(HiRegFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let rm_val = cpu.get_reg(self.0.rs);
 
         cpu.set_flag(CPSR::T, rm_val.bit(0) == 1);
         cpu.set_reg(15, (rm_val.bits(1, 31) as u32) << 1);
         cpu.flush_pipeline();
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(3, 6);
-        format!("BX r{}", rm)
+    fn decode(&self) -> Instr {
+        instr(Mnemonic::BX, vec![Operand::Reg(self.0.rs)])
     }
 }
 
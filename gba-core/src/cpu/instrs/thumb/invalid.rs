@@ -1,13 +1,19 @@
-use super::ThumbInstruction;
+use super::{instr, ThumbInstruction};
+use crate::cpu::ir::{Instr, Mnemonic};
 
+#[derive(Clone, Copy)]
 pub struct Invalid;
 
 impl ThumbInstruction for Invalid {
-    fn execute(&self, _: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        panic!("Executed invalid thumb instruction {:018b}", instruction);
+    fn parse(_: u16) -> Self {
+        Self
     }
 
-    fn disassembly(&self, _: u16) -> String {
-        format!("Invalid")
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        cpu.undefined_instruction();
+    }
+
+    fn decode(&self) -> Instr {
+        instr(Mnemonic::UND, vec![])
     }
 }
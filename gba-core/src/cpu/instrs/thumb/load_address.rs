@@ -1,45 +1,73 @@
-use super::ThumbInstruction;
-use crate::cpu::Bus;
+use super::{instr, reg, ThumbInstr, ThumbInstruction};
+use crate::cpu::ir::{Instr, Mnemonic, Operand};
+use crate::bus::MemoryInterface;
 use crate::cpu::Cpu;
 use crate::utils::AddressableBits;
 
-struct AddPc;
-struct AddSp;
+#[derive(Clone, Copy)]
+pub struct AddPc(LoadAddressFields);
+#[derive(Clone, Copy)]
+pub struct AddSp(LoadAddressFields);
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+#[derive(Clone, Copy)]
+pub struct LoadAddressFields {
+    rd: u16,
+    imm: u32,
+}
+
+impl LoadAddressFields {
+    fn parse(instruction: u16) -> Self {
+        Self {
+            rd: instruction.bits(8, 10),
+            imm: instruction.bits(0, 7).into(),
+        }
+    }
+}
+
+pub fn decode(instruction: u16) -> ThumbInstr {
     if instruction.bit(11) == 0 {
-        Box::new(AddPc)
+        AddPc::parse(instruction).into()
     } else {
-        Box::new(AddSp)
+        AddSp::parse(instruction).into()
     }
 }
 
 impl ThumbInstruction for AddPc {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let rd = instruction.bits(8, 10);
-        let imm: u32 = instruction.bits(0, 7).into();
+    fn parse(instruction: u16) -> Self {
+        Self(LoadAddressFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let LoadAddressFields { rd, imm } = self.0;
         let value = (cpu.get_reg(15) & 0xfffffffc).wrapping_add(imm * 4);
         cpu.set_reg(rd.into(), value);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd = instruction.bits(8, 10);
-        let imm: u32 = instruction.bits(0, 7).into();
-        format!("ADD r{}, PC, {:x}", rd, imm * 4)
+    fn decode(&self) -> Instr {
+        let LoadAddressFields { rd, imm } = self.0;
+        instr(
+            Mnemonic::ADD,
+            vec![reg(rd), Operand::Reg(15), Operand::Imm(imm * 4)],
+        )
     }
 }
 
 impl ThumbInstruction for AddSp {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let rd = instruction.bits(8, 10);
-        let imm: u32 = instruction.bits(0, 7).into();
+    fn parse(instruction: u16) -> Self {
+        Self(LoadAddressFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let LoadAddressFields { rd, imm } = self.0;
         let value = cpu.get_reg(13).wrapping_add(imm * 4);
         cpu.set_reg(rd.into(), value);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd = instruction.bits(8, 10);
-        let imm: u32 = instruction.bits(0, 7).into();
-        format!("ADD r{}, SP, {:x}", rd, imm * 4)
+    fn decode(&self) -> Instr {
+        let LoadAddressFields { rd, imm } = self.0;
+        instr(
+            Mnemonic::ADD,
+            vec![reg(rd), Operand::Reg(13), Operand::Imm(imm * 4)],
+        )
     }
 }
@@ -1,58 +1,62 @@
-use crate::{bus::Bus, cpu::Cpu, utils::AddressableBits};
+use crate::cpu::ir::{Instr, MemoryOffset, Mnemonic};
+use crate::{bus::MemoryInterface, cpu::Cpu, utils::AddressableBits};
 
-use super::ThumbInstruction;
+use super::load_store_immediate_offset::ImmediateOffsetFields;
+use super::{instr, memory, reg, ThumbInstr, ThumbInstruction};
 
-struct Ldrh;
-struct Strh;
+#[derive(Clone, Copy)]
+pub struct Ldrh(ImmediateOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Strh(ImmediateOffsetFields);
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+pub fn decode(instruction: u16) -> ThumbInstr {
     if instruction.bit(11) == 0 {
-        Box::new(Strh)
+        Strh::parse(instruction).into()
     } else {
-        Box::new(Ldrh)
+        Ldrh::parse(instruction).into()
     }
 }
 
 impl ThumbInstruction for Strh {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()) + 2 * offset as u32;
 
         // How does misalignment work here?
-        bus.write_half(address, cpu.get_reg(rd.into()) as u16);
+        cpu.write_half(bus, address, cpu.get_reg(rd.into()) as u16);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("STRH r{}, [r{}, {:x}]", rd, rn, offset * 2)
+    fn decode(&self) -> Instr {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Imm(u32::from(offset) * 2));
+        instr(Mnemonic::STRH, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Ldrh {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()) + 2 * offset as u32;
 
         // How does misalignment work here?
-        let data = bus.read_half(address, cpu);
+        let data = cpu.read_half(bus, address);
 
         cpu.set_reg(rd.into(), data);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("LDRH r{}, [r{}, {:x}]", rd, rn, offset * 2)
+    fn decode(&self) -> Instr {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Imm(u32::from(offset) * 2));
+        instr(Mnemonic::LDRH, vec![reg(rd), address])
     }
 }
@@ -1,105 +1,128 @@
-use crate::{bus::Bus, cpu::Cpu, utils::AddressableBits};
-
-use super::ThumbInstruction;
+use crate::cpu::ir::{Instr, MemoryOffset, Mnemonic};
+use crate::{bus::MemoryInterface, cpu::Cpu, utils::AddressableBits};
+
+use super::{instr, memory, reg, ThumbInstr, ThumbInstruction};
+
+#[derive(Clone, Copy)]
+pub struct Ldr(ImmediateOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Ldrb(ImmediateOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Str(ImmediateOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Strb(ImmediateOffsetFields);
+
+/// The fields of loads and stores with an immediate offset, which the halfword ones share.
+#[derive(Clone, Copy)]
+pub struct ImmediateOffsetFields {
+    pub(super) offset: u16,
+    pub(super) rn: u16,
+    pub(super) rd: u16,
+}
 
-struct Ldr;
-struct Ldrb;
-struct Str;
-struct Strb;
+impl ImmediateOffsetFields {
+    pub(super) fn parse(instruction: u16) -> Self {
+        Self {
+            offset: instruction.bits(6, 10),
+            rn: instruction.bits(3, 5),
+            rd: instruction.bits(0, 2),
+        }
+    }
+}
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+pub fn decode(instruction: u16) -> ThumbInstr {
     let b = instruction.bit(12) == 1;
     let l = instruction.bit(11) == 1;
     match (l, b) {
-        (false, false) => Box::new(Str),
-        (true, false) => Box::new(Ldr),
-        (false, true) => Box::new(Strb),
-        (true, true) => Box::new(Ldrb),
+        (false, false) => Str::parse(instruction).into(),
+        (true, false) => Ldr::parse(instruction).into(),
+        (false, true) => Strb::parse(instruction).into(),
+        (true, true) => Ldrb::parse(instruction).into(),
     }
 }
 
 impl ThumbInstruction for Str {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()) + 4 * offset as u32;
 
         // How does misalignment work here?
-        bus.write(address, cpu.get_reg(rd.into()));
+        cpu.write(bus, address, cpu.get_reg(rd.into()));
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("STR r{}, [r{}, {:x}]", rd, rn, offset * 4)
+    fn decode(&self) -> Instr {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Imm(u32::from(offset) * 4));
+        instr(Mnemonic::STR, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Strb {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()) + offset as u32;
 
-        bus.write_byte(address, cpu.get_reg(rd.into()) as u8);
+        cpu.write_byte(bus, address, cpu.get_reg(rd.into()) as u8);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("STRB r{}, [r{}, {:x}]", rd, rn, offset)
+    fn decode(&self) -> Instr {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Imm(u32::from(offset)));
+        instr(Mnemonic::STRB, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Ldr {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()) + 4 * offset as u32;
 
         // How does misalignment work here?
-        let data = bus.read(address, cpu);
+        let data = cpu.read(bus, address);
 
         cpu.set_reg(rd.into(), data);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("LDR r{}, [r{}, {:x}]", rd, rn, offset * 4)
+    fn decode(&self) -> Instr {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Imm(u32::from(offset) * 4));
+        instr(Mnemonic::LDR, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Ldrb {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()) + offset as u32;
 
-        let data = bus.read_byte(address, cpu);
+        let data = cpu.read_byte(bus, address);
 
         cpu.set_reg(rd.into(), data.into());
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let offset = instruction.bits(6, 10);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("LDRB r{}, [r{}, {:x}]", rd, rn, offset)
+    fn decode(&self) -> Instr {
+        let ImmediateOffsetFields { offset, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Imm(u32::from(offset)));
+        instr(Mnemonic::LDRB, vec![reg(rd), address])
     }
 }
@@ -1,93 +1,120 @@
-use crate::{bus::Bus, cpu::Cpu, utils::AddressableBits};
+use crate::cpu::ir::{Instr, MemoryOffset, Mnemonic};
+use crate::{bus::MemoryInterface, cpu::Cpu, utils::AddressableBits};
 
-use super::ThumbInstruction;
+use super::{instr, memory, reg, ThumbInstr, ThumbInstruction};
 
-struct Ldr;
-struct Ldrb;
-struct Str;
-struct Strb;
+#[derive(Clone, Copy)]
+pub struct Ldr(RegisterOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Ldrb(RegisterOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Str(RegisterOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Strb(RegisterOffsetFields);
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+/// The fields of loads and stores with a register offset, which the sign extended ones share.
+#[derive(Clone, Copy)]
+pub struct RegisterOffsetFields {
+    pub(super) rm: u16,
+    pub(super) rn: u16,
+    pub(super) rd: u16,
+}
+
+impl RegisterOffsetFields {
+    pub(super) fn parse(instruction: u16) -> Self {
+        Self {
+            rm: instruction.bits(6, 8),
+            rn: instruction.bits(3, 5),
+            rd: instruction.bits(0, 2),
+        }
+    }
+}
+
+pub fn decode(instruction: u16) -> ThumbInstr {
     let l = instruction.bit(11) == 1;
     let b = instruction.bit(10) == 1;
     match (l, b) {
-        (false, false) => Box::new(Str),
-        (true, false) => Box::new(Ldr),
-        (false, true) => Box::new(Strb),
-        (true, true) => Box::new(Ldrb),
+        (false, false) => Str::parse(instruction).into(),
+        (true, false) => Ldr::parse(instruction).into(),
+        (false, true) => Strb::parse(instruction).into(),
+        (true, true) => Ldrb::parse(instruction).into(),
     }
 }
 
 impl ThumbInstruction for Str {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(RegisterOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
-        bus.write(address, cpu.get_reg(rd.into()));
+        cpu.write(bus, address, cpu.get_reg(rd.into()));
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-        format!("STR r{}, [r{}, r{}]", rd, rn, rm)
+    fn decode(&self) -> Instr {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Reg(rm.into()));
+        instr(Mnemonic::STR, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Strb {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(RegisterOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
-        bus.write_byte(address, cpu.get_reg(rd.into()) as u8);
+        cpu.write_byte(bus, address, cpu.get_reg(rd.into()) as u8);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-        format!("STRB r{}, [r{}, r{}]", rd, rn, rm)
+    fn decode(&self) -> Instr {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Reg(rm.into()));
+        instr(Mnemonic::STRB, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Ldr {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(RegisterOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
-        let data = bus.read(address, cpu);
+        let data = cpu.read(bus, address);
         cpu.set_reg(rd.into(), data);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-        format!("LDR r{}, [r{}, r{}]", rd, rn, rm)
+    fn decode(&self) -> Instr {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Reg(rm.into()));
+        instr(Mnemonic::LDR, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Ldrb {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(RegisterOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
-        let data = bus.read_byte(address, cpu);
+        let data = cpu.read_byte(bus, address);
         cpu.set_reg(rd.into(), data.into());
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-        format!("LDRB r{}, [r{}, r{}]", rd, rn, rm)
+    fn decode(&self) -> Instr {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Reg(rm.into()));
+        instr(Mnemonic::LDRB, vec![reg(rd), address])
     }
 }
@@ -1,100 +1,110 @@
-use crate::{bus::Bus, cpu::Cpu, utils::AddressableBits};
-
-use super::ThumbInstruction;
-
-struct Strh;
-struct Ldrh;
-struct Ldrsb;
-struct Ldrsh;
-
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+use crate::cpu::ir::{Instr, MemoryOffset, Mnemonic};
+use crate::{bus::MemoryInterface, cpu::Cpu, utils::AddressableBits};
+
+use super::load_store_register_offset::RegisterOffsetFields;
+use super::{instr, memory, reg, ThumbInstr, ThumbInstruction};
+
+#[derive(Clone, Copy)]
+pub struct Strh(RegisterOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Ldrh(RegisterOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Ldrsb(RegisterOffsetFields);
+#[derive(Clone, Copy)]
+pub struct Ldrsh(RegisterOffsetFields);
+
+pub fn decode(instruction: u16) -> ThumbInstr {
     let h = instruction.bit(11) == 1;
     let s = instruction.bit(10) == 1;
     match (s, h) {
-        (false, false) => Box::new(Strh),
-        (false, true) => Box::new(Ldrh),
-        (true, false) => Box::new(Ldrsb),
-        (true, true) => Box::new(Ldrsh),
+        (false, false) => Strh::parse(instruction).into(),
+        (false, true) => Ldrh::parse(instruction).into(),
+        (true, false) => Ldrsb::parse(instruction).into(),
+        (true, true) => Ldrsh::parse(instruction).into(),
     }
 }
 
 impl ThumbInstruction for Strh {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(RegisterOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
-        bus.write_half(address, cpu.get_reg(rd.into()) as u16);
+        cpu.write_half(bus, address, cpu.get_reg(rd.into()) as u16);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-        format!("STRH r{}, [r{}, r{}]", rd, rn, rm)
+    fn decode(&self) -> Instr {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Reg(rm.into()));
+        instr(Mnemonic::STRH, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Ldrh {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(RegisterOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
-        let data = bus.read_half(address, cpu);
+        let data = cpu.read_half(bus, address);
         cpu.set_reg(rd.into(), data.into());
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-        format!("LDRH r{}, [r{}, r{}]", rd, rn, rm)
+    fn decode(&self) -> Instr {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Reg(rm.into()));
+        instr(Mnemonic::LDRH, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Ldrsb {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(RegisterOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
-        let data = i32::from(bus.read_byte(address, cpu) as i8) as u32;
+        let data = i32::from(cpu.read_byte(bus, address) as i8) as u32;
         cpu.set_reg(rd.into(), data);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-        format!("LDRSB r{}, [r{}, r{}]", rd, rn, rm)
+    fn decode(&self) -> Instr {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Reg(rm.into()));
+        instr(Mnemonic::LDRSB, vec![reg(rd), address])
     }
 }
 
 impl ThumbInstruction for Ldrsh {
-    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(RegisterOffsetFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, bus: &mut impl MemoryInterface) {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
 
         // Misaligned LDRSH loads the signed byte quantity stored at the offset address
         let data: u32 = if address.bit(0) == 0 {
-            i32::from(bus.read_half(address, cpu) as i16) as u32
+            i32::from(cpu.read_half(bus, address) as i16) as u32
         } else {
-            i32::from(bus.read_byte(address, cpu) as i8) as u32
+            i32::from(cpu.read_byte(bus, address) as i8) as u32
         };
         cpu.set_reg(rd.into(), data);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rm = instruction.bits(6, 8);
-        let rn = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-        format!("LDRSH r{}, [r{}, r{}]", rd, rn, rm)
+    fn decode(&self) -> Instr {
+        let RegisterOffsetFields { rm, rn, rd } = self.0;
+        let address = memory(rn, MemoryOffset::Reg(rm.into()));
+        instr(Mnemonic::LDRSH, vec![reg(rd), address])
     }
 }
@@ -1,31 +1,54 @@
+use crate::cpu::ir::{Instr, Mnemonic, Operand};
 use crate::{
-    bus::Bus,
+    bus::MemoryInterface,
     cpu::{Cpu, CPSR},
     utils::{add_overflows, sub_overflows, AddressableBits},
 };
 
-use super::ThumbInstruction;
+use super::{flag_setting_instr, reg, ThumbInstr, ThumbInstruction};
+
+#[derive(Clone, Copy)]
+pub struct Mov(ImmediateFields);
+#[derive(Clone, Copy)]
+pub struct Cmp(ImmediateFields);
+#[derive(Clone, Copy)]
+pub struct Add(ImmediateFields);
+#[derive(Clone, Copy)]
+pub struct Sub(ImmediateFields);
+
+#[derive(Clone, Copy)]
+pub struct ImmediateFields {
+    rd: u16,
+    imm: u16,
+}
 
-struct Mov;
-struct Cmp;
-struct Add;
-struct Sub;
+impl ImmediateFields {
+    fn parse(instruction: u16) -> Self {
+        Self {
+            rd: instruction.bits(8, 10),
+            imm: instruction.bits(0, 7),
+        }
+    }
+}
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+pub fn decode(instruction: u16) -> ThumbInstr {
     let op = instruction.bits(11, 12);
     match op {
-        0b00 => Box::new(Mov),
-        0b01 => Box::new(Cmp),
-        0b10 => Box::new(Add),
-        0b11 => Box::new(Sub),
+        0b00 => Mov::parse(instruction).into(),
+        0b01 => Cmp::parse(instruction).into(),
+        0b10 => Add::parse(instruction).into(),
+        0b11 => Sub::parse(instruction).into(),
         _ => unreachable!(),
     }
 }
 
 impl ThumbInstruction for Mov {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let rd = instruction.bits(8, 10);
-        let imm = instruction.bits(0, 7);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let ImmediateFields { rd, imm } = self.0;
 
         cpu.set_reg(rd.into(), imm as u32);
 
@@ -33,18 +56,21 @@ impl ThumbInstruction for Mov {
         cpu.set_flag(CPSR::Z, imm == 0);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd = instruction.bits(8, 10);
-        let offset = instruction.bits(0, 7);
-        format!("MOV r{}, {:x}", rd, offset)
+    fn decode(&self) -> Instr {
+        let ImmediateFields { rd, imm } = self.0;
+        flag_setting_instr(Mnemonic::MOV, vec![reg(rd), Operand::Imm(imm.into())])
     }
 }
 
 impl ThumbInstruction for Cmp {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let rn = instruction.bits(8, 10);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let ImmediateFields { rd: rn, imm } = self.0;
         let rn_val = cpu.get_reg(rn.into());
-        let imm = instruction.bits(0, 7) as u32;
+        let imm = imm as u32;
 
         let (result, borrow) = rn_val.overflowing_sub(imm);
         let overflow = sub_overflows(rn_val, imm, result);
@@ -55,18 +81,21 @@ impl ThumbInstruction for Cmp {
         cpu.set_flag(CPSR::V, overflow);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rn = instruction.bits(8, 10);
-        let offset = instruction.bits(0, 7);
-        format!("CMP r{}, {:x}", rn, offset)
+    fn decode(&self) -> Instr {
+        let ImmediateFields { rd, imm } = self.0;
+        flag_setting_instr(Mnemonic::CMP, vec![reg(rd), Operand::Imm(imm.into())])
     }
 }
 
 impl ThumbInstruction for Add {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let rn = instruction.bits(8, 10);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let ImmediateFields { rd: rn, imm } = self.0;
         let rn_val = cpu.get_reg(rn.into());
-        let imm = instruction.bits(0, 7) as u32;
+        let imm = imm as u32;
 
         let (result, carry) = rn_val.overflowing_add(imm);
         let overflow = add_overflows(rn_val, imm, result);
@@ -78,18 +107,21 @@ impl ThumbInstruction for Add {
         cpu.set_flag(CPSR::V, overflow);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rn = instruction.bits(8, 10);
-        let offset = instruction.bits(0, 7);
-        format!("ADD r{}, {:x}", rn, offset)
+    fn decode(&self) -> Instr {
+        let ImmediateFields { rd, imm } = self.0;
+        flag_setting_instr(Mnemonic::ADD, vec![reg(rd), Operand::Imm(imm.into())])
     }
 }
 
 impl ThumbInstruction for Sub {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-        let rn = instruction.bits(8, 10);
+    fn parse(instruction: u16) -> Self {
+        Self(ImmediateFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        let ImmediateFields { rd: rn, imm } = self.0;
         let rn_val = cpu.get_reg(rn.into());
-        let imm = instruction.bits(0, 7) as u32;
+        let imm = imm as u32;
 
         let (result, borrow) = rn_val.overflowing_sub(imm);
         let overflow = sub_overflows(rn_val, imm, result);
@@ -101,9 +133,8 @@ impl ThumbInstruction for Sub {
         cpu.set_flag(CPSR::V, overflow);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rn = instruction.bits(8, 10);
-        let offset = instruction.bits(0, 7);
-        format!("SUB r{}, {:x}", rn, offset)
+    fn decode(&self) -> Instr {
+        let ImmediateFields { rd, imm } = self.0;
+        flag_setting_instr(Mnemonic::SUB, vec![reg(rd), Operand::Imm(imm.into())])
     }
 }
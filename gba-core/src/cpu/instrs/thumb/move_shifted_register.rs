@@ -1,27 +1,50 @@
+use crate::cpu::ir::{Instr, Mnemonic, Operand};
 use crate::{cpu::CPSR, utils::AddressableBits};
 
-use super::ThumbInstruction;
+use super::{flag_setting_instr, reg, ThumbInstr, ThumbInstruction};
 
-struct LSL;
-struct LSR;
-struct ASR;
+#[derive(Clone, Copy)]
+pub struct LSL(ShiftFields);
+#[derive(Clone, Copy)]
+pub struct LSR(ShiftFields);
+#[derive(Clone, Copy)]
+pub struct ASR(ShiftFields);
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+#[derive(Clone, Copy)]
+pub struct ShiftFields {
+    imm: u16,
+    rm: u16,
+    rd: u16,
+}
+
+impl ShiftFields {
+    fn parse(instruction: u16) -> Self {
+        Self {
+            imm: instruction.bits(6, 10),
+            rm: instruction.bits(3, 5),
+            rd: instruction.bits(0, 2),
+        }
+    }
+}
+
+pub fn decode(instruction: u16) -> ThumbInstr {
     let opcode = instruction.bits(11, 12);
     match opcode {
-        0b00 => Box::new(LSL),
-        0b01 => Box::new(LSR),
-        0b10 => Box::new(ASR),
+        0b00 => LSL::parse(instruction).into(),
+        0b01 => LSR::parse(instruction).into(),
+        0b10 => ASR::parse(instruction).into(),
         0b11 => unreachable!(),
         _ => unreachable!(),
     }
 }
 
 impl ThumbInstruction for LSL {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        let imm = instruction.bits(6, 10);
-        let rm = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ShiftFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let ShiftFields { imm, rm, rd } = self.0;
         if imm == 0 {
             cpu.set_reg(rd.into(), cpu.get_reg(rm.into()));
         } else {
@@ -32,20 +55,22 @@ impl ThumbInstruction for LSL {
         cpu.set_flag(CPSR::Z, cpu.get_reg(rd.into()) == 0);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let imm = instruction.bits(6, 10);
-        let rm = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("LSL r{}, r{}, {:x}", rd, rm, imm)
+    fn decode(&self) -> Instr {
+        let ShiftFields { imm, rm, rd } = self.0;
+        flag_setting_instr(
+            Mnemonic::LSL,
+            vec![reg(rd), reg(rm), Operand::Imm(imm.into())],
+        )
     }
 }
 
 impl ThumbInstruction for LSR {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        let imm = instruction.bits(6, 10);
-        let rm = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ShiftFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let ShiftFields { imm, rm, rd } = self.0;
         if imm == 0 {
             cpu.set_flag(CPSR::C, cpu.get_reg(rm.into()).bit(31) == 1);
             cpu.set_reg(rd.into(), 0);
@@ -57,20 +82,24 @@ impl ThumbInstruction for LSR {
         cpu.set_flag(CPSR::Z, cpu.get_reg(rd.into()) == 0);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let imm = instruction.bits(6, 10);
-        let rm = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("LSR r{}, r{}, {:x}", rd, rm, imm)
+    fn decode(&self) -> Instr {
+        let ShiftFields { imm, rm, rd } = self.0;
+        // A shift by 0 encodes a shift by 32
+        let imm = if imm == 0 { 32 } else { imm };
+        flag_setting_instr(
+            Mnemonic::LSR,
+            vec![reg(rd), reg(rm), Operand::Imm(imm.into())],
+        )
     }
 }
 
 impl ThumbInstruction for ASR {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u16) {
-        let imm = instruction.bits(6, 10);
-        let rm = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
+    fn parse(instruction: u16) -> Self {
+        Self(ShiftFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut impl crate::bus::MemoryInterface) {
+        let ShiftFields { imm, rm, rd } = self.0;
         if imm == 0 {
             cpu.set_flag(CPSR::C, cpu.get_reg(rm.into()).bit(31) == 1);
             if cpu.get_reg(rm.into()).bit(31) == 0 {
@@ -86,11 +115,13 @@ impl ThumbInstruction for ASR {
         cpu.set_flag(CPSR::Z, cpu.get_reg(rd.into()) == 0);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let imm = instruction.bits(6, 10);
-        let rm = instruction.bits(3, 5);
-        let rd = instruction.bits(0, 2);
-
-        format!("ASR r{}, r{}, {:x}", rd, rm, imm)
+    fn decode(&self) -> Instr {
+        let ShiftFields { imm, rm, rd } = self.0;
+        // A shift by 0 encodes a shift by 32
+        let imm = if imm == 0 { 32 } else { imm };
+        flag_setting_instr(
+            Mnemonic::ASR,
+            vec![reg(rd), reg(rm), Operand::Imm(imm.into())],
+        )
     }
 }
@@ -1,22 +1,43 @@
-use crate::utils::{reg_list, AddressableBits};
+use crate::cpu::ir::{AddressingMode, Instr, Mnemonic, Operand};
+use crate::utils::AddressableBits;
 
-use super::ThumbInstruction;
+use super::{instr, reg, ThumbInstr, ThumbInstruction};
 
-struct Stmia;
-struct Ldmia;
+#[derive(Clone, Copy)]
+pub struct Stmia(MultipleFields);
+#[derive(Clone, Copy)]
+pub struct Ldmia(MultipleFields);
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+#[derive(Clone, Copy)]
+pub struct MultipleFields {
+    rn: u16,
+    reg_list: u16,
+}
+
+impl MultipleFields {
+    fn parse(instruction: u16) -> Self {
+        Self {
+            rn: instruction.bits(8, 10),
+            reg_list: instruction.bits(0, 7),
+        }
+    }
+}
+
+pub fn decode(instruction: u16) -> ThumbInstr {
     if instruction.bit(11) == 0 {
-        Box::new(Stmia)
+        Stmia::parse(instruction).into()
     } else {
-        Box::new(Ldmia)
+        Ldmia::parse(instruction).into()
     }
 }
 
 impl ThumbInstruction for Stmia {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u16) {
-        let rn = instruction.bits(8, 10);
-        let reg_list = instruction.bits(0, 7);
+    fn parse(instruction: u16) -> Self {
+        Self(MultipleFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let MultipleFields { rn, reg_list } = self.0;
         let start_address = cpu.get_reg(rn.into());
 
         if reg_list != 0 {
@@ -25,7 +46,7 @@ impl ThumbInstruction for Stmia {
 
             for i in 0..=7 {
                 if reg_list.bit(i) == 1 {
-                    bus.write(address, cpu.get_reg(i.try_into().unwrap()));
+                    cpu.write(bus, address, cpu.get_reg(i.try_into().unwrap()));
                     address += 4;
 
                     if first_reg {
@@ -36,29 +57,36 @@ impl ThumbInstruction for Stmia {
                 }
             }
         } else {
-            bus.write(start_address, cpu.get_reg(15) + 2);
+            cpu.write(bus, start_address, cpu.get_reg(15) + 2);
             cpu.set_reg(rn.into(), cpu.get_reg(rn.into()) + 0x40);
         }
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rn = instruction.bits(8, 10);
-        let regs = instruction.bits(0, 7);
-        format!("STMIA r{}!, {{{}}}", rn, reg_list(regs.into(), 8))
+    fn decode(&self) -> Instr {
+        let MultipleFields { rn, reg_list: regs } = self.0;
+        Instr {
+            writeback: true,
+            addressing: Some(AddressingMode::IncrementAfter),
+            ..instr(Mnemonic::STM, vec![reg(rn), Operand::RegList(regs)])
+        }
     }
 }
 
 impl ThumbInstruction for Ldmia {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u16) {
-        let rn = instruction.bits(8, 10);
-        let reg_list = instruction.bits(0, 7);
+    fn parse(instruction: u16) -> Self {
+        Self(MultipleFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let MultipleFields { rn, reg_list } = self.0;
         let start_address = cpu.get_reg(rn.into());
 
         if reg_list != 0 {
             let mut address = start_address;
             for i in 0..=7 {
                 if reg_list.bit(i) == 1 {
-                    cpu.set_reg(i.try_into().unwrap(), bus.read(address, cpu));
+                    let value = cpu.read(bus, address);
+                    cpu.set_reg(i.try_into().unwrap(), value);
                     address += 4;
                 }
             }
@@ -68,16 +96,19 @@ impl ThumbInstruction for Ldmia {
             );
         } else {
             // Empty register list loads value from memory into PC
-            let value = bus.read(start_address, cpu);
+            let value = cpu.read(bus, start_address);
             cpu.set_reg(15, value & 0xffff_fffc);
             cpu.flush_pipeline();
             cpu.set_reg(rn.into(), cpu.get_reg(rn.into()) + 0x40);
         }
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rn = instruction.bits(8, 10);
-        let regs = instruction.bits(0, 7);
-        format!("LDMIA r{}!, {{{}}}", rn, reg_list(regs.into(), 8))
+    fn decode(&self) -> Instr {
+        let MultipleFields { rn, reg_list: regs } = self.0;
+        Instr {
+            writeback: true,
+            addressing: Some(AddressingMode::IncrementAfter),
+            ..instr(Mnemonic::LDM, vec![reg(rn), Operand::RegList(regs)])
+        }
     }
 }
@@ -1,22 +1,35 @@
+use crate::cpu::ir::{Instr, MemoryOffset, Mnemonic};
 use crate::utils::AddressableBits;
 
-use super::ThumbInstruction;
+use super::{instr, memory, reg, ThumbInstruction};
 
-pub(super) struct LdrPc;
+#[derive(Clone, Copy)]
+pub struct LdrPc {
+    rd: u16,
+    imm: u16,
+}
 
 impl ThumbInstruction for LdrPc {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u16) {
-        let rd = instruction.bits(8, 10);
-        let imm = instruction.bits(0, 7);
+    fn parse(instruction: u16) -> Self {
+        Self {
+            rd: instruction.bits(8, 10),
+            imm: instruction.bits(0, 7),
+        }
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let Self { rd, imm } = *self;
 
         let address = (cpu.get_reg(15) & 0xffff_fffc) + imm as u32 * 4;
-        let value = bus.read(address, cpu);
+        let value = cpu.read(bus, address);
         cpu.set_reg(rd.into(), value);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd = instruction.bits(8, 10);
-        let imm = instruction.bits(0, 7);
-        format!("LDR r{}, [PC, {:x}]", rd, imm)
+    fn decode(&self) -> Instr {
+        let imm = u32::from(self.imm);
+        instr(
+            Mnemonic::LDR,
+            vec![reg(self.rd), memory(15, MemoryOffset::Imm(imm * 4))],
+        )
     }
 }
@@ -1,60 +1,85 @@
-use crate::utils::{reg_list, AddressableBits};
+use crate::cpu::ir::{Instr, Mnemonic, Operand};
+use crate::utils::AddressableBits;
 
-use super::ThumbInstruction;
+use super::{instr, ThumbInstr, ThumbInstruction};
 
-struct Push;
-struct Pop;
+#[derive(Clone, Copy)]
+pub struct Push {
+    /// r0-r7, then LR.
+    regs: u16,
+}
+
+#[derive(Clone, Copy)]
+pub struct Pop {
+    /// r0-r7, then PC.
+    regs: u16,
+}
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+pub fn decode(instruction: u16) -> ThumbInstr {
     let l = instruction.bit(11);
 
     if l == 0 {
-        Box::new(Push)
+        Push::parse(instruction).into()
     } else {
-        Box::new(Pop)
+        Pop::parse(instruction).into()
     }
 }
 
 impl ThumbInstruction for Push {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u16) {
-        let regs = instruction.bits(0, 8);
+    fn parse(instruction: u16) -> Self {
+        Self {
+            regs: instruction.bits(0, 8),
+        }
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let regs = self.regs;
         let start_address = cpu.get_reg(13) - 4 * regs.count_ones();
         let mut address = start_address;
 
         for i in 0..=7 {
             if regs.bit(i) == 1 {
-                bus.write(address, cpu.get_reg(i.try_into().unwrap()));
+                cpu.write(bus, address, cpu.get_reg(i.try_into().unwrap()));
                 address += 4;
             }
         }
         if regs.bit(8) == 1 {
-            bus.write(address, cpu.get_reg(14));
+            cpu.write(bus, address, cpu.get_reg(14));
         }
 
         cpu.set_reg(13, start_address);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let regs = reg_list(instruction.into(), 8);
-        let lr = if instruction.bit(8) == 1 { ",LR" } else { "" };
-        format!("PUSH {{{}{}}}", regs, lr)
+    fn decode(&self) -> Instr {
+        let mut regs = self.regs.bits(0, 7);
+        if self.regs.bit(8) == 1 {
+            regs |= 1 << 14;
+        }
+        instr(Mnemonic::PUSH, vec![Operand::RegList(regs)])
     }
 }
 
 impl ThumbInstruction for Pop {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u16) {
-        let regs = instruction.bits(0, 8);
+    fn parse(instruction: u16) -> Self {
+        Self {
+            regs: instruction.bits(0, 8),
+        }
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let regs = self.regs;
         let start_address = cpu.get_reg(13);
         let mut address = start_address;
 
         for i in 0..=7 {
             if regs.bit(i) == 1 {
-                cpu.set_reg(i.try_into().unwrap(), bus.read(address, cpu));
+                let value = cpu.read(bus, address);
+                cpu.set_reg(i.try_into().unwrap(), value);
                 address += 4;
             }
         }
         if regs.bit(8) == 1 {
-            let value = bus.read(address, cpu);
+            let value = cpu.read(bus, address);
             cpu.set_reg(15, value & 0xfffffffe);
             cpu.flush_pipeline();
             address += 4;
@@ -63,9 +88,11 @@ impl ThumbInstruction for Pop {
         cpu.set_reg(13, address);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let regs = reg_list(instruction.into(), 8);
-        let pc = if instruction.bit(8) == 1 { ",PC" } else { "" };
-        format!("POP {{{}{}}}", regs, pc)
+    fn decode(&self) -> Instr {
+        let mut regs = self.regs.bits(0, 7);
+        if self.regs.bit(8) == 1 {
+            regs |= 1 << 15;
+        }
+        instr(Mnemonic::POP, vec![Operand::RegList(regs)])
     }
 }
@@ -1,48 +1,76 @@
+use crate::cpu::ir::{Instr, MemoryOffset, Mnemonic, Operand};
 use crate::utils::AddressableBits;
 
-use super::ThumbInstruction;
+use super::{instr, memory, ThumbInstr, ThumbInstruction};
 
-struct Str;
-struct Ldr;
+#[derive(Clone, Copy)]
+pub struct Str(SpRelativeFields);
+#[derive(Clone, Copy)]
+pub struct Ldr(SpRelativeFields);
 
-pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
+#[derive(Clone, Copy)]
+pub struct SpRelativeFields {
+    rd: u32,
+    imm: u32,
+}
+
+impl SpRelativeFields {
+    fn parse(instruction: u16) -> Self {
+        Self {
+            rd: instruction.bits(8, 10).into(),
+            imm: instruction.bits(0, 7).into(),
+        }
+    }
+}
+
+pub fn decode(instruction: u16) -> ThumbInstr {
     let l = instruction.bit(11);
     if l == 0 {
-        Box::new(Str)
+        Str::parse(instruction).into()
     } else {
-        Box::new(Ldr)
+        Ldr::parse(instruction).into()
     }
 }
 
 impl ThumbInstruction for Str {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u16) {
-        let rd: u32 = instruction.bits(8, 10).into();
-        let imm: u32 = instruction.bits(0, 7).into();
+    fn parse(instruction: u16) -> Self {
+        Self(SpRelativeFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let SpRelativeFields { rd, imm } = self.0;
 
         let address = cpu.get_reg(13).wrapping_add(imm * 4);
-        bus.write(address, cpu.get_reg(rd));
+        cpu.write(bus, address, cpu.get_reg(rd));
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd: u32 = instruction.bits(8, 10).into();
-        let imm: u32 = instruction.bits(0, 7).into();
-        format!("STR r{}, [SP, {:x}]", rd, imm * 4)
+    fn decode(&self) -> Instr {
+        let SpRelativeFields { rd, imm } = self.0;
+        instr(
+            Mnemonic::STR,
+            vec![Operand::Reg(rd), memory(13, MemoryOffset::Imm(imm * 4))],
+        )
     }
 }
 
 impl ThumbInstruction for Ldr {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u16) {
-        let rd: u32 = instruction.bits(8, 10).into();
-        let imm: u32 = instruction.bits(0, 7).into();
+    fn parse(instruction: u16) -> Self {
+        Self(SpRelativeFields::parse(instruction))
+    }
+
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut impl crate::bus::MemoryInterface) {
+        let SpRelativeFields { rd, imm } = self.0;
 
         let address = cpu.get_reg(13).wrapping_add(imm * 4);
-        let data = bus.read(address, cpu);
+        let data = cpu.read(bus, address);
         cpu.set_reg(rd, data);
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let rd: u32 = instruction.bits(8, 10).into();
-        let imm: u32 = instruction.bits(0, 7).into();
-        format!("LDR r{}, [SP, {:x}]", rd, imm * 4)
+    fn decode(&self) -> Instr {
+        let SpRelativeFields { rd, imm } = self.0;
+        instr(
+            Mnemonic::LDR,
+            vec![Operand::Reg(rd), memory(13, MemoryOffset::Imm(imm * 4))],
+        )
     }
 }
@@ -1,14 +1,32 @@
-use crate::bus::Bus;
-use crate::cpu::{Cpu, Mode};
+use crate::bus::MemoryInterface;
+use crate::cpu::ir::{Instr, Mnemonic, Operand};
+use crate::cpu::{Cpu, Exception, Frame, Mode};
 use crate::utils::AddressableBits;
 
-use super::ThumbInstruction;
+use super::{instr, ThumbInstruction};
 
-pub struct Swi;
+#[derive(Clone, Copy)]
+pub struct Swi {
+    comment: u32,
+}
 
 impl ThumbInstruction for Swi {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, _: u16) {
-        cpu.set_reg_with_mode(14, Mode::Supervisor, cpu.get_reg(15) - 2);
+    fn parse(instruction: u16) -> Self {
+        Self {
+            comment: instruction.bits(0, 7).into(),
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu, _: &mut impl MemoryInterface) {
+        cpu.exception = Some(Exception::Swi(self.comment));
+        let return_address = cpu.get_reg(15) - 2;
+        cpu.call_stack.push(Frame {
+            function: 0x8,
+            call_site: return_address - 2,
+            return_address,
+            exception: cpu.exception,
+        });
+        cpu.set_reg_with_mode(14, Mode::Supervisor, return_address);
         *cpu.regs.spsr_mut(&Mode::Supervisor) = cpu.regs.cpsr;
         cpu.regs.cpsr = cpu.regs.cpsr.bits(6, 31) | 0b010011;
         cpu.regs.cpsr.mut_bit(7, true);
@@ -16,8 +34,7 @@ impl ThumbInstruction for Swi {
         cpu.flush_pipeline();
     }
 
-    fn disassembly(&self, instruction: u16) -> String {
-        let imm = instruction.bits(0, 23);
-        format!("SWI {:x}", imm)
+    fn decode(&self) -> Instr {
+        instr(Mnemonic::SWI, vec![Operand::Imm(self.comment)])
     }
 }
@@ -0,0 +1,477 @@
+//! A typed description of decoded instructions. Every ARM and THUMB instruction can be decoded
+//! into an [`Instr`], and the text disassembly is the `Display` output of that `Instr`.
+//!
+//! An opcode is parsed once, into the fields its executor runs on, and the `Instr` is built from
+//! those same fields. The IR is for disassembly and the assembler; the CPU never executes it.
+
+use std::fmt::{self, Display};
+
+use crate::utils::AddressableBits;
+
+use super::luts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    AND,
+    EOR,
+    SUB,
+    RSB,
+    ADD,
+    ADC,
+    SBC,
+    RSC,
+    TST,
+    TEQ,
+    CMP,
+    CMN,
+    ORR,
+    MOV,
+    BIC,
+    MVN,
+    // THUMB only, ARM code shifts through the second operand of a data processing instruction
+    LSL,
+    LSR,
+    ASR,
+    ROR,
+    NEG,
+    MRS,
+    MSR,
+    MUL,
+    MLA,
+    UMULL,
+    UMLAL,
+    SMULL,
+    SMLAL,
+    SWP,
+    SWPB,
+    B,
+    BL,
+    /// The first half of a THUMB long branch with link, which puts the high part of the
+    /// target in LR.
+    BL1,
+    /// The second half of a THUMB long branch with link, which branches to LR plus its offset.
+    BL2,
+    BX,
+    LDR,
+    LDRB,
+    LDRT,
+    LDRBT,
+    STR,
+    STRB,
+    STRT,
+    STRBT,
+    LDRH,
+    STRH,
+    LDRSB,
+    LDRSH,
+    LDM,
+    STM,
+    PUSH,
+    POP,
+    SWI,
+    CDP,
+    LDC,
+    STC,
+    MCR,
+    MRC,
+    /// An undefined or unimplemented instruction.
+    UND,
+}
+
+impl Mnemonic {
+    /// Whether this always sets the flags, and so is never written with an S suffix.
+    pub(crate) fn only_sets_flags(&self) -> bool {
+        matches!(*self, Self::TST | Self::TEQ | Self::CMP | Self::CMN)
+    }
+}
+
+impl Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::BL1 => write!(f, "BL(1)"),
+            Self::BL2 => write!(f, "BL(2)"),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    EQ,
+    NE,
+    CS,
+    CC,
+    MI,
+    PL,
+    VS,
+    VC,
+    HI,
+    LS,
+    GE,
+    LT,
+    GT,
+    LE,
+    AL,
+    NV,
+}
+
+impl Condition {
+    pub fn from_bits(bits: u32) -> Self {
+        match bits {
+            0b0000 => Self::EQ,
+            0b0001 => Self::NE,
+            0b0010 => Self::CS,
+            0b0011 => Self::CC,
+            0b0100 => Self::MI,
+            0b0101 => Self::PL,
+            0b0110 => Self::VS,
+            0b0111 => Self::VC,
+            0b1000 => Self::HI,
+            0b1001 => Self::LS,
+            0b1010 => Self::GE,
+            0b1011 => Self::LT,
+            0b1100 => Self::GT,
+            0b1101 => Self::LE,
+            0b1110 => Self::AL,
+            0b1111 => Self::NV,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::AL => Ok(()),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shift {
+    LSL,
+    LSR,
+    ASR,
+    ROR,
+    RRX,
+}
+
+impl Shift {
+    pub fn from_bits(bits: u32) -> Self {
+        match bits {
+            0b00 => Self::LSL,
+            0b01 => Self::LSR,
+            0b10 => Self::ASR,
+            0b11 => Self::ROR,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftAmount {
+    Imm(u32),
+    Reg(u32),
+}
+
+/// The offset added to or subtracted from the base register of a memory operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryOffset {
+    Imm(u32),
+    Reg(u32),
+    ShiftedReg { rm: u32, shift: Shift, amount: u32 },
+}
+
+/// Which of the CPSR or SPSR an MRS or MSR accesses, and for MSR, which fields it writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Psr {
+    pub spsr: bool,
+    /// Bits 16-19 of an MSR, in the order control, extension, status, flags.
+    pub fields: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Reg(u32),
+    Imm(u32),
+    ShiftedReg {
+        rm: u32,
+        shift: Shift,
+        amount: ShiftAmount,
+    },
+    /// A bitmask of registers, with r0 as bit 0.
+    RegList(u16),
+    Psr(Psr),
+    Memory {
+        rn: u32,
+        offset: MemoryOffset,
+        subtract: bool,
+    },
+    /// A branch target, relative to the address of the branch itself.
+    Relative(i32),
+    /// An absolute address, which a `Relative` operand becomes once the instruction's address is
+    /// known.
+    Address(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    /// `[rn, offset]`
+    Offset,
+    /// `[rn, offset]!`
+    PreIndexed,
+    /// `[rn], offset`
+    PostIndexed,
+    IncrementAfter,
+    IncrementBefore,
+    DecrementAfter,
+    DecrementBefore,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instr {
+    pub mnemonic: Mnemonic,
+    pub condition: Condition,
+    /// The S bit. For LDM and STM, this selects the user mode registers rather than the flags.
+    pub set_flags: bool,
+    pub writeback: bool,
+    pub addressing: Option<AddressingMode>,
+    pub operands: Vec<Operand>,
+}
+
+impl Instr {
+    pub fn new(mnemonic: Mnemonic, condition: Condition, operands: Vec<Operand>) -> Self {
+        Self {
+            mnemonic,
+            condition,
+            set_flags: false,
+            writeback: false,
+            addressing: None,
+            operands,
+        }
+    }
+
+    /// Returns the address this branches to when it's at `address`, if it's known ahead of time.
+    pub fn branch_target(&self, address: u32) -> Option<u32> {
+        match (self.mnemonic, self.operands.as_slice()) {
+            (Mnemonic::B | Mnemonic::BL, [Operand::Relative(offset)]) => {
+                Some(address.wrapping_add_signed(*offset))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+
+        let is_block_transfer = matches!(self.mnemonic, Mnemonic::LDM | Mnemonic::STM);
+        if self.set_flags && !is_block_transfer && !self.mnemonic.only_sets_flags() {
+            write!(f, "S")?;
+        }
+        if is_block_transfer {
+            match self.addressing {
+                Some(AddressingMode::IncrementAfter) => write!(f, "IA")?,
+                Some(AddressingMode::IncrementBefore) => write!(f, "IB")?,
+                Some(AddressingMode::DecrementAfter) => write!(f, "DA")?,
+                Some(AddressingMode::DecrementBefore) => write!(f, "DB")?,
+                _ => {}
+            }
+        }
+        write!(f, "{}", self.condition)?;
+
+        for (i, operand) in self.operands.iter().enumerate() {
+            write!(f, "{}", if i == 0 { " " } else { ", " })?;
+            match *operand {
+                Operand::Memory {
+                    rn,
+                    offset,
+                    subtract,
+                } => fmt_memory(f, self.addressing, rn, offset, subtract)?,
+                _ => write!(f, "{}", operand)?,
+            }
+
+            if i == 0 && is_block_transfer && self.writeback {
+                write!(f, "!")?;
+            }
+        }
+
+        if is_block_transfer && self.set_flags {
+            write!(f, "^")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Reg(reg) => write!(f, "{}", Reg(reg)),
+            Self::Imm(imm) => write!(f, "#{}", Imm(imm)),
+            Self::ShiftedReg {
+                rm,
+                shift: Shift::RRX,
+                ..
+            } => write!(f, "{}, RRX", Reg(rm)),
+            Self::ShiftedReg { rm, shift, amount } => match amount {
+                ShiftAmount::Imm(imm) => write!(f, "{}, {:?} #{}", Reg(rm), shift, Imm(imm)),
+                ShiftAmount::Reg(rs) => write!(f, "{}, {:?} {}", Reg(rm), shift, Reg(rs)),
+            },
+            Self::RegList(list) => {
+                let mut regs = (0..16u32)
+                    .filter(|i| u32::from(list).bit(*i as usize) == 1)
+                    .peekable();
+                write!(f, "{{")?;
+                let mut first = true;
+                while let Some(start) = regs.next() {
+                    let mut end = start;
+                    while regs.peek() == Some(&(end + 1)) {
+                        end = regs.next().unwrap();
+                    }
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    first = false;
+                    if start == end {
+                        write!(f, "{}", Reg(start))?;
+                    } else {
+                        write!(f, "{}-{}", Reg(start), Reg(end))?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            Self::Psr(Psr { spsr, fields }) => {
+                write!(f, "{}", if spsr { "SPSR" } else { "CPSR" })?;
+                if fields != 0 {
+                    write!(f, "_")?;
+                    for (bit, name) in [(3, "f"), (2, "s"), (1, "x"), (0, "c")] {
+                        if fields.bit(bit) == 1 {
+                            write!(f, "{}", name)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Self::Memory {
+                rn,
+                offset,
+                subtract,
+            } => fmt_memory(f, None, rn, offset, subtract),
+            Self::Relative(offset) if offset < 0 => write!(f, "$-{:#x}", offset.unsigned_abs()),
+            Self::Relative(offset) => write!(f, "$+{:#x}", offset),
+            Self::Address(address) => write!(f, "{:#010x}", address),
+        }
+    }
+}
+
+fn fmt_memory(
+    f: &mut fmt::Formatter<'_>,
+    addressing: Option<AddressingMode>,
+    rn: u32,
+    offset: MemoryOffset,
+    subtract: bool,
+) -> fmt::Result {
+    let sign = if subtract { "-" } else { "" };
+    let offset = match offset {
+        MemoryOffset::Imm(0) if !subtract => None,
+        MemoryOffset::Imm(imm) => Some(format!("#{}{}", sign, Imm(imm))),
+        MemoryOffset::Reg(rm) => Some(format!("{}{}", sign, Reg(rm))),
+        MemoryOffset::ShiftedReg { rm, shift, amount } => {
+            let shifted = Operand::ShiftedReg {
+                rm,
+                shift,
+                amount: ShiftAmount::Imm(amount),
+            };
+            Some(format!("{}{}", sign, shifted))
+        }
+    };
+
+    match (addressing, offset) {
+        (_, None) => write!(f, "[{}]", Reg(rn)),
+        (Some(AddressingMode::PostIndexed), Some(offset)) => write!(f, "[{}], {}", Reg(rn), offset),
+        (Some(AddressingMode::PreIndexed), Some(offset)) => write!(f, "[{}, {}]!", Reg(rn), offset),
+        (_, Some(offset)) => write!(f, "[{}, {}]", Reg(rn), offset),
+    }
+}
+
+struct Reg(u32);
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            13 => write!(f, "sp"),
+            14 => write!(f, "lr"),
+            15 => write!(f, "pc"),
+            reg => write!(f, "r{}", reg),
+        }
+    }
+}
+
+/// Small immediates are written in decimal and everything else in hex.
+struct Imm(u32);
+
+impl Display for Imm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 < 10 {
+            write!(f, "{}", self.0)
+        } else {
+            write!(f, "{:#x}", self.0)
+        }
+    }
+}
+
+/// Decodes an ARM opcode.
+pub fn decode_arm(opcode: u32) -> Instr {
+    let index = opcode.bits(20, 27) << 4 | opcode.bits(4, 7);
+    luts().arm[index as usize](opcode).decode(opcode)
+}
+
+/// Decodes a THUMB opcode.
+pub fn decode_thumb(opcode: u16) -> Instr {
+    luts().thumb[opcode.bits(4, 15) as usize](opcode).decode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembles_arm() {
+        assert_eq!(decode_arm(0xe3a00001).to_string(), "MOV r0, #1");
+        assert_eq!(decode_arm(0x10910002).to_string(), "ADDSNE r0, r1, r2");
+        assert_eq!(decode_arm(0xe59f1010).to_string(), "LDR r1, [pc, #0x10]");
+        assert_eq!(
+            decode_arm(0xe8bd4011).to_string(),
+            "LDMIA sp!, {r0, r4, lr}"
+        );
+        assert_eq!(decode_arm(0xeafffffe).to_string(), "B $+0x0");
+    }
+
+    #[test]
+    fn disassembles_thumb() {
+        assert_eq!(decode_thumb(0x2001).to_string(), "MOVS r0, #1");
+        assert_eq!(decode_thumb(0xb510).to_string(), "PUSH {r4, lr}");
+        assert_eq!(decode_thumb(0x6848).to_string(), "LDR r0, [r1, #4]");
+    }
+
+    #[test]
+    fn decodes_operands() {
+        let instr = decode_arm(0xe0910312);
+        assert_eq!(instr.mnemonic, Mnemonic::ADD);
+        assert!(instr.set_flags);
+        assert_eq!(
+            instr.operands[2],
+            Operand::ShiftedReg {
+                rm: 2,
+                shift: Shift::LSL,
+                amount: ShiftAmount::Reg(3),
+            }
+        );
+    }
+}
@@ -1,30 +1,51 @@
+pub mod assembler;
+mod block_cache;
+mod call_stack;
+mod coverage;
+pub mod disassembler;
 mod instrs;
+pub mod ir;
 mod regs;
+#[cfg(test)]
+mod single_step;
+pub mod trace;
 
 use std::collections::VecDeque;
+use std::sync::OnceLock;
 
 use serde::{Deserialize, Serialize};
+use tracing::debug;
 use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen::JsValue;
-use web_sys::console;
 
-use crate::bus::Bus;
+use crate::bus::{Access, AccessKind, MemoryInterface};
 use crate::utils::AddressableBits;
 
-pub use self::instrs::arm::ArmInstruction;
-pub use self::instrs::thumb::ThumbInstruction;
+pub use self::instrs::arm::ArmInstr;
+pub use self::instrs::thumb::ThumbInstr;
+use self::block_cache::{BlockCache, Fetched};
+pub use self::call_stack::Frame;
+use self::call_stack::CallStack;
+pub use self::coverage::{Coverage, CoveredRange};
 use self::regs::Regs;
+use self::trace::{TraceEntry, Tracer};
+
+/// Instruction parsers, indexed by bits 20-27 and 4-7 of an ARM opcode or bits 4-15 of a THUMB
+/// opcode. These are built once and shared by every `Cpu`.
+pub struct Luts {
+    pub arm: [fn(u32) -> ArmInstr; 0x1000],
+    pub thumb: [fn(u16) -> ThumbInstr; 0x1000],
+}
 
-type ArmLut = [Box<dyn ArmInstruction>; 0x1000];
-type ThumbLut = [Box<dyn ThumbInstruction>; 0x1000];
+static LUTS: OnceLock<Luts> = OnceLock::new();
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum State {
     ARM,
     Thumb,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Mode {
     User,
@@ -36,6 +57,73 @@ pub enum Mode {
     Abort,
 }
 
+impl Mode {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "user" | "usr" => Ok(Self::User),
+            "system" | "sys" => Ok(Self::System),
+            "irq" => Ok(Self::IRQ),
+            "fiq" => Ok(Self::FIQ),
+            "supervisor" | "svc" => Ok(Self::Supervisor),
+            "undefined" | "und" => Ok(Self::Undefined),
+            "abort" | "abt" => Ok(Self::Abort),
+            _ => Err(format!("`{}` isn't a mode", text)),
+        }
+    }
+}
+
+/// A bit of the CPSR or an SPSR that the debugger can set or clear by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    N,
+    Z,
+    C,
+    V,
+    /// IRQs disabled.
+    I,
+    /// FIQs disabled.
+    F,
+    /// THUMB state.
+    T,
+}
+
+impl Flag {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "n" => Ok(Self::N),
+            "z" => Ok(Self::Z),
+            "c" => Ok(Self::C),
+            "v" => Ok(Self::V),
+            "i" => Ok(Self::I),
+            "f" => Ok(Self::F),
+            "t" => Ok(Self::T),
+            _ => Err(format!("expected n, z, c, v, i, f or t, found `{}`", text)),
+        }
+    }
+
+    pub fn bit(self) -> u32 {
+        match self {
+            Self::N => 31,
+            Self::Z => 30,
+            Self::C => 29,
+            Self::V => 28,
+            Self::I => 7,
+            Self::F => 6,
+            Self::T => 5,
+        }
+    }
+}
+
+/// An exception the CPU took, which the debugger can stop on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Exception {
+    /// An IRQ, with the interrupts that were pending as a mask of IE bits.
+    Irq(u16),
+    /// A software interrupt, with the number of the BIOS function it calls.
+    Swi(u32),
+    Undefined,
+}
+
 enum CPSR {
     T,
     V,
@@ -77,32 +165,63 @@ impl CpuDetails {
     }
 }
 
+/// The CPU, which save states serialize apart from the caches and what the debugger has set up.
+#[derive(Serialize, Deserialize)]
 pub struct Cpu {
     regs: Regs,
+    #[serde(skip, default = "luts")]
+    luts: &'static Luts,
+    #[serde(skip)]
+    block_cache: BlockCache,
 
-    instr_pipeline: [u32; 2],
+    instr_pipeline: [Fetched; 2],
     instr_pipeline_size: usize,
     cycle: u128,
+    /// The cycles the bus has reported for every access so far.
+    memory_cycles: u128,
+    /// The cycles of those that were spent waiting, beyond the one every access takes.
+    waitstates: u128,
+    /// The kind and address of the access that would be sequential, following the last one.
+    next_sequential: Option<(AccessKind, u32)>,
     old_interrupt: bool,
+    /// The last exception taken, until the debugger takes it.
+    #[serde(skip)]
+    exception: Option<Exception>,
 
     pc_history: VecDeque<u32>,
+    call_stack: CallStack,
+    #[serde(skip)]
+    tracer: Option<Tracer>,
+    #[serde(skip)]
+    coverage: Option<Coverage>,
 }
 
 impl Default for Cpu {
     fn default() -> Self {
         let mut cpu = Self {
             regs: Regs::default(),
+            luts: luts(),
+            block_cache: BlockCache::default(),
 
-            instr_pipeline: [0, 0],
+            instr_pipeline: [Fetched::decode(0, State::ARM, luts()); 2],
             instr_pipeline_size: 0,
 
             cycle: 0,
+            memory_cycles: 0,
+            waitstates: 0,
+            next_sequential: None,
 
             old_interrupt: false,
+            exception: None,
 
             pc_history: VecDeque::new(),
+            call_stack: CallStack::default(),
+            tracer: None,
+            coverage: None,
         };
-        cpu.set_mode(Mode::User);
+        // Reset leaves the CPU in supervisor mode with IRQs and FIQs disabled, for the BIOS
+        cpu.set_mode(Mode::Supervisor);
+        cpu.regs.cpsr |= 0xc0;
         cpu
     }
 }
@@ -112,8 +231,21 @@ impl Cpu {
         self.pc_history.iter().copied().collect()
     }
 
-    fn handle_interrupt(&mut self) {
-        self.set_reg_with_mode(14, Mode::IRQ, self.get_executing_instruction_pc() + 4);
+    /// The frames of the shadow call stack, outermost first.
+    pub fn call_stack(&self) -> &[Frame] {
+        self.call_stack.frames()
+    }
+
+    fn handle_interrupt(&mut self, pending_interrupts: u16) {
+        self.exception = Some(Exception::Irq(pending_interrupts));
+        let pc = self.get_executing_instruction_pc();
+        self.call_stack.push(Frame {
+            function: 0x18,
+            call_site: pc,
+            return_address: pc,
+            exception: self.exception,
+        });
+        self.set_reg_with_mode(14, Mode::IRQ, pc + 4);
         self.regs.spsr_irq = self.regs.cpsr;
 
         self.set_mode(Mode::IRQ);
@@ -126,6 +258,37 @@ impl Cpu {
         self.flush_pipeline();
     }
 
+    /// Takes the undefined instruction exception for the instruction that's executing. The GBA
+    /// has no coprocessors to handle anything undefined, so this ends up in the BIOS.
+    fn undefined_instruction(&mut self) {
+        self.exception = Some(Exception::Undefined);
+        let size = match self.get_state() {
+            State::ARM => 4,
+            State::Thumb => 2,
+        };
+        let return_address = self.get_reg(15) - size;
+        self.call_stack.push(Frame {
+            function: 0x4,
+            call_site: return_address - size,
+            return_address,
+            exception: self.exception,
+        });
+        self.set_reg_with_mode(14, Mode::Undefined, return_address);
+        self.regs.spsr_und = self.regs.cpsr;
+
+        self.set_mode(Mode::Undefined);
+        self.set_state(State::ARM);
+        self.regs.cpsr.mut_bit(7, true);
+
+        self.set_reg(15, 0x4);
+        self.flush_pipeline();
+    }
+
+    /// Returns the exception taken since the last call, if there was one.
+    pub fn take_exception(&mut self) -> Option<Exception> {
+        self.exception.take()
+    }
+
     pub fn get_state(&self) -> State {
         if self.get_cpsr_bit(CPSR::T) == 0 {
             State::ARM
@@ -141,7 +304,7 @@ impl Cpu {
         }
     }
 
-    fn get_mode(&self) -> Mode {
+    pub(crate) fn get_mode(&self) -> Mode {
         // highest bit is always set to 1 - TODO: verify this claim
         match self.regs.cpsr.bits(0, 4) | 0x10 {
             0b10000 => Mode::User,
@@ -184,20 +347,83 @@ impl Cpu {
     }
 
     fn get_reg_internal(&self, idx: u32) -> u32 {
-        self.regs.get(idx, &self.get_mode())
+        // Only r8-r14 are banked, so the rest don't need the mode worked out
+        match idx {
+            8..=14 => self.regs.get(idx, &self.get_mode()),
+            _ => self.regs.get(idx, &Mode::User),
+        }
+    }
+
+    /// Reads a register as it's banked in the current mode. r15 is where the next fetch is from,
+    /// rather than the value an instruction would read.
+    pub fn reg(&self, idx: u32) -> u32 {
+        self.get_reg_internal(idx)
+    }
+
+    pub fn cpsr(&self) -> u32 {
+        self.regs.cpsr
+    }
+
+    /// Reads a register as it's banked in `mode`, whichever mode the CPU is in.
+    pub fn reg_with_mode(&self, idx: u32, mode: Mode) -> u32 {
+        self.regs.get(idx, &mode)
+    }
+
+    /// The SPSR of `mode`, or `None` for User and System mode, which don't have one.
+    pub fn spsr(&self, mode: Mode) -> Option<u32> {
+        match mode {
+            Mode::User | Mode::System => None,
+            _ => Some(self.regs.spsr(&mode)),
+        }
+    }
+
+    /// Writes the SPSR of `mode`, if it has one.
+    pub fn write_spsr(&mut self, mode: Mode, value: u32) {
+        if self.spsr(mode).is_some() {
+            *self.regs.spsr_mut(&mode) = value;
+        }
+    }
+
+    /// Writes a register as it's banked in the current mode. Writing r15 branches to the value,
+    /// so the pipeline has to be refilled before the next instruction executes.
+    pub fn write_reg(&mut self, idx: u32, value: u32) {
+        if idx == 15 {
+            let pc = match self.get_state() {
+                State::ARM => value & !3,
+                State::Thumb => value & !1,
+            };
+            self.set_reg(15, pc);
+            self.flush_pipeline();
+        } else {
+            self.set_reg(idx, value);
+        }
+    }
+
+    /// Writes the CPSR. Switching state flushes the pipeline, so that the instruction that was
+    /// about to execute is fetched again in the new state.
+    pub fn write_cpsr(&mut self, value: u32) {
+        let pc = self.get_executing_instruction_pc();
+        let state = self.get_state();
+        self.regs.cpsr = value;
+        if self.get_state() != state {
+            self.write_reg(15, pc);
+        }
     }
 
     /// Only correct outside of .tick() calls
     pub fn get_executing_instruction_pc(&self) -> u32 {
         match self.get_state() {
-            State::ARM => self.get_reg_internal(15) - 8,
-            State::Thumb => self.get_reg_internal(15) - 4,
+            State::ARM => self.regs.pc().wrapping_sub(8),
+            State::Thumb => self.regs.pc().wrapping_sub(4),
         }
     }
 
     fn set_reg(&mut self, idx: u32, val: u32) {
-        let mode = &self.get_mode();
-        *self.regs.get_mut(idx, mode) = val;
+        let mode = match idx {
+            8..=14 => self.get_mode(),
+            _ => Mode::User,
+        };
+        *self.regs.get_mut(idx, &mode) = val;
     }
 
     pub fn set_reg_with_mode(&mut self, idx: u32, mode: Mode, val: u32) {
@@ -228,17 +454,29 @@ impl Cpu {
         self.instr_pipeline_size = 0;
     }
 
+    /// Whether the pipeline has been refilled since the last branch, so the next tick executes
+    /// the instruction at `get_executing_instruction_pc`.
+    pub fn pipeline_full(&self) -> bool {
+        self.instr_pipeline_size == 2
+    }
+
     pub fn skip_bios(&mut self) {
+        self.skip_bios_to(0x8000000);
+    }
+
+    /// Sets up the registers as the BIOS leaves them, and starts at `entry`, which is where a
+    /// cartridge or multiboot image starts.
+    pub fn skip_bios_to(&mut self, entry: u32) {
         //self.regs.visible[0] = 0xca5;
         *self.regs.get_mut(13, &Mode::User) = 0x3007f00;
         *self.regs.get_mut(13, &Mode::IRQ) = 0x3007fa0;
         *self.regs.get_mut(13, &Mode::Supervisor) = 0x3007fe0;
-        *self.regs.get_mut(15, &Mode::User) = 0x8000000;
+        *self.regs.get_mut(15, &Mode::User) = entry;
         self.regs.cpsr = 0xdf;
         //self.mode = Mode::System;
     }
 
-    pub fn tick(&mut self, bus: &mut Bus, arm_lut: &ArmLut, thumb_lut: &ThumbLut) {
+    pub fn tick(&mut self, bus: &mut impl MemoryInterface) {
         if self.instr_pipeline_size == 2 {
             self.pc_history
                 .push_front(self.get_executing_instruction_pc());
@@ -247,22 +485,21 @@ impl Cpu {
             }
         }
 
-        let ime_flag = bus.read_byte(0x4000208, self);
-        let ie_flag = bus.read_half(0x4000200, self);
-        let if_flag = bus.read_half(0x4000202, self);
-
-        let new_interrupt = ime_flag.bit(0) == 1 && ie_flag & if_flag != 0;
+        let pending_interrupts = bus.pending_interrupts();
+        let new_interrupt = pending_interrupts != 0;
 
         if !self.old_interrupt && new_interrupt {
-            self.handle_interrupt();
-            console::log_1(&format!("handling interrupt {:b}", ie_flag & if_flag).into());
+            self.handle_interrupt(pending_interrupts);
+            debug!("handling interrupt {:b}", pending_interrupts);
         }
         self.old_interrupt = new_interrupt;
 
+        let pc = self.get_executing_instruction_pc();
+        let mode = self.get_mode();
         let instruction = self.instr_pipeline[0];
 
         self.instr_pipeline[0] = self.instr_pipeline[1];
-        self.instr_pipeline[1] = bus.read(self.regs.pc(), self);
+        self.fetch(bus);
 
         match self.get_state() {
             State::ARM => *self.regs.pc_mut() += 4,
@@ -270,7 +507,21 @@ impl Cpu {
         }
 
         if self.instr_pipeline_size == 2 {
-            self.execute(bus, instruction, arm_lut, thumb_lut);
+            if self.tracer.is_some() {
+                self.trace(instruction.opcode);
+            }
+            let state = self.get_state();
+            if let Some(coverage) = &mut self.coverage {
+                coverage.mark(pc, state);
+            }
+            let next = match state {
+                State::ARM => pc.wrapping_add(4),
+                State::Thumb => pc.wrapping_add(2),
+            };
+            self.execute_fetched(bus, instruction);
+            if self.instr_pipeline_size == 0 {
+                self.track_branch(pc, next, mode);
+            }
         } else {
             self.instr_pipeline_size += 1;
         }
@@ -278,6 +529,171 @@ impl Cpu {
         self.cycle += 1;
     }
 
+    /// Updates the call stack after the instruction at `pc` branched, where `next` is the
+    /// instruction after it and `mode` is the mode it ran in.
+    fn track_branch(&mut self, pc: u32, next: u32, mode: Mode) {
+        let target = self.regs.pc();
+        self.call_stack.branch(target);
+        // Exceptions change mode, and push their own frames
+        if self.get_mode() == mode && self.get_reg_internal(14) & !1 == next {
+            self.call_stack.push(Frame {
+                function: target,
+                call_site: pc,
+                return_address: next,
+                exception: None,
+            });
+        }
+    }
+
+    /// Fetches the instruction at r15 into the back of the pipeline. It's written there directly
+    /// rather than returned, as moving it about costs as much as fetching it from the cache.
+    #[inline]
+    fn fetch(&mut self, bus: &mut impl MemoryInterface) {
+        let pc = self.regs.pc();
+        let state = self.get_state();
+        let size = match state {
+            State::ARM => 4,
+            State::Thumb => 2,
+        };
+        let access = self.access(AccessKind::Code, pc, size);
+        if let Some(fetched) = self.block_cache.fetch(pc, state, self.luts, bus) {
+            self.instr_pipeline[1] = *fetched;
+            self.count_cycles(bus.access_cycles(pc, size, access));
+            return;
+        }
+        let (opcode, cycles) = match state {
+            State::ARM => bus.read(pc, access, self),
+            State::Thumb => bus.read_half(pc, access, self),
+        };
+        self.count_cycles(cycles);
+        self.instr_pipeline[1] = Fetched::decode(opcode, state, self.luts);
+    }
+
+    /// Works out whether an access of `size` bytes at `index` is sequential, and remembers it for
+    /// the next access.
+    fn access(&mut self, kind: AccessKind, index: u32, size: u32) -> Access {
+        let sequential = self.next_sequential == Some((kind, index));
+        self.next_sequential = Some((kind, index.wrapping_add(size)));
+        Access { kind, sequential }
+    }
+
+    fn read(&mut self, bus: &mut impl MemoryInterface, index: u32) -> u32 {
+        let access = self.access(AccessKind::Data, index, 4);
+        let (value, cycles) = bus.read(index, access, self);
+        self.count_cycles(cycles);
+        value
+    }
+
+    fn read_half(&mut self, bus: &mut impl MemoryInterface, index: u32) -> u32 {
+        let access = self.access(AccessKind::Data, index, 2);
+        let (value, cycles) = bus.read_half(index, access, self);
+        self.count_cycles(cycles);
+        value
+    }
+
+    fn read_signed_half(&mut self, bus: &mut impl MemoryInterface, index: u32) -> u32 {
+        let access = self.access(AccessKind::Data, index, 2);
+        let (value, cycles) = bus.read_signed_half(index, access, self);
+        self.count_cycles(cycles);
+        value
+    }
+
+    fn read_byte(&mut self, bus: &mut impl MemoryInterface, index: u32) -> u8 {
+        let access = self.access(AccessKind::Data, index, 1);
+        let (value, cycles) = bus.read_byte(index, access, self);
+        self.count_cycles(cycles);
+        value
+    }
+
+    fn write(&mut self, bus: &mut impl MemoryInterface, index: u32, value: u32) {
+        let access = self.access(AccessKind::Data, index, 4);
+        self.count_cycles(bus.write(index, value, access, self));
+    }
+
+    fn write_half(&mut self, bus: &mut impl MemoryInterface, index: u32, value: u16) {
+        let access = self.access(AccessKind::Data, index, 2);
+        self.count_cycles(bus.write_half(index, value, access, self));
+    }
+
+    fn write_byte(&mut self, bus: &mut impl MemoryInterface, index: u32, value: u8) {
+        let access = self.access(AccessKind::Data, index, 1);
+        self.count_cycles(bus.write_byte(index, value, access, self));
+    }
+
+    fn count_cycles(&mut self, cycles: u32) {
+        self.memory_cycles += u128::from(cycles);
+        self.waitstates += u128::from(cycles.saturating_sub(1));
+    }
+
+    /// The cycles spent on memory accesses so far, as reported by the bus.
+    pub fn memory_cycles(&self) -> u128 {
+        self.memory_cycles
+    }
+
+    /// The waitstates in `memory_cycles`, which are the cycles accesses took beyond the first.
+    pub fn waitstates(&self) -> u128 {
+        self.waitstates
+    }
+
+    fn trace(&mut self, opcode: u32) {
+        let Some(mut tracer) = self.tracer.take() else {
+            return;
+        };
+
+        let state = self.get_state();
+        let pc = match state {
+            State::ARM => self.get_reg(15) - 8,
+            State::Thumb => self.get_reg(15) - 4,
+        };
+        tracer.trace(&TraceEntry {
+            pc,
+            fetched: Fetched::decode(opcode, state, self.luts),
+            state,
+            regs: std::array::from_fn(|reg| self.get_reg(reg as u32)),
+            cpsr: self.regs.cpsr,
+            cycle: self.cycle,
+        });
+        self.tracer = Some(tracer);
+    }
+
+    /// Traces every executed instruction to `tracer`, or stops tracing if it's `None`. Returns
+    /// the previous tracer.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    /// Records which instructions are executed in `coverage`, or stops recording if it's `None`.
+    /// Returns what was recorded before.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) -> Option<Coverage> {
+        std::mem::replace(&mut self.coverage, coverage)
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Takes the state of the machine from `saved`, which came from a save state, and keeps what
+    /// the debugger has set up.
+    pub(crate) fn restore(&mut self, saved: Cpu) {
+        let exception = self.exception.take();
+        let tracer = self.tracer.take();
+        let coverage = self.coverage.take();
+        let enabled = self.block_cache.enabled;
+        *self = Cpu {
+            exception,
+            tracer,
+            coverage,
+            ..saved
+        };
+        self.enable_block_cache(enabled);
+    }
+
+    /// Enables or disables caching decoded blocks of code. Disabling it also empties the cache.
+    pub fn enable_block_cache(&mut self, enabled: bool) {
+        self.block_cache.enabled = enabled;
+        self.block_cache.clear();
+    }
+
     pub fn in_privileged_mode(&self) -> bool {
         match self.get_mode() {
             Mode::User => false,
@@ -306,47 +722,54 @@ impl Cpu {
 
     pub fn prefetched_instruction(&self) -> u32 {
         match self.get_state() {
-            State::ARM => self.instr_pipeline[0],
+            State::ARM => self.instr_pipeline[0].opcode,
             State::Thumb => 0, // TODO: implement this properly
         }
     }
 }
 
-pub fn generate_luts() -> (ArmLut, ThumbLut) {
-    let arm_lut = (0u32..0x1000)
+/// Returns the shared instruction lookup tables, building them on first use.
+pub fn luts() -> &'static Luts {
+    LUTS.get_or_init(generate_luts)
+}
+
+fn generate_luts() -> Luts {
+    let arm = (0u32..0x1000)
         .into_iter()
         .map(|val| {
             let low = val.bits(0, 3);
             let high = val.bits(4, 11);
             let instruction = (high << 20) | (low << 4);
-            Cpu::decode_arm(instruction)
+            Cpu::decode_arm(instruction).parser()
         })
-        .collect::<Vec<Box<dyn ArmInstruction>>>()
+        .collect::<Vec<_>>()
         .try_into()
         .unwrap();
 
-    let thumb_lut = (0u16..0x1000)
+    let thumb = (0u16..0x1000)
         .into_iter()
         .map(|val| {
             let instruction = val << 4;
-            Cpu::decode_thumb(instruction)
+            Cpu::decode_thumb(instruction).parser()
         })
-        .collect::<Vec<Box<dyn ThumbInstruction>>>()
+        .collect::<Vec<_>>()
         .try_into()
         .unwrap();
 
-    (arm_lut, thumb_lut)
+    Luts { arm, thumb }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::bus::Bus;
+
     use super::*;
 
-    fn test_div(r0: u32, r1: u32) {
+    /// Runs DIV in the official BIOS.
+    fn test_div(bios: &[u8], r0: u32, r1: u32) {
         let mut cpu = Cpu::default();
         let mut bus = Bus::default();
-        let (arm_lut, thumb_lut) = generate_luts();
-        bus.set_bios(include_bytes!("../../cog-bios.bin"));
+        bus.set_bios(bios);
 
         cpu.skip_bios();
         cpu.set_reg_with_mode(0, Mode::System, r0);
@@ -356,7 +779,7 @@ mod tests {
         cpu.flush_pipeline();
 
         while cpu.get_reg(15) - 4 != 0x400 {
-            cpu.tick(&mut bus, &arm_lut, &thumb_lut);
+            cpu.tick(&mut bus);
         }
 
         let expected0 = (r0 as i32) / (r1 as i32);
@@ -372,11 +795,11 @@ mod tests {
         assert_eq!(result3, expected3, "og abs div {} by {}", r0, r1);
     }
 
+    /// Runs DIV in the Cult of GBA BIOS, which is the one that's checked in.
     fn test_div_cultofgba(r0: u32, r1: u32) {
         let mut cpu = Cpu::default();
         let mut bus = Bus::default();
-        let (arm_lut, thumb_lut) = generate_luts();
-        bus.set_bios(include_bytes!("../../bios.bin"));
+        bus.set_bios(include_bytes!("../../cog-bios.bin"));
 
         cpu.skip_bios();
         cpu.set_reg_with_mode(0, Mode::System, r0);
@@ -386,7 +809,7 @@ mod tests {
         cpu.flush_pipeline();
 
         while cpu.get_reg(15) - 4 != 0x790 {
-            cpu.tick(&mut bus, &arm_lut, &thumb_lut);
+            cpu.tick(&mut bus);
         }
 
         let expected0 = (r0 as i32) / (r1 as i32);
@@ -402,35 +825,162 @@ mod tests {
         assert_eq!(result3, expected3, "cult abs div {} by {}", r0, r1);
     }
 
+    #[test]
+    fn luts_are_shared() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Luts>();
+
+        assert!(std::ptr::eq(Cpu::default().luts, Cpu::default().luts));
+    }
+
+    #[test]
+    fn luts_parse_like_the_decoder() {
+        // The parsers are picked with the other bits of the opcode clear, so check they give the
+        // same instruction as decoding the whole opcode
+        let mut opcode: u32 = 0x1234_5678;
+        for _ in 0..0x10000 {
+            opcode ^= opcode << 13;
+            opcode ^= opcode >> 17;
+            opcode ^= opcode << 5;
+
+            let index = opcode.bits(20, 27) << 4 | opcode.bits(4, 7);
+            let parsed = luts().arm[index as usize](opcode);
+            let decoded = Cpu::decode_arm(opcode);
+            assert_eq!(
+                parsed.disassembly(opcode),
+                decoded.disassembly(opcode),
+                "{:#010x}",
+                opcode
+            );
+
+            let opcode = opcode as u16;
+            let parsed = luts().thumb[opcode.bits(4, 15) as usize](opcode);
+            let decoded = Cpu::decode_thumb(opcode);
+            assert_eq!(
+                parsed.disassembly(),
+                decoded.disassembly(),
+                "{:#06x}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn runs_assembled_code() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+        let source = "
+            MOV r0, #6
+            MOV r1, #7
+            MUL r2, r0, r1
+        ";
+        bus.poke(
+            0x3000000,
+            &assembler::assemble(source, 0x3000000, State::ARM).unwrap(),
+        );
+
+        cpu.set_reg(15, 0x3000000);
+        cpu.flush_pipeline();
+        for _ in 0..5 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!(cpu.get_reg(2), 42);
+    }
+
+    #[test]
+    fn multiplies_and_adds_with_wraparound() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+        let source = "
+            MVN r0, #0
+            MOV r1, #2
+            MOV r2, #5
+            MLAS r3, r0, r1, r2
+        ";
+        bus.poke(
+            0x3000000,
+            &assembler::assemble(source, 0x3000000, State::ARM).unwrap(),
+        );
+
+        cpu.set_reg(15, 0x3000000);
+        cpu.flush_pipeline();
+        for _ in 0..6 {
+            cpu.tick(&mut bus);
+        }
+        // 0xffffffff * 2 + 5 wraps around to 3, as it does on the hardware
+        assert_eq!(cpu.get_reg(3), 3);
+        assert_eq!(cpu.get_cpsr_bit(CPSR::N), 0);
+        assert_eq!(cpu.get_cpsr_bit(CPSR::Z), 0);
+    }
+
+    #[test]
+    fn counts_memory_cycles() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+        let source = "
+            LDR r0, [r1]
+            MOV r0, r0
+            MOV r0, r0
+            MOV r0, r0
+        ";
+        bus.poke(
+            0x8000000,
+            &assembler::assemble(source, 0x8000000, State::ARM).unwrap(),
+        );
+
+        cpu.set_reg(1, 0x2000000);
+        cpu.set_reg(15, 0x8000000);
+        cpu.flush_pipeline();
+        // A non-sequential fetch from ROM, then two sequential ones
+        for _ in 0..2 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!(cpu.memory_cycles(), 8 + 6);
+        // The load reads from EWRAM, after which the next fetch isn't sequential
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.memory_cycles(), 8 + 6 + 6 + 6);
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.memory_cycles(), 8 + 6 + 6 + 6 + 8);
+    }
+
     #[test]
     fn test_div_1_by_1() {
         test_div_cultofgba(1, 1);
-        test_div(1, 1);
     }
 
     #[test]
     fn test_div_2_by_1() {
         test_div_cultofgba(2, 1);
-        test_div(2, 1);
     }
 
     #[test]
     fn test_div_many_by_1() {
         for i in 0..0x100 {
             test_div_cultofgba(i, 1);
-            test_div(i, 1);
         }
     }
 
     #[test]
     fn test_div_by_10() {
         test_div_cultofgba(123, 10);
-        test_div(123, 10);
     }
 
     #[test]
     fn test_div_by_16() {
         test_div_cultofgba(0xa000000, 0x10);
-        test_div(0xa000000, 0x10);
+    }
+
+    /// The official BIOS can't be checked in, so this needs a copy of it in `bios.bin`.
+    #[test]
+    #[ignore = "needs the official BIOS in gba-core/bios.bin"]
+    fn test_div_official_bios() {
+        let bios = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/bios.bin")).unwrap();
+        let many_by_1 = (0..0x100).map(|i| (i, 1));
+        for (r0, r1) in [(1, 1), (2, 1), (123, 10), (0xa000000, 0x10)]
+            .into_iter()
+            .chain(many_by_1)
+        {
+            test_div(&bios, r0, r1);
+        }
     }
 }
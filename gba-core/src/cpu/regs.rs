@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use super::Mode;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Regs {
     sys_user: [u32; 16],
     fiq: [u32; 7],
@@ -0,0 +1,451 @@
+//! Runs ARM7TDMI single-step test vectors, in the JSON format of the SingleStepTests project.
+//! Each vector gives the CPU state before and after one instruction, along with every memory
+//! access the instruction makes.
+//!
+//! `tests/single_step` has a few vectors for each class of opcode, written by hand with the final
+//! states worked out from the ARM7TDMI manual, and they always run. The full set is too big to
+//! check in, so it's an ignored test: point `SINGLE_STEP_TESTS` at its JSON files and run it with
+//! `--ignored`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::bus::{Access, AccessKind, MemoryInterface};
+
+use super::block_cache::Fetched;
+use super::{ir, Cpu, Mode};
+
+const FETCH: u8 = 0;
+const READ: u8 = 1;
+const WRITE: u8 = 2;
+
+/// The banked registers in each vector, and the modes they belong to.
+const BANKS: [(Mode, u32); 5] = [
+    (Mode::FIQ, 8),
+    (Mode::Supervisor, 13),
+    (Mode::Abort, 13),
+    (Mode::IRQ, 13),
+    (Mode::Undefined, 13),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct CpuState {
+    #[serde(rename = "R")]
+    r: [u32; 16],
+    #[serde(rename = "R_fiq")]
+    r_fiq: [u32; 7],
+    #[serde(rename = "R_svc")]
+    r_svc: [u32; 2],
+    #[serde(rename = "R_abt")]
+    r_abt: [u32; 2],
+    #[serde(rename = "R_irq")]
+    r_irq: [u32; 2],
+    #[serde(rename = "R_und")]
+    r_und: [u32; 2],
+    #[serde(rename = "CPSR")]
+    cpsr: u32,
+    /// In the order fiq, svc, abt, irq, und.
+    #[serde(rename = "SPSR")]
+    spsr: [u32; 5],
+    pipeline: [u32; 2],
+}
+
+impl CpuState {
+    fn banks(&self) -> [&[u32]; 5] {
+        [
+            &self.r_fiq,
+            &self.r_svc,
+            &self.r_abt,
+            &self.r_irq,
+            &self.r_und,
+        ]
+    }
+
+    fn banks_mut(&mut self) -> [&mut [u32]; 5] {
+        [
+            &mut self.r_fiq,
+            &mut self.r_svc,
+            &mut self.r_abt,
+            &mut self.r_irq,
+            &mut self.r_und,
+        ]
+    }
+
+    /// Puts `cpu` in this state, part way through a run with a full pipeline.
+    fn load(&self, cpu: &mut Cpu) {
+        cpu.regs.cpsr = self.cpsr;
+        for reg in 0..16 {
+            *cpu.regs.get_mut(reg, &Mode::User) = self.r[reg as usize];
+        }
+        for ((mode, first), bank) in BANKS.iter().zip(self.banks()) {
+            for (reg, value) in (*first..).zip(bank) {
+                *cpu.regs.get_mut(reg, mode) = *value;
+            }
+        }
+        for ((mode, _), spsr) in BANKS.iter().zip(self.spsr) {
+            *cpu.regs.spsr_mut(mode) = spsr;
+        }
+
+        let state = cpu.get_state();
+        cpu.instr_pipeline = self
+            .pipeline
+            .map(|opcode| Fetched::decode(opcode, state, cpu.luts));
+        cpu.instr_pipeline_size = 2;
+    }
+
+    fn save(cpu: &Cpu) -> Self {
+        let mut saved = Self {
+            r: std::array::from_fn(|reg| cpu.regs.get(reg as u32, &Mode::User)),
+            r_fiq: [0; 7],
+            r_svc: [0; 2],
+            r_abt: [0; 2],
+            r_irq: [0; 2],
+            r_und: [0; 2],
+            cpsr: cpu.regs.cpsr,
+            spsr: BANKS.map(|(mode, _)| cpu.regs.spsr(&mode)),
+            pipeline: cpu.instr_pipeline.map(|fetched| fetched.opcode),
+        };
+        for ((mode, first), bank) in BANKS.iter().zip(saved.banks_mut()) {
+            for (reg, value) in (*first..).zip(bank.iter_mut()) {
+                *value = cpu.regs.get(reg, mode);
+            }
+        }
+        saved
+    }
+
+    /// Describes every difference from `expected`.
+    fn diff(&self, expected: &Self, mismatches: &mut Vec<String>) {
+        for reg in 0..16 {
+            if self.r[reg] != expected.r[reg] {
+                mismatches.push(format!(
+                    "r{}: expected {:#010x}, got {:#010x}",
+                    reg, expected.r[reg], self.r[reg]
+                ));
+            }
+        }
+        let banks = self.banks().into_iter().zip(expected.banks());
+        for ((mode, first), (bank, expected_bank)) in BANKS.iter().zip(banks) {
+            for ((reg, value), expected) in (*first..).zip(bank).zip(expected_bank) {
+                if value != expected {
+                    mismatches.push(format!(
+                        "r{}_{:?}: expected {:#010x}, got {:#010x}",
+                        reg, mode, expected, value
+                    ));
+                }
+            }
+        }
+        if self.cpsr != expected.cpsr {
+            mismatches.push(format!(
+                "cpsr: expected {:#010x}, got {:#010x}",
+                expected.cpsr, self.cpsr
+            ));
+        }
+        for ((mode, _), (spsr, expected)) in BANKS.iter().zip(self.spsr.iter().zip(expected.spsr)) {
+            if *spsr != expected {
+                mismatches.push(format!(
+                    "spsr_{:?}: expected {:#010x}, got {:#010x}",
+                    mode, expected, spsr
+                ));
+            }
+        }
+        if self.pipeline != expected.pipeline {
+            mismatches.push(format!(
+                "pipeline: expected {:08x?}, got {:08x?}",
+                expected.pipeline, self.pipeline
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+struct Transaction {
+    /// 0 for an instruction fetch, 1 for a data read and 2 for a write.
+    kind: u8,
+    size: u32,
+    addr: u32,
+    data: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Vector {
+    initial: CpuState,
+    #[serde(rename = "final")]
+    expected: CpuState,
+    transactions: Vec<Transaction>,
+    opcode: u32,
+}
+
+/// Answers reads from a vector's transactions and records everything the CPU does.
+struct MockBus<'a> {
+    transactions: &'a [Transaction],
+    writes: Vec<Transaction>,
+    unexpected_reads: Vec<u32>,
+}
+
+impl<'a> MockBus<'a> {
+    fn new(transactions: &'a [Transaction]) -> Self {
+        Self {
+            transactions,
+            writes: vec![],
+            unexpected_reads: vec![],
+        }
+    }
+
+    /// Returns the data read from `index`, as the bus put it out before any rotation.
+    fn read_transaction(&mut self, index: u32, access: Access) -> Option<Transaction> {
+        let kind = match access.kind {
+            AccessKind::Code => FETCH,
+            AccessKind::Data => READ,
+        };
+        let read = self
+            .transactions
+            .iter()
+            .find(|transaction| transaction.kind == kind && transaction.addr == index)
+            .copied();
+        if read.is_none() {
+            self.unexpected_reads.push(index);
+        }
+        read
+    }
+
+    fn record_write(&mut self, size: u32, addr: u32, data: u32) {
+        self.writes.push(Transaction {
+            kind: WRITE,
+            size,
+            addr,
+            data,
+        });
+    }
+}
+
+// Misaligned accesses are rotated and aligned the same way `Bus` does it, so only the CPU is
+// under test.
+impl MemoryInterface for MockBus<'_> {
+    fn read(&mut self, index: u32, access: Access, _: &Cpu) -> (u32, u32) {
+        let value = self
+            .read_transaction(index, access)
+            .map_or(0, |read| read.data.rotate_right(8 * (index & 3)));
+        (value, 1)
+    }
+
+    fn read_half(&mut self, index: u32, access: Access, _: &Cpu) -> (u32, u32) {
+        let value = self
+            .read_transaction(index, access)
+            .map_or(0, |read| (read.data & 0xffff).rotate_right(8 * (index & 1)));
+        (value, 1)
+    }
+
+    fn read_signed_half(&mut self, index: u32, access: Access, _: &Cpu) -> (u32, u32) {
+        let value = self.read_transaction(index, access).map_or(0, |read| {
+            (i32::from(read.data as i16)).rotate_right(8 * (index & 1)) as u32
+        });
+        (value, 1)
+    }
+
+    fn read_byte(&mut self, index: u32, access: Access, _: &Cpu) -> (u8, u32) {
+        let value = self
+            .read_transaction(index, access)
+            .map_or(0, |read| read.data as u8);
+        (value, 1)
+    }
+
+    fn write(&mut self, index: u32, value: u32, _: Access, _: &Cpu) -> u32 {
+        self.record_write(4, index & !3, value);
+        1
+    }
+
+    fn write_half(&mut self, index: u32, value: u16, _: Access, _: &Cpu) -> u32 {
+        self.record_write(2, index & !1, value.into());
+        1
+    }
+
+    fn write_byte(&mut self, index: u32, value: u8, _: Access, _: &Cpu) -> u32 {
+        self.record_write(1, index, value.into());
+        1
+    }
+}
+
+/// Runs one vector, returning a description of each way the CPU got it wrong.
+fn run_vector(vector: &Vector) -> Vec<String> {
+    let mut cpu = Cpu::default();
+    let mut bus = MockBus::new(&vector.transactions);
+    vector.initial.load(&mut cpu);
+
+    let ran = panic::catch_unwind(AssertUnwindSafe(|| {
+        cpu.tick(&mut bus);
+        // A branch flushes the pipeline, and the vector expects it refilled from the target
+        while cpu.instr_pipeline_size < 2 {
+            cpu.tick(&mut bus);
+        }
+    }));
+    if let Err(err) = ran {
+        let message = err
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| err.downcast_ref::<&str>().copied())
+            .unwrap_or("unknown panic");
+        return vec![format!("panicked: {}", message)];
+    }
+
+    let mut mismatches = vec![];
+    CpuState::save(&cpu).diff(&vector.expected, &mut mismatches);
+
+    let expected_writes: Vec<Transaction> = vector
+        .transactions
+        .iter()
+        .filter(|transaction| transaction.kind == WRITE)
+        .copied()
+        .collect();
+    if bus.writes != expected_writes {
+        mismatches.push(format!(
+            "writes: expected {:x?}, got {:x?}",
+            expected_writes, bus.writes
+        ));
+    }
+    for index in bus.unexpected_reads {
+        mismatches.push(format!("unexpected read from {:#010x}", index));
+    }
+    mismatches
+}
+
+/// The class an opcode is reported under, which is the state it runs in and its mnemonic.
+fn opcode_class(vector: &Vector) -> String {
+    if vector.initial.cpsr & (1 << 5) == 0 {
+        format!("ARM {:?}", ir::decode_arm(vector.opcode).mnemonic)
+    } else {
+        format!(
+            "THUMB {:?}",
+            ir::decode_thumb(vector.opcode as u16).mnemonic
+        )
+    }
+}
+
+#[derive(Default)]
+struct ClassReport {
+    passed: usize,
+    failed: usize,
+    first_failure: Option<(u32, Vec<String>)>,
+}
+
+/// Runs every vector in the JSON files in `dir`, failing with the first failure in each class.
+fn run_vectors(dir: &Path) {
+    let entries = fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("can't read vectors from {}: {}", dir.display(), err));
+    let mut paths: Vec<PathBuf> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    assert!(
+        !paths.is_empty(),
+        "there are no vectors in {}",
+        dir.display()
+    );
+
+    let mut reports: BTreeMap<String, ClassReport> = BTreeMap::new();
+    for path in paths {
+        let json = fs::read_to_string(&path).unwrap();
+        let vectors: Vec<Vector> = serde_json::from_str(&json)
+            .unwrap_or_else(|err| panic!("{} isn't a list of vectors: {}", path.display(), err));
+        for vector in &vectors {
+            let report = reports.entry(opcode_class(vector)).or_default();
+            let mismatches = run_vector(vector);
+            if mismatches.is_empty() {
+                report.passed += 1;
+            } else {
+                report.failed += 1;
+                report
+                    .first_failure
+                    .get_or_insert((vector.opcode, mismatches));
+            }
+        }
+    }
+
+    let mut failures = String::new();
+    for (class, report) in &reports {
+        eprintln!(
+            "{}: {}/{} passed",
+            class,
+            report.passed,
+            report.passed + report.failed
+        );
+        if let Some((opcode, mismatches)) = &report.first_failure {
+            failures += &format!(
+                "{}: {} failed, first was {:08x}:\n    {}\n",
+                class,
+                report.failed,
+                opcode,
+                mismatches.join("\n    ")
+            );
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures);
+}
+
+#[test]
+fn runs_checked_in_vectors() {
+    run_vectors(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/single_step"));
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests vectors, in the directory SINGLE_STEP_TESTS points at"]
+fn runs_single_step_vectors() {
+    let dir = std::env::var_os("SINGLE_STEP_TESTS")
+        .expect("SINGLE_STEP_TESTS should point at the SingleStepTests vectors");
+    run_vectors(Path::new(&dir));
+}
+
+#[test]
+fn reports_mismatched_writes() {
+    let initial = CpuState {
+        r: [
+            0x12345678, 0x3000000, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x8000008,
+        ],
+        r_fiq: [0; 7],
+        r_svc: [0; 2],
+        r_abt: [0; 2],
+        r_irq: [0; 2],
+        r_und: [0; 2],
+        cpsr: 0x1f,
+        spsr: [0; 5],
+        // str r0, [r1]; mov r0, r0
+        pipeline: [0xe5810000, 0xe1a00000],
+    };
+    let mut vector = Vector {
+        expected: CpuState {
+            r: [
+                0x12345678, 0x3000000, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x800000c,
+            ],
+            pipeline: [0xe1a00000, 0xe1a00000],
+            ..initial.clone()
+        },
+        initial,
+        transactions: vec![
+            Transaction {
+                kind: FETCH,
+                size: 4,
+                addr: 0x8000008,
+                data: 0xe1a00000,
+            },
+            Transaction {
+                kind: WRITE,
+                size: 4,
+                addr: 0x3000000,
+                data: 0x12345678,
+            },
+        ],
+        opcode: 0xe5810000,
+    };
+    assert_eq!(run_vector(&vector), Vec::<String>::new());
+    assert_eq!(opcode_class(&vector), "ARM STR");
+
+    vector.transactions[1].data = 0x87654321;
+    assert_eq!(run_vector(&vector).len(), 1);
+}
@@ -0,0 +1,316 @@
+//! Per-instruction execution traces, for diffing against traces from other emulators.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use tracing::error;
+
+use super::block_cache::{Decoded, Fetched};
+use super::State;
+use crate::utils::template::{self, Piece};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Pc,
+    Opcode,
+    Reg(u32),
+    Regs,
+    Cpsr,
+    Cycle,
+    Disasm,
+}
+
+/// How each traced instruction is written, as a template with placeholders:
+///
+/// - `{pc}`: the address of the instruction
+/// - `{opcode}`: the opcode, as 8 hex digits in ARM state and 4 in THUMB state
+/// - `{r0}` to `{r15}`: a register, where r15 is the pipelined PC as the instruction sees it
+/// - `{regs}`: all 16 registers separated by spaces
+/// - `{cpsr}`: the CPSR
+/// - `{cycle}`: the number of CPU ticks so far, in decimal
+/// - `{disasm}`: the disassembled instruction
+///
+/// Values are upper case hex without a prefix, padded to 8 digits unless noted above.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFormat {
+    segments: Vec<Segment>,
+}
+
+impl TraceFormat {
+    /// The layout of mGBA's `trace` debugger command.
+    pub const MGBA: &'static str = "{regs} cpsr: {cpsr} | {opcode}: {disasm}";
+
+    pub fn new(template: &str) -> Result<Self, String> {
+        let segments = template::parse(template)?
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => Ok(Segment::Text(text.to_string())),
+                Piece::Placeholder("pc") => Ok(Segment::Pc),
+                Piece::Placeholder("opcode") => Ok(Segment::Opcode),
+                Piece::Placeholder("regs") => Ok(Segment::Regs),
+                Piece::Placeholder("cpsr") => Ok(Segment::Cpsr),
+                Piece::Placeholder("cycle") => Ok(Segment::Cycle),
+                Piece::Placeholder("disasm") => Ok(Segment::Disasm),
+                Piece::Placeholder(name) => match name.strip_prefix('r').map(str::parse) {
+                    Some(Ok(reg)) if reg < 16 => Ok(Segment::Reg(reg)),
+                    _ => Err(format!("unknown placeholder `{{{}}}`", name)),
+                },
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { segments })
+    }
+
+    pub fn mgba() -> Self {
+        Self::new(Self::MGBA).unwrap()
+    }
+
+    fn format(&self, entry: &TraceEntry, line: &mut String) {
+        for segment in &self.segments {
+            // Writing to a String can't fail
+            let _ = match *segment {
+                Segment::Text(ref text) => write!(line, "{}", text),
+                Segment::Pc => write!(line, "{:08X}", entry.pc),
+                Segment::Opcode => match entry.state {
+                    State::ARM => write!(line, "{:08X}", entry.fetched.opcode),
+                    State::Thumb => write!(line, "{:04X}", entry.fetched.opcode & 0xffff),
+                },
+                Segment::Reg(reg) => write!(line, "{:08X}", entry.regs[reg as usize]),
+                Segment::Regs => {
+                    let regs: Vec<String> = entry
+                        .regs
+                        .iter()
+                        .map(|reg| format!("{:08X}", reg))
+                        .collect();
+                    write!(line, "{}", regs.join(" "))
+                }
+                Segment::Cpsr => write!(line, "{:08X}", entry.cpsr),
+                Segment::Cycle => write!(line, "{}", entry.cycle),
+                Segment::Disasm => match entry.fetched.decoded {
+                    Decoded::Arm(instr) => write!(line, "{}", instr.decode(entry.fetched.opcode)),
+                    Decoded::Thumb(instr) => write!(line, "{}", instr.decode()),
+                },
+            };
+        }
+    }
+}
+
+/// Starts or stops a trace, when the instruction at an address is about to execute or once the
+/// CPU has ticked a number of times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Address(u32),
+    Cycle(u128),
+}
+
+impl Trigger {
+    fn hit(&self, pc: u32, cycle: u128) -> bool {
+        match *self {
+            Self::Address(address) => address == pc,
+            Self::Cycle(start) => cycle >= start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Waiting,
+    Tracing,
+    Finished,
+}
+
+/// The CPU state just before an instruction executes.
+pub(super) struct TraceEntry {
+    pub pc: u32,
+    pub fetched: Fetched,
+    pub state: State,
+    pub regs: [u32; 16],
+    pub cpsr: u32,
+    pub cycle: u128,
+}
+
+/// Writes a line for every executed instruction to a sink, between optional start and stop
+/// triggers.
+pub struct Tracer {
+    format: TraceFormat,
+    sink: Box<dyn Write + Send>,
+    start: Option<Trigger>,
+    stop: Option<Trigger>,
+    status: Status,
+    line: String,
+}
+
+impl Tracer {
+    pub fn new(format: TraceFormat, sink: impl Write + Send + 'static) -> Self {
+        Self {
+            format,
+            sink: Box::new(sink),
+            start: None,
+            stop: None,
+            status: Status::Tracing,
+            line: String::new(),
+        }
+    }
+
+    pub fn to_file(format: TraceFormat, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(format, BufWriter::new(File::create(path)?)))
+    }
+
+    /// Only start tracing once `trigger` is hit, rather than straight away.
+    pub fn start_at(mut self, trigger: Trigger) -> Self {
+        self.start = Some(trigger);
+        self.status = Status::Waiting;
+        self
+    }
+
+    /// Stop tracing once `trigger` is hit. The instruction that hits it isn't traced.
+    pub fn stop_at(mut self, trigger: Trigger) -> Self {
+        self.stop = Some(trigger);
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status == Status::Finished
+    }
+
+    pub(super) fn trace(&mut self, entry: &TraceEntry) {
+        if self.status == Status::Waiting
+            && self
+                .start
+                .is_some_and(|start| start.hit(entry.pc, entry.cycle))
+        {
+            self.status = Status::Tracing;
+        }
+        if self.status == Status::Tracing
+            && self
+                .stop
+                .is_some_and(|stop| stop.hit(entry.pc, entry.cycle))
+        {
+            self.finish();
+        }
+        if self.status != Status::Tracing {
+            return;
+        }
+
+        self.line.clear();
+        self.format.format(entry, &mut self.line);
+        self.line.push('\n');
+        if let Err(err) = self.sink.write_all(self.line.as_bytes()) {
+            error!("Stopping trace after failing to write it: {}", err);
+            self.status = Status::Finished;
+        }
+    }
+
+    fn finish(&mut self) {
+        self.status = Status::Finished;
+        if let Err(err) = self.sink.flush() {
+            error!("Failed to flush trace: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::bus::Bus;
+    use crate::cpu::{assembler, Cpu};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn traces_between_triggers() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+        let source = "
+            MOV r0, #1
+            MOV r1, #2
+            ADD r2, r0, r1
+            B $+0x0
+        ";
+        bus.poke(
+            0x3000000,
+            &assembler::assemble(source, 0x3000000, State::ARM).unwrap(),
+        );
+
+        let buffer = SharedBuffer::default();
+        let format = TraceFormat::new("{pc} {opcode} {r0} {r1} {r15} {disasm}").unwrap();
+        let tracer = Tracer::new(format, buffer.clone())
+            .start_at(Trigger::Address(0x3000004))
+            .stop_at(Trigger::Address(0x300000c));
+        cpu.set_tracer(Some(tracer));
+
+        cpu.set_reg(15, 0x3000000);
+        cpu.flush_pipeline();
+        for _ in 0..10 {
+            cpu.tick(&mut bus);
+        }
+
+        let trace = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            trace,
+            "03000004 E3A01002 00000001 00000000 0300000C MOV r1, #2\n\
+             03000008 E0802001 00000001 00000002 03000010 ADD r2, r0, r1\n"
+        );
+        assert!(cpu.set_tracer(None).unwrap().is_finished());
+    }
+
+    #[test]
+    fn traces_thumb_opcodes_as_halfwords() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+        let source = "
+            MOVS r0, #1
+            MOVS r1, #2
+            ADDS r2, r0, r1
+            B $+0x0
+        ";
+        bus.poke(
+            0x3000000,
+            &assembler::assemble(source, 0x3000000, State::Thumb).unwrap(),
+        );
+
+        let buffer = SharedBuffer::default();
+        let format = TraceFormat::new("{pc} {opcode} {disasm}").unwrap();
+        let tracer = Tracer::new(format, buffer.clone())
+            .start_at(Trigger::Address(0x3000002))
+            .stop_at(Trigger::Address(0x3000006));
+        cpu.set_tracer(Some(tracer));
+
+        cpu.set_state(State::Thumb);
+        cpu.set_reg(15, 0x3000000);
+        cpu.flush_pipeline();
+        for _ in 0..10 {
+            cpu.tick(&mut bus);
+        }
+
+        let trace = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            trace,
+            "03000002 2102 MOVS r1, #2\n\
+             03000004 1842 ADDS r2, r0, r1\n"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        assert!(TraceFormat::new("{r16}").is_err());
+        assert!(TraceFormat::new("{pc").is_err());
+        assert_eq!(TraceFormat::new(TraceFormat::MGBA), Ok(TraceFormat::mgba()));
+    }
+}
@@ -0,0 +1,333 @@
+//! Just enough of ELF to load what devkitARM links: 32-bit little-endian ARM executables.
+
+use super::reader::Reader;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHN_UNDEF: u16 = 0;
+const EM_ARM: u16 = 40;
+
+/// A part of the file to copy into memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment<'a> {
+    /// Where the data is loaded, which for initialised data in RAM is in ROM, as the startup
+    /// code copies it from there.
+    pub load_address: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section<'a> {
+    pub name: &'a str,
+    pub address: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolType {
+    Function,
+    Object,
+    NoType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfSymbol<'a> {
+    pub name: &'a str,
+    pub value: u32,
+    pub size: u32,
+    pub kind: SymbolType,
+}
+
+#[derive(Debug, Clone)]
+pub struct Elf<'a> {
+    pub entry: u32,
+    pub segments: Vec<Segment<'a>>,
+    pub sections: Vec<Section<'a>>,
+    pub symbols: Vec<ElfSymbol<'a>>,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        let mut header = Reader::new(bytes);
+        if header.bytes(4)? != b"\x7fELF" {
+            return Err("not an ELF file".to_string());
+        }
+        if header.bytes(2)? != [1, 1] {
+            return Err("not a 32-bit little-endian ELF file".to_string());
+        }
+        header.offset = 0x10;
+        let file_type = header.u16()?;
+        if header.u16()? != EM_ARM {
+            return Err("not an ARM ELF file".to_string());
+        }
+        if file_type != 2 {
+            return Err("not an executable, it might need linking".to_string());
+        }
+        header.offset = 0x18;
+        let entry = header.u32()?;
+        let program_headers = header.u32()? as usize;
+        let section_headers = header.u32()? as usize;
+        header.offset = 0x2a;
+        let program_header_size = usize::from(header.u16()?);
+        let program_header_count = usize::from(header.u16()?);
+        let section_header_size = usize::from(header.u16()?);
+        let section_header_count = usize::from(header.u16()?);
+        let names_index = usize::from(header.u16()?);
+
+        let mut segments = vec![];
+        for i in 0..program_header_count {
+            let mut header = Reader::at(bytes, program_headers + i * program_header_size);
+            let kind = header.u32()?;
+            let offset = header.u32()? as usize;
+            let _virtual_address = header.u32()?;
+            let load_address = header.u32()?;
+            let file_size = header.u32()? as usize;
+            if kind == PT_LOAD && file_size > 0 {
+                let data = Reader::at(bytes, offset).bytes(file_size)?;
+                segments.push(Segment { load_address, data });
+            }
+        }
+
+        // Names are filled in once the section holding them has been found
+        let mut headers = vec![];
+        for i in 0..section_header_count {
+            let mut header = Reader::at(bytes, section_headers + i * section_header_size);
+            let name = header.u32()? as usize;
+            let kind = header.u32()?;
+            let _flags = header.u32()?;
+            let address = header.u32()?;
+            let offset = header.u32()? as usize;
+            let size = header.u32()? as usize;
+            let link = header.u32()? as usize;
+            // NOBITS sections take no space in the file
+            let data = match kind {
+                8 => &[][..],
+                _ => Reader::at(bytes, offset).bytes(size)?,
+            };
+            headers.push((name, kind, address, data, link));
+        }
+        let names = headers.get(names_index).map_or(&[][..], |header| header.3);
+        let name = |offset: usize| Reader::at(names, offset).cstr();
+        let sections = headers
+            .iter()
+            .map(|&(name_offset, _, address, data, _)| {
+                Ok(Section {
+                    name: name(name_offset)?,
+                    address,
+                    data,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut symbols = vec![];
+        for &(_, kind, _, data, link) in &headers {
+            if kind != SHT_SYMTAB {
+                continue;
+            }
+            let strings = headers.get(link).map_or(&[][..], |header| header.3);
+            symbols.extend(parse_symbols(data, strings)?);
+        }
+
+        Ok(Self {
+            entry,
+            segments,
+            sections,
+            symbols,
+        })
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section<'a>> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+}
+
+fn parse_symbols<'a>(data: &'a [u8], strings: &'a [u8]) -> Result<Vec<ElfSymbol<'a>>, String> {
+    let mut symbols = vec![];
+    let mut reader = Reader::new(data);
+    while !reader.is_empty() {
+        let name = reader.u32()? as usize;
+        let value = reader.u32()?;
+        let size = reader.u32()?;
+        let info = reader.u8()?;
+        let _other = reader.u8()?;
+        let section = reader.u16()?;
+        let kind = match info & 0xf {
+            0 => SymbolType::NoType,
+            1 => SymbolType::Object,
+            2 => SymbolType::Function,
+            // Sections, files and the like aren't places in memory
+            _ => continue,
+        };
+        let name = Reader::at(strings, name).cstr()?;
+        if section == SHN_UNDEF || name.is_empty() {
+            continue;
+        }
+        symbols.push(ElfSymbol {
+            name,
+            value,
+            size,
+            kind,
+        });
+    }
+    Ok(symbols)
+}
+
+/// Builds ELF files for tests, as there's no ARM linker to make them with.
+#[cfg(test)]
+pub mod builder {
+    /// A section, which is also loaded if it has an address.
+    pub struct TestSection<'a> {
+        pub name: &'a str,
+        pub address: u32,
+        pub data: Vec<u8>,
+    }
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        bytes.extend(value.to_le_bytes());
+    }
+
+    fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+        bytes.extend(value.to_le_bytes());
+    }
+
+    /// Lays out the sections, a segment for each one with an address and a symbol table with
+    /// functions as `(name, value, size)`.
+    pub fn build(entry: u32, sections: &[TestSection], functions: &[(&str, u32, u32)]) -> Vec<u8> {
+        let mut strings = vec![0];
+        let mut symtab = vec![0; 16];
+        for &(name, value, size) in functions {
+            push_u32(&mut symtab, strings.len() as u32);
+            strings.extend(name.bytes().chain([0]));
+            push_u32(&mut symtab, value);
+            push_u32(&mut symtab, size);
+            // A global function in section 1
+            symtab.extend([0x12, 0]);
+            push_u16(&mut symtab, 1);
+        }
+
+        let mut all: Vec<(&str, u32, u32, Vec<u8>, u32)> = sections
+            .iter()
+            .map(|section| (section.name, 1, section.address, section.data.clone(), 0))
+            .collect();
+        let strtab_index = all.len() + 2;
+        all.push((".symtab", 2, 0, symtab, strtab_index as u32));
+        all.push((".strtab", 3, 0, strings, 0));
+        let mut names = vec![0];
+        let mut name_offsets = vec![];
+        for (name, ..) in &all {
+            name_offsets.push(names.len() as u32);
+            names.extend(name.bytes().chain([0]));
+        }
+        name_offsets.push(names.len() as u32);
+        names.extend(b".shstrtab\0");
+        all.push((".shstrtab", 3, 0, names, 0));
+
+        let loaded: Vec<_> = sections.iter().filter(|s| s.address != 0).collect();
+        let program_headers = 0x34;
+        let mut data_offset = program_headers + 32 * loaded.len();
+        let mut offsets = vec![];
+        for section in &all {
+            offsets.push(data_offset);
+            data_offset += section.3.len();
+        }
+        let section_headers = data_offset;
+
+        let mut bytes = b"\x7fELF\x01\x01\x01".to_vec();
+        bytes.resize(0x10, 0);
+        push_u16(&mut bytes, 2);
+        push_u16(&mut bytes, 40);
+        push_u32(&mut bytes, 1);
+        push_u32(&mut bytes, entry);
+        push_u32(&mut bytes, program_headers as u32);
+        push_u32(&mut bytes, section_headers as u32);
+        push_u32(&mut bytes, 0x5000000);
+        push_u16(&mut bytes, 0x34);
+        push_u16(&mut bytes, 32);
+        push_u16(&mut bytes, loaded.len() as u16);
+        push_u16(&mut bytes, 40);
+        push_u16(&mut bytes, all.len() as u16 + 1);
+        push_u16(&mut bytes, all.len() as u16);
+
+        for (i, section) in sections.iter().enumerate() {
+            if section.address == 0 {
+                continue;
+            }
+            for value in [1, offsets[i] as u32, section.address, section.address] {
+                push_u32(&mut bytes, value);
+            }
+            let size = section.data.len() as u32;
+            for value in [size, size, 5, 4] {
+                push_u32(&mut bytes, value);
+            }
+        }
+        for section in &all {
+            bytes.extend(&section.3);
+        }
+
+        bytes.extend([0; 40]);
+        for (i, (_, kind, address, data, link)) in all.iter().enumerate() {
+            let entry_size = if *kind == 2 { 16 } else { 0 };
+            let header = [
+                name_offsets[i],
+                *kind,
+                0,
+                *address,
+                offsets[i] as u32,
+                data.len() as u32,
+                *link,
+                0,
+                4,
+                entry_size,
+            ];
+            for value in header {
+                push_u32(&mut bytes, value);
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::builder::{build, TestSection};
+    use super::*;
+
+    #[test]
+    fn parses_segments_sections_and_symbols() {
+        let sections = [
+            TestSection {
+                name: ".text",
+                address: 0x8000000,
+                data: vec![1, 2, 3, 4],
+            },
+            TestSection {
+                name: ".comment",
+                address: 0,
+                data: b"GCC".to_vec(),
+            },
+        ];
+        let bytes = build(0x8000000, &sections, &[("main", 0x8000001, 4)]);
+        let elf = Elf::parse(&bytes).unwrap();
+        assert_eq!(elf.entry, 0x8000000);
+        assert_eq!(
+            elf.segments,
+            [Segment {
+                load_address: 0x8000000,
+                data: &[1, 2, 3, 4]
+            }]
+        );
+        assert_eq!(elf.section(".comment").unwrap().data, b"GCC");
+        assert_eq!(
+            elf.symbols,
+            [ElfSymbol {
+                name: "main",
+                value: 0x8000001,
+                size: 4,
+                kind: SymbolType::Function
+            }]
+        );
+
+        assert!(Elf::parse(b"\x7fELF").is_err());
+        assert!(Elf::parse(&bytes[..0x40]).is_err());
+    }
+}
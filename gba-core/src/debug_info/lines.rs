@@ -0,0 +1,421 @@
+//! Maps addresses to source lines with the line number programs in `.debug_line`, from DWARF 2
+//! to 5.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::reader::Reader;
+
+/// The sections that line number programs can take strings from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Strings<'a> {
+    /// `.debug_str`
+    pub str: &'a [u8],
+    /// `.debug_line_str`
+    pub line_str: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Row {
+    address: u32,
+    file: usize,
+    line: u32,
+    /// Whether this is somewhere to stop for the line, rather than part way through it.
+    is_stmt: bool,
+    /// The first address after a sequence, which isn't in any line.
+    end_sequence: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LineTable {
+    files: Vec<String>,
+    /// Sorted by address.
+    rows: Vec<Row>,
+}
+
+/// Reads a form used in the DWARF 5 directory and file tables, returning it if it's a string or
+/// a number.
+enum Value<'a> {
+    String(&'a str),
+    Number(u64),
+    Other,
+}
+
+fn read_form<'a>(
+    reader: &mut Reader<'a>,
+    form: u64,
+    offset_size: usize,
+    strings: Strings<'a>,
+) -> Result<Value<'a>, String> {
+    let offset = |reader: &mut Reader<'a>| match offset_size {
+        8 => reader.u64().map(|offset| offset as usize),
+        _ => reader.u32().map(|offset| offset as usize),
+    };
+    Ok(match form {
+        // DW_FORM_string
+        0x08 => Value::String(reader.cstr()?),
+        // DW_FORM_line_strp
+        0x1f => Value::String(Reader::at(strings.line_str, offset(reader)?).cstr()?),
+        // DW_FORM_strp
+        0x0e => Value::String(Reader::at(strings.str, offset(reader)?).cstr()?),
+        // DW_FORM_udata
+        0x0f => Value::Number(reader.uleb128()?),
+        // DW_FORM_data1, data2, data4 and data8
+        0x0b => Value::Number(reader.u8()?.into()),
+        0x05 => Value::Number(reader.u16()?.into()),
+        0x06 => Value::Number(reader.u32()?.into()),
+        0x07 => Value::Number(reader.u64()?),
+        // DW_FORM_data16, which is how MD5s are stored
+        0x1e => {
+            reader.bytes(16)?;
+            Value::Other
+        }
+        // DW_FORM_block
+        0x09 => {
+            let len = reader.uleb128()? as usize;
+            reader.bytes(len)?;
+            Value::Other
+        }
+        _ => return Err(format!("unsupported form {:#x} in a line table", form)),
+    })
+}
+
+/// Reads a DWARF 5 directory or file table, returning the paths and directory indices.
+fn read_entries<'a>(
+    reader: &mut Reader<'a>,
+    offset_size: usize,
+    strings: Strings<'a>,
+) -> Result<Vec<(&'a str, usize)>, String> {
+    let format_count = reader.u8()?;
+    let mut format = vec![];
+    for _ in 0..format_count {
+        format.push((reader.uleb128()?, reader.uleb128()?));
+    }
+    let count = reader.uleb128()?;
+    let mut entries = vec![];
+    for _ in 0..count {
+        let mut entry = ("", 0);
+        for &(content, form) in &format {
+            match (content, read_form(reader, form, offset_size, strings)?) {
+                // DW_LNCT_path
+                (1, Value::String(path)) => entry.0 = path,
+                // DW_LNCT_directory_index
+                (2, Value::Number(index)) => entry.1 = index as usize,
+                _ => {}
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn join(directory: &str, file: &str) -> String {
+    if directory.is_empty() || file.starts_with('/') {
+        file.to_string()
+    } else {
+        format!("{}/{}", directory.trim_end_matches('/'), file)
+    }
+}
+
+impl LineTable {
+    pub fn parse(debug_line: &[u8], strings: Strings) -> Result<Self, String> {
+        let mut table = Self::default();
+        let mut file_indices = HashMap::new();
+        let mut reader = Reader::new(debug_line);
+        while !reader.is_empty() {
+            let (length, offset_size) = match reader.u32()? {
+                0xffffffff => (reader.u64()? as usize, 8),
+                length => (length as usize, 4),
+            };
+            let unit = reader.bytes(length)?;
+            table.parse_unit(unit, offset_size, strings, &mut file_indices)?;
+        }
+        // Where one sequence ends at the start of another, the end has to come first
+        table
+            .rows
+            .sort_by_key(|row| (row.address, !row.end_sequence));
+        Ok(table)
+    }
+
+    fn parse_unit(
+        &mut self,
+        unit: &[u8],
+        offset_size: usize,
+        strings: Strings,
+        file_indices: &mut HashMap<String, usize>,
+    ) -> Result<(), String> {
+        let mut reader = Reader::new(unit);
+        let version = reader.u16()?;
+        if !(2..=5).contains(&version) {
+            return Err(format!("unsupported line table version {}", version));
+        }
+        if version >= 5 {
+            let _address_size = reader.u8()?;
+            let _segment_selector_size = reader.u8()?;
+        }
+        let header_length = match offset_size {
+            8 => reader.u64()? as usize,
+            _ => reader.u32()? as usize,
+        };
+        let program = reader.offset + header_length;
+        let min_instruction_length = u32::from(reader.u8()?);
+        if version >= 4 {
+            let _max_ops_per_instruction = reader.u8()?;
+        }
+        let default_is_stmt = reader.u8()? != 0;
+        let line_base = reader.u8()? as i8;
+        let line_range = reader.u8()?;
+        let opcode_base = reader.u8()?;
+        if line_range == 0 {
+            return Err("line table has a line range of 0".to_string());
+        }
+        let opcode_lengths = reader.bytes(usize::from(opcode_base.saturating_sub(1)))?;
+
+        // Before DWARF 5, directory 0 is the compilation directory and file 0 isn't used
+        let mut directories = vec![];
+        let mut files = vec![];
+        if version >= 5 {
+            let entries = read_entries(&mut reader, offset_size, strings)?;
+            directories.extend(entries.into_iter().map(|(path, _)| path));
+            for (path, directory) in read_entries(&mut reader, offset_size, strings)? {
+                files.push(join(directories.get(directory).unwrap_or(&""), path));
+            }
+        } else {
+            directories.push("");
+            loop {
+                match reader.cstr()? {
+                    "" => break,
+                    directory => directories.push(directory),
+                }
+            }
+            files.push(String::new());
+            loop {
+                let path = reader.cstr()?;
+                if path.is_empty() {
+                    break;
+                }
+                let directory = reader.uleb128()? as usize;
+                let _modified = reader.uleb128()?;
+                let _length = reader.uleb128()?;
+                files.push(join(directories.get(directory).unwrap_or(&""), path));
+            }
+        }
+
+        let mut file_index = |files: &[String], index: u64| {
+            let path = files.get(index as usize).cloned().unwrap_or_default();
+            let next = self.files.len();
+            *file_indices.entry(path.clone()).or_insert_with(|| {
+                self.files.push(path);
+                next
+            })
+        };
+
+        reader.offset = program;
+        let mut rows = vec![];
+        let first_file = file_index(&files, 1);
+        let (mut address, mut file, mut line, mut is_stmt) = (0, first_file, 1i64, default_is_stmt);
+        let row = |address: u32, file: usize, line: i64, is_stmt: bool| Row {
+            address,
+            file,
+            line: line.max(0) as u32,
+            is_stmt,
+            end_sequence: false,
+        };
+        while !reader.is_empty() {
+            let opcode = reader.u8()?;
+            if opcode >= opcode_base {
+                let adjusted = opcode - opcode_base;
+                address += u32::from(adjusted / line_range) * min_instruction_length;
+                line += i64::from(line_base) + i64::from(adjusted % line_range);
+                rows.push(row(address, file, line, is_stmt));
+                continue;
+            }
+            match opcode {
+                0 => {
+                    let len = reader.uleb128()? as usize;
+                    let mut extended = Reader::new(reader.bytes(len)?);
+                    match extended.u8()? {
+                        // DW_LNE_end_sequence
+                        1 => {
+                            rows.push(Row {
+                                end_sequence: true,
+                                ..row(address, file, line, false)
+                            });
+                            (address, file, line, is_stmt) = (0, first_file, 1, default_is_stmt);
+                        }
+                        // DW_LNE_set_address
+                        2 => address = extended.u32()?,
+                        // DW_LNE_define_file
+                        3 => {
+                            let path = extended.cstr()?;
+                            let directory = extended.uleb128()? as usize;
+                            files.push(join(directories.get(directory).unwrap_or(&""), path));
+                        }
+                        // DW_LNE_set_discriminator and anything vendor specific
+                        _ => {}
+                    }
+                }
+                // DW_LNS_copy
+                1 => rows.push(row(address, file, line, is_stmt)),
+                // DW_LNS_advance_pc
+                2 => address += reader.uleb128()? as u32 * min_instruction_length,
+                // DW_LNS_advance_line
+                3 => line += reader.sleb128()?,
+                // DW_LNS_set_file
+                4 => file = file_index(&files, reader.uleb128()?),
+                // DW_LNS_negate_stmt
+                6 => is_stmt = !is_stmt,
+                // DW_LNS_const_add_pc
+                8 => {
+                    address += u32::from((255 - opcode_base) / line_range) * min_instruction_length
+                }
+                // DW_LNS_fixed_advance_pc
+                9 => address += u32::from(reader.u16()?),
+                // The rest just take arguments that aren't needed, including ones that are
+                // newer than the table says it is
+                _ => {
+                    for _ in 0..opcode_lengths[usize::from(opcode) - 1] {
+                        reader.uleb128()?;
+                    }
+                }
+            }
+        }
+        self.rows.extend(rows);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The file and line that the instruction at `address` is from.
+    pub fn location(&self, address: u32) -> Option<(&str, u32)> {
+        let end = self.rows.partition_point(|row| row.address <= address);
+        let row = self.rows[..end].last().filter(|row| !row.end_sequence)?;
+        Some((&self.files[row.file], row.line))
+    }
+
+    /// The code for each line, as the file, the line and the addresses from the first up to the
+    /// end. A line can have more than one range.
+    pub fn ranges(&self) -> impl Iterator<Item = (&str, u32, Range<u32>)> {
+        self.rows.windows(2).filter_map(|rows| {
+            let (row, next) = (rows[0], rows[1]);
+            let range = row.address..next.address;
+            (!row.end_sequence && !range.is_empty())
+                .then(|| (self.files[row.file].as_str(), row.line, range))
+        })
+    }
+
+    /// The lowest address of the code for `line` of the file whose path ends with `file`, or if
+    /// there's no code for it, the next line that has some.
+    pub fn address_of(&self, file: &str, line: u32) -> Option<u32> {
+        let matches = |path: &str| {
+            path == file
+                || path
+                    .strip_suffix(file)
+                    .is_some_and(|directory| directory.ends_with('/'))
+        };
+        self.rows
+            .iter()
+            .filter(|row| row.is_stmt && row.line >= line && matches(&self.files[row.file]))
+            .min_by_key(|row| (row.line, row.address))
+            .map(|row| row.address)
+    }
+}
+
+/// Line tables for tests.
+#[cfg(test)]
+pub mod test_data {
+    /// A DWARF 5 line table for `main.c` in `/src`, as GCC would write it with strings in
+    /// `.debug_line_str`, mapping 0x8000100 to line 3, 0x8000104 and 0x8000106 to line 4 and
+    /// 0x8000108 to line 7.
+    pub fn test_line_table() -> (Vec<u8>, Vec<u8>) {
+        let line_str = b"/src\0main.c\0".to_vec();
+        let mut header = vec![];
+        // Minimum instruction length, max ops, default is_stmt, line base, line range and
+        // opcode base, then the lengths of the standard opcodes
+        header.extend([2, 1, 1, 0xfb, 14, 13]);
+        header.extend([0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        // One directory, then the file as both file 0 and 1 like GCC does, as paths in
+        // .debug_line_str along with the file's directory
+        header.extend([1, 1, 0x1f, 1, 0, 0, 0, 0]);
+        header.extend([2, 1, 0x1f, 2, 0x0b, 2, 5, 0, 0, 0, 0, 5, 0, 0, 0, 0]);
+        let program = [
+            // DW_LNE_set_address 0x8000100
+            0x00,
+            5,
+            2,
+            0x00,
+            0x01,
+            0x00,
+            0x08,
+            // DW_LNS_advance_line 2, DW_LNS_copy
+            3,
+            2,
+            1,
+            // Special opcode: 2 instructions (4 bytes) and 1 line
+            13 + 2 * 14 + 6,
+            // DW_LNS_negate_stmt, a row that isn't a statement, then back
+            6,
+            13 + 14 + 5,
+            6,
+            // DW_LNS_advance_pc 1, DW_LNS_advance_line 3, DW_LNS_copy
+            2,
+            1,
+            3,
+            3,
+            1,
+            // DW_LNS_advance_pc 2, DW_LNE_end_sequence
+            2,
+            2,
+            0x00,
+            1,
+            1,
+        ];
+        let mut unit = 5u16.to_le_bytes().to_vec();
+        unit.extend([4, 0]);
+        unit.extend((header.len() as u32).to_le_bytes());
+        unit.extend(header);
+        unit.extend(program);
+        let mut debug_line = (unit.len() as u32).to_le_bytes().to_vec();
+        debug_line.extend(unit);
+        (debug_line, line_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_data::test_line_table;
+    use super::*;
+
+    #[test]
+    fn maps_addresses_to_lines() {
+        let (debug_line, line_str) = test_line_table();
+        let strings = Strings {
+            line_str: &line_str,
+            ..Strings::default()
+        };
+        let table = LineTable::parse(&debug_line, strings).unwrap();
+        assert_eq!(table.location(0x80000fe), None);
+        assert_eq!(table.location(0x8000100), Some(("/src/main.c", 3)));
+        assert_eq!(table.location(0x8000106), Some(("/src/main.c", 4)));
+        assert_eq!(table.location(0x8000108), Some(("/src/main.c", 7)));
+        assert_eq!(table.location(0x800010c), None);
+
+        assert_eq!(table.address_of("main.c", 4), Some(0x8000104));
+        assert_eq!(table.address_of("src/main.c", 5), Some(0x8000108));
+        assert_eq!(table.address_of("ain.c", 4), None);
+        assert_eq!(table.address_of("main.c", 8), None);
+
+        let ranges: Vec<_> = table.ranges().collect();
+        assert_eq!(
+            ranges,
+            [
+                ("/src/main.c", 3, 0x8000100..0x8000104),
+                ("/src/main.c", 4, 0x8000104..0x8000106),
+                ("/src/main.c", 4, 0x8000106..0x8000108),
+                ("/src/main.c", 7, 0x8000108..0x800010c),
+            ]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Symbols and source lines for the debugger, from the ELF files that toolchains link before
+//! making the ROM.
+
+mod elf;
+mod lines;
+mod reader;
+mod symbol_map;
+mod symbols;
+
+pub use elf::{Elf, Segment, SymbolType};
+pub use lines::{LineTable, Strings};
+pub use symbol_map::SymbolMap;
+pub use symbols::{Symbol, Symbols};
+
+use crate::cpu::State;
+
+/// The parts of memory an ELF can be loaded into, as `(start, end)`.
+const LOADABLE: [(u32, u32); 3] = [
+    (0x2000000, 0x2040000),
+    (0x3000000, 0x3008000),
+    (0x8000000, 0xa000000),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    pub symbols: Symbols,
+    pub lines: LineTable,
+}
+
+impl DebugInfo {
+    pub fn from_elf(elf: &Elf) -> Result<Self, String> {
+        let mut symbols = Symbols::default();
+        let mut named = vec![];
+        for symbol in &elf.symbols {
+            // The ARM ELF mapping symbols say what's ARM, THUMB or data, like `$t` or `$d.1`
+            let mapping = match symbol.name.get(..2) {
+                Some("$a") => Some(Some(State::ARM)),
+                Some("$t") => Some(Some(State::Thumb)),
+                Some("$d") => Some(None),
+                _ => None,
+            };
+            if let Some(state) = mapping {
+                if matches!(symbol.name.as_bytes().get(2), None | Some(b'.')) {
+                    symbols.mark_region(symbol.value, state);
+                    continue;
+                }
+            }
+            // Function addresses have bit 0 set if they're THUMB, as they would to call them with BX
+            let state = match symbol.kind {
+                SymbolType::Function if symbol.value & 1 == 1 => Some(State::Thumb),
+                SymbolType::Function => Some(State::ARM),
+                _ => None,
+            };
+            let address = match state {
+                Some(_) => symbol.value & !1,
+                None => symbol.value,
+            };
+            named.push(Symbol {
+                name: symbol.name.to_string(),
+                address,
+                size: symbol.size,
+                state,
+            });
+        }
+        symbols.extend(named);
+
+        let section = |name| elf.section(name).map_or(&[][..], |section| section.data);
+        let strings = Strings {
+            str: section(".debug_str"),
+            line_str: section(".debug_line_str"),
+        };
+        let lines = LineTable::parse(section(".debug_line"), strings)?;
+        Ok(Self { symbols, lines })
+    }
+
+    /// Adds the symbols from a symbol map to the ones already loaded, returning how many there
+    /// were.
+    pub fn add_symbol_map(&mut self, map: SymbolMap) -> usize {
+        for (address, state) in map.regions {
+            self.symbols.mark_region(address, Some(state));
+        }
+        let count = map.symbols.len();
+        self.symbols.extend(map.symbols);
+        count
+    }
+
+    /// Finds the address of `location`, which is a symbol, a line as `file:line`, or an address
+    /// in hex.
+    pub fn resolve(&self, location: &str) -> Result<u32, String> {
+        let location = location.trim();
+        if let Some(digits) = location.strip_prefix("0x") {
+            return u32::from_str_radix(digits, 16)
+                .map_err(|_| format!("`{}` isn't an address", location));
+        }
+        if let Some(symbol) = self.symbols.get(location) {
+            return Ok(symbol.address);
+        }
+        if let Some((file, line)) = location.rsplit_once(':') {
+            let line = line
+                .parse()
+                .map_err(|_| format!("`{}` isn't a line number", line))?;
+            return self
+                .lines
+                .address_of(file, line)
+                .ok_or_else(|| format!("no code for line {} of {}", line, file));
+        }
+        u32::from_str_radix(location, 16).map_err(|_| format!("unknown symbol `{}`", location))
+    }
+}
+
+/// Checks that a segment is somewhere it can be loaded.
+pub fn check_segment(segment: &Segment) -> Result<(), String> {
+    let start = segment.load_address;
+    let end = u64::from(start) + segment.data.len() as u64;
+    if LOADABLE
+        .iter()
+        .any(|&(first, last)| start >= first && end <= u64::from(last))
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "a segment at {:#x} of {:#x} bytes isn't in ROM, EWRAM or IWRAM",
+            start,
+            segment.data.len()
+        ))
+    }
+}
+
+/// ELF files and line tables for tests.
+#[cfg(test)]
+pub mod test_data {
+    pub use super::elf::builder::{build as build_elf, TestSection};
+    pub use super::lines::test_data::test_line_table;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_locations() {
+        let mut info = DebugInfo::default();
+        info.symbols.extend([Symbol {
+            name: "add".to_string(),
+            address: 0x8000200,
+            size: 8,
+            state: Some(State::Thumb),
+        }]);
+        assert_eq!(info.resolve("add"), Ok(0x8000200));
+        assert_eq!(info.resolve("0xadd"), Ok(0xadd));
+        assert_eq!(info.resolve("8000000"), Ok(0x8000000));
+        assert!(info.resolve("main").is_err());
+        assert!(info.resolve("main.c:x").is_err());
+        assert!(info.resolve("main.c:3").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+/// Reads the little-endian values that ELF and DWARF are made of, failing rather than panicking
+/// when the data runs out.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pub offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub fn at(data: &'a [u8], offset: usize) -> Self {
+        Self { data, offset }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len());
+        let Some(end) = end else {
+            return Err(format!(
+                "{} bytes at {:#x} run past the end",
+                len, self.offset
+            ));
+        };
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn uleb128(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    pub fn sleb128(&mut self) -> Result<i64, String> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Reads a NUL-terminated string.
+    pub fn cstr(&mut self) -> Result<&'a str, String> {
+        let rest = &self.data[self.offset.min(self.data.len())..];
+        let Some(len) = rest.iter().position(|&byte| byte == 0) else {
+            return Err(format!("unterminated string at {:#x}", self.offset));
+        };
+        let text = std::str::from_utf8(&rest[..len])
+            .map_err(|_| format!("string at {:#x} isn't UTF-8", self.offset))?;
+        self.offset += len + 1;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_leb128() {
+        let mut reader = Reader::new(&[0xe5, 0x8e, 0x26, 0x7f, 0x80, 0x7f, 0x02]);
+        assert_eq!(reader.uleb128(), Ok(624485));
+        assert_eq!(reader.sleb128(), Ok(-1));
+        assert_eq!(reader.sleb128(), Ok(-128));
+        assert_eq!(reader.sleb128(), Ok(2));
+        assert!(reader.is_empty());
+        assert!(reader.u8().is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Reads symbols from the text files that tools write alongside a ROM: no$gba `.sym` files and
+//! GNU ld `.map` files.
+
+use crate::cpu::State;
+
+use super::Symbol;
+
+/// The symbols from a symbol map, along with where it says ARM and THUMB code starts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    pub symbols: Vec<Symbol>,
+    pub regions: Vec<(u32, State)>,
+}
+
+fn label(name: &str, address: u32) -> Symbol {
+    Symbol {
+        name: name.to_string(),
+        address,
+        size: 0,
+        state: None,
+    }
+}
+
+impl SymbolMap {
+    /// Reads either kind of symbol map, telling them apart by the header ld writes.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if text.contains("Linker script and memory map") {
+            Ok(Self::parse_ld_map(text))
+        } else {
+            Self::parse_sym(text)
+        }
+    }
+
+    /// Reads a no$gba `.sym` file, which has a symbol per line as a hex address and a name.
+    /// `.arm` and `.thumb` in place of a name mark where code in that state starts, and other
+    /// names starting with a dot describe data, which is skipped. Comments start with `;`.
+    pub fn parse_sym(text: &str) -> Result<Self, String> {
+        let mut map = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = line
+                .split_once(char::is_whitespace)
+                .and_then(|(address, name)| {
+                    Some((u32::from_str_radix(address, 16).ok()?, name.trim()))
+                });
+            let Some((address, name)) = parsed else {
+                return Err(format!(
+                    "line {}: expected an address and a name",
+                    number + 1
+                ));
+            };
+            match name {
+                ".arm" => map.regions.push((address, State::ARM)),
+                ".thumb" => map.regions.push((address, State::Thumb)),
+                _ if name.starts_with('.') => {}
+                _ => map.symbols.push(label(name, address)),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Reads the symbols out of the memory map in a GNU ld `.map` file, which lists each global
+    /// symbol as its address and name on a line of its own, as well as symbols the linker script
+    /// assigns, like `__iwram_start = ORIGIN (iwram)`. It doesn't say what state code is in.
+    pub fn parse_ld_map(text: &str) -> Self {
+        let mut map = Self::default();
+        let Some((_, memory_map)) = text.split_once("Linker script and memory map") else {
+            return map;
+        };
+        for line in memory_map.lines() {
+            let mut words = line.split_whitespace();
+            let (Some(address), Some(name)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let Some(address) = address
+                .strip_prefix("0x")
+                .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+            else {
+                continue;
+            };
+            let is_symbol = match words.next() {
+                None => true,
+                Some(word) => word == "=",
+            };
+            let is_name = name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+                && !name.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+            if is_symbol && is_name {
+                map.symbols.push(label(name, address as u32));
+            }
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sym_files() {
+        let text = "
+            ; no$gba symbols
+            08000000 .arm
+            08000000 _start
+            080000C0 .thumb
+            080000C0 main   ; the entry point
+            080001F0 .byt:0010
+            03000000 irq_handler
+        ";
+        let map = SymbolMap::parse(text).unwrap();
+        assert_eq!(
+            map.symbols,
+            [
+                label("_start", 0x8000000),
+                label("main", 0x80000c0),
+                label("irq_handler", 0x3000000)
+            ]
+        );
+        assert_eq!(
+            map.regions,
+            [(0x8000000, State::ARM), (0x80000c0, State::Thumb)]
+        );
+        assert_eq!(
+            SymbolMap::parse("08000000 main\nmain"),
+            Err("line 2: expected an address and a name".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_ld_maps() {
+        let text = "
+Memory Configuration
+
+Name             Origin             Length             Attributes
+rom              0x08000000         0x02000000         xr
+
+Linker script and memory map
+
+                0x08000000                __text_start = ORIGIN (rom)
+
+.text           0x08000000      0x1f4
+ *(.text)
+ .text          0x08000000       0xc0 crt0.o
+                0x08000000                _start
+ .text.main
+                0x080000c0       0x40 main.o
+                0x080000c0                main
+ *fill*         0x08000100        0x4
+                [!provide]                PROVIDE (__end = .)
+                0x03000000                . = ALIGN (0x4)
+";
+        let map = SymbolMap::parse(text).unwrap();
+        assert_eq!(
+            map.symbols,
+            [
+                label("__text_start", 0x8000000),
+                label("_start", 0x8000000),
+                label("main", 0x80000c0)
+            ]
+        );
+        assert!(map.regions.is_empty());
+    }
+}
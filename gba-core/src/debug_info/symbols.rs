@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::cpu::State;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    /// The number of bytes the symbol covers, or 0 if that isn't known, in which case it's taken
+    /// to go on until the next symbol.
+    pub size: u32,
+    /// The state a function is in, or `None` for data.
+    pub state: Option<State>,
+}
+
+/// Names for addresses, and which parts of memory hold ARM code, THUMB code or data.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    /// Sorted by address.
+    symbols: Vec<Symbol>,
+    by_name: HashMap<String, usize>,
+    /// The state from each address until the next, as marked by the ARM ELF mapping symbols `$a`,
+    /// `$t` and `$d`. `None` is data.
+    regions: BTreeMap<u32, Option<State>>,
+    /// The size of the largest symbol, which is as far back as a lookup has to search.
+    max_size: u32,
+}
+
+impl Symbols {
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty() && self.regions.is_empty()
+    }
+
+    /// Adds symbols, keeping the first of any with the same name.
+    pub fn extend(&mut self, symbols: impl IntoIterator<Item = Symbol>) {
+        self.symbols.extend(symbols);
+        // Labels sort after sized symbols at the same address, so lookups can find them last
+        self.symbols
+            .sort_by_key(|symbol| (symbol.address, symbol.size == 0));
+        self.max_size = self
+            .symbols
+            .iter()
+            .map(|symbol| symbol.size)
+            .max()
+            .unwrap_or(0);
+        self.by_name.clear();
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            self.by_name.entry(symbol.name.clone()).or_insert(i);
+        }
+    }
+
+    /// Marks the code or data from `address` up to the next marked address as being in `state`.
+    pub fn mark_region(&mut self, address: u32, state: Option<State>) {
+        self.regions.insert(address, state);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.by_name.get(name).map(|&i| &self.symbols[i])
+    }
+
+    /// The symbol that `address` is in, and how far into it `address` is. Symbols with a size
+    /// win over labels, and of those the one that starts last, which is the innermost.
+    pub fn lookup(&self, address: u32) -> Option<(&Symbol, u32)> {
+        let end = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address);
+        let before = &self.symbols[..end];
+        let symbol = before
+            .iter()
+            .rev()
+            .take_while(|symbol| address - symbol.address < self.max_size)
+            .find(|symbol| address - symbol.address < symbol.size)
+            .or_else(|| before.last().filter(|symbol| symbol.size == 0))?;
+        Some((symbol, address - symbol.address))
+    }
+
+    /// Describes `address` as `symbol` or `symbol+offset`.
+    pub fn describe(&self, address: u32) -> Option<String> {
+        self.lookup(address).map(|(symbol, offset)| match offset {
+            0 => symbol.name.clone(),
+            _ => format!("{}+{:#x}", symbol.name, offset),
+        })
+    }
+
+    /// The state of the code at `address`, going by the mapping symbols and then the function it's
+    /// in, or `None` if it isn't known to be code.
+    pub fn state_at(&self, address: u32) -> Option<State> {
+        match self.regions.range(..=address).next_back() {
+            Some((_, state)) => *state,
+            None => self.lookup(address).and_then(|(symbol, _)| symbol.state),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, address: u32, size: u32, state: Option<State>) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            address,
+            size,
+            state,
+        }
+    }
+
+    #[test]
+    fn looks_up_symbols() {
+        let mut symbols = Symbols::default();
+        symbols.extend([
+            symbol("main", 0x8000100, 0x20, Some(State::Thumb)),
+            symbol("loop", 0x8000110, 0, Some(State::Thumb)),
+            symbol("_start", 0x8000000, 0, None),
+            symbol("buffer", 0x2000000, 0x100, None),
+        ]);
+        assert_eq!(symbols.get("main").unwrap().address, 0x8000100);
+        assert_eq!(symbols.describe(0x8000100).as_deref(), Some("main"));
+        assert_eq!(symbols.describe(0x8000108).as_deref(), Some("main+0x8"));
+        assert_eq!(symbols.describe(0x8000112).as_deref(), Some("main+0x12"));
+        assert_eq!(symbols.describe(0x8000120).as_deref(), Some("loop+0x10"));
+        assert_eq!(symbols.describe(0x80000f0).as_deref(), Some("_start+0xf0"));
+        assert_eq!(symbols.describe(0x2000100), None);
+        assert_eq!(symbols.describe(0x1000000), None);
+
+        assert_eq!(symbols.state_at(0x8000104), Some(State::Thumb));
+        assert_eq!(symbols.state_at(0x8000000), None);
+        symbols.mark_region(0x8000000, Some(State::ARM));
+        symbols.mark_region(0x80000c0, None);
+        assert_eq!(symbols.state_at(0x8000000), Some(State::ARM));
+        assert_eq!(symbols.state_at(0x80000c4), None);
+    }
+}
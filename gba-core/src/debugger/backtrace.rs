@@ -0,0 +1,83 @@
+use serde::Serialize;
+
+use crate::cpu::{Exception, Frame};
+use crate::debug_info::Symbols;
+
+/// A frame of a backtrace, innermost first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BacktraceFrame {
+    /// The instruction the frame is at: the one about to execute in the innermost frame, and
+    /// the call or the interrupted instruction in the others.
+    pub address: u32,
+    /// Where the function the frame is in starts, if the call into it was seen.
+    pub function: Option<u32>,
+    /// `address` as a symbol and an offset, when the core has symbols.
+    pub symbol: Option<String>,
+    /// The exception that was taken at `address`, which the frame inside this one handles.
+    pub exception: Option<Exception>,
+}
+
+/// Builds a backtrace from the CPU's call stack, starting from `pc`.
+pub fn backtrace(call_stack: &[Frame], pc: u32, symbols: &Symbols) -> Vec<BacktraceFrame> {
+    let innermost = BacktraceFrame {
+        address: pc,
+        function: call_stack.last().map(|frame| frame.function),
+        symbol: symbols.describe(pc),
+        exception: None,
+    };
+    let outer = call_stack.iter().enumerate().rev().map(|(i, frame)| {
+        let caller = i.checked_sub(1).map(|i| &call_stack[i]);
+        BacktraceFrame {
+            address: frame.call_site,
+            function: caller.map(|caller| caller.function),
+            symbol: symbols.describe(frame.call_site),
+            exception: frame.exception,
+        }
+    });
+    std::iter::once(innermost).chain(outer).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_info::Symbol;
+
+    #[test]
+    fn names_frames_from_symbols() {
+        let mut symbols = Symbols::default();
+        symbols.extend([Symbol {
+            name: "main".to_string(),
+            address: 0x8000100,
+            size: 0x40,
+            state: None,
+        }]);
+        let call_stack = [
+            Frame {
+                function: 0x8000100,
+                call_site: 0x80000f0,
+                return_address: 0x80000f4,
+                exception: None,
+            },
+            Frame {
+                function: 0x18,
+                call_site: 0x8000108,
+                return_address: 0x8000108,
+                exception: Some(Exception::Irq(1)),
+            },
+        ];
+        let frames = backtrace(&call_stack, 0x20, &symbols);
+        let summary: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.address, frame.function, frame.symbol.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0x20, Some(0x18), None),
+                (0x8000108, Some(0x8000100), Some("main+0x8")),
+                (0x80000f0, None, None),
+            ]
+        );
+        assert_eq!(frames[1].exception, Some(Exception::Irq(1)));
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt::Write as _;
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::utils::template::{self, Piece};
+
+use super::Expr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Expr(Expr),
+}
+
+/// A logpoint's message, as a template where each `{expression}` is replaced by the value of the
+/// expression, in hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogMessage {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl LogMessage {
+    pub fn new(template: &str) -> Result<Self, String> {
+        let segments = template::parse(template)?
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => Ok(Segment::Text(text.to_string())),
+                Piece::Placeholder(expr) => Expr::parse(expr).map(Segment::Expr),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            template: template.to_string(),
+            segments,
+        })
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Fills in the template. Expressions that fail to evaluate are replaced by the error.
+    pub fn format(&self, cpu: &Cpu, bus: &Bus) -> String {
+        let mut message = String::new();
+        for segment in &self.segments {
+            // Writing to a String can't fail
+            let _ = match segment {
+                Segment::Text(text) => write!(message, "{}", text),
+                Segment::Expr(expr) => match expr.eval(cpu, bus) {
+                    Ok(value) => write!(message, "{:#x}", value),
+                    Err(err) => write!(message, "<{}>", err),
+                },
+            };
+        }
+        message
+    }
+}
+
+/// What reaching a breakpoint did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hit {
+    /// The condition was false, or the hit was ignored.
+    Continue,
+    Stop,
+    /// The breakpoint is a logpoint, so it logged this rather than stopping.
+    Log(String),
+    /// The condition couldn't be evaluated, which stops so that it can be fixed.
+    Error(String),
+}
+
+/// A breakpoint on the instruction at an address, in either state.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// Only counts as hit when this evaluates to something other than 0.
+    pub condition: Option<Expr>,
+    /// The number of hits to let pass before stopping or logging.
+    pub ignore_count: u32,
+    /// Logs this every time it's hit instead of stopping.
+    pub log_message: Option<LogMessage>,
+    hits: u32,
+}
+
+impl Breakpoint {
+    /// The number of times the instruction was reached with the condition true, including the
+    /// ignored ones.
+    pub fn hits(&self) -> u32 {
+        self.hits
+    }
+
+    /// Called when the instruction at the breakpoint's address is about to execute.
+    pub fn hit(&mut self, cpu: &Cpu, bus: &Bus) -> Hit {
+        if let Some(condition) = &self.condition {
+            match condition.eval(cpu, bus) {
+                Ok(0) => return Hit::Continue,
+                Ok(_) => {}
+                Err(err) => return Hit::Error(format!("in condition `{}`: {}", condition, err)),
+            }
+        }
+
+        self.hits = self.hits.saturating_add(1);
+        if self.hits <= self.ignore_count {
+            return Hit::Continue;
+        }
+        match &self.log_message {
+            Some(message) => Hit::Log(message.format(cpu, bus)),
+            None => Hit::Stop,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_hits_when_the_condition_holds() {
+        let mut cpu = Cpu::default();
+        let bus = Bus::default();
+        cpu.skip_bios();
+
+        let mut breakpoint = Breakpoint {
+            condition: Some(Expr::parse("sp == 0x3007f00").unwrap()),
+            ignore_count: 1,
+            ..Breakpoint::default()
+        };
+        assert_eq!(breakpoint.hit(&cpu, &bus), Hit::Continue);
+        assert_eq!(breakpoint.hit(&cpu, &bus), Hit::Stop);
+        assert_eq!(breakpoint.hits(), 2);
+
+        breakpoint.condition = Some(Expr::parse("sp == 0").unwrap());
+        assert_eq!(breakpoint.hit(&cpu, &bus), Hit::Continue);
+        assert_eq!(breakpoint.hits(), 2);
+
+        breakpoint.condition = Some(Expr::parse("sp / 0").unwrap());
+        assert!(matches!(breakpoint.hit(&cpu, &bus), Hit::Error(_)));
+    }
+
+    #[test]
+    fn formats_log_messages() {
+        let mut cpu = Cpu::default();
+        let bus = Bus::default();
+        cpu.skip_bios();
+
+        let message = LogMessage::new("r0 is {r0}, sp is {sp - 0x100}, {1 / 0}").unwrap();
+        assert_eq!(
+            message.format(&cpu, &bus),
+            "r0 is 0x0, sp is 0x3007e00, <division by zero>"
+        );
+        assert!(LogMessage::new("{r0").is_err());
+        assert!(LogMessage::new("{r16}").is_err());
+    }
+}
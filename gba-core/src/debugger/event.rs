@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::cpu::{Exception, Mode, State};
+
+/// Something other than reaching an address that an event breakpoint stops the core on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "type")]
+pub enum Event {
+    /// Entering IRQ mode to handle any of these interrupts, as a mask of IE bits, or any
+    /// interrupt if it's 0.
+    Irq { sources: u16 },
+    /// Executing a SWI that calls this BIOS function, or any SWI if `None`.
+    Swi { number: Option<u32> },
+    /// Taking the undefined instruction exception.
+    Undefined,
+    /// Any change to the CPSR mode bits.
+    ModeChange,
+    /// Switching between ARM and THUMB.
+    StateChange,
+    /// An instruction writing to the I/O register whose first halfword is at `address`.
+    IoWrite { address: u32 },
+}
+
+/// Why the core stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type")]
+pub enum StopReason {
+    /// The debugger stopped it.
+    Paused,
+    /// The instruction at a breakpoint is about to execute.
+    Breakpoint {
+        address: u32,
+    },
+    /// The last instruction hit a watchpoint. The hits say which accesses it made.
+    Watchpoint {
+        id: u32,
+    },
+    /// At the start of the handler for these interrupts.
+    Irq {
+        id: u32,
+        sources: u16,
+    },
+    /// At the start of the SWI handler, for this BIOS function.
+    Swi {
+        id: u32,
+        number: u32,
+    },
+    /// At the start of the undefined instruction handler, for the instruction at `address`.
+    Undefined {
+        id: u32,
+        address: u32,
+    },
+    ModeChange {
+        id: u32,
+        from: Mode,
+        to: Mode,
+    },
+    StateChange {
+        id: u32,
+        thumb: bool,
+    },
+    /// The last instruction wrote to this I/O register.
+    IoWrite {
+        id: u32,
+        address: u32,
+    },
+}
+
+/// What happened to the CPU during a tick, which event breakpoints are checked against.
+pub struct Tick {
+    /// Where the instruction that was about to execute is.
+    pub pc: u32,
+    pub exception: Option<Exception>,
+    pub modes: (Mode, Mode),
+    pub states: (State, State),
+    /// The I/O registers watched by event breakpoints that were written to.
+    pub written_registers: Vec<u32>,
+}
+
+/// The event breakpoints, by ID.
+#[derive(Debug, Default)]
+pub struct EventBreakpoints {
+    events: BTreeMap<u32, Event>,
+    next_id: u32,
+}
+
+impl EventBreakpoints {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Adds an event breakpoint, returning the ID to remove it with.
+    pub fn add(&mut self, event: Event) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.events.insert(id, event);
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.events.remove(&id);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Event)> {
+        self.events.iter().map(|(id, event)| (*id, event))
+    }
+
+    /// The I/O registers to watch for writes.
+    pub fn registers(&self) -> impl Iterator<Item = u32> + '_ {
+        self.events.values().filter_map(|event| match event {
+            Event::IoWrite { address } => Some(*address),
+            _ => None,
+        })
+    }
+
+    /// Returns why to stop for the first event breakpoint that `tick` matches, if any.
+    pub fn check(&self, tick: &Tick) -> Option<StopReason> {
+        self.iter()
+            .find_map(|(id, event)| match (*event, tick.exception) {
+                (Event::Irq { sources }, Some(Exception::Irq(pending)))
+                    if sources == 0 || sources & pending != 0 =>
+                {
+                    Some(StopReason::Irq {
+                        id,
+                        sources: pending,
+                    })
+                }
+                (Event::Swi { number }, Some(Exception::Swi(called)))
+                    if number.map_or(true, |number| number == called) =>
+                {
+                    Some(StopReason::Swi { id, number: called })
+                }
+                (Event::Undefined, Some(Exception::Undefined)) => Some(StopReason::Undefined {
+                    id,
+                    address: tick.pc,
+                }),
+                (Event::ModeChange, _) if tick.modes.0 != tick.modes.1 => {
+                    Some(StopReason::ModeChange {
+                        id,
+                        from: tick.modes.0,
+                        to: tick.modes.1,
+                    })
+                }
+                (Event::StateChange, _) if tick.states.0 != tick.states.1 => {
+                    Some(StopReason::StateChange {
+                        id,
+                        thumb: tick.states.1 == State::Thumb,
+                    })
+                }
+                (Event::IoWrite { address }, _) if tick.written_registers.contains(&address) => {
+                    Some(StopReason::IoWrite { id, address })
+                }
+                _ => None,
+            })
+    }
+}
+
+/// Looks up the address of an I/O register by its name in GBATEK.
+pub fn io_register(name: &str) -> Option<u32> {
+    let offset = match name.to_uppercase().as_str() {
+        "DISPCNT" => 0x0,
+        "DISPSTAT" => 0x4,
+        "VCOUNT" => 0x6,
+        "BG0CNT" => 0x8,
+        "BG1CNT" => 0xa,
+        "BG2CNT" => 0xc,
+        "BG3CNT" => 0xe,
+        "BG0HOFS" => 0x10,
+        "BG0VOFS" => 0x12,
+        "BG1HOFS" => 0x14,
+        "BG1VOFS" => 0x16,
+        "BG2HOFS" => 0x18,
+        "BG2VOFS" => 0x1a,
+        "BG3HOFS" => 0x1c,
+        "BG3VOFS" => 0x1e,
+        "BG2X" => 0x28,
+        "BG2Y" => 0x2c,
+        "BG3X" => 0x38,
+        "BG3Y" => 0x3c,
+        "WIN0H" => 0x40,
+        "WIN1H" => 0x42,
+        "WIN0V" => 0x44,
+        "WIN1V" => 0x46,
+        "WININ" => 0x48,
+        "WINOUT" => 0x4a,
+        "MOSAIC" => 0x4c,
+        "BLDCNT" => 0x50,
+        "BLDALPHA" => 0x52,
+        "BLDY" => 0x54,
+        "SOUNDCNT_L" => 0x80,
+        "SOUNDCNT_H" => 0x82,
+        "SOUNDCNT_X" => 0x84,
+        "SOUNDBIAS" => 0x88,
+        "DMA0CNT_H" => 0xba,
+        "DMA1CNT_H" => 0xc6,
+        "DMA2CNT_H" => 0xd2,
+        "DMA3CNT_H" => 0xde,
+        "TM0CNT_L" => 0x100,
+        "TM0CNT_H" => 0x102,
+        "TM1CNT_L" => 0x104,
+        "TM1CNT_H" => 0x106,
+        "TM2CNT_L" => 0x108,
+        "TM2CNT_H" => 0x10a,
+        "TM3CNT_L" => 0x10c,
+        "TM3CNT_H" => 0x10e,
+        "SIOCNT" => 0x128,
+        "KEYINPUT" => 0x130,
+        "KEYCNT" => 0x132,
+        "RCNT" => 0x134,
+        "IE" => 0x200,
+        "IF" => 0x202,
+        "WAITCNT" => 0x204,
+        "IME" => 0x208,
+        "POSTFLG" => 0x300,
+        _ => return None,
+    };
+    Some(0x4000000 + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(exception: Option<Exception>) -> Tick {
+        Tick {
+            pc: 0x8000000,
+            exception,
+            modes: (Mode::System, Mode::System),
+            states: (State::ARM, State::ARM),
+            written_registers: vec![],
+        }
+    }
+
+    #[test]
+    fn filters_irqs_and_swis() {
+        let mut events = EventBreakpoints::default();
+        events.add(Event::Irq { sources: 0b10 });
+        events.add(Event::Swi { number: Some(5) });
+
+        assert_eq!(events.check(&tick(Some(Exception::Irq(0b01)))), None);
+        assert_eq!(
+            events.check(&tick(Some(Exception::Irq(0b11)))),
+            Some(StopReason::Irq {
+                id: 0,
+                sources: 0b11
+            })
+        );
+        assert_eq!(events.check(&tick(Some(Exception::Swi(6)))), None);
+        assert_eq!(
+            events.check(&tick(Some(Exception::Swi(5)))),
+            Some(StopReason::Swi { id: 1, number: 5 })
+        );
+        assert_eq!(events.check(&tick(Some(Exception::Undefined))), None);
+    }
+
+    #[test]
+    fn checks_mode_changes_and_registers() {
+        let mut events = EventBreakpoints::default();
+        events.add(Event::ModeChange);
+        let dispcnt = io_register("dispcnt").unwrap();
+        events.add(Event::IoWrite { address: dispcnt });
+        assert_eq!(events.registers().collect::<Vec<_>>(), [0x4000000]);
+
+        let mut irq = tick(Some(Exception::Irq(1)));
+        irq.modes.1 = Mode::IRQ;
+        assert!(matches!(
+            events.check(&irq),
+            Some(StopReason::ModeChange {
+                from: Mode::System,
+                to: Mode::IRQ,
+                ..
+            })
+        ));
+
+        let mut write = tick(None);
+        write.written_registers.push(0x4000000);
+        assert_eq!(
+            events.check(&write),
+            Some(StopReason::IoWrite {
+                id: 1,
+                address: 0x4000000
+            })
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! Expressions over registers, flags and memory, like `r0 == 0x1234 && [0x3000100].h > 5`, for
+//! breakpoint conditions and logpoint messages.
+//!
+//! - Numbers are decimal, or hex or binary with a `0x` or `0b` prefix.
+//! - `r0` to `r15`, `sp`, `lr` and `pc` are registers as banked in the current mode. `pc` is the
+//!   address of the instruction about to execute rather than the pipelined value.
+//! - `cpsr` is the CPSR, and `n`, `z`, `c`, `v` and `t` are its bits as 0 or 1.
+//! - `[address]` reads a word, and `[address].b`, `.h` and `.w` read a byte, halfword or word.
+//!   Memory is read like the debugger's other views of it, at any alignment and without the side
+//!   effects the CPU's reads can have.
+//! - Operators are C's, with the same precedence: `! ~ -`, `* / %`, `+ -`, `<< >>`,
+//!   `< <= > >=`, `== !=`, `&`, `^`, `|`, `&&` and `||`.
+//!
+//! Values are 32 bit and unsigned, and arithmetic wraps. Comparisons and logical operators give 0
+//! or 1.
+
+use std::fmt::{self, Display};
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Num(u32),
+    Name(String),
+    Symbol(&'static str),
+}
+
+/// Longer symbols come first, so that `<=` isn't read as `<` then `=`.
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "<<", ">>", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "&", "|",
+    "^", "!", "~", "(", ")", "[", "]", ".",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Not,
+    Complement,
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+/// Each binary operator, with how tightly it binds.
+const BINARY_OPS: &[(&str, BinaryOp, u8)] = &[
+    ("*", BinaryOp::Mul, 10),
+    ("/", BinaryOp::Div, 10),
+    ("%", BinaryOp::Rem, 10),
+    ("+", BinaryOp::Add, 9),
+    ("-", BinaryOp::Sub, 9),
+    ("<<", BinaryOp::Shl, 8),
+    (">>", BinaryOp::Shr, 8),
+    ("<", BinaryOp::Lt, 7),
+    ("<=", BinaryOp::Le, 7),
+    (">", BinaryOp::Gt, 7),
+    (">=", BinaryOp::Ge, 7),
+    ("==", BinaryOp::Eq, 6),
+    ("!=", BinaryOp::Ne, 6),
+    ("&", BinaryOp::BitAnd, 5),
+    ("^", BinaryOp::BitXor, 4),
+    ("|", BinaryOp::BitOr, 3),
+    ("&&", BinaryOp::And, 2),
+    ("||", BinaryOp::Or, 1),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Num(u32),
+    Reg(u32),
+    Cpsr,
+    /// A CPSR bit.
+    Flag(u32),
+    /// A read of 1, 2 or 4 bytes.
+    Mem(Box<Node>, u32),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    node: Node,
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+        };
+        let node = parser.expr(0)?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {} in `{}`", describe(token), source));
+        }
+        Ok(Self {
+            source: source.trim().to_string(),
+            node,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the expression without side effects. Fails on division by zero.
+    pub fn eval(&self, cpu: &Cpu, bus: &Bus) -> Result<u32, String> {
+        eval(&self.node, cpu, bus)
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        let word_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        if word_len > 0 {
+            let word = &rest[..word_len];
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                tokens.push(Token::Num(parse_number(word)?));
+            } else {
+                tokens.push(Token::Name(word.to_lowercase()));
+            }
+            rest = &rest[word_len..];
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            let c = rest.chars().next().unwrap();
+            return Err(format!("unexpected `{}` in `{}`", c, source));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Result<u32, String> {
+    let lower = text.to_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+    parsed.map_err(|_| format!("expected a number, found `{}`", text))
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Num(value) => format!("`{:#x}`", value),
+        Token::Name(name) => format!("`{}`", name),
+        Token::Symbol(symbol) => format!("`{}`", symbol),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or("unexpected end of expression")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(found)) if *found == symbol) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next()? {
+            Token::Symbol(found) if *found == symbol => Ok(()),
+            token => Err(format!("expected `{}`, found {}", symbol, describe(token))),
+        }
+    }
+
+    /// Parses binary operators that bind more tightly than `min_precedence`, by precedence
+    /// climbing.
+    fn expr(&mut self, min_precedence: u8) -> Result<Node, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Symbol(symbol)) = self.peek() {
+            let Some(&(_, op, precedence)) = BINARY_OPS
+                .iter()
+                .find(|(name, _, precedence)| name == symbol && *precedence > min_precedence)
+            else {
+                break;
+            };
+            self.position += 1;
+            let rhs = self.expr(precedence)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        let op = match self.peek() {
+            Some(Token::Symbol("!")) => UnaryOp::Not,
+            Some(Token::Symbol("~")) => UnaryOp::Complement,
+            Some(Token::Symbol("-")) => UnaryOp::Negate,
+            _ => return self.primary(),
+        };
+        self.position += 1;
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next()?.clone() {
+            Token::Num(value) => Ok(Node::Num(value)),
+            Token::Name(name) => parse_name(&name),
+            Token::Symbol("(") => {
+                let node = self.expr(0)?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Token::Symbol("[") => {
+                let address = self.expr(0)?;
+                self.expect("]")?;
+                let size = if self.eat(".") {
+                    match self.next()? {
+                        Token::Name(size) if size == "b" => 1,
+                        Token::Name(size) if size == "h" => 2,
+                        Token::Name(size) if size == "w" => 4,
+                        token => {
+                            return Err(format!("expected b, h or w, found {}", describe(token)))
+                        }
+                    }
+                } else {
+                    4
+                };
+                Ok(Node::Mem(Box::new(address), size))
+            }
+            token => Err(format!("unexpected {}", describe(&token))),
+        }
+    }
+}
+
+fn parse_name(name: &str) -> Result<Node, String> {
+    let node = match name {
+        "sp" => Node::Reg(13),
+        "lr" => Node::Reg(14),
+        "pc" => Node::Reg(15),
+        "cpsr" => Node::Cpsr,
+        "n" => Node::Flag(31),
+        "z" => Node::Flag(30),
+        "c" => Node::Flag(29),
+        "v" => Node::Flag(28),
+        "t" => Node::Flag(5),
+        _ => match name.strip_prefix('r').map(str::parse) {
+            Some(Ok(reg)) if reg < 16 => Node::Reg(reg),
+            _ => return Err(format!("unknown name `{}`", name)),
+        },
+    };
+    Ok(node)
+}
+
+fn eval(node: &Node, cpu: &Cpu, bus: &Bus) -> Result<u32, String> {
+    let value = match node {
+        Node::Num(value) => *value,
+        Node::Reg(15) => cpu.get_executing_instruction_pc(),
+        Node::Reg(reg) => cpu.reg(*reg),
+        Node::Cpsr => cpu.cpsr(),
+        Node::Flag(bit) => (cpu.cpsr() >> bit) & 1,
+        Node::Mem(address, size) => {
+            let address = eval(address, cpu, bus)?;
+            let mut bytes = [0; 4];
+            bytes[..*size as usize].copy_from_slice(&bus.peek(address, *size));
+            u32::from_le_bytes(bytes)
+        }
+        Node::Unary(op, operand) => {
+            let operand = eval(operand, cpu, bus)?;
+            match op {
+                UnaryOp::Not => u32::from(operand == 0),
+                UnaryOp::Complement => !operand,
+                UnaryOp::Negate => operand.wrapping_neg(),
+            }
+        }
+        Node::Binary(BinaryOp::And, lhs, rhs) => {
+            u32::from(eval(lhs, cpu, bus)? != 0 && eval(rhs, cpu, bus)? != 0)
+        }
+        Node::Binary(BinaryOp::Or, lhs, rhs) => {
+            u32::from(eval(lhs, cpu, bus)? != 0 || eval(rhs, cpu, bus)? != 0)
+        }
+        Node::Binary(op, lhs, rhs) => {
+            let lhs = eval(lhs, cpu, bus)?;
+            let rhs = eval(rhs, cpu, bus)?;
+            match op {
+                BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                BinaryOp::Div => lhs.checked_div(rhs).ok_or("division by zero")?,
+                BinaryOp::Rem => lhs.checked_rem(rhs).ok_or("division by zero")?,
+                BinaryOp::Add => lhs.wrapping_add(rhs),
+                BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                BinaryOp::Shl => lhs.checked_shl(rhs).unwrap_or(0),
+                BinaryOp::Shr => lhs.checked_shr(rhs).unwrap_or(0),
+                BinaryOp::Lt => u32::from(lhs < rhs),
+                BinaryOp::Le => u32::from(lhs <= rhs),
+                BinaryOp::Gt => u32::from(lhs > rhs),
+                BinaryOp::Ge => u32::from(lhs >= rhs),
+                BinaryOp::Eq => u32::from(lhs == rhs),
+                BinaryOp::Ne => u32::from(lhs != rhs),
+                BinaryOp::BitAnd => lhs & rhs,
+                BinaryOp::BitXor => lhs ^ rhs,
+                BinaryOp::BitOr => lhs | rhs,
+                BinaryOp::And | BinaryOp::Or => unreachable!(),
+            }
+        }
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_str(source: &str, cpu: &Cpu, bus: &Bus) -> Result<u32, String> {
+        Expr::parse(source)?.eval(cpu, bus)
+    }
+
+    #[test]
+    fn evaluates_registers_memory_and_operators() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+        cpu.skip_bios();
+        bus.write_half(0x3000100, 6);
+        bus.write(0x3000104, 0x12345678);
+
+        assert_eq!(eval_str("1 + 2 * 3 == 7", &cpu, &bus), Ok(1));
+        assert_eq!(eval_str("(1 + 2) * 3", &cpu, &bus), Ok(9));
+        assert_eq!(eval_str("0 - 1 >> 28", &cpu, &bus), Ok(0xf));
+        assert_eq!(eval_str("!0 && ~0 == 0xffffffff", &cpu, &bus), Ok(1));
+        assert_eq!(eval_str("sp == 0x3007F00 && lr == 0", &cpu, &bus), Ok(1));
+        assert_eq!(
+            eval_str("[0x3000100].h > 5 && [0x3000104].b", &cpu, &bus),
+            Ok(1)
+        );
+        assert_eq!(eval_str("[0x3000104]", &cpu, &bus), Ok(0x12345678));
+        assert_eq!(eval_str("[0x3000105].h", &cpu, &bus), Ok(0x3456));
+        assert_eq!(eval_str("cpsr & 0x1f | t << 5", &cpu, &bus), Ok(0x1f));
+        assert_eq!(eval_str("1 || 1 / 0", &cpu, &bus), Ok(1));
+        assert!(eval_str("1 / 0", &cpu, &bus).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for source in ["", "r16", "1 +", "(1", "[1].q", "1 2", "r0 = 1", "foo"] {
+            assert!(Expr::parse(source).is_err(), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Breakpoints, the expressions that control them, watchpoints, event breakpoints, backtraces
+//! and the profiler.
+
+mod backtrace;
+mod breakpoint;
+mod event;
+mod expr;
+mod profiler;
+mod watchpoint;
+
+pub use backtrace::{backtrace, BacktraceFrame};
+pub use breakpoint::{Breakpoint, Hit, LogMessage};
+pub use event::{io_register, Event, EventBreakpoints, StopReason, Tick};
+pub use expr::Expr;
+pub use profiler::{Counts, FunctionProfile, PcProfile, Profile, Profiler, RegionCycles};
+pub use watchpoint::{WatchHit, WatchKind, Watchpoint, Watchpoints};
@@ -0,0 +1,402 @@
+//! Counts where the CPU spends its time, by instruction and by function.
+//!
+//! Cycles are the bus cycles the CPU accounts for, which include fetching, so the cycles the
+//! pipeline takes to refill after a branch count towards the branch. Functions are the calls the
+//! call stack has seen, so code that's run without being called, like the startup code before
+//! `main`, is in an `[entry]` function of its own.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use crate::cpu::Frame;
+use crate::debug_info::Symbols;
+
+/// The parts of memory code can run from, which take different numbers of cycles to fetch from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Region {
+    Bios,
+    Ewram,
+    Iwram,
+    Rom,
+    Other,
+}
+
+impl Region {
+    pub fn of(address: u32) -> Self {
+        match address {
+            0x0000000..=0x0003fff => Self::Bios,
+            0x2000000..=0x2ffffff => Self::Ewram,
+            0x3000000..=0x3ffffff => Self::Iwram,
+            0x8000000..=0xdffffff => Self::Rom,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Cycles, split by the region the code that took them ran from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RegionCycles {
+    pub bios: u64,
+    pub ewram: u64,
+    pub iwram: u64,
+    pub rom: u64,
+    pub other: u64,
+}
+
+impl RegionCycles {
+    fn add(&mut self, region: Region, cycles: u64) {
+        *match region {
+            Region::Bios => &mut self.bios,
+            Region::Ewram => &mut self.ewram,
+            Region::Iwram => &mut self.iwram,
+            Region::Rom => &mut self.rom,
+            Region::Other => &mut self.other,
+        } += cycles;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.bios + self.ewram + self.iwram + self.rom + self.other
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    /// The instructions executed, or the samples taken when sampling.
+    pub instructions: u64,
+    pub cycles: u64,
+    /// The cycles of `cycles` that were spent waiting on memory.
+    pub waitstates: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+        self.waitstates += other.waitstates;
+    }
+}
+
+/// The time spent at an instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PcProfile {
+    pub address: u32,
+    pub symbol: Option<String>,
+    pub counts: Counts,
+}
+
+/// The time spent in a function, or `[entry]` for code that wasn't called, where the function's
+/// `address` is `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionProfile {
+    pub address: Option<u32>,
+    pub name: String,
+    /// The cycles spent in the function and everything it called.
+    pub inclusive: RegionCycles,
+    /// The cycles spent in the function itself.
+    pub exclusive: RegionCycles,
+    /// The instructions and waitstates in the function itself.
+    pub instructions: u64,
+    pub waitstates: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Profile {
+    pub total: Counts,
+    /// The instructions that were executed, with the most cycles first.
+    pub pcs: Vec<PcProfile>,
+    /// The functions that were executed, with the most inclusive cycles first.
+    pub functions: Vec<FunctionProfile>,
+}
+
+/// A call stack, as the functions in it outermost first, and the region of the code running in
+/// the innermost one.
+type StackKey = (Vec<u32>, Region);
+
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    /// Records an instruction every this many cycles, each standing for all the cycles since the
+    /// last, or every instruction if `None`.
+    sample_interval: Option<u64>,
+    /// The cycles since the last sample.
+    elapsed: u64,
+    pcs: HashMap<u32, Counts>,
+    stacks: Vec<(StackKey, Counts)>,
+    stack_indices: HashMap<StackKey, usize>,
+    /// The instruction being recorded and the index of its stack, which the cycles it takes are
+    /// added to, or `None` if it isn't sampled.
+    current: Option<(u32, usize)>,
+}
+
+impl Profiler {
+    pub fn new(sample_interval: Option<u64>) -> Self {
+        Self {
+            sample_interval: sample_interval.filter(|&interval| interval > 0),
+            ..Self::default()
+        }
+    }
+
+    /// Starts recording the instruction at `pc`, which is about to execute with `call_stack`.
+    pub fn instruction(&mut self, pc: u32, call_stack: &[Frame]) {
+        if let Some(interval) = self.sample_interval {
+            if self.elapsed < interval {
+                self.current = None;
+                return;
+            }
+            self.elapsed %= interval;
+        }
+        let region = Region::of(pc);
+        let stack = match self.current {
+            Some((_, index)) if self.is_stack(index, call_stack, region) => index,
+            _ => self.stack_index(call_stack, region),
+        };
+        self.current = Some((pc, stack));
+        self.count(Counts {
+            instructions: 1,
+            ..Counts::default()
+        });
+    }
+
+    /// Adds cycles to the instruction being recorded.
+    pub fn cycles(&mut self, cycles: u64, waitstates: u64) {
+        self.elapsed += cycles;
+        let counts = match self.sample_interval {
+            // The sampled instruction stands for the whole interval
+            Some(interval) if cycles > 0 => Counts {
+                instructions: 0,
+                cycles: interval,
+                waitstates: waitstates * interval / cycles,
+            },
+            Some(_) => return,
+            None => Counts {
+                instructions: 0,
+                cycles,
+                waitstates,
+            },
+        };
+        self.count(counts);
+        // Only the instruction itself is sampled, not the refill after it
+        if self.sample_interval.is_some() {
+            self.current = None;
+        }
+    }
+
+    fn count(&mut self, counts: Counts) {
+        if let Some((pc, stack)) = self.current {
+            self.pcs.entry(pc).or_default().add(counts);
+            self.stacks[stack].1.add(counts);
+        }
+    }
+
+    /// Whether the stack at `index` is `call_stack` running code in `region`.
+    fn is_stack(&self, index: usize, call_stack: &[Frame], region: Region) -> bool {
+        let key = &self.stacks[index].0;
+        key.1 == region
+            && key.0.len() == call_stack.len()
+            && key
+                .0
+                .iter()
+                .zip(call_stack)
+                .all(|(&function, frame)| function == frame.function)
+    }
+
+    fn stack_index(&mut self, call_stack: &[Frame], region: Region) -> usize {
+        let key = (
+            call_stack.iter().map(|frame| frame.function).collect(),
+            region,
+        );
+        *self.stack_indices.entry(key).or_insert_with_key(|key| {
+            self.stacks.push((key.clone(), Counts::default()));
+            self.stacks.len() - 1
+        })
+    }
+
+    pub fn profile(&self, symbols: &Symbols) -> Profile {
+        let mut total = Counts::default();
+        let mut pcs: Vec<_> = self
+            .pcs
+            .iter()
+            .map(|(&address, &counts)| {
+                total.add(counts);
+                PcProfile {
+                    address,
+                    symbol: symbols.describe(address),
+                    counts,
+                }
+            })
+            .collect();
+        pcs.sort_by_key(|pc| (std::cmp::Reverse(pc.counts.cycles), pc.address));
+
+        let mut functions: BTreeMap<Option<u32>, FunctionProfile> = BTreeMap::new();
+        for ((stack, region), counts) in &self.stacks {
+            // Recursive functions are only counted once for each stack they're in
+            let mut addresses = vec![None];
+            addresses.extend(stack.iter().copied().map(Some));
+            addresses.sort_unstable();
+            addresses.dedup();
+            for address in addresses {
+                function_profile(&mut functions, address, symbols)
+                    .inclusive
+                    .add(*region, counts.cycles);
+            }
+            let leaf = function_profile(&mut functions, stack.last().copied(), symbols);
+            leaf.exclusive.add(*region, counts.cycles);
+            leaf.instructions += counts.instructions;
+            leaf.waitstates += counts.waitstates;
+        }
+        let mut functions: Vec<_> = functions.into_values().collect();
+        functions.sort_by_key(|function| std::cmp::Reverse(function.inclusive.total()));
+
+        Profile {
+            total,
+            pcs,
+            functions,
+        }
+    }
+
+    /// The cycles spent in each call stack in the collapsed stack format that flame graph tools
+    /// read, with a line for each stack like `[entry];main;update 1234`.
+    pub fn collapsed_stacks(&self, symbols: &Symbols) -> String {
+        let mut cycles: BTreeMap<String, u64> = BTreeMap::new();
+        for ((stack, _), counts) in &self.stacks {
+            let mut names = vec![function_name(None, symbols)];
+            names.extend(
+                stack
+                    .iter()
+                    .map(|&address| function_name(Some(address), symbols)),
+            );
+            *cycles.entry(names.join(";")).or_default() += counts.cycles;
+        }
+        let mut text = String::new();
+        for (stack, cycles) in cycles.into_iter().filter(|&(_, cycles)| cycles > 0) {
+            let _ = writeln!(text, "{} {}", stack, cycles);
+        }
+        text
+    }
+}
+
+fn function_profile<'a>(
+    functions: &'a mut BTreeMap<Option<u32>, FunctionProfile>,
+    address: Option<u32>,
+    symbols: &Symbols,
+) -> &'a mut FunctionProfile {
+    functions.entry(address).or_insert_with(|| FunctionProfile {
+        address,
+        name: function_name(address, symbols),
+        inclusive: RegionCycles::default(),
+        exclusive: RegionCycles::default(),
+        instructions: 0,
+        waitstates: 0,
+    })
+}
+
+/// Names a function by its symbol, or by its address if it doesn't have one. The BIOS's
+/// exception vectors are named for the exception.
+fn function_name(address: Option<u32>, symbols: &Symbols) -> String {
+    match address {
+        None => "[entry]".to_string(),
+        Some(0x4) => "[undefined]".to_string(),
+        Some(0x8) => "[swi]".to_string(),
+        Some(0x18) => "[irq]".to_string(),
+        Some(address) => symbols
+            .describe(address)
+            .unwrap_or_else(|| format!("{:#010x}", address)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_info::Symbol;
+
+    fn call(function: u32) -> Frame {
+        Frame {
+            function,
+            call_site: 0,
+            return_address: 0,
+            exception: None,
+        }
+    }
+
+    fn run(profiler: &mut Profiler, pc: u32, call_stack: &[Frame], cycles: u64) {
+        profiler.instruction(pc, call_stack);
+        profiler.cycles(cycles, cycles - 1);
+    }
+
+    #[test]
+    fn attributes_cycles_to_functions() {
+        let mut symbols = Symbols::default();
+        symbols.extend([Symbol {
+            name: "main".to_string(),
+            address: 0x8000100,
+            size: 0x100,
+            state: None,
+        }]);
+        let main = [call(0x8000100)];
+        let fast = [call(0x8000100), call(0x3000000)];
+        let mut profiler = Profiler::new(None);
+        run(&mut profiler, 0x8000000, &[], 4);
+        run(&mut profiler, 0x8000100, &main, 4);
+        run(&mut profiler, 0x3000000, &fast, 1);
+        run(&mut profiler, 0x3000004, &fast, 1);
+        // The refill after a branch
+        profiler.cycles(2, 1);
+        run(&mut profiler, 0x8000104, &main, 4);
+
+        let profile = profiler.profile(&symbols);
+        assert_eq!(
+            profile.total,
+            Counts {
+                instructions: 5,
+                cycles: 16,
+                waitstates: 10
+            }
+        );
+        // The refill counts towards the branch before it
+        assert_eq!(profile.pcs[3].address, 0x3000004);
+        assert_eq!(profile.pcs[3].counts.cycles, 3);
+
+        let summary: Vec<_> = profile
+            .functions
+            .iter()
+            .map(|function| {
+                (
+                    function.name.as_str(),
+                    function.inclusive,
+                    function.exclusive.total(),
+                )
+            })
+            .collect();
+        let cycles = |rom, iwram| RegionCycles {
+            rom,
+            iwram,
+            ..RegionCycles::default()
+        };
+        assert_eq!(
+            summary,
+            [
+                ("[entry]", cycles(12, 4), 4),
+                ("main", cycles(8, 4), 8),
+                ("0x03000000", cycles(0, 4), 4),
+            ]
+        );
+        assert_eq!(
+            profiler.collapsed_stacks(&symbols),
+            "[entry] 4\n[entry];main 8\n[entry];main;0x03000000 4\n"
+        );
+    }
+
+    #[test]
+    fn samples_every_interval() {
+        let mut profiler = Profiler::new(Some(10));
+        for i in 0..10 {
+            run(&mut profiler, 0x8000000 + 4 * (i % 2), &[], 4);
+        }
+        let profile = profiler.profile(&Symbols::default());
+        // The instructions that start 12, 20 and 32 cycles in
+        assert_eq!(profile.total.instructions, 3);
+        assert_eq!(profile.total.cycles, 30);
+        assert_eq!(profile.total.waitstates, 3 * (10 * 3 / 4));
+    }
+}
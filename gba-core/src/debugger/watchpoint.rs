@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+/// What a watchpoint watches for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Reads and writes.
+    Access,
+    /// Writes that change the value in memory.
+    Change,
+}
+
+impl WatchKind {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            "access" => Ok(Self::Access),
+            "change" => Ok(Self::Change),
+            _ => Err(format!(
+                "expected read, write, access or change, found `{}`",
+                text
+            )),
+        }
+    }
+}
+
+/// Watches the bytes from `start` to `end` inclusive for accesses made by instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Watchpoint {
+    pub start: u32,
+    pub end: u32,
+    pub kind: WatchKind,
+    /// Only watches accesses of this many bytes, or of any size if `None`.
+    pub size: Option<u32>,
+}
+
+impl Watchpoint {
+    fn matches(&self, address: u32, size: u32, write: bool, changed: bool) -> bool {
+        let kind = match self.kind {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+            WatchKind::Change => changed,
+        };
+        kind && self.size.map_or(true, |watched| watched == size)
+            && address <= self.end
+            && address.wrapping_add(size - 1) >= self.start
+    }
+}
+
+/// An access that hit a watchpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WatchHit {
+    pub watchpoint: u32,
+    /// The address of the instruction that made the access.
+    pub pc: u32,
+    /// The instruction that made the access, disassembled.
+    pub instruction: String,
+    /// The address of the access, aligned to its size.
+    pub address: u32,
+    pub size: u32,
+    pub write: bool,
+    /// The value read or written.
+    pub value: u32,
+    /// What a write replaced.
+    pub old_value: Option<u32>,
+}
+
+/// The watchpoints, which the bus checks every data access against, and the hits since they were
+/// last taken. I/O registers that event breakpoints stop on writes to are watched here too.
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+    watchpoints: BTreeMap<u32, Watchpoint>,
+    next_id: u32,
+    /// Hits that the instruction that made them hasn't been filled in for yet.
+    hits: Vec<WatchHit>,
+    registers: BTreeSet<u32>,
+    written_registers: Vec<u32>,
+}
+
+impl Watchpoints {
+    /// Whether there's nothing to check, which is all the bus looks at when there aren't any
+    /// watchpoints.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty() && self.registers.is_empty()
+    }
+
+    /// Adds a watchpoint, returning the ID to remove it with.
+    pub fn add(&mut self, watchpoint: Watchpoint) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchpoints.insert(id, watchpoint);
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.watchpoints.remove(&id);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    pub fn check_read(&mut self, address: u32, size: u32, value: u32) {
+        self.check(address, size, value, None);
+    }
+
+    pub fn check_write(&mut self, address: u32, size: u32, value: u32, old_value: u32) {
+        self.check(address, size, value, Some(old_value));
+    }
+
+    fn check(&mut self, address: u32, size: u32, value: u32, old_value: Option<u32>) {
+        let write = old_value.is_some();
+        let changed = old_value.is_some_and(|old_value| old_value != value);
+        if write {
+            // Registers are at least a halfword, so any write to either byte counts
+            let written = self
+                .registers
+                .range(address.saturating_sub(1)..address.saturating_add(size));
+            for register in written {
+                if !self.written_registers.contains(register) {
+                    self.written_registers.push(*register);
+                }
+            }
+        }
+        for (id, watchpoint) in &self.watchpoints {
+            if watchpoint.matches(address, size, write, changed) {
+                self.hits.push(WatchHit {
+                    watchpoint: *id,
+                    pc: 0,
+                    instruction: String::new(),
+                    address,
+                    size,
+                    write,
+                    value,
+                    old_value,
+                });
+            }
+        }
+    }
+
+    /// Takes the hits since the last call, for the caller to fill in the instruction that made
+    /// them.
+    pub fn take_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+
+    pub fn has_hits(&self) -> bool {
+        !self.hits.is_empty()
+    }
+
+    /// Replaces the I/O registers watched for writes, by the address of their first halfword.
+    pub fn set_registers(&mut self, registers: impl IntoIterator<Item = u32>) {
+        self.registers = registers.into_iter().collect();
+    }
+
+    /// Takes the watched I/O registers that were written to since the last call.
+    pub fn take_written_registers(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.written_registers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watchpoints(kind: WatchKind, size: Option<u32>) -> Watchpoints {
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.add(Watchpoint {
+            start: 0x3000102,
+            end: 0x3000103,
+            kind,
+            size,
+        });
+        watchpoints
+    }
+
+    #[test]
+    fn matches_overlapping_accesses() {
+        let mut reads = watchpoints(WatchKind::Read, None);
+        reads.check_read(0x3000100, 4, 1);
+        reads.check_read(0x3000104, 4, 1);
+        reads.check_read(0x3000101, 1, 1);
+        reads.check_write(0x3000102, 2, 1, 0);
+        let hits = reads.take_hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].address, hits[0].size), (0x3000100, 4));
+        assert!(!reads.has_hits());
+    }
+
+    #[test]
+    fn filters_by_kind_and_size() {
+        let mut changes = watchpoints(WatchKind::Change, None);
+        changes.check_write(0x3000102, 2, 5, 5);
+        changes.check_write(0x3000102, 2, 6, 5);
+        assert_eq!(changes.take_hits().len(), 1);
+
+        let mut halves = watchpoints(WatchKind::Access, Some(2));
+        halves.check_write(0x3000100, 4, 0, 0);
+        halves.check_read(0x3000102, 2, 0);
+        halves.check_write(0x3000102, 2, 0, 0);
+        assert_eq!(halves.take_hits().len(), 2);
+    }
+
+    #[test]
+    fn watches_register_writes() {
+        let mut registers = Watchpoints::default();
+        registers.set_registers([0x4000004]);
+        assert!(!registers.is_empty());
+        registers.check_read(0x4000004, 2, 0);
+        registers.check_write(0x4000000, 4, 0, 0);
+        registers.check_write(0x4000006, 2, 0, 0);
+        assert!(registers.take_written_registers().is_empty());
+
+        registers.check_write(0x4000005, 1, 0, 0);
+        registers.check_write(0x4000004, 4, 0, 0);
+        assert_eq!(registers.take_written_registers(), [0x4000004]);
+        assert!(!registers.has_hits());
+    }
+}
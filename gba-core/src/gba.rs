@@ -1,41 +1,81 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, VecDeque};
 
-use crate::bus::{self, Bus};
-use crate::cpu::generate_luts;
+use crate::bus::{self, Bus, MemoryRegion, MAX_MULTIBOOT_SIZE, MULTIBOOT_ENTRY};
+use crate::cpu::assembler;
+use crate::cpu::disassembler::{self, DisassembledInstr};
+use crate::cpu::ir::Mnemonic;
+use crate::cpu::trace::Tracer;
 use crate::cpu::State;
-use crate::cpu::{ArmInstruction, Cpu, ThumbInstruction};
+use crate::cpu::{Coverage, Cpu, Flag, Mode};
+use crate::debug_info::{self, DebugInfo, Elf, SymbolMap};
+use crate::debugger::{
+    self, io_register, BacktraceFrame, Breakpoint, Event, EventBreakpoints, Expr, Hit, LogMessage,
+    Profile, Profiler, StopReason, Tick, WatchHit, WatchKind, Watchpoint,
+};
+use crate::rewind::Rewind;
+use crate::save_state;
 
+use tracing::info;
 use wasm_bindgen::prelude::*;
 
+/// 4 ticks per dot, 308 dots per line and 228 lines per frame.
+const TICKS_PER_FRAME: u64 = 4 * 308 * 228;
+/// How long the run commands keep going when they don't reach where they were headed, which is a
+/// second of emulated time.
+const MAX_RUN_TICKS: u64 = 60 * TICKS_PER_FRAME;
+/// The number of logpoint messages and watchpoint hits kept for the debugger to take.
+const MAX_LOG_MESSAGES: usize = 1000;
+
 #[cfg_attr(feature="debugger", wasm_bindgen)]
 pub struct GbaCore {
     pub(crate) cpu: Cpu,
     pub(crate) bus: Bus,
 
-    arm_lut: [Box<dyn ArmInstruction>; 0x1000],
-    thumb_lut: [Box<dyn ThumbInstruction>; 0x1000],
-
     pub stopped: bool,
     debugger_enabled: bool,
-    arm_breakpoints: HashSet<u32>,
-    thumb_breakpoints: HashSet<u32>,
+    breakpoints: BTreeMap<u32, Breakpoint>,
+    /// Set when resuming, so the instruction the core stopped on isn't stopped on again.
+    skip_breakpoint: bool,
+    log_messages: VecDeque<String>,
+    watch_hits: VecDeque<WatchHit>,
+    events: EventBreakpoints,
+    /// Set when an instruction hits a watchpoint or an event breakpoint, until the core stops for
+    /// it.
+    pending_stop: Option<StopReason>,
+    stop_reason: Option<StopReason>,
+    debug_info: DebugInfo,
+    /// Where the game starts once the BIOS is done, which is in EWRAM for multiboot images.
+    entry: u32,
+    profiler: Option<Profiler>,
+    /// Whether coverage starts again every frame, keeping the last frame's in
+    /// `last_frame_coverage`.
+    coverage_per_frame: bool,
+    last_frame_coverage: Option<Coverage>,
+    /// Snapshots to rewind to, taken as frames finish drawing.
+    rewind: Option<Rewind>,
 }
 
 impl Default for GbaCore {
     fn default() -> Self {
-        let (arm_lut, thumb_lut) = generate_luts();
-
         Self {
             cpu: Cpu::default(),
             bus: Bus::default(),
 
-            arm_lut,
-            thumb_lut,
-
             stopped: false,
             debugger_enabled: true,
-            arm_breakpoints: HashSet::new(),
-            thumb_breakpoints: HashSet::new(),
+            breakpoints: BTreeMap::new(),
+            skip_breakpoint: false,
+            log_messages: VecDeque::new(),
+            watch_hits: VecDeque::new(),
+            events: EventBreakpoints::default(),
+            pending_stop: None,
+            stop_reason: None,
+            debug_info: DebugInfo::default(),
+            entry: 0x8000000,
+            profiler: None,
+            coverage_per_frame: false,
+            last_frame_coverage: None,
+            rewind: None,
         }
     }
 }
@@ -45,6 +85,331 @@ impl GbaCore {
     pub fn screen(&self) -> Vec<u8> {
         self.bus.ppu.screen()
     }
+
+    /// Disassembles `count` instructions starting at `address`, without side effects. Symbols
+    /// that start at an instruction become its label, and branch targets are named by symbol.
+    pub fn disassemble(&self, address: u32, count: usize, state: State) -> Vec<DisassembledInstr> {
+        let mut instrs = disassembler::disassemble(&self.bus, address, count, state);
+        let symbols = &self.debug_info.symbols;
+        if !symbols.is_empty() {
+            for instr in &mut instrs {
+                if let Some((symbol, 0)) = symbols.lookup(instr.address) {
+                    instr.label = Some(symbol.name.clone());
+                }
+                instr.target_symbol = instr.target.and_then(|target| symbols.describe(target));
+            }
+        }
+        instrs
+    }
+
+    /// Starts writing a trace of every executed instruction, or stops if `tracer` is `None`.
+    /// Returns the previous tracer.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        self.cpu.set_tracer(tracer)
+    }
+
+    pub fn breakpoint(&self, address: u32) -> Option<&Breakpoint> {
+        self.breakpoints.get(&address)
+    }
+
+    /// Adds a breakpoint on the instruction at `address`, replacing any that's already there.
+    pub fn set_breakpoint(&mut self, address: u32, breakpoint: Breakpoint) {
+        self.breakpoints.insert(address, breakpoint);
+    }
+
+    /// Checks the breakpoint on the instruction that's about to execute, if there is one, and
+    /// returns whether to stop. Logpoint messages and condition errors are logged.
+    fn hit_breakpoint(&mut self) -> bool {
+        let pc = self.pc();
+        let Some(breakpoint) = self.breakpoints.get_mut(&pc) else {
+            return false;
+        };
+        let (stop, message) = match breakpoint.hit(&self.cpu, &self.bus) {
+            Hit::Continue => return false,
+            Hit::Stop => return true,
+            Hit::Log(message) => (false, message),
+            Hit::Error(err) => (true, format!("breakpoint at {:#010x}: {}", pc, err)),
+        };
+        info!("{}", message);
+        if self.log_messages.len() == MAX_LOG_MESSAGES {
+            self.log_messages.pop_front();
+        }
+        self.log_messages.push_back(message);
+        stop
+    }
+
+    /// Adds a watchpoint, returning the ID to remove it with.
+    pub fn watch(&mut self, watchpoint: Watchpoint) -> u32 {
+        self.bus.watchpoints.add(watchpoint)
+    }
+
+    /// Takes the watchpoint hits since the last call, oldest first.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.watch_hits.drain(..).collect()
+    }
+
+    /// Adds an event breakpoint, returning the ID to remove it with.
+    pub fn add_event_breakpoint(&mut self, event: Event) -> Result<u32, String> {
+        if let Event::IoWrite { address } = event {
+            if !(0x4000000..0x4000400).contains(&address) || address % 2 != 0 {
+                return Err(format!("{:#x} isn't an I/O register", address));
+            }
+        }
+        let id = self.events.add(event);
+        self.bus.watchpoints.set_registers(self.events.registers());
+        Ok(id)
+    }
+
+    /// Why the core stopped, or `None` if it's running.
+    pub fn stop_reason(&self) -> Option<&StopReason> {
+        self.stop_reason.as_ref()
+    }
+
+    /// Moves the PC, refilling the pipeline so that the core carries on from there.
+    pub fn set_pc(&mut self, pc: u32) {
+        self.cpu.write_reg(15, pc);
+        self.fill_pipeline();
+    }
+
+    /// Writes a register as it's banked in the current mode. Writing r15 moves the PC like
+    /// `set_pc` does.
+    pub fn set_reg(&mut self, index: u32, value: u32) -> Result<(), String> {
+        check_reg(index)?;
+        if index == 15 {
+            self.set_pc(value);
+        } else {
+            self.cpu.write_reg(index, value);
+        }
+        Ok(())
+    }
+
+    /// Writes a register as it's banked in `mode`, whichever mode the CPU is in. r15 isn't
+    /// banked, so writing it moves the PC like `set_pc` does.
+    pub fn set_reg_with_mode(&mut self, index: u32, mode: Mode, value: u32) -> Result<(), String> {
+        check_reg(index)?;
+        if index == 15 {
+            self.set_pc(value);
+        } else {
+            self.cpu.set_reg_with_mode(index, mode, value);
+        }
+        Ok(())
+    }
+
+    /// Writes the CPSR, unless the mode bits don't name a mode. Changing the T bit carries on
+    /// from the same instruction in the new state.
+    pub fn set_cpsr(&mut self, value: u32) -> Result<(), String> {
+        check_mode_bits(value)?;
+        self.cpu.write_cpsr(value);
+        self.fill_pipeline();
+        Ok(())
+    }
+
+    /// Writes the SPSR of `mode`, unless the mode doesn't have one or the mode bits don't name a
+    /// mode, which would leave the CPU in no mode when it returns from the exception.
+    pub fn set_spsr(&mut self, mode: Mode, value: u32) -> Result<(), String> {
+        if self.cpu.spsr(mode).is_none() {
+            return Err(format!("{:?} mode has no SPSR", mode));
+        }
+        check_mode_bits(value)?;
+        self.cpu.write_spsr(mode, value);
+        Ok(())
+    }
+
+    /// Sets or clears a bit of the CPSR, or of the SPSR of `spsr` if it's given.
+    pub fn set_flag(&mut self, flag: Flag, value: bool, spsr: Option<Mode>) -> Result<(), String> {
+        let psr = match spsr {
+            Some(mode) => self
+                .cpu
+                .spsr(mode)
+                .ok_or_else(|| format!("{:?} mode has no SPSR", mode))?,
+            None => self.cpu.cpsr(),
+        };
+        let psr = psr & !(1 << flag.bit()) | u32::from(value) << flag.bit();
+        match spsr {
+            Some(mode) => self.set_spsr(mode, psr),
+            None => self.set_cpsr(psr),
+        }
+    }
+
+    /// The symbols and source lines from the last ELF loaded.
+    pub fn debug_info(&self) -> &DebugInfo {
+        &self.debug_info
+    }
+
+    /// The state of the code at `address`, going by the symbols if they say, or else the state
+    /// the CPU is in.
+    pub fn state_at(&self, address: u32) -> State {
+        self.debug_info
+            .symbols
+            .state_at(address)
+            .unwrap_or_else(|| self.cpu.get_state())
+    }
+
+    /// The calls and exceptions that led to the current instruction, innermost first, with
+    /// symbols if the core has them.
+    pub fn backtrace(&self) -> Vec<BacktraceFrame> {
+        debugger::backtrace(self.cpu.call_stack(), self.pc(), &self.debug_info.symbols)
+    }
+
+    /// The instructions executed since coverage started, or in the last complete frame if it's
+    /// per frame. `None` if coverage isn't running.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        if self.coverage_per_frame {
+            self.last_frame_coverage.as_ref()
+        } else {
+            self.cpu.coverage()
+        }
+    }
+
+    /// What the profiler has recorded so far, or `None` if it isn't running.
+    pub fn profile(&self) -> Option<Profile> {
+        let profiler = self.profiler.as_ref()?;
+        Some(profiler.profile(&self.debug_info.symbols))
+    }
+
+    /// Stops the core, after filling the pipeline so that `pc` is the next instruction to
+    /// execute.
+    fn stop(&mut self, reason: StopReason) {
+        self.fill_pipeline();
+        self.pending_stop = None;
+        self.stopped = true;
+        self.stop_reason = Some(reason);
+    }
+
+    /// Ticks the CPU and PPU once, ignoring breakpoints and `stopped`.
+    fn tick_unchecked(&mut self) {
+        // Only an instruction can hit a watchpoint, and this is where it's from
+        let watched = if self.bus.watchpoints.is_empty() {
+            None
+        } else {
+            Some((self.pc(), self.cpu.get_state()))
+        };
+        let before = if self.events.is_empty() {
+            None
+        } else {
+            Some((self.pc(), self.cpu.get_mode(), self.cpu.get_state()))
+        };
+        let cycles = self
+            .profiler
+            .is_some()
+            .then(|| (self.cpu.memory_cycles(), self.cpu.waitstates()));
+        let drawing =
+            (self.coverage_per_frame || self.rewind.is_some()) && !self.bus.ppu.in_vblank();
+        if let Some(profiler) = &mut self.profiler {
+            if self.cpu.pipeline_full() {
+                let pc = self.cpu.get_executing_instruction_pc();
+                profiler.instruction(pc, self.cpu.call_stack());
+            }
+        }
+        self.cpu.tick(&mut self.bus);
+        self.bus.ppu.tick(&mut self.bus.io_map);
+        let exception = self.cpu.take_exception();
+
+        if drawing && self.bus.ppu.in_vblank() {
+            if self.coverage_per_frame {
+                self.last_frame_coverage = self.cpu.set_coverage(Some(Coverage::default()));
+            }
+            if let Some(rewind) = &mut self.rewind {
+                if rewind.frame() {
+                    rewind.push(save_state::snapshot(&self.cpu, &self.bus));
+                }
+            }
+        }
+        if let (Some(profiler), Some((cycles, waitstates))) = (&mut self.profiler, cycles) {
+            profiler.cycles(
+                (self.cpu.memory_cycles() - cycles) as u64,
+                (self.cpu.waitstates() - waitstates) as u64,
+            );
+        }
+
+        if let Some((pc, mode, state)) = before {
+            let tick = Tick {
+                pc,
+                exception,
+                modes: (mode, self.cpu.get_mode()),
+                states: (state, self.cpu.get_state()),
+                written_registers: self.bus.watchpoints.take_written_registers(),
+            };
+            if let Some(reason) = self.events.check(&tick) {
+                self.pending_stop.get_or_insert(reason);
+            }
+        }
+
+        if let Some((pc, state)) = watched.filter(|_| self.bus.watchpoints.has_hits()) {
+            let instruction = self
+                .disassemble(pc, 1, state)
+                .pop()
+                .map_or_else(String::new, |instr| instr.to_string());
+            let hits = self.bus.watchpoints.take_hits();
+            self.pending_stop.get_or_insert(StopReason::Watchpoint {
+                id: hits[0].watchpoint,
+            });
+            for mut hit in hits {
+                hit.pc = pc;
+                hit.instruction = instruction.clone();
+                if self.watch_hits.len() == MAX_LOG_MESSAGES {
+                    self.watch_hits.pop_front();
+                }
+                self.watch_hits.push_back(hit);
+            }
+        }
+    }
+
+    /// Ticks until the next tick executes an instruction, so that `pc` is where it's from.
+    fn fill_pipeline(&mut self) -> u64 {
+        let mut ticks = 0;
+        while !self.cpu.pipeline_full() {
+            self.tick_unchecked();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    /// Executes one instruction, or takes an interrupt instead if one is pending, and returns the
+    /// number of ticks that took.
+    fn step(&mut self) -> u64 {
+        let ticks = self.fill_pipeline();
+        self.tick_unchecked();
+        ticks + 1 + self.fill_pipeline()
+    }
+
+    /// Steps until `done` returns true before an instruction, which is where this stops. Also
+    /// stops, returning false, on reaching a breakpoint, watchpoint or event breakpoint, or
+    /// running for `MAX_RUN_TICKS`. The instruction this starts on is always executed, even if it
+    /// has a breakpoint.
+    fn run_until(&mut self, mut done: impl FnMut(&Self) -> bool) -> bool {
+        let mut ticks = 0;
+        while ticks < MAX_RUN_TICKS {
+            ticks += self.step();
+            if done(self) {
+                self.pending_stop = None;
+                return true;
+            }
+            let pending_stop = self.pending_stop.take();
+            if !self.debugger_enabled {
+                continue;
+            }
+            let reason = match pending_stop {
+                Some(reason) => reason,
+                None if self.hit_breakpoint() => StopReason::Breakpoint { address: self.pc() },
+                None => continue,
+            };
+            self.stop(reason);
+            return false;
+        }
+        false
+    }
+
+    /// Runs until the instruction at `address` is about to be executed in the same mode, with the
+    /// stack no deeper than it is now. This is where a call returns to, which makes sure that
+    /// recursive calls returning to the same address don't count.
+    fn run_to_return(&mut self, address: u32) -> bool {
+        let mode = self.cpu.get_mode();
+        let sp = self.cpu.reg(13);
+        self.run_until(|gba| {
+            gba.pc() == address && gba.cpu.get_mode() == mode && gba.cpu.reg(13) >= sp
+        })
+    }
 }
 
 #[cfg_attr(feature="debugger", wasm_bindgen)]
@@ -53,6 +418,10 @@ impl GbaCore {
         self.cpu.get_executing_instruction_pc()
     }
 
+    pub fn reg(&self, index: u32) -> u32 {
+        self.cpu.reg(index)
+    }
+
     pub fn read_halfword(&self, address: u32) -> u32 {
         self.bus.read_half(address, &self.cpu)
     }
@@ -62,13 +431,23 @@ impl GbaCore {
     }
 
     pub fn tick(&mut self) {
-        if self.debugger_enabled && self.should_break(&self.cpu.get_executing_instruction_pc()) {
-            self.stopped = true;
+        if self.stopped {
+            return;
         }
-
-        if !self.stopped {
-            self.cpu.tick(&mut self.bus, &self.arm_lut, &self.thumb_lut);
-            self.bus.ppu.tick(&mut self.bus.io_map);
+        // Breakpoints are only checked once per instruction, when it's about to execute
+        if self.debugger_enabled && self.cpu.pipeline_full() {
+            if self.skip_breakpoint {
+                self.skip_breakpoint = false;
+            } else if self.hit_breakpoint() {
+                self.stop(StopReason::Breakpoint { address: self.pc() });
+                return;
+            }
+        }
+        self.tick_unchecked();
+        if let Some(reason) = self.pending_stop.take() {
+            if self.debugger_enabled {
+                self.stop(reason);
+            }
         }
     }
 
@@ -78,84 +457,609 @@ impl GbaCore {
         }
     }
 
-    fn should_break(&self, address: &u32) -> bool {
-        match self.cpu.get_state() {
-            State::ARM => self.arm_breakpoints.contains(address),
-            State::Thumb => self.thumb_breakpoints.contains(address),
+    /// Executes the next instruction, even if the core is stopped. If an interrupt is pending,
+    /// this takes it instead and stops at the start of the handler.
+    pub fn step_instruction(&mut self) {
+        self.step();
+        self.pending_stop = None;
+    }
+
+    /// Like `step_instruction`, but runs a BL until the call returns rather than stopping in the
+    /// called function. Returns false if the call hit a breakpoint or didn't return within a
+    /// second of emulated time. The ARM7TDMI doesn't have BLX, so BL is the only call to handle.
+    pub fn step_over(&mut self) -> bool {
+        self.fill_pipeline();
+        let call = self
+            .disassemble(self.pc(), 1, self.cpu.get_state())
+            .pop()
+            .filter(|instr| matches!(instr.instr.mnemonic, Mnemonic::BL | Mnemonic::BL2));
+        match call {
+            Some(call) => self.run_to_return(call.address.wrapping_add(call.size)),
+            None => {
+                self.step_instruction();
+                true
+            }
         }
     }
 
+    /// Runs until the current function or exception handler returns to its caller, which is once
+    /// the shadow call stack is shallower than it is now, however the function returns and
+    /// whatever it's done with LR. If the stack is empty, like when the PC was moved into the
+    /// function rather than it being called, this goes by the address in LR instead. Returns
+    /// false if it stopped elsewhere.
+    pub fn step_out(&mut self) -> bool {
+        self.fill_pipeline();
+        let depth = self.cpu.call_stack().len();
+        if depth == 0 {
+            return self.run_to_return(self.cpu.reg(14) & !1);
+        }
+        self.run_until(|gba| gba.cpu.call_stack().len() < depth)
+    }
+
+    /// Runs until the instruction at `address` is about to be executed, in either state. Returns
+    /// false if a breakpoint was hit first or it wasn't reached within a second of emulated time.
+    pub fn run_to(&mut self, address: u32) -> bool {
+        self.fill_pipeline();
+        self.run_until(|gba| gba.pc() == address & !1)
+    }
+
+    /// Runs until the PPU finishes drawing the current frame and enters VBlank. Returns false if a
+    /// breakpoint was hit first.
+    pub fn run_frame(&mut self) -> bool {
+        self.fill_pipeline();
+        let mut was_drawing = !self.bus.ppu.in_vblank();
+        self.run_until(|gba| {
+            let drawing = !gba.bus.ppu.in_vblank();
+            let finished = was_drawing && !drawing;
+            was_drawing = drawing;
+            finished
+        })
+    }
+
     pub fn load_test_rom(&mut self) {
         let bytes = include_bytes!("../tests/roms/armwrestler-gba-fixed.gba");
         //let bytes = include_bytes!("../tests/roms/panda.gba");
         self.load_rom(bytes);
     }
 
+    /// Loads a cartridge ROM, or a multiboot image if it looks like one.
     pub fn load_rom(&mut self, bytes: &[u8]) {
+        if bus::is_multiboot(bytes) {
+            self.bus.load_multiboot(bytes);
+            self.entry = MULTIBOOT_ENTRY;
+        } else {
+            self.load_cartridge(bytes);
+        }
+    }
+
+    /// Loads a cartridge ROM, even if it looks like a multiboot image.
+    pub fn load_cartridge(&mut self, bytes: &[u8]) {
         self.bus.load_rom(bytes);
+        self.entry = 0x8000000;
+    }
+
+    /// Loads a multiboot image into EWRAM to boot from there, even if it doesn't look like one.
+    pub fn load_multiboot(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if bytes.len() > MAX_MULTIBOOT_SIZE {
+            return Err(format!("{:#x} bytes won't fit in EWRAM", bytes.len()));
+        }
+        self.bus.load_multiboot(bytes);
+        self.entry = MULTIBOOT_ENTRY;
+        Ok(())
+    }
+
+    /// Loads an ELF's segments at their load addresses to boot from its entry point, and keeps its
+    /// symbols and DWARF line info for the debugger. Nothing is loaded if it fails.
+    pub fn load_elf(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let elf = Elf::parse(bytes)?;
+        for segment in &elf.segments {
+            debug_info::check_segment(segment)?;
+        }
+        let info = DebugInfo::from_elf(&elf)?;
+        for segment in &elf.segments {
+            self.bus.poke(segment.load_address, segment.data);
+        }
+        self.entry = elf.entry & !1;
+        // Images linked to run from RAM are booted like multiboot images, from a ROM that copies
+        // the segments in the same part of RAM back after the BIOS clears it
+        if !(0x8000000..0xa000000).contains(&self.entry) {
+            let in_ram = || {
+                elf.segments
+                    .iter()
+                    .filter(|segment| segment.load_address >> 24 == self.entry >> 24)
+            };
+            let start = in_ram().map(|segment| segment.load_address).min();
+            let mut image = vec![];
+            for segment in in_ram() {
+                let offset = (segment.load_address - start.unwrap()) as usize;
+                let end = offset + segment.data.len();
+                image.resize(image.len().max(end), 0);
+                image[offset..end].copy_from_slice(segment.data);
+            }
+            let start = start.unwrap_or(self.entry);
+            self.bus.load_rom(&bus::boot_rom(&image, start, self.entry));
+        }
+        self.debug_info = info;
+        Ok(())
     }
 
+    /// Adds the symbols from a no$gba `.sym` file or a GNU ld `.map` file to the symbol table,
+    /// returning how many there were. `.arm` and `.thumb` markers in `.sym` files say what state
+    /// the code from there on is in.
+    pub fn load_symbol_map(&mut self, text: &str) -> Result<u32, String> {
+        let map = SymbolMap::parse(text)?;
+        Ok(self.debug_info.add_symbol_map(map) as u32)
+    }
+
+    /// Finds the address of a symbol, a source line as `file:line`, or an address in hex.
+    pub fn resolve_location(&self, location: &str) -> Result<u32, String> {
+        self.debug_info.resolve(location)
+    }
+
+    /// Describes `address` as a symbol and offset, like `main+0x1c`, if it's in one.
+    pub fn symbol_at(&self, address: u32) -> Option<String> {
+        self.debug_info.symbols.describe(address)
+    }
+
+    /// The source file and line of the instruction at `address`, as `file:line`.
+    pub fn source_line(&self, address: u32) -> Option<String> {
+        let (file, line) = self.debug_info.lines.location(address)?;
+        Some(format!("{}:{}", file, line))
+    }
+
+    /// Starts the game without running the BIOS, from EWRAM for multiboot images.
     pub fn skip_bios(&mut self) {
-        self.cpu.skip_bios();
+        self.cpu.skip_bios_to(self.entry);
     }
 
     pub fn reset(self) -> Self {
-        Self {
+        let mut gba = Self {
             stopped: self.stopped,
-            arm_breakpoints: self.arm_breakpoints,
-            thumb_breakpoints: self.thumb_breakpoints,
+            breakpoints: self.breakpoints,
+            events: self.events,
+            stop_reason: self.stop_reason,
+            debug_info: self.debug_info,
             ..Self::default()
-        }
+        };
+        gba.bus.watchpoints = self.bus.watchpoints;
+        gba.bus.watchpoints.set_registers(gba.events.registers());
+        gba
     }
 
     pub fn enable_debugger(&mut self, enabled: bool) {
         self.debugger_enabled = enabled;
     }
 
+    pub fn enable_block_cache(&mut self, enabled: bool) {
+        self.cpu.enable_block_cache(enabled);
+    }
+
+    /// Stops or resumes the core. Stopping finishes refilling the pipeline, so that `pc` is the
+    /// next instruction. Resuming always executes the current instruction, even if there's a
+    /// breakpoint on it.
     pub fn set_stopped(&mut self, value: bool) {
-        self.stopped = value;
+        match (self.stopped, value) {
+            (false, true) => self.stop(StopReason::Paused),
+            (true, false) => {
+                self.skip_breakpoint = true;
+                self.stopped = false;
+                self.stop_reason = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Why the core stopped, as an object with a `type` and the details of what it stopped on, or
+    /// null if it's running.
+    pub fn describe_stop_reason(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.stop_reason).unwrap_or(JsValue::NULL)
+    }
+
+    /// Starts profiling, which records every instruction, or if `sample_interval` is given, one
+    /// every that many cycles. This throws away anything the profiler has already recorded.
+    pub fn start_profiling(&mut self, sample_interval: Option<u32>) {
+        self.profiler = Some(Profiler::new(sample_interval.map(u64::from)));
+    }
+
+    pub fn stop_profiling(&mut self) {
+        self.profiler = None;
+    }
+
+    /// What the profiler has recorded, as an object with the totals and the instructions and
+    /// functions that took the most cycles, or null if it isn't running.
+    pub fn describe_profile(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.profile()).unwrap_or(JsValue::NULL)
+    }
+
+    /// The cycles spent in each call stack the profiler has seen, in the collapsed stack format
+    /// that flame graph tools read.
+    pub fn collapsed_stacks(&self) -> Option<String> {
+        let profiler = self.profiler.as_ref()?;
+        Some(profiler.collapsed_stacks(&self.debug_info.symbols))
+    }
+
+    /// Starts recording which instructions are executed, from scratch. If `per_frame` is set,
+    /// it starts again at the start of every frame, and only the last complete frame is kept.
+    pub fn start_coverage(&mut self, per_frame: bool) {
+        self.cpu.set_coverage(Some(Coverage::default()));
+        self.coverage_per_frame = per_frame;
+        self.last_frame_coverage = None;
+    }
+
+    pub fn stop_coverage(&mut self) {
+        self.cpu.set_coverage(None);
+        self.coverage_per_frame = false;
+        self.last_frame_coverage = None;
+    }
+
+    /// The executed code, as an array of `{ start, end, thumb }` ranges of instructions, or null
+    /// if coverage isn't running.
+    pub fn coverage_ranges(&self) -> JsValue {
+        let ranges = self.coverage().map(Coverage::ranges);
+        serde_wasm_bindgen::to_value(&ranges).unwrap_or(JsValue::NULL)
+    }
+
+    /// Line coverage in the lcov tracefile format, for the lines in the ELF's line info. `None`
+    /// if coverage isn't running, or there's no line info.
+    pub fn coverage_lcov(&self) -> Option<String> {
+        if self.debug_info.lines.is_empty() {
+            return None;
+        }
+        Some(self.coverage()?.lcov(&self.debug_info.lines))
+    }
+
+    /// The backtrace, as an array of objects with the address, function and symbol of each
+    /// frame, innermost first.
+    pub fn describe_backtrace(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.backtrace()).unwrap_or(JsValue::NULL)
+    }
+
+    /// The addresses of all breakpoints, including logpoints.
+    pub fn breakpoints(&self) -> Vec<u32> {
+        self.breakpoints.keys().copied().collect()
+    }
+
+    /// Adds an unconditional breakpoint, unless there's already one at `address`.
+    pub fn add_breakpoint(&mut self, address: u32) {
+        self.breakpoints.entry(address).or_default();
+    }
+
+    /// Adds an unconditional breakpoint at a symbol, a source line as `file:line`, or an address
+    /// in hex, returning the address.
+    pub fn add_breakpoint_at(&mut self, location: &str) -> Result<u32, String> {
+        let address = self.resolve_location(location)?;
+        self.add_breakpoint(address);
+        Ok(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u32) {
+        self.breakpoints.remove(&address);
+    }
+
+    /// Sets the condition of the breakpoint at `address`, adding the breakpoint if needed. An
+    /// empty condition removes it. See [`Expr`] for the syntax.
+    pub fn set_breakpoint_condition(
+        &mut self,
+        address: u32,
+        condition: &str,
+    ) -> Result<(), String> {
+        let condition = match condition.trim() {
+            "" => None,
+            condition => Some(Expr::parse(condition)?),
+        };
+        self.breakpoints.entry(address).or_default().condition = condition;
+        Ok(())
+    }
+
+    /// Sets how many hits of the breakpoint at `address` to let pass, adding the breakpoint if
+    /// needed.
+    pub fn set_breakpoint_ignore_count(&mut self, address: u32, ignore_count: u32) {
+        self.breakpoints.entry(address).or_default().ignore_count = ignore_count;
+    }
+
+    /// Turns the breakpoint at `address` into a logpoint with this message, adding it if needed.
+    /// An empty message turns it back into a breakpoint. See [`LogMessage`] for the syntax.
+    pub fn set_logpoint(&mut self, address: u32, message: &str) -> Result<(), String> {
+        let message = match message {
+            "" => None,
+            message => Some(LogMessage::new(message)?),
+        };
+        self.breakpoints.entry(address).or_default().log_message = message;
+        Ok(())
+    }
+
+    /// The number of times the breakpoint at `address` has been hit with its condition true.
+    pub fn breakpoint_hits(&self, address: u32) -> u32 {
+        self.breakpoints.get(&address).map_or(0, Breakpoint::hits)
+    }
+
+    /// Takes the messages logged by logpoints and failed breakpoint conditions since the last
+    /// call, oldest first.
+    pub fn take_log_messages(&mut self) -> Vec<String> {
+        self.log_messages.drain(..).collect()
+    }
+
+    /// Watches the bytes from `start` to `end` inclusive for `kind` accesses, which is one of
+    /// "read", "write", "access" or "change". Only accesses of `size` bytes are watched, unless
+    /// it's 0. The core stops after the instruction that hits a watchpoint. Returns the ID to
+    /// remove the watchpoint with.
+    pub fn add_watchpoint(
+        &mut self,
+        start: u32,
+        end: u32,
+        kind: &str,
+        size: u32,
+    ) -> Result<u32, String> {
+        if start > end {
+            return Err(format!("{:#x} is after {:#x}", start, end));
+        }
+        let size = match size {
+            0 => None,
+            1 | 2 | 4 => Some(size),
+            _ => return Err(format!("accesses can't be {} bytes", size)),
+        };
+        Ok(self.watch(Watchpoint {
+            start,
+            end,
+            kind: WatchKind::parse(kind)?,
+            size,
+        }))
+    }
+
+    pub fn remove_watchpoint(&mut self, id: u32) {
+        self.bus.watchpoints.remove(id);
+    }
+
+    /// The watchpoints, as an array of `[id, watchpoint]` pairs.
+    pub fn watchpoints(&self) -> JsValue {
+        let watchpoints: Vec<_> = self.bus.watchpoints.iter().collect();
+        serde_wasm_bindgen::to_value(&watchpoints).unwrap_or(JsValue::NULL)
     }
 
+    /// Takes the watchpoint hits since the last call, as an array of objects, each with the
+    /// address and disassembly of the instruction that made the access.
+    pub fn drain_watch_hits(&mut self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.take_watch_hits()).unwrap_or(JsValue::NULL)
+    }
+
+    /// Stops at the start of the IRQ handler when any of `sources` is pending, as a mask of IE
+    /// bits, or for any interrupt if it's 0. Returns the ID to remove the event breakpoint with.
+    pub fn break_on_irq(&mut self, sources: u16) -> u32 {
+        self.events.add(Event::Irq { sources })
+    }
+
+    /// Stops at the start of the SWI handler when it's called for BIOS function `number`, or for
+    /// any function if it's `None`.
+    pub fn break_on_swi(&mut self, number: Option<u32>) -> u32 {
+        self.events.add(Event::Swi { number })
+    }
+
+    /// Stops at the start of the undefined instruction handler.
+    pub fn break_on_undefined(&mut self) -> u32 {
+        self.events.add(Event::Undefined)
+    }
+
+    /// Stops after anything that changes the CPSR mode, including exceptions.
+    pub fn break_on_mode_change(&mut self) -> u32 {
+        self.events.add(Event::ModeChange)
+    }
+
+    /// Stops after anything that switches between ARM and THUMB.
+    pub fn break_on_state_change(&mut self) -> u32 {
+        self.events.add(Event::StateChange)
+    }
+
+    /// Stops after an instruction writes to an I/O register, given by its name, such as
+    /// "DISPCNT", or its address.
+    pub fn break_on_io_write(&mut self, register: &str) -> Result<u32, String> {
+        let address = match io_register(register) {
+            Some(address) => address,
+            None => {
+                let digits = register.trim_start_matches("0x");
+                u32::from_str_radix(digits, 16)
+                    .map_err(|_| format!("unknown I/O register `{}`", register))?
+            }
+        };
+        self.add_event_breakpoint(Event::IoWrite { address })
+    }
+
+    pub fn remove_event_breakpoint(&mut self, id: u32) {
+        self.events.remove(id);
+        self.bus.watchpoints.set_registers(self.events.registers());
+    }
+
+    /// The event breakpoints, as an array of `[id, event]` pairs.
+    pub fn event_breakpoints(&self) -> JsValue {
+        let events: Vec<_> = self.events.iter().collect();
+        serde_wasm_bindgen::to_value(&events).unwrap_or(JsValue::NULL)
+    }
+
+    /// Breakpoints don't depend on the state any more, so this is the same as `breakpoints`.
     pub fn arm_breakpoints(&self) -> Vec<u32> {
-        self.arm_breakpoints.iter().copied().collect()
+        self.breakpoints()
     }
 
+    /// Breakpoints don't depend on the state any more, so this is the same as `breakpoints`.
     pub fn thumb_breakpoints(&self) -> Vec<u32> {
-        self.thumb_breakpoints.iter().copied().collect()
+        self.breakpoints()
     }
 
     pub fn add_arm_breakpoint(&mut self, breakpoint: u32) {
-        self.arm_breakpoints.insert(breakpoint);
+        self.add_breakpoint(breakpoint);
     }
 
     pub fn add_thumb_breakpoint(&mut self, breakpoint: u32) {
-        self.thumb_breakpoints.insert(breakpoint);
+        self.add_breakpoint(breakpoint);
     }
 
     pub fn remove_arm_breakpoint(&mut self, breakpoint: u32) {
-        self.arm_breakpoints.remove(&breakpoint);
+        self.remove_breakpoint(breakpoint);
     }
 
     pub fn remove_thumb_breakpoint(&mut self, breakpoint: u32) {
-        self.thumb_breakpoints.remove(&breakpoint);
+        self.remove_breakpoint(breakpoint);
     }
 
     pub fn read_address(&self, address: u32) -> u32 {
         self.bus.read(address, &self.cpu)
     }
 
+    /// Reads a byte without the side effects a read by the CPU can have. Memory that isn't
+    /// mapped reads as 0.
+    pub fn peek_byte(&self, address: u32) -> u8 {
+        self.bus.peek_byte(address)
+    }
+
+    /// Reads a halfword like `peek_byte`, at any alignment.
+    pub fn peek_half(&self, address: u32) -> u16 {
+        u16::from_le_bytes(self.bus.peek(address, 2).try_into().unwrap())
+    }
+
+    /// Reads a word like `peek_byte`, at any alignment.
+    pub fn peek_word(&self, address: u32) -> u32 {
+        u32::from_le_bytes(self.bus.peek(address, 4).try_into().unwrap())
+    }
+
+    pub fn peek_range(&self, address: u32, length: u32) -> Vec<u8> {
+        self.bus.peek(address, length)
+    }
+
+    /// Writes a byte without the side effects a write by the CPU can have, which can patch the
+    /// ROM and the BIOS too.
+    pub fn poke_byte(&mut self, address: u32, value: u8) {
+        self.poke_range(address, &[value]);
+    }
+
+    /// Writes a halfword like `poke_byte`, at any alignment.
+    pub fn poke_half(&mut self, address: u32, value: u16) {
+        self.poke_range(address, &value.to_le_bytes());
+    }
+
+    /// Writes a word like `poke_byte`, at any alignment.
+    pub fn poke_word(&mut self, address: u32, value: u32) {
+        self.poke_range(address, &value.to_le_bytes());
+    }
+
+    /// Writes `bytes` like `poke_byte`. If that overwrites the instructions in the pipeline,
+    /// they're fetched again, so the core runs what's in memory now.
+    pub fn poke_range(&mut self, address: u32, bytes: &[u8]) {
+        self.bus.poke(address, bytes);
+        let pc = self.pc();
+        let fetched = if self.thumb_state() { 4 } else { 8 };
+        let overwritten =
+            pc.wrapping_sub(address) < bytes.len() as u32 || address.wrapping_sub(pc) < fetched;
+        if self.cpu.pipeline_full() && overwritten {
+            self.set_pc(pc);
+        }
+    }
+
+    /// A copy of a region of memory, which is one of bios, ewram, iwram, io, palette, vram, oam or
+    /// save.
+    pub fn dump_region(&self, region: &str) -> Result<Vec<u8>, String> {
+        Ok(self.bus.dump(MemoryRegion::parse(region)?))
+    }
+
+    /// Disassembles a range of memory for the debugger, as an array of objects.
+    pub fn disassemble_range(&self, address: u32, count: u32, thumb: bool) -> JsValue {
+        let state = if thumb { State::Thumb } else { State::ARM };
+        let instrs = self.disassemble(address, count as usize, state);
+        serde_wasm_bindgen::to_value(&instrs).unwrap_or(JsValue::NULL)
+    }
+
+    /// Disassembles from a symbol, a source line as `file:line`, or an address in hex, in the
+    /// state the symbols say the code is in.
+    pub fn disassemble_at(&self, location: &str, count: u32) -> Result<JsValue, String> {
+        let address = self.resolve_location(location)?;
+        let instrs = self.disassemble(address, count as usize, self.state_at(address));
+        Ok(serde_wasm_bindgen::to_value(&instrs).unwrap_or(JsValue::NULL))
+    }
+
+    /// Assembles `source` and writes it to `address`, returning the number of bytes written.
+    pub fn patch(&mut self, address: u32, source: &str, thumb: bool) -> Result<u32, String> {
+        let state = if thumb { State::Thumb } else { State::ARM };
+        let bytes = assembler::assemble(source, address, state).map_err(|err| err.to_string())?;
+        self.poke_range(address, &bytes);
+        Ok(bytes.len() as u32)
+    }
+
     pub fn set_key(&mut self, key: bus::Key, pressed: bool) {
         self.bus.set_key(key, pressed);
     }
+
+    /// Saves the state of the whole machine, apart from the BIOS and the ROM, along with a
+    /// thumbnail of the screen.
+    pub fn save_state(&self) -> Vec<u8> {
+        save_state::save(&self.cpu, &self.bus)
+    }
+
+    /// Loads a save state of the game that's loaded. What the debugger has set up, like
+    /// breakpoints, stays as it is.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let state = save_state::load(bytes, &self.bus)?;
+        self.cpu.restore(state.cpu);
+        self.bus.restore(state.bus);
+        Ok(())
+    }
+
+    /// The thumbnail of the screen in a save state, as RGB pixels, 120 by 80.
+    pub fn save_state_thumbnail(bytes: &[u8]) -> Result<Vec<u8>, String> {
+        save_state::load_thumbnail(bytes)
+    }
+
+    /// Starts keeping the last `capacity` frames that are `interval` frames apart to rewind to,
+    /// from the next frame on. Only the newest is kept whole, and the rest as what changed.
+    pub fn start_rewind(&mut self, interval: u32, capacity: usize) {
+        self.rewind = Some(Rewind::new(interval, capacity));
+    }
+
+    pub fn stop_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Goes back to the start of the newest frame kept that's at least `frames` frames back, or
+    /// the oldest if none are that far, and forgets the frames after it. Returns how many frames
+    /// back it went.
+    pub fn rewind(&mut self, frames: u32) -> Result<u32, String> {
+        let Some(rewind) = &mut self.rewind else {
+            return Err("rewind isn't on".to_string());
+        };
+        let Some((snapshot, back)) = rewind.rewind(frames) else {
+            return Ok(0);
+        };
+        let (cpu, bus) = save_state::load_snapshot(&snapshot)?;
+        self.cpu.restore(cpu);
+        self.bus.restore(bus);
+        Ok(back)
+    }
+
+    /// The bytes the frames kept to rewind to take up.
+    pub fn rewind_size(&self) -> usize {
+        self.rewind.as_ref().map_or(0, Rewind::size)
+    }
+}
+
+fn check_mode_bits(psr: u32) -> Result<(), String> {
+    match psr & 0x1f {
+        0x10 | 0x11 | 0x12 | 0x13 | 0x17 | 0x1b | 0x1f => Ok(()),
+        bits => Err(format!("{:#07b} isn't a mode", bits)),
+    }
+}
+
+fn check_reg(index: u32) -> Result<(), String> {
+    match index {
+        0..=15 => Ok(()),
+        _ => Err(format!("there's no r{}", index)),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cpu::{CoveredRange, Exception};
 
     #[test]
     fn write_to_if_clears_bit() {
-        let mut gba = GbaCore::new();
+        let mut gba = GbaCore::default();
         gba.bus.io_map.set_interrupt(bus::Interrupt::VBlank, true);
 
         assert_eq!(gba.bus.read_half(0x4000202, &gba.cpu), 1);
@@ -164,4 +1068,634 @@ mod tests {
 
         assert_eq!(gba.bus.read_half(0x4000202, &gba.cpu), 0);
     }
+
+    /// Boots into `source`, assembled at the start of ROM.
+    fn boot(source: &str, thumb: bool) -> GbaCore {
+        let mut gba = GbaCore::default();
+        gba.skip_bios();
+        gba.patch(0x8000000, source, thumb).unwrap();
+        gba
+    }
+
+    #[test]
+    fn edits_banked_registers_and_flags() {
+        let mut gba = boot("MOV r0, #1\nMOVS r1, r0\nB 0x8000008", false);
+        gba.step_instruction();
+        assert_eq!(gba.pc(), 0x8000004);
+
+        gba.set_reg_with_mode(13, Mode::IRQ, 0x3001000).unwrap();
+        gba.set_reg_with_mode(8, Mode::FIQ, 8).unwrap();
+        assert_eq!(
+            gba.set_reg_with_mode(16, Mode::IRQ, 0),
+            Err("there's no r16".to_string())
+        );
+        assert_eq!(gba.set_reg(16, 0), Err("there's no r16".to_string()));
+        assert_eq!(gba.cpu.reg_with_mode(13, Mode::IRQ), 0x3001000);
+        assert_eq!(gba.reg(13), 0x3007f00);
+        assert_eq!(gba.reg(8), 0);
+
+        assert_eq!(
+            gba.set_spsr(Mode::System, 0x1f),
+            Err("System mode has no SPSR".to_string())
+        );
+        assert_eq!(
+            gba.set_spsr(Mode::IRQ, 0),
+            Err("0b00000 isn't a mode".to_string())
+        );
+        assert_eq!(gba.set_spsr(Mode::IRQ, 0x1f), Ok(()));
+        assert_eq!(gba.set_flag(Flag::C, true, Some(Mode::IRQ)), Ok(()));
+        assert_eq!(gba.cpu.spsr(Mode::IRQ), Some(0x2000001f));
+
+        assert_eq!(Mode::parse("SVC"), Ok(Mode::Supervisor));
+        assert!(Flag::parse("q").is_err());
+        let flag = Flag::parse("I").unwrap();
+        gba.set_flag(flag, false, None).unwrap();
+        gba.set_flag(Flag::Z, true, None).unwrap();
+        assert_eq!(gba.cpu.cpsr(), 0x4000005f);
+
+        // Switching state carries on from the same instruction
+        gba.set_flag(Flag::T, true, None).unwrap();
+        assert!(gba.thumb_state());
+        assert_eq!(gba.pc(), 0x8000004);
+        gba.set_flag(Flag::T, false, None).unwrap();
+        assert!(!gba.thumb_state());
+        assert_eq!(gba.pc(), 0x8000004);
+
+        // r15 isn't banked, so this moves the PC
+        gba.set_reg_with_mode(15, Mode::FIQ, 0x8000000).unwrap();
+        assert_eq!(gba.pc(), 0x8000000);
+        gba.step_instruction();
+        gba.step_instruction();
+        assert_eq!(gba.reg(1), 1);
+        assert_eq!(gba.cpu.cpsr() >> 28, 0);
+    }
+
+    #[test]
+    fn pokes_instructions_that_were_already_fetched() {
+        let mut gba = boot("MOV r0, #1\nMOV r0, #2\nB 0x8000008", false);
+        gba.step_instruction();
+        let mov = assembler::assemble("MOV r0, #3", 0x8000004, State::ARM).unwrap();
+        let mov = u32::from_le_bytes(mov.try_into().unwrap());
+        gba.poke_word(0x8000004, mov);
+        gba.step_instruction();
+        assert_eq!(gba.reg(0), 3);
+        assert_eq!(gba.peek_word(0x8000004), mov);
+
+        gba.poke_half(0x2000001, 0x1234);
+        assert_eq!(gba.peek_range(0x2000000, 4), [0, 0x34, 0x12, 0]);
+        assert_eq!(gba.peek_half(0x2000001), 0x1234);
+        assert_eq!(gba.dump_region("EWRAM").unwrap()[1..3], [0x34, 0x12]);
+        assert!(gba.dump_region("rom").is_err());
+    }
+
+    #[test]
+    fn loads_save_states() {
+        let source = "MOV r1, #0x2000000\nADD r0, r0, #1\nSTR r0, [r1]\nB 0x8000004";
+        let mut gba = boot(source, false);
+        assert!(gba.run_frame());
+        let state = gba.save_state();
+        let snapshot = |gba: &GbaCore| {
+            (
+                gba.pc(),
+                gba.reg(0),
+                gba.peek_word(0x2000000),
+                gba.cpu.memory_cycles(),
+                gba.bus.ppu.in_vblank(),
+            )
+        };
+        let saved = snapshot(&gba);
+        assert!(gba.run_frame());
+        let later = snapshot(&gba);
+
+        gba.load_state(&state).unwrap();
+        assert_eq!(snapshot(&gba), saved);
+        assert!(gba.run_frame());
+        assert_eq!(snapshot(&gba), later);
+
+        let mut other = boot(source, false);
+        other.load_state(&state).unwrap();
+        assert!(other.run_frame());
+        assert_eq!(snapshot(&other), later);
+        assert_eq!(
+            GbaCore::save_state_thumbnail(&state).unwrap().len(),
+            120 * 80 * 3
+        );
+    }
+
+    #[test]
+    fn rewinds_to_earlier_frames() {
+        let source = "MOV r1, #0x2000000\nADD r0, r0, #1\nSTR r0, [r1]\nB 0x8000004";
+        let mut gba = boot(source, false);
+        assert_eq!(gba.rewind(1), Err("rewind isn't on".to_string()));
+        gba.start_rewind(1, 3);
+        assert_eq!(gba.rewind(1), Ok(0));
+        let frame = |gba: &GbaCore| (gba.pc(), gba.reg(0), gba.peek_word(0x2000000), gba.screen());
+        let mut frames = vec![];
+        for _ in 0..5 {
+            assert!(gba.run_frame());
+            frames.push(frame(&gba));
+        }
+
+        assert_eq!(gba.rewind(1), Ok(1));
+        assert_eq!(frame(&gba), frames[3]);
+        // Only the last three frames were kept
+        assert_eq!(gba.rewind(100), Ok(1));
+        assert_eq!(frame(&gba), frames[2]);
+        assert!(gba.run_frame());
+        assert_eq!(frame(&gba), frames[3]);
+        assert!(gba.rewind_size() > 0);
+    }
+
+    #[test]
+    fn loads_elfs_with_symbols_and_lines() {
+        use crate::debug_info::test_data::{build_elf, test_line_table, TestSection};
+
+        let mut text =
+            assembler::assemble("ADD r0, pc, #0xf9\nBX r0", 0x8000000, State::ARM).unwrap();
+        text.resize(0x100, 0);
+        let thumb = "MOVS r1, #1\nMOVS r2, #2\nADDS r0, r1, r2\nB 0x8000106";
+        text.extend(assembler::assemble(thumb, 0x8000100, State::Thumb).unwrap());
+        let (debug_line, line_str) = test_line_table();
+        let sections = [
+            TestSection {
+                name: ".text",
+                address: 0x8000000,
+                data: text,
+            },
+            TestSection {
+                name: ".iwram",
+                address: 0x3000000,
+                data: vec![1, 2, 3, 4],
+            },
+            TestSection {
+                name: ".debug_line",
+                address: 0,
+                data: debug_line,
+            },
+            TestSection {
+                name: ".debug_line_str",
+                address: 0,
+                data: line_str,
+            },
+        ];
+        let functions = [("_start", 0x8000000, 8), ("add", 0x8000101, 8)];
+        let mut gba = GbaCore::default();
+        gba.load_elf(&build_elf(0x8000000, &sections, &functions))
+            .unwrap();
+        gba.skip_bios();
+
+        assert_eq!(gba.read_address(0x3000000), 0x04030201);
+        assert_eq!(gba.resolve_location("add"), Ok(0x8000100));
+        assert_eq!(gba.symbol_at(0x8000102).as_deref(), Some("add+0x2"));
+        assert_eq!(gba.state_at(0x8000100), State::Thumb);
+        assert_eq!(gba.state_at(0x8000004), State::ARM);
+        assert_eq!(gba.source_line(0x8000104).as_deref(), Some("/src/main.c:4"));
+
+        gba.start_coverage(false);
+        assert_eq!(gba.add_breakpoint_at("main.c:4"), Ok(0x8000104));
+        assert!(!gba.run_frame());
+        assert_eq!(gba.pc(), 0x8000104);
+        assert!(gba.thumb_state());
+        assert_eq!(gba.reg(2), 2);
+
+        gba.remove_breakpoint(0x8000104);
+        assert!(gba.run_frame());
+        assert_eq!(
+            gba.coverage().unwrap().ranges(),
+            [
+                CoveredRange {
+                    start: 0x8000000,
+                    end: 0x8000008,
+                    thumb: false
+                },
+                CoveredRange {
+                    start: 0x8000100,
+                    end: 0x8000108,
+                    thumb: true
+                }
+            ]
+        );
+        let lcov = gba.coverage_lcov().unwrap();
+        assert!(lcov.contains("DA:3,1\nDA:4,1\nDA:7,0\n"));
+
+        let sections = [TestSection {
+            name: ".text",
+            address: 0x6000000,
+            data: vec![0; 4],
+        }];
+        let mut gba = GbaCore::default();
+        assert!(gba.load_elf(&build_elf(0x6000000, &sections, &[])).is_err());
+        assert!(gba.load_elf(b"not an ELF").is_err());
+    }
+
+    #[test]
+    fn names_disassembly_from_symbol_maps() {
+        let mut gba = boot("ADD r0, pc, #1\nBX r0", false);
+        gba.patch(0x8000008, "BL 0x800000e\nB 0x800000c\nBX lr", true)
+            .unwrap();
+        let sym = "
+            08000000 .arm
+            08000000 _start
+            08000008 .thumb
+            08000008 main
+            0800000E done
+        ";
+        assert_eq!(gba.load_symbol_map(sym), Ok(3));
+        assert_eq!(gba.state_at(0x8000004), State::ARM);
+        assert_eq!(gba.state_at(0x8000008), State::Thumb);
+
+        let address = gba.resolve_location("main").unwrap();
+        let instrs = gba.disassemble(address, 3, gba.state_at(address));
+        assert_eq!(instrs[0].label.as_deref(), Some("main"));
+        assert_eq!(instrs[0].target_symbol.as_deref(), Some("done"));
+        assert_eq!(instrs[1].label.as_deref(), Some("loc_0800000c"));
+        assert_eq!(instrs[1].target_symbol.as_deref(), Some("main+0x4"));
+        assert_eq!(instrs[2].label.as_deref(), Some("done"));
+    }
+
+    #[test]
+    fn boots_multiboot_images_from_ewram() {
+        let mut image = vec![0; 0xf8];
+        let mut set = |offset: usize, word: u32| {
+            image[offset..offset + 4].copy_from_slice(&word.to_le_bytes());
+        };
+        // B 0xc0, B 0xe0, LDR r0, [pc], B 0xec, the address that loads, MOV r1, #5,
+        // STR r1, [r0] and B 0xf4
+        set(0, 0xea00002e);
+        set(0xc0, 0xea000006);
+        set(0xe0, 0xe59f0000);
+        set(0xe4, 0xea000000);
+        set(0xe8, 0x2000100);
+        set(0xec, 0xe3a01005);
+        set(0xf0, 0xe5801000);
+        set(0xf4, 0xeafffffe);
+        image[0xb2] = 0x96;
+
+        let mut gba = GbaCore::default();
+        gba.load_rom(&image);
+        gba.skip_bios();
+        assert!(gba.run_to(0x20000f4));
+        assert_eq!(gba.read_address(0x2000100), 5);
+
+        // The BIOS boots it from a stand-in cartridge
+        let mut gba = GbaCore::default();
+        gba.load_rom(&image);
+        for _ in 0..10 {
+            if gba.run_to(0x20000f4) {
+                break;
+            }
+        }
+        assert_eq!(gba.pc(), 0x20000f4);
+        assert_eq!(gba.read_address(0x2000100), 5);
+
+        let mut gba = GbaCore::default();
+        assert!(gba.load_multiboot(&[0; MAX_MULTIBOOT_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn steps_over_and_out_of_calls() {
+        let source = "
+            MOV r0, #1
+            BL 0x8000010
+            MOV r0, #3
+            B 0x800000c
+            MOV r1, #2
+            MOV pc, lr
+        ";
+        let mut gba = boot(source, false);
+        gba.step_instruction();
+        assert_eq!(gba.pc(), 0x8000004);
+        assert!(gba.step_over());
+        assert_eq!(gba.pc(), 0x8000008);
+        assert_eq!(gba.reg(1), 2);
+
+        let mut gba = boot(source, false);
+        gba.step_instruction();
+        gba.step_instruction();
+        assert_eq!(gba.pc(), 0x8000010);
+        assert!(gba.step_out());
+        assert_eq!(gba.pc(), 0x8000008);
+
+        assert!(gba.run_to(0x800000c));
+        assert_eq!(gba.reg(0), 3);
+    }
+
+    #[test]
+    fn steps_out_of_functions_that_made_calls() {
+        let source = "
+            MOV r0, #1
+            BL 0x8000010
+            MOV r0, #3
+            B 0x800000c
+            STMDB sp!, {lr}
+            BL 0x8000020
+            MOV r1, #2
+            LDMIA sp!, {pc}
+            MOV r2, #4
+            MOV pc, lr
+        ";
+        let mut gba = boot(source, false);
+        // Back in the outer function, where LR still points here after the inner call
+        assert!(gba.run_to(0x8000018));
+        assert_eq!(gba.reg(14), 0x8000018);
+        assert!(gba.step_out());
+        assert_eq!(gba.pc(), 0x8000008);
+        assert_eq!((gba.reg(1), gba.reg(2)), (2, 4));
+        assert!(gba.cpu.call_stack().is_empty());
+
+        // Without a call, there's only LR to go by
+        gba.set_reg(14, 0x8000008).unwrap();
+        gba.set_pc(0x8000020);
+        assert!(gba.step_out());
+        assert_eq!(gba.pc(), 0x8000008);
+    }
+
+    #[test]
+    fn steps_over_thumb_calls() {
+        let mut gba = boot("ADD r0, pc, #1\nBX r0", false);
+        let source = "
+            MOVS r0, #1
+            BL 0x8000012
+            MOVS r0, #3
+            B 0x8000010
+            MOVS r1, #2
+            BX lr
+        ";
+        gba.patch(0x8000008, source, true).unwrap();
+        assert!(gba.run_to(0x800000a));
+        assert!(gba.thumb_state());
+        assert!(gba.step_over());
+        assert_eq!(gba.pc(), 0x800000e);
+        assert_eq!(gba.reg(1), 2);
+    }
+
+    #[test]
+    fn backtraces_through_calls_and_exceptions() {
+        let source = "
+            BL 0x8000010
+            B 0x8000004
+            MOV r0, #0
+            MOV r0, #0
+            MOV r4, lr
+            ADD r1, pc, #8
+            MOV lr, pc
+            BX r1
+            BX r4
+            SWI #0x60000
+            MOV pc, lr
+        ";
+        let mut gba = boot(source, false);
+        let symbols = "08000000 main\n08000010 f\n08000024 g";
+        gba.load_symbol_map(symbols).unwrap();
+        let backtrace = |gba: &GbaCore| -> Vec<_> {
+            gba.backtrace()
+                .into_iter()
+                .map(|frame| (frame.address, frame.function, frame.symbol))
+                .collect()
+        };
+        let name = |name: &str| Some(name.to_string());
+
+        assert!(gba.run_to(0x8));
+        assert_eq!(
+            backtrace(&gba),
+            [
+                (0x8, Some(0x8), None),
+                (0x8000024, Some(0x8000024), name("g")),
+                (0x800001c, Some(0x8000010), name("f+0xc")),
+                (0x8000000, None, name("main")),
+            ]
+        );
+        assert_eq!(gba.backtrace()[1].exception, Some(Exception::Swi(6)));
+
+        assert!(gba.run_to(0x8000020));
+        assert_eq!(
+            backtrace(&gba),
+            [
+                (0x8000020, Some(0x8000010), name("f+0x10")),
+                (0x8000000, None, name("main")),
+            ]
+        );
+        assert!(gba.run_to(0x8000004));
+        assert_eq!(backtrace(&gba), [(0x8000004, None, name("main+0x4"))]);
+    }
+
+    #[test]
+    fn profiles_functions() {
+        let source = "
+            BL 0x8000010
+            B 0x8000004
+            MOV r0, #0
+            MOV r0, #0
+            MOV r4, lr
+            BL 0x8000020
+            BX r4
+            MOV r0, #0
+            MOV r1, #1
+            MOV pc, lr
+        ";
+        let mut gba = boot(source, false);
+        gba.load_symbol_map("08000010 f\n08000020 g").unwrap();
+        assert!(gba.profile().is_none());
+        gba.start_profiling(None);
+        assert!(gba.run_to(0x8000004));
+
+        let profile = gba.profile().unwrap();
+        assert_eq!(profile.total.instructions, 6);
+        assert!(profile.total.waitstates > 0);
+        let names: Vec<_> = profile
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect();
+        assert_eq!(names, ["[entry]", "f", "g"]);
+        let g = &profile.functions[2];
+        assert_eq!(g.instructions, 2);
+        assert_eq!(g.inclusive, g.exclusive);
+        assert_eq!(g.inclusive.total(), g.inclusive.rom);
+        let stacks: Vec<_> = gba
+            .collapsed_stacks()
+            .unwrap()
+            .lines()
+            .map(|line| line.split(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(stacks, ["[entry]", "[entry];f", "[entry];f;g"]);
+
+        gba.stop_profiling();
+        assert!(gba.collapsed_stacks().is_none());
+    }
+
+    #[test]
+    fn records_coverage_per_frame() {
+        let mut gba = boot("MOV r0, #1\nB 0x8000004", false);
+        assert!(gba.coverage_lcov().is_none());
+        gba.start_coverage(true);
+        assert!(gba.coverage().is_none());
+        let ranges = |gba: &GbaCore| -> Vec<_> {
+            let ranges = gba.coverage().unwrap().ranges();
+            ranges
+                .iter()
+                .map(|range| (range.start, range.end))
+                .collect()
+        };
+        assert!(gba.run_frame());
+        assert_eq!(ranges(&gba), [(0x8000000, 0x8000008)]);
+        assert!(gba.run_frame());
+        assert_eq!(ranges(&gba), [(0x8000004, 0x8000008)]);
+
+        gba.stop_coverage();
+        assert!(gba.coverage().is_none());
+    }
+
+    #[test]
+    fn stops_at_breakpoints_while_running() {
+        let mut gba = boot("MOV r0, #1\nBL 0x800000c\nB 0x8000008\nMOV pc, lr", false);
+        gba.add_breakpoint(0x800000c);
+        gba.step_instruction();
+        assert!(!gba.step_over());
+        assert_eq!(gba.pc(), 0x800000c);
+        assert!(gba.stopped);
+
+        // The breakpoint doesn't stop the instruction it's on from being run again
+        assert!(gba.run_frame());
+        assert!(gba.bus.ppu.in_vblank());
+        assert_eq!(gba.pc(), 0x8000008);
+    }
+
+    #[test]
+    fn stops_after_instructions_that_hit_watchpoints() {
+        let source = "
+            MOV r0, #0x3000000
+            ADD r0, r0, #0x100
+            MOV r1, #5
+            STR r1, [r0]
+            STRH r1, [r0, #2]
+            LDR r2, [r0]
+            B 0x8000018
+        ";
+        let mut gba = boot(source, false);
+        gba.add_watchpoint(0x3000100, 0x3000103, "change", 0)
+            .unwrap();
+        gba.tick_multiple(100);
+        assert!(gba.stopped);
+        assert_eq!(gba.pc(), 0x8000010);
+
+        let hits = gba.take_watch_hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            (hits[0].pc, hits[0].instruction.as_str()),
+            (0x800000c, "STR r1, [r0]")
+        );
+        assert_eq!((hits[0].value, hits[0].old_value), (5, Some(0)));
+
+        // The halfword write leaves the value as it was
+        gba.remove_watchpoint(0);
+        gba.add_watchpoint(0x3000100, 0x3000100, "read", 4).unwrap();
+        gba.set_stopped(false);
+        gba.tick_multiple(100);
+        assert_eq!(gba.pc(), 0x8000018);
+        let hits = gba.take_watch_hits();
+        assert_eq!((hits[0].pc, hits[0].value), (0x8000014, 0x50005));
+        assert!(gba
+            .add_watchpoint(0x3000100, 0x3000100, "execute", 0)
+            .is_err());
+    }
+
+    #[test]
+    fn keeps_watchpoints_across_resets() {
+        let source = "
+            MOV r0, #0x3000000
+            MOV r1, #5
+            STR r1, [r0]
+            B 0x800000c
+        ";
+        let mut gba = boot(source, false);
+        gba.add_watchpoint(0x3000000, 0x3000003, "write", 0)
+            .unwrap();
+
+        let mut gba = gba.reset();
+        gba.skip_bios();
+        gba.patch(0x8000000, source, false).unwrap();
+        gba.tick_multiple(100);
+        assert!(gba.stopped);
+        let hits = gba.take_watch_hits();
+        assert_eq!((hits.len(), hits[0].pc), (1, 0x8000008));
+    }
+
+    #[test]
+    fn stops_on_register_writes_and_swis() {
+        let source = "
+            MOV r0, #0x4000000
+            MOV r1, #0x80
+            STRH r1, [r0, #4]
+            STRH r1, [r0]
+            SWI #0x60000
+            SWI #0x50000
+        ";
+        let mut gba = boot(source, false);
+        gba.break_on_io_write("DISPCNT").unwrap();
+        gba.break_on_swi(Some(5));
+        gba.tick_multiple(100);
+        assert!(gba.stopped);
+        assert_eq!(
+            gba.stop_reason(),
+            Some(&StopReason::IoWrite {
+                id: 0,
+                address: 0x4000000
+            })
+        );
+        assert_eq!(gba.pc(), 0x8000010);
+
+        // The BIOS returns from Div, which isn't the SWI being watched for
+        gba.set_stopped(false);
+        assert_eq!(gba.stop_reason(), None);
+        gba.tick_multiple(1000);
+        assert_eq!(
+            gba.stop_reason(),
+            Some(&StopReason::Swi { id: 1, number: 5 })
+        );
+        assert_eq!(gba.pc(), 0x8);
+        assert_eq!(gba.cpu.get_mode(), Mode::Supervisor);
+        assert!(gba.break_on_io_write("0x3000000").is_err());
+        assert!(gba.break_on_io_write("DISPCOUNT").is_err());
+    }
+
+    #[test]
+    fn takes_undefined_instructions() {
+        let mut gba = boot("MOV r0, #1", false);
+        gba.bus.poke(0x8000004, &0xe7f000f0u32.to_le_bytes());
+        gba.break_on_undefined();
+        gba.break_on_mode_change();
+        assert!(!gba.run_to(0x8000010));
+        assert_eq!(
+            gba.stop_reason(),
+            Some(&StopReason::Undefined {
+                id: 0,
+                address: 0x8000004
+            })
+        );
+        assert_eq!(gba.pc(), 0x4);
+        assert_eq!(gba.cpu.get_mode(), Mode::Undefined);
+        assert_eq!(gba.reg(14), 0x8000008);
+    }
+
+    #[test]
+    fn stops_on_conditions_and_logs() {
+        let mut gba = boot("MOV r0, #0\nADD r0, r0, #1\nB 0x8000004", false);
+        gba.set_breakpoint_condition(0x8000008, "r0 >= 3").unwrap();
+        gba.set_logpoint(0x8000004, "r0 = {r0}").unwrap();
+        gba.set_breakpoint_ignore_count(0x8000004, 1);
+        gba.tick_multiple(1000);
+        assert!(gba.stopped);
+        assert_eq!((gba.pc(), gba.reg(0)), (0x8000008, 3));
+        assert_eq!(gba.take_log_messages(), ["r0 = 0x1", "r0 = 0x2"]);
+
+        // Resuming runs the instruction it stopped on before checking breakpoints again
+        gba.set_stopped(false);
+        gba.tick_multiple(1000);
+        assert_eq!((gba.pc(), gba.reg(0)), (0x8000008, 4));
+        assert_eq!(gba.breakpoint_hits(0x8000008), 2);
+        assert_eq!(gba.take_log_messages(), ["r0 = 0x3"]);
+
+        assert!(gba.set_breakpoint_condition(0x8000008, "r0 >=").is_err());
+    }
 }
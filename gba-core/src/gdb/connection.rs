@@ -0,0 +1,191 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+/// What GDB sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Incoming {
+    Packet(Vec<u8>),
+    /// A Ctrl-C, which GDB sends on its own rather than in a packet.
+    Interrupt,
+}
+
+/// Reads and writes `$packet#checksum` frames over a connection to GDB, acknowledging them until
+/// GDB asks not to.
+pub struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    pub no_ack: bool,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Frames `data` as a packet, escaping the bytes that would end it early.
+pub fn frame(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend([b'}', byte ^ 0x20]);
+        } else {
+            escaped.push(byte);
+        }
+    }
+    let mut packet = vec![b'$'];
+    packet.extend(&escaped);
+    packet.extend(format!("#{:02x}", checksum(&escaped)).bytes());
+    packet
+}
+
+/// Takes the first packet or interrupt out of `buffer`, along with whether its checksum was
+/// right. Anything before it, such as acknowledgements, is dropped.
+pub fn parse(buffer: &mut Vec<u8>) -> Option<(Incoming, bool)> {
+    let start = buffer
+        .iter()
+        .position(|&byte| byte == b'$' || byte == 0x03)?;
+    if buffer[start] == 0x03 {
+        buffer.drain(..=start);
+        return Some((Incoming::Interrupt, true));
+    }
+    let end = start + buffer[start..].iter().position(|&byte| byte == b'#')?;
+    if buffer.len() < end + 3 {
+        return None;
+    }
+    let escaped = &buffer[start + 1..end];
+    let valid = std::str::from_utf8(&buffer[end + 1..end + 3])
+        .ok()
+        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+        == Some(checksum(escaped));
+
+    let mut data = Vec::with_capacity(escaped.len());
+    let mut bytes = escaped.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => data.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            _ => data.push(byte),
+        }
+    }
+    buffer.drain(..end + 3);
+    Some((Incoming::Packet(data), valid))
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buffer: vec![],
+            no_ack: false,
+        }
+    }
+
+    /// Reads more of the stream into the buffer. Returns false if GDB disconnected.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        let read = self.stream.read(&mut chunk)?;
+        self.buffer.extend(&chunk[..read]);
+        Ok(read > 0)
+    }
+
+    /// Waits for the next packet or interrupt, or returns `None` if GDB disconnected.
+    pub fn read(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            match parse(&mut self.buffer) {
+                Some((incoming, valid)) => {
+                    if !self.no_ack {
+                        self.stream.write_all(if valid { b"+" } else { b"-" })?;
+                    }
+                    if valid {
+                        return Ok(Some(incoming));
+                    }
+                }
+                None => {
+                    if !self.fill()? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a packet, and waits for GDB to acknowledge it unless acknowledgements are off.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let packet = frame(data);
+        loop {
+            self.stream.write_all(&packet)?;
+            if self.no_ack {
+                return Ok(());
+            }
+            loop {
+                match self
+                    .buffer
+                    .iter()
+                    .position(|&byte| byte == b'+' || byte == b'-')
+                {
+                    Some(ack) => {
+                        let resend = self.buffer[ack] == b'-';
+                        self.buffer.remove(ack);
+                        if !resend {
+                            return Ok(());
+                        }
+                        break;
+                    }
+                    None => {
+                        if !self.fill()? {
+                            return Err(ErrorKind::UnexpectedEof.into());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks for an interrupt without waiting, for while the core is running. Returns `None` if
+    /// GDB disconnected.
+    pub fn poll_interrupt(&mut self) -> io::Result<Option<bool>> {
+        self.stream.set_nonblocking(true)?;
+        let filled = self.fill();
+        self.stream.set_nonblocking(false)?;
+        match filled {
+            Ok(false) => return Ok(None),
+            Err(err) if err.kind() != ErrorKind::WouldBlock => return Err(err),
+            _ => {}
+        }
+        match self.buffer.iter().position(|&byte| byte == 0x03) {
+            Some(interrupt) => {
+                self.buffer.drain(..=interrupt);
+                Ok(Some(true))
+            }
+            None => Ok(Some(false)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_and_parses_packets() {
+        assert_eq!(frame(b"OK"), b"$OK#9a");
+        assert_eq!(frame(b"a#b"), b"$a}\x03b#43");
+
+        let mut buffer = b"+$m8000000,4#26\x03$a}\x03b#43$g#6".to_vec();
+        assert_eq!(
+            parse(&mut buffer),
+            Some((Incoming::Packet(b"m8000000,4".to_vec()), false))
+        );
+        assert_eq!(parse(&mut buffer), Some((Incoming::Interrupt, true)));
+        assert_eq!(
+            parse(&mut buffer),
+            Some((Incoming::Packet(b"a#b".to_vec()), true))
+        );
+        // The checksum hasn't all arrived yet
+        assert_eq!(parse(&mut buffer), None);
+        buffer.push(b'7');
+        assert_eq!(
+            parse(&mut buffer),
+            Some((Incoming::Packet(b"g".to_vec()), true))
+        );
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,456 @@
+//! A GDB remote serial protocol server for native builds, so that homebrew can be debugged with
+//! `arm-none-eabi-gdb` by running `target remote localhost:<port>`.
+//!
+//! GDB's breakpoints and watchpoints are the core's own, and it sees the registers of the current
+//! mode. Only one GDB is served at a time.
+
+mod connection;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+
+use tracing::{debug, info};
+
+use crate::debugger::{StopReason, WatchKind};
+use crate::GbaCore;
+
+use self::connection::{Connection, Incoming};
+
+const TARGET_XML: &str = include_str!("target.xml");
+/// GDB's number for the CPSR in the target description, which is where the old FPA registers
+/// left it.
+const CPSR_REGNUM: u32 = 25;
+/// The most memory read in one packet, which also bounds the packet size.
+const MAX_READ: usize = 0x800;
+/// How long the core runs between checks for an interrupt from GDB, which is a scanline.
+const TICKS_PER_POLL: u32 = 4 * 308;
+
+/// Listens for GDB on a localhost port.
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+/// What to do after replying to a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Reply,
+    Continue,
+    Step,
+    /// GDB detached or killed the session.
+    End,
+}
+
+/// The state of a connection to GDB.
+struct Session<'a> {
+    gba: &'a mut GbaCore,
+    /// The core's IDs for GDB's watchpoints, by their type, address and length.
+    watchpoints: BTreeMap<(u8, u32, u32), u32>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// Registers are sent in the target's byte order.
+fn parse_reg(text: &str) -> Option<u32> {
+    Some(u32::from_le_bytes(unhex(text)?.try_into().ok()?))
+}
+
+impl GdbServer {
+    /// Listens on `port` on localhost only, as the protocol has no authentication. Port 0 picks a
+    /// free port, which `local_addr` returns.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        Ok(Self { listener })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for GDB to connect, stopping `gba` when it does, and serves it until it detaches or
+    /// disconnects. The core is left running after a detach.
+    pub fn serve(&self, gba: &mut GbaCore) -> io::Result<()> {
+        let (stream, address) = self.listener.accept()?;
+        info!("GDB connected from {}", address);
+        stream.set_nodelay(true)?;
+        let mut connection = Connection::new(stream);
+        let mut session = Session {
+            gba,
+            watchpoints: BTreeMap::new(),
+        };
+        session.gba.set_stopped(true);
+
+        while let Some(incoming) = connection.read()? {
+            let Incoming::Packet(packet) = incoming else {
+                // Already stopped, but GDB still wants to hear that it is
+                connection.write(b"S02")?;
+                continue;
+            };
+            let packet = String::from_utf8_lossy(&packet);
+            debug!("GDB sent {}", packet);
+            let (action, reply) = session.handle(&packet);
+            match action {
+                Action::Reply => {
+                    connection.write(reply.as_bytes())?;
+                    if packet == "QStartNoAckMode" {
+                        connection.no_ack = true;
+                    }
+                }
+                Action::Step => {
+                    session.gba.step_instruction();
+                    connection.write(b"S05")?;
+                }
+                Action::Continue => {
+                    let Some(reply) = session.run(&mut connection)? else {
+                        break;
+                    };
+                    connection.write(reply.as_bytes())?;
+                }
+                Action::End => {
+                    // Nothing is expected after a kill
+                    if !reply.is_empty() {
+                        connection.write(reply.as_bytes())?;
+                    }
+                    break;
+                }
+            }
+        }
+        info!("GDB disconnected");
+        Ok(())
+    }
+}
+
+impl Session<'_> {
+    /// Runs the core until it stops, or GDB interrupts it. Returns the stop reply, or `None` if
+    /// GDB disconnected.
+    fn run(&mut self, connection: &mut Connection) -> io::Result<Option<String>> {
+        self.gba.set_stopped(false);
+        loop {
+            self.gba.tick_multiple(TICKS_PER_POLL);
+            if self.gba.stopped {
+                return Ok(Some(self.stop_reply()));
+            }
+            match connection.poll_interrupt()? {
+                Some(false) => {}
+                Some(true) => {
+                    self.gba.set_stopped(true);
+                    return Ok(Some("S02".to_string()));
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Describes why the core stopped. Everything is a SIGTRAP, with the watchpoint or breakpoint
+    /// that caused it if there was one.
+    fn stop_reply(&mut self) -> String {
+        match self.gba.stop_reason() {
+            Some(StopReason::Breakpoint { .. }) => "T05swbreak:;".to_string(),
+            Some(&StopReason::Watchpoint { id }) => {
+                let kind = self
+                    .gba
+                    .bus
+                    .watchpoints
+                    .iter()
+                    .find(|(watchpoint, _)| *watchpoint == id)
+                    .map(|(_, watchpoint)| watchpoint.kind);
+                let hit = self
+                    .gba
+                    .take_watch_hits()
+                    .into_iter()
+                    .rfind(|hit| hit.watchpoint == id);
+                match (kind, hit) {
+                    (Some(kind), Some(hit)) => {
+                        let name = match kind {
+                            WatchKind::Read => "rwatch",
+                            WatchKind::Access => "awatch",
+                            WatchKind::Write | WatchKind::Change => "watch",
+                        };
+                        format!("T05{}:{:x};", name, hit.address)
+                    }
+                    _ => "S05".to_string(),
+                }
+            }
+            _ => "S05".to_string(),
+        }
+    }
+
+    fn read_reg(&self, index: u32) -> Option<u32> {
+        match index {
+            0..=14 => Some(self.gba.reg(index)),
+            15 => Some(self.gba.pc()),
+            CPSR_REGNUM => Some(self.gba.cpu.cpsr()),
+            _ => None,
+        }
+    }
+
+    fn write_reg(&mut self, index: u32, value: u32) -> Result<(), String> {
+        match index {
+            0..=15 => self.gba.set_reg(index, value)?,
+            CPSR_REGNUM => self.gba.set_cpsr(value)?,
+            _ => return Err(format!("no register {}", index)),
+        }
+        Ok(())
+    }
+
+    /// The registers in the order of the `g` packet, which is by GDB's number.
+    fn reg_numbers() -> impl Iterator<Item = u32> {
+        (0..16).chain([CPSR_REGNUM])
+    }
+
+    /// Handles a packet that doesn't resume the core, returning what to do next and the reply.
+    /// Unsupported packets get an empty reply, as the protocol asks.
+    fn handle(&mut self, packet: &str) -> (Action, String) {
+        let reply = |reply: &str| (Action::Reply, reply.to_string());
+        let error = || reply("E01");
+        let command = packet.get(..1).unwrap_or("");
+        let args = packet.get(1..).unwrap_or("");
+        match command {
+            "?" => (Action::Reply, self.stop_reply()),
+            "g" => {
+                let regs = Self::reg_numbers()
+                    .filter_map(|index| self.read_reg(index))
+                    .flat_map(u32::to_le_bytes)
+                    .collect::<Vec<_>>();
+                (Action::Reply, hex(&regs))
+            }
+            "G" => {
+                let values = (0..args.len() / 8).map(|i| parse_reg(&args[i * 8..i * 8 + 8]));
+                for (index, value) in Self::reg_numbers().zip(values) {
+                    let Some(value) = value else {
+                        return error();
+                    };
+                    if self.write_reg(index, value).is_err() {
+                        return error();
+                    }
+                }
+                reply("OK")
+            }
+            "p" => match parse_number(args).and_then(|index| self.read_reg(index)) {
+                Some(value) => (Action::Reply, hex(&value.to_le_bytes())),
+                None => error(),
+            },
+            "P" => {
+                let Some((index, value)) = args.split_once('=') else {
+                    return error();
+                };
+                match (parse_number(index), parse_reg(value)) {
+                    (Some(index), Some(value)) if self.write_reg(index, value).is_ok() => {
+                        reply("OK")
+                    }
+                    _ => error(),
+                }
+            }
+            "m" => {
+                let Some((address, length)) = args.split_once(',') else {
+                    return error();
+                };
+                let (Some(address), Some(length)) = (parse_number(address), parse_number(length))
+                else {
+                    return error();
+                };
+                let bytes = self.gba.peek_range(address, length.min(MAX_READ as u32));
+                (Action::Reply, hex(&bytes))
+            }
+            "M" => {
+                let Some((address, data)) = args.split_once(':') else {
+                    return error();
+                };
+                let address = address.split(',').next().and_then(parse_number);
+                match (address, unhex(data)) {
+                    (Some(address), Some(bytes)) => {
+                        self.gba.poke_range(address, &bytes);
+                        reply("OK")
+                    }
+                    _ => error(),
+                }
+            }
+            "Z" | "z" => self.set_breakpoint(command == "Z", args),
+            "c" | "C" | "s" | "S" => {
+                // `c addr` resumes from `addr`, but `C sig` only passes on a signal
+                if command == "c" || command == "s" {
+                    if let Some(address) = parse_number(args) {
+                        self.gba.set_pc(address);
+                    }
+                }
+                let action = if command.eq_ignore_ascii_case("c") {
+                    Action::Continue
+                } else {
+                    Action::Step
+                };
+                (action, String::new())
+            }
+            "D" => {
+                self.gba.set_stopped(false);
+                (Action::End, "OK".to_string())
+            }
+            "k" => (Action::End, String::new()),
+            "H" | "T" => reply("OK"),
+            _ => self.query(packet),
+        }
+    }
+
+    /// Handles the packets that are named rather than a letter.
+    fn query(&mut self, packet: &str) -> (Action, String) {
+        let reply = |reply: &str| (Action::Reply, reply.to_string());
+        if packet.starts_with("qSupported") {
+            return (
+                Action::Reply,
+                format!(
+                    "PacketSize={:x};qXfer:features:read+;swbreak+;QStartNoAckMode+",
+                    MAX_READ * 2 + 16
+                ),
+            );
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',') else {
+                return reply("E01");
+            };
+            let (Some(offset), Some(length)) = (parse_number(offset), parse_number(length)) else {
+                return reply("E01");
+            };
+            let start = (offset as usize).min(TARGET_XML.len());
+            let end = start.saturating_add(length as usize).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { "m" } else { "l" };
+            return (
+                Action::Reply,
+                format!("{}{}", more, &TARGET_XML[start..end]),
+            );
+        }
+        match packet {
+            "QStartNoAckMode" => reply("OK"),
+            "qAttached" => reply("1"),
+            "qC" => reply("QC1"),
+            "qfThreadInfo" => reply("m1"),
+            "qsThreadInfo" => reply("l"),
+            _ => reply(""),
+        }
+    }
+
+    /// Handles `Z` and `z` packets, which insert and remove breakpoints and watchpoints. The
+    /// kind of a breakpoint says whether it's on ARM or THUMB code, which doesn't matter here.
+    fn set_breakpoint(&mut self, insert: bool, args: &str) -> (Action, String) {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(address), Some(length)) = (
+            fields.next().and_then(|kind| kind.parse::<u8>().ok()),
+            fields.next().and_then(parse_number),
+            fields.next().and_then(parse_number),
+        ) else {
+            return (Action::Reply, "E01".to_string());
+        };
+        match (kind, insert) {
+            (0 | 1, true) => self.gba.add_breakpoint(address & !1),
+            (0 | 1, false) => self.gba.remove_breakpoint(address & !1),
+            (2..=4, true) => {
+                let watch_kind = ["write", "read", "access"][kind as usize - 2];
+                let end = address.wrapping_add(length.max(1) - 1);
+                match self.gba.add_watchpoint(address, end, watch_kind, 0) {
+                    Ok(id) => {
+                        self.watchpoints.insert((kind, address, length), id);
+                    }
+                    Err(_) => return (Action::Reply, "E01".to_string()),
+                }
+            }
+            (2..=4, false) => {
+                if let Some(id) = self.watchpoints.remove(&(kind, address, length)) {
+                    self.gba.remove_watchpoint(id);
+                }
+            }
+            _ => return (Action::Reply, String::new()),
+        }
+        (Action::Reply, "OK".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    use super::connection::{frame, parse};
+    use super::*;
+
+    /// Sends a packet as GDB would and returns the reply, acknowledging it.
+    fn request(stream: &mut TcpStream, buffer: &mut Vec<u8>, packet: &str) -> String {
+        stream.write_all(&frame(packet.as_bytes())).unwrap();
+        loop {
+            if let Some((Incoming::Packet(reply), true)) = parse(buffer) {
+                stream.write_all(b"+").unwrap();
+                return String::from_utf8(reply).unwrap();
+            }
+            let mut chunk = [0; 4096];
+            let read = stream.read(&mut chunk).unwrap();
+            assert!(read > 0, "the server hung up");
+            buffer.extend(&chunk[..read]);
+        }
+    }
+
+    #[test]
+    fn debugs_over_tcp() {
+        let source = "
+            MOV r0, #0x3000000
+            MOV r1, #7
+            STR r1, [r0]
+            B 0x800000c
+        ";
+        let mut gba = GbaCore::default();
+        gba.skip_bios();
+        gba.patch(0x8000000, source, false).unwrap();
+
+        let server = GdbServer::bind(0).unwrap();
+        let address = server.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let mut buffer = vec![];
+            let mut request = |packet: &str| request(&mut stream, &mut buffer, packet);
+
+            assert!(request("qSupported:swbreak+").contains("qXfer:features:read+"));
+            let xml = request("qXfer:features:read:target.xml:0,fff");
+            assert!(xml.starts_with('l') && xml.contains("cpsr"));
+            assert_eq!(request("?"), "S05");
+            assert_eq!(request("pf"), "00000008");
+            assert_eq!(request("p19"), "df000000");
+            assert_eq!(request("m8000004,4"), "0710a0e3");
+
+            assert_eq!(request("s"), "S05");
+            assert_eq!(request("p0"), "00000003");
+            assert_eq!(request("Z2,3000000,4"), "OK");
+            assert_eq!(request("c"), "T05watch:3000000;");
+            assert_eq!(request("z2,3000000,4"), "OK");
+            assert_eq!(request("Z0,800000c,4"), "OK");
+            assert_eq!(request("c"), "T05swbreak:;");
+            assert_eq!(request("pf"), "0c000008");
+
+            // Switching to THUMB carries on from the same address
+            assert_eq!(request("P19=3f000000"), "OK");
+            assert_eq!(request("p19"), "3f000000");
+            assert_eq!(request("P1=2a000000"), "OK");
+            assert_eq!(request("M3000004,2:3412"), "OK");
+            assert_eq!(request("m3000004,2"), "3412");
+            stream.write_all(&frame(b"k")).unwrap();
+        });
+        server.serve(&mut gba).unwrap();
+        client.join().unwrap();
+
+        assert!(gba.thumb_state());
+        assert_eq!((gba.pc(), gba.reg(1)), (0x800000c, 42));
+        assert_eq!(gba.bus.read_half(0x3000004, &gba.cpu), 0x1234);
+    }
+}
@@ -1,11 +1,28 @@
 mod bus;
 mod cpu;
+mod debug_info;
+mod debugger;
 mod gba;
+#[cfg(not(target_arch = "wasm32"))]
+mod gdb;
 mod ppu;
+mod rewind;
+mod save_state;
 mod utils;
 
 pub use bus::Bus;
 pub use bus::Key;
-pub use cpu::Cpu;
+pub use cpu::{
+    assembler, disassembler, ir, trace, Coverage, CoveredRange, Cpu, Exception, Flag, Frame, Mode,
+    State,
+};
+pub use debug_info::{DebugInfo, LineTable, Symbol, SymbolMap, Symbols};
+pub use debugger::{
+    BacktraceFrame, Breakpoint, Counts, Event, Expr, FunctionProfile, Hit, LogMessage, PcProfile,
+    Profile, RegionCycles, StopReason, WatchHit, WatchKind, Watchpoint,
+};
 pub use gba::GbaCore;
+#[cfg(not(target_arch = "wasm32"))]
+pub use gdb::GdbServer;
 pub use ppu::Ppu;
+pub use save_state::{THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
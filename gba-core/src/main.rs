@@ -1,7 +1,9 @@
-use gba_core::GbaCore;
+use gba_core::{GbaCore, GdbServer};
 use tracing;
 use tracing_subscriber::{self, EnvFilter};
 
+const USAGE: &str = "usage: gba-core [ROM] [--gdb PORT]";
+
 fn main() {
     pretty_env_logger::init();
     let subscriber = tracing_subscriber::fmt()
@@ -10,12 +12,45 @@ fn main() {
         .finish();
     tracing::subscriber::set_global_default(subscriber).unwrap();
 
+    let mut rom = None;
+    let mut gdb_port = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gdb" => {
+                let port = args.next().and_then(|port| port.parse::<u16>().ok());
+                gdb_port = Some(port.unwrap_or_else(|| fail("--gdb needs a port")));
+            }
+            _ if rom.is_none() => rom = Some(arg),
+            _ => fail(&format!("unexpected argument {}", arg)),
+        }
+    }
+
     let mut gba = GbaCore::default();
-    let bytes = include_bytes!("../tests/roms/armwrestler-gba-fixed.gba");
+    let bytes = match rom {
+        Some(path) => {
+            std::fs::read(&path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)))
+        }
+        None => include_bytes!("../tests/roms/armwrestler-gba-fixed.gba").to_vec(),
+    };
 
-    gba.load_rom(bytes);
+    gba.load_rom(&bytes);
     gba.skip_bios();
 
+    if let Some(port) = gdb_port {
+        let server = GdbServer::bind(port)
+            .unwrap_or_else(|err| fail(&format!("can't listen on port {}: {}", port, err)));
+        if let Ok(address) = server.local_addr() {
+            println!("Waiting for GDB on {}", address);
+        }
+        if let Err(err) = server.serve(&mut gba) {
+            fail(&format!("lost the GDB connection: {}", err));
+        }
+        // Nothing can see the core once GDB has gone, so there's no point running it on
+        println!("GDB detached, exiting");
+        return;
+    }
+
     let mut i = 0;
     loop {
         log::debug!("Tick {i}");
@@ -24,3 +59,8 @@ fn main() {
         i += 1;
     }
 }
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(1);
+}
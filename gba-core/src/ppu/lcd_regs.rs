@@ -1,7 +1,10 @@
+use serde::{Deserialize, Serialize};
+
 use crate::utils::AddressableBits;
 
 use super::masked_byte::Masked;
 
+#[derive(Serialize, Deserialize)]
 pub struct LcdRegs {
     placeholder: Reg,
     pub dispcnt: Reg,
@@ -26,7 +29,7 @@ impl Default for LcdRegs {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Reg {
     Simple(u16),
     Masked(Masked<u16>),
@@ -113,13 +116,24 @@ impl LcdRegs {
     pub fn write_byte(&mut self, index: usize, value: u8) {
         if index & 1 == 0 {
             let mem = self.get_halfword_mut(index);
-            mem.write(mem.read().bits(8, 15) | u16::from(value));
+            mem.write(mem.read() & 0xff00 | u16::from(value));
         } else {
             let mem = self.get_halfword_mut(index - 1);
             mem.write(mem.read().bits(0, 7) | (u16::from(value) << 8));
         }
     }
 
+    /// Writes a byte without masking out the read-only bits.
+    pub fn poke_byte(&mut self, index: usize, value: u8) {
+        let shift = 8 * (index & 1);
+        let reg = self.get_halfword_mut(index & !1);
+        let halfword = reg.read() & !(0xff << shift) | u16::from(value) << shift;
+        match reg {
+            Reg::Masked(_) => reg.force_write(halfword),
+            _ => reg.write(halfword),
+        }
+    }
+
     pub fn get_bg_mode(&self) -> u8 {
         u8::try_from(self.dispcnt.read() & 0b111).unwrap().min(5)
     }
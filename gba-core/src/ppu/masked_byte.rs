@@ -1,8 +1,10 @@
 use std::ops::BitAnd;
 
+use serde::{Deserialize, Serialize};
+
 use super::lcd_regs::LcdReg;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Masked<T> {
     value: T,
     mask: T,
@@ -5,6 +5,7 @@ mod utils;
 mod debug;
 
 use num_traits::{FromBytes, ToBytes, Zero};
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
 use js_sys;
 
@@ -24,6 +25,7 @@ const H_BLANK_WIDTH: u16 = 68;
 const V_BLANK_HEIGHT: u16 = 68;
 
 #[cfg_attr(feature="debugger", wasm_bindgen)]
+#[derive(Serialize, Deserialize)]
 pub struct Ppu {
     pub(crate) lcd_regs: LcdRegs,
     bg_obj_palette: Vec<u8>,
@@ -126,6 +128,11 @@ impl Ppu {
         }
     }
 
+    /// Writes a byte of an LCD I/O register for the debugger, read-only bits included.
+    pub fn poke_lcd_io_reg(&mut self, index: usize, value: u8) {
+        self.lcd_regs.poke_byte(index, value);
+    }
+
     fn bg_mode(&self) -> u8 {
         self.lcd_regs.dispcnt.read().bits(0, 2) as u8
     }
@@ -329,6 +336,11 @@ impl Ppu {
     pub fn screen(&self) -> Vec<u8> {
         self.screen.clone()
     }
+
+    /// Whether the PPU has finished drawing the frame and is in the lines below the screen.
+    pub fn in_vblank(&self) -> bool {
+        self.lcd_regs.vcount.read() >= SCREEN_HEIGHT
+    }
 }
 
 impl Ppu {
@@ -404,4 +416,17 @@ mod tests {
         assert_eq!(ppu.vram[3], 1);
         assert_eq!(ppu.read_simple::<u32, 4>(0x6000000), 0x01020304);
     }
+
+    #[test]
+    fn writes_keep_the_other_byte_of_lcd_registers() {
+        let mut ppu = Ppu::default();
+
+        ppu.write_lcd_io_regs::<u16, 2>(0x4000000, 0x0404);
+        ppu.write_lcd_io_regs::<u16, 2>(0x4000000, 0x0403);
+        assert_eq!(ppu.read_lcd_io_regs::<u16, 2>(0x4000000), 0x0403);
+        ppu.write_lcd_io_regs::<u8, 1>(0x4000001, 0x10);
+        assert_eq!(ppu.read_lcd_io_regs::<u16, 2>(0x4000000), 0x1003);
+        ppu.write_lcd_io_regs::<u8, 1>(0x4000000, 0x05);
+        assert_eq!(ppu.read_lcd_io_regs::<u16, 2>(0x4000000), 0x1005);
+    }
 }
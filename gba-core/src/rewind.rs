@@ -0,0 +1,187 @@
+//! Rewind, which keeps a ring of snapshots of the machine taken every few frames.
+//!
+//! Only the newest snapshot is kept whole. Each older one is kept as a delta against the one
+//! taken after it: the two XORed together, which is mostly zeros as little changes in a few
+//! frames, with the runs of zeros left out.
+
+use std::collections::VecDeque;
+
+/// How many zeros in a row end a run of bytes that differ. Shorter runs of zeros are kept in with
+/// the bytes around them, as skipping them would take more bytes than it saves.
+const MIN_ZEROS: usize = 4;
+
+pub struct Rewind {
+    /// How many frames apart the snapshots are.
+    interval: u32,
+    /// How many snapshots are kept, counting the newest.
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    /// The snapshots before the newest, oldest first, each as a delta against the one after it.
+    deltas: VecDeque<Vec<u8>>,
+    /// The frames since the newest snapshot was taken.
+    frames: u32,
+}
+
+impl Rewind {
+    /// Keeps `capacity` snapshots taken `interval` frames apart. Both are at least 1.
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            newest: None,
+            deltas: VecDeque::new(),
+            frames: 0,
+        }
+    }
+
+    /// Counts a frame, returning whether it's time to take a snapshot.
+    pub fn frame(&mut self) -> bool {
+        self.frames += 1;
+        self.newest.is_none() || self.frames >= self.interval
+    }
+
+    /// Adds a snapshot, dropping the oldest if there are too many.
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            self.deltas.push_back(encode(&previous, &snapshot));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(snapshot);
+        self.frames = 0;
+    }
+
+    /// Goes back to the newest snapshot that's at least `frames` frames back, or to the oldest if
+    /// none are, and drops the snapshots after it. Returns the snapshot and how many frames back
+    /// it is, or `None` if there aren't any snapshots yet.
+    pub fn rewind(&mut self, frames: u32) -> Option<(Vec<u8>, u32)> {
+        let mut snapshot = self.newest.take()?;
+        let mut back = self.frames;
+        while back < frames {
+            let Some(delta) = self.deltas.pop_back() else {
+                break;
+            };
+            snapshot = decode(&snapshot, &delta);
+            back += self.interval;
+        }
+        self.newest = Some(snapshot.clone());
+        self.frames = 0;
+        Some((snapshot, back))
+    }
+
+    /// The bytes the snapshots take up.
+    pub fn size(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+/// Encodes `older` against `newer` as its length, then runs of the two XORed together, each as
+/// the number of zeros it starts with, then the number of bytes after them and those bytes. Where
+/// one is longer than the other, the shorter counts as zeros.
+fn encode(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = older
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| byte ^ newer.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut delta = vec![];
+    write_varint(&mut delta, xor.len());
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|&&byte| byte == 0).count();
+        i += zeros;
+        let start = i;
+        while i < xor.len() && !xor[i..].starts_with(&[0; MIN_ZEROS]) {
+            i += 1;
+        }
+        write_varint(&mut delta, zeros);
+        write_varint(&mut delta, i - start);
+        delta.extend_from_slice(&xor[start..i]);
+    }
+    delta
+}
+
+/// Undoes `encode`, getting the older snapshot back from `newer` and the delta.
+fn decode(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut delta = delta;
+    let mut older = newer.to_vec();
+    older.resize(read_varint(&mut delta), 0);
+    let mut i = 0;
+    while !delta.is_empty() {
+        i += read_varint(&mut delta);
+        let length = read_varint(&mut delta);
+        let (bytes, rest) = delta.split_at(length);
+        for (byte, xor) in older[i..i + length].iter_mut().zip(bytes) {
+            *byte ^= xor;
+        }
+        i += length;
+        delta = rest;
+    }
+    older
+}
+
+/// Writes `value` 7 bits at a time, lowest first, with the top bit set on all but the last byte.
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = bytes.split_first() {
+        *bytes = rest;
+        value |= usize::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_deltas_between_snapshots() {
+        let newer: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let mut older = newer.clone();
+        older[5] ^= 1;
+        older[7] ^= 2;
+        older[200] ^= 3;
+        let delta = encode(&older, &newer);
+        // The length, then a run of 5 zeros and 3 bytes, then one of 192 zeros and a byte, then
+        // the zeros up to the end
+        assert_eq!(delta, [172, 2, 5, 3, 1, 0, 2, 192, 1, 1, 3, 99, 0]);
+        assert_eq!(decode(&newer, &delta), older);
+
+        let shorter = &older[..100];
+        assert_eq!(decode(&newer, &encode(shorter, &newer)), shorter);
+        let longer = [older.as_slice(), &[9; 10]].concat();
+        assert_eq!(decode(&newer, &encode(&longer, &newer)), longer);
+    }
+
+    #[test]
+    fn rewinds_through_the_ring() {
+        let mut rewind = Rewind::new(2, 3);
+        assert_eq!(rewind.rewind(1), None);
+        let snapshot = |frame: u8| vec![frame; 64];
+        for frame in 1..=10 {
+            if rewind.frame() {
+                rewind.push(snapshot(frame));
+            }
+        }
+        // Snapshots were taken in frames 1, 3, 5, 7 and 9, and only the last three are kept
+        assert_eq!(rewind.deltas.len(), 2);
+        assert_eq!(rewind.rewind(3), Some((snapshot(7), 3)));
+        assert_eq!(rewind.rewind(0), Some((snapshot(7), 0)));
+        assert_eq!(rewind.rewind(100), Some((snapshot(5), 2)));
+        assert_eq!(rewind.rewind(1), Some((snapshot(5), 0)));
+    }
+}
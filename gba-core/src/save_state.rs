@@ -0,0 +1,273 @@
+//! Save states, which hold everything about the machine apart from the BIOS and the ROM, so that
+//! loading one carries on exactly where it was taken.
+//!
+//! A save state starts with the magic bytes `GBAS` and the version as a little-endian `u32`,
+//! followed by the state as bincode, compressed with DEFLATE. In every version the state starts
+//! with a thumbnail of the screen, so that it can be shown without loading the rest.
+//!
+//! Whenever the machine's state changes shape, like when the timers or DMA start keeping state,
+//! `VERSION` goes up, and `STEPS` gets a step that reads the previous version's layout and
+//! converts it, so that older save states keep loading. The versions so far are:
+//!
+//! 1. The thumbnail, the game it was taken in, the CPU and the bus.
+
+use std::fmt::{self, Display};
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+
+pub const VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"GBAS";
+
+pub const THUMBNAIL_WIDTH: usize = 120;
+pub const THUMBNAIL_HEIGHT: usize = 80;
+
+/// The game a save state was taken in, going by the title and the checksum in the ROM's header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Game {
+    title: String,
+    checksum: u8,
+}
+
+impl Game {
+    /// The game that's loaded. Multiboot images and ELFs are booted from a ROM with their header,
+    /// so this works for them too.
+    fn of(bus: &Bus) -> Self {
+        let title = bus.peek(0x80000a0, 12);
+        Self {
+            title: String::from_utf8_lossy(&title)
+                .trim_end_matches('\0')
+                .to_string(),
+            checksum: bus.peek_byte(0x80000bd),
+        }
+    }
+}
+
+impl Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` (checksum {:#04x})", self.title, self.checksum)
+    }
+}
+
+#[derive(Serialize)]
+struct SavingState<'a> {
+    thumbnail: Vec<u8>,
+    game: Game,
+    cpu: &'a Cpu,
+    bus: &'a Bus,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SaveState {
+    /// Only read by `load_thumbnail`, which stops after it.
+    #[serde(rename = "thumbnail")]
+    _thumbnail: Vec<u8>,
+    pub game: Game,
+    pub cpu: Cpu,
+    pub bus: Bus,
+}
+
+pub(crate) fn save(cpu: &Cpu, bus: &Bus) -> Vec<u8> {
+    let state = SavingState {
+        thumbnail: thumbnail(&bus.ppu.screen()),
+        game: Game::of(bus),
+        cpu,
+        bus,
+    };
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+    let state = bincode::serialize(&state).expect("the machine can always be serialized");
+    let mut encoder = DeflateEncoder::new(bytes, Compression::fast());
+    encoder
+        .write_all(&state)
+        .and_then(|_| encoder.finish())
+        .expect("writing to a Vec can't fail")
+}
+
+/// Reads a save state, unless it was taken in a different game from the one on `bus`.
+pub(crate) fn load(bytes: &[u8], bus: &Bus) -> Result<SaveState, String> {
+    let (version, state) = decompress(bytes)?;
+    let state: SaveState =
+        bincode::deserialize(&migrate(version, state)?).map_err(|err| err.to_string())?;
+    let loaded = Game::of(bus);
+    if state.game != loaded {
+        return Err(format!(
+            "the save state is of {}, but {} is loaded",
+            state.game, loaded
+        ));
+    }
+    Ok(state)
+}
+
+/// The machine as plain bincode, without the header, the thumbnail or compression, for snapshots
+/// that never leave memory, like the ones rewind keeps.
+pub(crate) fn snapshot(cpu: &Cpu, bus: &Bus) -> Vec<u8> {
+    bincode::serialize(&(cpu, bus)).expect("the machine can always be serialized")
+}
+
+pub(crate) fn load_snapshot(bytes: &[u8]) -> Result<(Cpu, Bus), String> {
+    bincode::deserialize(bytes).map_err(|err| err.to_string())
+}
+
+/// The thumbnail from a save state, as RGB pixels `THUMBNAIL_WIDTH` wide and `THUMBNAIL_HEIGHT`
+/// high.
+pub fn load_thumbnail(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let (_, state) = decompress(bytes)?;
+    bincode::deserialize(&state).map_err(|err| err.to_string())
+}
+
+fn decompress(bytes: &[u8]) -> Result<(u32, Vec<u8>), String> {
+    let Some(compressed) = bytes.strip_prefix(MAGIC) else {
+        return Err("this isn't a save state".to_string());
+    };
+    let Some((version, compressed)) = compressed.split_first_chunk() else {
+        return Err("the save state is cut off".to_string());
+    };
+    let version = u32::from_le_bytes(*version);
+    if version > VERSION {
+        return Err(format!(
+            "the save state is version {}, which is newer than this version ({}) can load",
+            version, VERSION
+        ));
+    }
+    let mut state = vec![];
+    DeflateDecoder::new(compressed)
+        .read_to_end(&mut state)
+        .map_err(|err| format!("the save state is corrupt: {}", err))?;
+    Ok((version, state))
+}
+
+/// Converts the state of a save state from one version to the next.
+type Step = fn(Vec<u8>) -> Result<Vec<u8>, String>;
+
+/// The step from each older version to the one after it, starting from version 1. There are none
+/// yet, as version 1 is the only one.
+const STEPS: &[Step] = &[];
+const _: () = assert!(STEPS.len() + 1 == VERSION as usize);
+
+/// Converts the state of a save state from `version` to the current version.
+fn migrate(version: u32, state: Vec<u8>) -> Result<Vec<u8>, String> {
+    migrate_with(STEPS, version, state)
+}
+
+/// Runs the steps from `version` onwards, one version at a time.
+fn migrate_with(steps: &[Step], version: u32, state: Vec<u8>) -> Result<Vec<u8>, String> {
+    let start = (version as usize).checked_sub(1);
+    let Some(steps) = start.and_then(|start| steps.get(start..)) else {
+        return Err(format!(
+            "save states of version {} can't be loaded",
+            version
+        ));
+    };
+    steps.iter().try_fold(state, |state, step| step(state))
+}
+
+/// Shrinks the screen to half its size, averaging each 2x2 block of pixels.
+fn thumbnail(screen: &[u8]) -> Vec<u8> {
+    let width = THUMBNAIL_WIDTH * 2;
+    let mut pixels = Vec::with_capacity(THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3);
+    for y in 0..THUMBNAIL_HEIGHT {
+        for x in 0..THUMBNAIL_WIDTH {
+            for channel in 0..3 {
+                let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|(dx, dy)| {
+                        let index = ((y * 2 + dy) * width + x * 2 + dx) * 3 + channel;
+                        u32::from(screen[index])
+                    })
+                    .sum();
+                pixels.push((sum / 4) as u8);
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_what_it_cant_load() {
+        let bus = Bus::default();
+        let state = save(&Cpu::default(), &bus);
+        assert_eq!(&state[..8], b"GBAS\x01\x00\x00\x00");
+        assert_eq!(
+            load_thumbnail(&state).unwrap().len(),
+            THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3
+        );
+
+        let mut newer = state.clone();
+        newer[4] = 2;
+        assert_eq!(
+            load(&newer, &bus).err().unwrap(),
+            "the save state is version 2, which is newer than this version (1) can load"
+        );
+        assert_eq!(
+            load(&state[..6], &bus).err().unwrap(),
+            "the save state is cut off"
+        );
+        assert_eq!(load(b"PNG", &bus).err().unwrap(), "this isn't a save state");
+        assert!(load(&state[..state.len() / 2], &bus).is_err());
+
+        let mut other = Bus::default();
+        other.load_rom(&game_header("OTHER", 0x12));
+        assert_eq!(
+            load(&state, &other).err().unwrap(),
+            "the save state is of `` (checksum 0x00), but `OTHER` (checksum 0x12) is loaded"
+        );
+    }
+
+    /// A ROM that's only a header with `title` and `checksum`.
+    fn game_header(title: &str, checksum: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0xc0];
+        rom[0xa0..0xa0 + title.len()].copy_from_slice(title.as_bytes());
+        rom[0xbd] = checksum;
+        rom
+    }
+
+    #[test]
+    fn migrates_one_version_at_a_time() {
+        // Three made-up versions before a fourth, each step recording that it ran
+        let steps: &[Step] = &[
+            |mut state| {
+                state.push(1);
+                Ok(state)
+            },
+            |mut state| {
+                state.push(2);
+                Ok(state)
+            },
+            |mut state| {
+                state.push(3);
+                Ok(state)
+            },
+        ];
+        assert_eq!(migrate_with(steps, 1, vec![0]), Ok(vec![0, 1, 2, 3]));
+        assert_eq!(migrate_with(steps, 3, vec![0]), Ok(vec![0, 3]));
+        assert_eq!(migrate_with(steps, 4, vec![0]), Ok(vec![0]));
+        assert_eq!(
+            migrate_with(steps, 0, vec![0]),
+            Err("save states of version 0 can't be loaded".to_string())
+        );
+
+        let failing: &[Step] = &[|_| Err("bad".to_string())];
+        assert_eq!(migrate_with(failing, 1, vec![]), Err("bad".to_string()));
+        assert_eq!(migrate(VERSION, vec![0]), Ok(vec![0]));
+    }
+
+    #[test]
+    fn averages_pixels_for_the_thumbnail() {
+        let mut screen = vec![0; 240 * 160 * 3];
+        screen[..6].copy_from_slice(&[100, 0, 0, 200, 0, 0]);
+        screen[240 * 3..240 * 3 + 3].copy_from_slice(&[0, 40, 0]);
+        let thumbnail = thumbnail(&screen);
+        assert_eq!(thumbnail[..6], [75, 10, 0, 0, 0, 0]);
+    }
+}
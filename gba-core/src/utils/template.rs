@@ -0,0 +1,50 @@
+//! Templates with `{…}` placeholders, like trace formats and logpoint messages.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Piece<'a> {
+    Text(&'a str),
+    /// What's between the braces of a placeholder.
+    Placeholder(&'a str),
+}
+
+/// Splits `template` into its text and its placeholders, in order.
+pub fn parse(template: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            pieces.push(Piece::Text(&rest[..start]));
+        }
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!("unclosed placeholder in `{}`", template));
+        };
+        pieces.push(Piece::Placeholder(&rest[start + 1..start + end]));
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_text_and_placeholders() {
+        assert_eq!(
+            parse("r0={r0}{r1} done"),
+            Ok(vec![
+                Piece::Text("r0="),
+                Piece::Placeholder("r0"),
+                Piece::Placeholder("r1"),
+                Piece::Text(" done"),
+            ])
+        );
+        assert_eq!(
+            parse("pc {pc"),
+            Err("unclosed placeholder in `pc {pc`".to_string())
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Times a few ROMs with the block cache on and off. This is a benchmark rather than a test, so
+//! it's ignored by default; run it in release mode with
+//!
+//! ```text
+//! cargo test --release --test block_cache -- --ignored --nocapture
+//! ```
+//!
+//! Each ROM is run a few times either way and the fastest run is reported, as single runs are
+//! noisy. The runs have to end on the same screen, so that a faster cache can't hide a broken
+//! one.
+//!
+//! Decoding is only a small part of each tick, next to executing the instruction and drawing, so
+//! the cache makes runs about 1.1-1.3x faster rather than several times.
+
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use common::TICKS_PER_FRAME;
+use gba_core::GbaCore;
+
+const FRAMES: u32 = 300;
+const RUNS: usize = 5;
+const ROMS: &[&str] = &[
+    "tonc_bins/m7_demo.gba",
+    "tonc_bins/swi_demo.gba",
+    "armwrestler-gba-fixed.gba",
+];
+
+fn run_once(rom: &[u8], block_cache: bool) -> (Duration, Vec<u8>) {
+    let mut gba = GbaCore::default();
+    gba.enable_debugger(false);
+    gba.enable_block_cache(block_cache);
+    gba.load_rom(rom);
+    gba.skip_bios();
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        gba.tick_multiple(TICKS_PER_FRAME);
+    }
+    (start.elapsed(), gba.screen())
+}
+
+/// Runs the ROM called `name` with the block cache off and on in turn, returning the fastest run
+/// each way. The runs alternate so that a slow patch on a busy machine slows both alike.
+fn run(name: &str, rom: &[u8]) -> (Duration, Duration) {
+    let mut fastest = (Duration::MAX, Duration::MAX);
+    let mut screen = None;
+    for _ in 0..RUNS {
+        let (off, off_screen) = run_once(rom, false);
+        let (on, on_screen) = run_once(rom, true);
+        assert!(
+            off_screen == on_screen,
+            "{} ends on a different screen with the block cache on",
+            name
+        );
+        assert!(
+            screen.get_or_insert(off_screen) == &on_screen,
+            "runs end on different screens"
+        );
+        fastest = (fastest.0.min(off), fastest.1.min(on));
+    }
+    fastest
+}
+
+#[test]
+#[ignore]
+fn block_cache_speed() {
+    for name in ROMS {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/roms")
+            .join(name);
+        let rom = std::fs::read(&path).unwrap();
+
+        let (off, on) = run(name, &rom);
+        println!(
+            "{:<28} off {:>6.2?}  on {:>6.2?}  ({:.2}x)",
+            name,
+            off,
+            on,
+            off.as_secs_f64() / on.as_secs_f64()
+        );
+    }
+}
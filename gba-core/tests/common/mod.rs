@@ -0,0 +1,64 @@
+//! What the screen tests share: running frames and keeping reference screens as PNGs.
+
+// Each test uses only some of this
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// 4 ticks per dot, 308 dots per line and 228 lines per frame.
+pub const TICKS_PER_FRAME: u32 = 4 * 308 * 228;
+const WIDTH: u32 = 240;
+const HEIGHT: u32 = 160;
+
+pub fn write_png(path: &Path, rgb: &[u8]) {
+    let file = BufWriter::new(File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(rgb)
+        .unwrap();
+}
+
+pub fn read_png(path: &Path) -> Option<Vec<u8>> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let mut reader = decoder.read_info().ok()?;
+    let mut rgb = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut rgb).ok()?;
+    Some(rgb)
+}
+
+/// Draws pixels that changed in red, over a faded copy of the actual screen.
+fn diff(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    expected
+        .chunks_exact(3)
+        .zip(actual.chunks_exact(3))
+        .flat_map(|(expected, actual)| {
+            if expected == actual {
+                let grey = (actual.iter().map(|&c| u16::from(c)).sum::<u16>() / 3 / 4) as u8;
+                [grey, grey, grey]
+            } else {
+                [255, 0, 0]
+            }
+        })
+        .collect()
+}
+
+/// Writes the actual screen of a mismatch into `dir`, and a diff if there's a reference screen to
+/// diff it against, returning where the actual screen went.
+pub fn write_mismatch(dir: &Path, name: &str, actual: &[u8], reference: Option<&[u8]>) -> PathBuf {
+    std::fs::create_dir_all(dir).unwrap();
+    let actual_path = dir.join(format!("{}.actual.png", name));
+    write_png(&actual_path, actual);
+    if let Some(reference) = reference {
+        write_png(
+            &dir.join(format!("{}.diff.png", name)),
+            &diff(reference, actual),
+        );
+    }
+    actual_path
+}
@@ -0,0 +1,158 @@
+//! Runs the CPU test ROMs in `tests/roms` headlessly. The jsmolka ROMs leave the number of the
+//! failing test in r12, and every ROM's screens are checked against the reference screens in
+//! `tests/test_rom_screens`, which show every test passing.
+//!
+//! Run with `TEST_ROMS_BLESS=1` to save the screens as the new references, which then need
+//! looking at to see that they still show every test passing.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::{read_png, write_mismatch, write_png, TICKS_PER_FRAME};
+use gba_core::{GbaCore, Key};
+
+/// "All tests passed", shown by both jsmolka ROMs.
+const JSMOLKA_PASSED: &str = "jsmolka_passed";
+/// "End of testing" with no failures listed, shown by all of the FuzzARM ROMs.
+const FUZZARM_PASSED: &str = "fuzzarm_passed";
+
+fn boot(rom: &[u8]) -> GbaCore {
+    let mut gba = GbaCore::default();
+    gba.enable_debugger(false);
+    gba.load_rom(rom);
+    gba.skip_bios();
+    gba
+}
+
+fn run_frames(gba: &mut GbaCore, frames: u32) {
+    for _ in 0..frames {
+        gba.tick_multiple(TICKS_PER_FRAME);
+    }
+}
+
+/// Holds `key` for long enough that the ROM sees it, then lets the ROM react for a while.
+fn press(gba: &mut GbaCore, key: Key) {
+    gba.set_key(key, true);
+    run_frames(gba, 2);
+    gba.set_key(key, false);
+    run_frames(gba, 2);
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/test_rom_screens/{}.png", name))
+}
+
+/// Checks the screen against the reference screen `name`.
+fn assert_screen(gba: &GbaCore, name: &str, what: &str) {
+    let screen = gba.screen();
+    let path = reference_path(name);
+    if std::env::var_os("TEST_ROMS_BLESS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        write_png(&path, &screen);
+        return;
+    }
+
+    let reference = read_png(&path);
+    if reference.as_deref() != Some(screen.as_slice()) {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_roms");
+        let actual = write_mismatch(&dir, name, &screen, reference.as_deref());
+        panic!(
+            "{} doesn't match {} (see {})",
+            what,
+            path.display(),
+            actual.display()
+        );
+    }
+}
+
+fn run_jsmolka(rom: &[u8]) {
+    let mut gba = boot(rom);
+    run_frames(&mut gba, 10);
+    assert_eq!(gba.reg(12), 0, "failed test {}", gba.reg(12));
+    assert_screen(&gba, JSMOLKA_PASSED, "the result");
+}
+
+#[test]
+fn jsmolka_arm() {
+    run_jsmolka(include_bytes!("roms/arm.gba"));
+}
+
+#[test]
+fn jsmolka_thumb() {
+    run_jsmolka(include_bytes!("roms/thumb.gba"));
+}
+
+/// The FuzzARM ROMs run thousands of generated tests, listing any failures, which takes them
+/// about 130 frames.
+fn run_fuzzarm(rom: &[u8]) {
+    let mut gba = boot(rom);
+    run_frames(&mut gba, 150);
+    assert_screen(&gba, FUZZARM_PASSED, "the result");
+}
+
+#[test]
+fn fuzzarm() {
+    run_fuzzarm(include_bytes!("roms/FuzzARM.gba"));
+}
+
+#[test]
+fn fuzzarm_arm_any() {
+    run_fuzzarm(include_bytes!("roms/ARM_Any.gba"));
+}
+
+#[test]
+fn fuzzarm_arm_data_processing() {
+    run_fuzzarm(include_bytes!("roms/ARM_DataProcessing.gba"));
+}
+
+#[test]
+fn fuzzarm_thumb_any() {
+    run_fuzzarm(include_bytes!("roms/THUMB_Any.gba"));
+}
+
+#[test]
+fn fuzzarm_thumb_data_processing() {
+    run_fuzzarm(include_bytes!("roms/THUMB_DataProcessing.gba"));
+}
+
+/// Picks the menu entry `entry` places below the first, then pages through its results with
+/// START, checking each page against its reference screen, `name` and the page's number. The last
+/// page leads back to the menu.
+fn run_armwrestler(name: &str, entry: usize, pages: &[&str]) {
+    let mut gba = boot(include_bytes!("roms/armwrestler-gba-fixed.gba"));
+    run_frames(&mut gba, 10);
+    for _ in 0..entry {
+        press(&mut gba, Key::Down);
+    }
+
+    for (number, title) in (1..).zip(pages) {
+        press(&mut gba, Key::Start);
+        run_frames(&mut gba, 10);
+        assert_screen(&gba, &format!("{}_{}", name, number), title);
+    }
+}
+
+#[test]
+fn armwrestler_arm() {
+    run_armwrestler(
+        "armwrestler_arm",
+        0,
+        &[
+            "ALU tests part 1",
+            "ALU pt 2 / misc",
+            "Load tests part 1",
+            "Load tests part 2",
+            "LDM/STM tests 1",
+        ],
+    );
+}
+
+#[test]
+fn armwrestler_thumb() {
+    run_armwrestler(
+        "armwrestler_thumb",
+        3,
+        &["ALU test", "LDR/STR test 1", "LDM/STM test"],
+    );
+}
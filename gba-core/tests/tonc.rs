@@ -0,0 +1,288 @@
+//! Screen regression tests for the tonc demos in `tests/roms/tonc_bins`. Each demo runs to a
+//! fixed frame, with scripted input for the interactive ones, and a hash of the screen is checked
+//! against `tests/tonc_expected.txt`. A screen that's a single colour is never accepted, as the
+//! demo hasn't drawn anything on it. Demos that use something the emulator can't do yet, like
+//! sprites or windows, are only run to check that they don't crash it, so that a partly drawn
+//! screen is never blessed as the expectation.
+//!
+//! Run with `TONC_BLESS=1` to rewrite the expectations after an intended change. Blessing also
+//! saves every screen as a reference PNG in `tests/tonc_screens`, so that the screens can be
+//! looked at, and mismatches write the actual screen and a diff against the reference into the
+//! test's temporary directory.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use common::{read_png, write_mismatch, write_png, TICKS_PER_FRAME};
+use gba_core::{GbaCore, Key};
+
+/// Holds `key` down from the start of frame `from` until the start of frame `until`.
+struct Hold {
+    key: Key,
+    from: u32,
+    until: u32,
+}
+
+/// How a demo's screen is checked.
+enum Check {
+    /// Against the expected hash.
+    Screen,
+    /// The demo only draws with something the emulator can't do yet, so its screen stays blank.
+    Undrawn(&'static str),
+    /// Some of what the demo draws uses something the emulator can't do yet, so its screen is
+    /// incomplete and isn't checked.
+    Incomplete(&'static str),
+}
+
+struct Demo {
+    name: &'static str,
+    frames: u32,
+    input: &'static [Hold],
+    check: Check,
+}
+
+const fn demo(name: &'static str, frames: u32) -> Demo {
+    Demo {
+        name,
+        frames,
+        input: &[],
+        check: Check::Screen,
+    }
+}
+
+const fn undrawn(name: &'static str, what: &'static str) -> Demo {
+    Demo {
+        name,
+        frames: 30,
+        input: &[],
+        check: Check::Undrawn(what),
+    }
+}
+
+const fn incomplete(name: &'static str, what: &'static str) -> Demo {
+    Demo {
+        name,
+        frames: 30,
+        input: &[],
+        check: Check::Incomplete(what),
+    }
+}
+
+const DEMOS: &[Demo] = &[
+    incomplete("bigmap", "more than one background"),
+    incomplete("bld_demo", "sprites and blending"),
+    demo("bm_modes", 30),
+    Demo {
+        name: "brin_demo",
+        frames: 30,
+        input: &[
+            Hold {
+                key: Key::Right,
+                from: 5,
+                until: 20,
+            },
+            Hold {
+                key: Key::Down,
+                from: 10,
+                until: 25,
+            },
+        ],
+        check: Check::Screen,
+    },
+    incomplete("cbb_demo", "more than one background"),
+    incomplete("dma_demo", "windows"),
+    demo("first", 10),
+    demo("hello", 30),
+    undrawn("irq_demo", "mode 5"),
+    Demo {
+        name: "key_demo",
+        frames: 20,
+        input: &[
+            Hold {
+                key: Key::A,
+                from: 10,
+                until: 20,
+            },
+            Hold {
+                key: Key::Left,
+                from: 10,
+                until: 20,
+            },
+        ],
+        check: Check::Screen,
+    },
+    demo("m3_demo", 30),
+    undrawn("m7_demo", "mode 1"),
+    undrawn("m7_demo_mb", "mode 1"),
+    undrawn("m7_ex", "mode 1"),
+    incomplete("mos_demo", "sprites and mosaic"),
+    incomplete("oacombo", "sprites"),
+    incomplete("obj_aff", "sprites"),
+    Demo {
+        name: "obj_demo",
+        frames: 30,
+        input: &[Hold {
+            key: Key::Right,
+            from: 5,
+            until: 25,
+        }],
+        check: Check::Undrawn("sprites"),
+    },
+    undrawn("octtest", "mode 1"),
+    demo("pageflip", 30),
+    incomplete("prio_demo", "sprites"),
+    undrawn("sbb_aff", "mode 1"),
+    demo("sbb_reg", 30),
+    demo("second", 10),
+    demo("snd1_demo", 30),
+    demo("swi_demo", 30),
+    undrawn("swi_vsync", "sprites"),
+    demo("tmr_demo", 30),
+    // It clears the screen between its pages of text, which it's in the middle of at frame 30
+    demo("tte_demo", 60),
+    demo("txt_bm", 30),
+    incomplete("txt_obj", "sprites"),
+    demo("txt_se1", 30),
+    // It times itself with cascaded timers, which always read 0
+    incomplete("txt_se2", "cascaded timers"),
+    incomplete("win_demo", "windows and sprites"),
+];
+
+fn manifest_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    manifest_path(&format!("tests/tonc_screens/{}.png", name))
+}
+
+fn mismatches_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("tonc")
+}
+
+/// Runs `demo`, returning its screen or `None` if the emulator panicked.
+fn run(demo: &Demo) -> Option<Vec<u8>> {
+    let path = format!("tests/roms/tonc_bins/{}.gba", demo.name);
+    let rom = fs::read(manifest_path(&path)).unwrap();
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut gba = GbaCore::default();
+        gba.enable_debugger(false);
+        gba.load_rom(&rom);
+        gba.skip_bios();
+        for frame in 0..demo.frames {
+            for hold in demo.input {
+                if frame == hold.from {
+                    gba.set_key(hold.key, true);
+                } else if frame == hold.until {
+                    gba.set_key(hold.key, false);
+                }
+            }
+            gba.tick_multiple(TICKS_PER_FRAME);
+        }
+        gba.screen()
+    }))
+    .ok()
+}
+
+/// FNV-1a, which is stable across Rust versions unlike `DefaultHasher`.
+fn screen_hash(screen: &[u8]) -> String {
+    let hash = screen.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:#018x}", hash)
+}
+
+fn is_uniform(screen: &[u8]) -> bool {
+    screen.chunks_exact(3).all(|pixel| pixel == &screen[..3])
+}
+
+fn read_expectations(path: &Path) -> BTreeMap<String, String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return BTreeMap::new();
+    };
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, outcome)| (name.to_string(), outcome.to_string()))
+        .collect()
+}
+
+fn write_expectations(path: &Path, hashes: &BTreeMap<String, String>) {
+    let mut text = String::from("# Generated by running the tonc test with TONC_BLESS=1\n");
+    for (name, hash) in hashes {
+        text += &format!("{} {}\n", name, hash);
+    }
+    fs::write(path, text).unwrap();
+}
+
+#[test]
+fn tonc_demos_match_expected_screens() {
+    let expectations_path = manifest_path("tests/tonc_expected.txt");
+    let bless = std::env::var_os("TONC_BLESS").is_some();
+    if bless {
+        fs::create_dir_all(manifest_path("tests/tonc_screens")).unwrap();
+    }
+
+    let expected = read_expectations(&expectations_path);
+    let mut hashes = BTreeMap::new();
+    let mut failures = vec![];
+    let mut mismatches = vec![];
+    for demo in DEMOS {
+        let Some(screen) = run(demo) else {
+            failures.push(format!("{}: the emulator panicked", demo.name));
+            continue;
+        };
+        match (&demo.check, is_uniform(&screen)) {
+            (Check::Incomplete(what), _) => {
+                println!("{}: not checked, as it uses {}", demo.name, what);
+                continue;
+            }
+            (Check::Undrawn(_), true) => continue,
+            (Check::Undrawn(what), false) => {
+                failures.push(format!(
+                    "{}: it draws now, so it can be checked rather than listed as using {}",
+                    demo.name, what
+                ));
+                continue;
+            }
+            (Check::Screen, true) => {
+                failures.push(format!(
+                    "{}: the screen is a single colour at frame {}",
+                    demo.name, demo.frames
+                ));
+                continue;
+            }
+            (Check::Screen, false) => {}
+        }
+
+        let hash = screen_hash(&screen);
+        if bless {
+            write_png(&reference_path(demo.name), &screen);
+        } else if expected.get(demo.name) != Some(&hash) {
+            let reference = read_png(&reference_path(demo.name));
+            let path = write_mismatch(&mismatches_dir(), demo.name, &screen, reference.as_deref());
+            mismatches.push(format!(
+                "{}: expected {}, got {} (see {})",
+                demo.name,
+                expected.get(demo.name).map_or("nothing", String::as_str),
+                hash,
+                path.display()
+            ));
+        }
+        hashes.insert(demo.name.to_string(), hash);
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    if bless {
+        write_expectations(&expectations_path, &hashes);
+    }
+    assert!(
+        mismatches.is_empty(),
+        "screens changed, rerun with TONC_BLESS=1 if that's intended:\n{}",
+        mismatches.join("\n")
+    );
+}
@@ -1,4 +1,4 @@
-use gba_core::Key;
+use gba_core::{Flag, Key, Mode};
 
 use crate::cpu_debug::CpuDebugInfo;
 
@@ -9,6 +9,14 @@ pub enum Event {
     ScreenData,
     CpuDebugInfo,
     KeyEvent{key: Key, pressed: bool},
+    /// Write a register as it's banked in `mode`, or in the current mode if it's `None`
+    SetReg{index: u32, mode: Option<Mode>, value: u32},
+    /// Write the SPSR of `mode`, or the CPSR if it's `None`
+    SetPsr{mode: Option<Mode>, value: u32},
+    /// Set or clear a flag in the SPSR of `mode`, or in the CPSR if it's `None`
+    SetPsrFlag{flag: Flag, value: bool, mode: Option<Mode>},
+    /// Step back in time, a frame at a time, for as long as it's held
+    Rewind(bool),
 }
 
 pub enum ControlEvent {
@@ -1,7 +1,7 @@
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc;
 
-use gba_core::Key;
+use gba_core::{Flag, Key, Mode};
 
 use js_sys::Uint8ClampedArray;
 use wasm_bindgen::prelude::*;
@@ -54,6 +54,11 @@ impl Gba {
         self.tx.send(Event::KeyEvent{key, pressed}).to_js_result()
     }
 
+    /// Step back in time while `held` is set
+    pub fn set_rewind(&self, held: bool) -> Result<(), JsValue> {
+        self.tx.send(Event::Rewind(held)).to_js_result()
+    }
+
     /// Pause the GBA execution
     pub fn set_pause(&self, pause: bool) -> Result<(), JsValue> {
         self.tx.send(Event::ControlEvent(ControlEvent::Pause(pause))).to_js_result()
@@ -67,6 +72,27 @@ impl Gba {
         self.tx.send(Event::ScreenData).to_js_result()
     }
 
+    /// Write register `index` as it's banked in `mode`, or in the current mode if it's not given.
+    /// Writing r15 moves the PC.
+    pub fn set_reg(&self, index: u32, mode: Option<String>, value: u32) -> Result<(), JsValue> {
+        let mode = parse_mode(mode)?;
+        self.tx.send(Event::SetReg{index, mode, value}).to_js_result()
+    }
+
+    /// Write the SPSR of `mode`, or the CPSR if it's not given
+    pub fn set_psr(&self, value: u32, mode: Option<String>) -> Result<(), JsValue> {
+        let mode = parse_mode(mode)?;
+        self.tx.send(Event::SetPsr{mode, value}).to_js_result()
+    }
+
+    /// Set or clear `flag`, one of n, z, c, v, i, f or t, in the SPSR of `mode`, or in the CPSR if
+    /// it's not given
+    pub fn set_psr_flag(&self, flag: &str, value: bool, mode: Option<String>) -> Result<(), JsValue> {
+        let flag = Flag::parse(flag)?;
+        let mode = parse_mode(mode)?;
+        self.tx.send(Event::SetPsrFlag{flag, value, mode}).to_js_result()
+    }
+
     pub fn request_cpu_debug_info(&self) -> Result<(), JsValue> {
         self.tx.send(Event::CpuDebugInfo).to_js_result()
     }
@@ -88,3 +114,7 @@ impl Gba {
         Ok(())
     }
 }
+
+fn parse_mode(mode: Option<String>) -> Result<Option<Mode>, JsValue> {
+    Ok(mode.map(|mode| Mode::parse(&mode)).transpose()?)
+}
@@ -9,6 +9,11 @@ use crate::cpu_debug::CpuDebugInfo;
 use crate::to_js_result::{ToJsResult, OptionToJsResult};
 use crate::control::{Event, ControlState, Response};
 
+/// Keep the last 10 seconds to rewind to
+const REWIND_FRAMES: usize = 600;
+/// How often to step back a frame while rewinding, in ms
+const REWIND_STEP: f64 = 1000. / 60.;
+
 pub struct GbaThread {
     gba: GbaCore,
 
@@ -43,9 +48,12 @@ impl GbaThread {
 
         let mut screen_render = false;
         let mut cpu_debug_info = false;
+        let mut rewinding = false;
+        let mut last_rewind = 0.;
 
         self.gba.load_test_rom();
         self.gba.skip_bios();
+        self.gba.start_rewind(1, REWIND_FRAMES);
 
         loop {
             for event in self.rx.try_iter() {
@@ -57,6 +65,7 @@ impl GbaThread {
                         self.gba = GbaCore::default();
                         self.gba.load_rom(&rom);
                         self.gba.skip_bios();
+                        self.gba.start_rewind(1, REWIND_FRAMES);
                     }
                     Event::ScreenData => {
                         screen_render = true;
@@ -67,6 +76,32 @@ impl GbaThread {
                     Event::KeyEvent { key, pressed } => {
                         self.gba.set_key(key, pressed);
                     }
+                    Event::SetReg { index, mode, value } => {
+                        let result = match mode {
+                            Some(mode) => self.gba.set_reg_with_mode(index, mode, value),
+                            None => self.gba.set_reg(index, value),
+                        };
+                        if let Err(err) = result {
+                            console::error_1(&err.into());
+                        }
+                    }
+                    Event::SetPsr { mode, value } => {
+                        let result = match mode {
+                            Some(mode) => self.gba.set_spsr(mode, value),
+                            None => self.gba.set_cpsr(value),
+                        };
+                        if let Err(err) = result {
+                            console::error_1(&err.into());
+                        }
+                    }
+                    Event::SetPsrFlag { flag, value, mode } => {
+                        if let Err(err) = self.gba.set_flag(flag, value, mode) {
+                            console::error_1(&err.into());
+                        }
+                    }
+                    Event::Rewind(held) => {
+                        rewinding = held;
+                    }
                 }
             }
              
@@ -74,12 +109,22 @@ impl GbaThread {
                 continue;
             }
 
-            let start_time = performance.now();
-            self.gba.tick_multiple(ticks);
-            let end_time = performance.now();
-            let elapsed = end_time - start_time;
-            // Mult by 1000 for ms -> s
-            let ticks_per_sec = ticks as f64 / elapsed * 1000.;
+            if rewinding {
+                let now = performance.now();
+                if now - last_rewind >= REWIND_STEP {
+                    last_rewind = now;
+                    if let Err(err) = self.gba.rewind(1) {
+                        console::error_1(&err.into());
+                    }
+                }
+            } else {
+                let start_time = performance.now();
+                self.gba.tick_multiple(ticks);
+                let end_time = performance.now();
+                let elapsed = end_time - start_time;
+                // Mult by 1000 for ms -> s
+                let ticks_per_sec = ticks as f64 / elapsed * 1000.;
+            }
 
 
             if screen_render {
@@ -1,6 +1,10 @@
 import { Gba, Key } from '$lib/pkg/debug/gba_web';
 
 export const handleKey = (gba: Gba | undefined, event: KeyboardEvent, pressed: boolean) => {
+	if (event.key === 'r') {
+		gba?.set_rewind(pressed);
+		return;
+	}
 	const key = getKey(event);
 	if (key && gba) {
 		gba.set_key(key, pressed);